@@ -0,0 +1,115 @@
+use std::hint::black_box;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use instance_chart::{bench, BincodeOptions, Chart, ChartBuilder, IntEncoding, WireFormat};
+
+const CLUSTER: u64 = 1000;
+
+fn chart(discovery_port: u16) -> Chart<1, u16> {
+    ChartBuilder::new()
+        .with_id(0)
+        .with_service_port(8042)
+        .with_discovery_port(discovery_port)
+        .local_discovery(true)
+        .with_expected_capacity(CLUSTER as usize)
+        .finish()
+        .unwrap()
+}
+
+fn cluster(chart: &Chart<1, u16>) -> Vec<(Vec<u8>, SocketAddr)> {
+    (1..=CLUSTER)
+        .map(|id| {
+            let ip = Ipv4Addr::from(0x0a00_0000 + id as u32);
+            (bench::heartbeat(chart, id), SocketAddr::from((ip, 8080)))
+        })
+        .collect()
+}
+
+fn wire(c: &mut Criterion) {
+    let varint = WireFormat::Bincode(BincodeOptions {
+        int_encoding: IntEncoding::Varint,
+        ..BincodeOptions::default()
+    });
+    for (name, format) in [("fixint", WireFormat::default()), ("varint", varint)] {
+        let ports = [8042u16, 8043, 8044];
+        c.bench_function(&format!("encode {name}"), |b| {
+            b.iter(|| bench::encode(format, black_box(ports)))
+        });
+        let buf = bench::encode(format, ports);
+        c.bench_function(&format!("decode {name}"), |b| {
+            b.iter(|| bench::decode::<3>(format, black_box(&buf)))
+        });
+    }
+}
+
+fn announce(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+
+    let chart = chart(43818);
+    c.bench_function("discovery msg", |b| {
+        b.iter(|| bench::discovery_buf(black_box(&chart)).len())
+    });
+}
+
+fn process(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+
+    let chart = chart(43803);
+    let datagrams = cluster(&chart);
+    let mut group = c.benchmark_group("process");
+    group.bench_function("newcomers", |b| {
+        b.iter_batched(
+            || (1..=CLUSTER).for_each(|id| chart.forget(id)),
+            |()| bench::process(&chart, &datagrams),
+            BatchSize::PerIteration,
+        )
+    });
+
+    bench::process(&chart, &datagrams);
+    group.bench_function("known heartbeats", |b| {
+        b.iter(|| bench::process(&chart, black_box(&datagrams)))
+    });
+    group.finish();
+}
+
+fn read_contention(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+
+    let chart = chart(43804);
+    let datagrams = cluster(&chart);
+    bench::process(&chart, &datagrams);
+
+    let mut group = c.benchmark_group("get_addr");
+    group.bench_function("uncontended", |b| {
+        b.iter(|| chart.get_addr(black_box(CLUSTER / 2)))
+    });
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let receiver = {
+        let (chart, stop) = (chart.clone(), stop.clone());
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                bench::process(&chart, &datagrams);
+            }
+        })
+    };
+    group.bench_function("while receiving", |b| {
+        b.iter(|| chart.get_addr(black_box(CLUSTER / 2)))
+    });
+    group.finish();
+    stop.store(true, Ordering::Relaxed);
+    receiver.join().unwrap();
+
+    let counters = chart.counters();
+    println!("receive path waited {:?} for the lock", counters.lock_wait);
+}
+
+criterion_group!(benches, wire, announce, process, read_contention);
+criterion_main!(benches);
@@ -1,19 +1,14 @@
-use instance_chart::{discovery, ChartBuilder};
-use std::io::Write;
+use instance_chart::{discovery, peers::Peers, ChartBuilder};
 use tokio::io::AsyncBufReadExt;
 use tokio::io::BufReader;
 use tokio::net::TcpListener;
 use tokio::task;
-use tokio::task::spawn_blocking;
-
-use instance_chart;
 
 async fn print_user(stream: tokio::net::TcpStream, user: String) {
-    let mut reader = BufReader::new(stream);
-    let mut line = String::new();
-    reader.read_line(&mut line).await.unwrap();
-
-    print!("\t{user}: {line}");
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        println!("\t{user}: {line}");
+    }
 }
 
 async fn print_incoming(listener: TcpListener) {
@@ -58,19 +53,12 @@ async fn main() {
     tokio::spawn(async move { discovery::maintain(chart2).await });
     tokio::spawn(async move { print_incoming(listener).await });
 
-    spawn_blocking(move || {
-        let reader = std::io::stdin();
-        loop {
-            let mut line = String::new();
-            reader.read_line(&mut line).unwrap();
-            for (_, addr) in chart.addr_vec() {
-                let mut peer = std::net::TcpStream::connect(addr).unwrap();
-                peer.write_all(line.as_bytes()).unwrap();
-            }
+    let mut peers = Peers::new(chart);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        let line = format!("{line}\n");
+        for (id, err) in peers.broadcast_tcp(line.as_bytes()).await {
+            println!("could not reach {id}: {err}");
         }
-    })
-    .await
-    .unwrap();
-
-    unreachable!()
+    }
 }
@@ -6,7 +6,6 @@ use std::time::Duration;
 #[tokio::main]
 async fn main() {
     let jobs: Vec<_> = (1024..10_000u16)
-        .into_iter()
         .map(port_can_multicast)
         .collect();
 
@@ -41,7 +41,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
 
     let maintain = discovery::maintain(chart.clone());
-    let _ = tokio::spawn(maintain);
+    tokio::spawn(maintain);
 
     discovery::found_everyone(&chart, cluster_size).await;
     info!("discovery complete: {chart:?}");
@@ -1,55 +1,356 @@
-use std::collections::HashMap;
 use std::fmt::Debug;
+use std::future::Future;
+use std::io;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::ops::Deref;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
-use serde_big_array::BigArray;
 use tokio::net::UdpSocket;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::Stream;
 
+mod array;
+mod clock;
+pub use clock::{Clock, TokioClock};
 mod interval;
-use interval::Interval;
-use tracing::trace;
+use interval::{Interval, Jitter};
+mod map;
+use map::Map;
+mod host;
+use host::HostIps;
+mod transport;
+pub use transport::LocalDiscovery;
+use transport::Transport;
+#[cfg(unix)]
+use transport::UnixSocket;
+mod wake;
 
 mod notify;
 pub use notify::Notify;
+mod events;
+pub use events::Event;
+mod conflict;
+pub use conflict::ConflictPolicy;
+mod counters;
+pub use counters::Counters;
+mod metrics;
+pub use metrics::{ChartMetrics, NoMetrics};
+mod store;
+pub use store::ChartStore;
+mod endpoint;
+pub use endpoint::Endpoint;
+mod reply;
+pub use reply::ReplyPolicy;
+mod quarantine;
+use quarantine::Quarantine;
+mod debounce;
+use debounce::Debounce;
+mod unreachable;
+use unreachable::Unreachable;
+mod budget;
+use budget::Budget;
+mod claim;
+mod metadata;
+pub use metadata::Metadata;
+mod health;
+pub use health::PortHealth;
+mod observer;
+pub use observer::Announcement;
+use observer::Observer;
+mod middleware;
+pub use middleware::{Action, Meta};
+use middleware::{Inbound, Outbound};
+#[cfg(feature = "challenge")]
+mod challenge;
+#[cfg(feature = "challenge")]
+use challenge::Challenges;
+#[cfg(feature = "packet-tap")]
+mod tap;
+#[cfg(feature = "packet-tap")]
+pub use tap::Direction;
+#[cfg(feature = "packet-tap")]
+use tap::Tap;
+#[cfg(feature = "fault-injection")]
+mod faults;
+#[cfg(feature = "fault-injection")]
+use faults::Faults;
+mod callbacks;
+#[cfg(feature = "rand")]
+mod id_rng;
+#[cfg(feature = "json-log")]
+mod json_log;
+mod maintained;
+#[cfg(feature = "rand")]
+use id_rng::IdRng;
+mod autoassign;
+mod freeze;
+#[cfg(feature = "testing")]
+mod partition;
+mod probe;
+#[cfg(feature = "testing")]
+pub(crate) use partition::{Cut, Groups};
+mod dot;
+mod stats;
+pub use stats::ChartStats;
+mod reconcile;
+pub use reconcile::{AddrMismatch, ReconcileReport};
+mod subnet;
+pub use subnet::Subnet;
+mod priority;
+mod visibility;
+use visibility::Visibility;
+mod trend;
+use trend::Ewma;
+pub use trend::SizeTrend;
+mod socket_info;
+pub use socket_info::SocketInfo;
+mod capabilities;
+pub use capabilities::Capabilities;
+mod snapshot;
+pub use snapshot::{IntoIter as SnapshotIntoIter, Iter as SnapshotIter, Snapshot};
+mod resolver;
+pub use resolver::{ChartResolver, Target};
+mod merged;
+pub use merged::{MergedChart, MergedEntry};
+mod wire;
+use wire::DecodeError;
+#[cfg(feature = "protobuf")]
+pub use wire::PROTO_SCHEMA;
+pub use wire::{BincodeOptions, Endian, IntEncoding, WireFormat};
+#[cfg(test)]
+mod fixtures;
 
-use crate::Id;
+use crate::{DiscoveryError, Error, Id, SelfTestError};
 mod builder;
+#[cfg(all(unix, feature = "systemd"))]
+pub(crate) use builder::prepare_bound;
 use builder::Port;
 
-pub use builder::ChartBuilder;
+pub use builder::{ChartBuilder, ChartConfig, DynamicBuilder};
+pub use builder::{
+    DEFAULT_DISCOVERY_PORT, DEFAULT_HEADER, DEFAULT_MAX_INTERVAL, DEFAULT_MIN_INTERVAL,
+    DEFAULT_RAMPDOWN,
+};
 
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod get;
 pub mod to_vec;
 
-use self::interval::Until;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
-pub struct DiscoveryMsg<const N: usize, T>
-where
-    T: Serialize + DeserializeOwned,
-{
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
+pub struct DiscoveryMsg<const N: usize, T> {
     header: u64,
     id: Id,
-    #[serde(with = "BigArray")]
+    status: Status,
+    /// the longest the sender waits in between broadcasts
+    interval_ms: u32,
+    group: Option<u16>,
+    capabilities: Capabilities,
+    #[serde(with = "array::counted")]
     msg: [T; N],
+    // the fields below are not in every version, the bincode format sends
+    // them as extensions gated by a capability, see `wire::Extension`
+    #[serde(skip)]
+    meta: Metadata,
+    #[serde(skip)]
+    port_health: PortHealth,
+    /// how long to keep the sender charted without hearing from it, in
+    /// milliseconds, see [`ChartBuilder::with_ttl`]
+    #[serde(skip)]
+    ttl_ms: Option<u32>,
+    /// see [`ChartBuilder::with_priority`]
+    #[serde(skip)]
+    priority: u32,
+    /// the number of instances the sender charted, including itself. Zero
+    /// if the sender does not support [`Capabilities::VISIBILITY`]
+    #[serde(skip)]
+    sees: u32,
+}
+
+impl<const N: usize, T> DiscoveryMsg<N, T> {
+    /// with the fields that change while running set to what takes the
+    /// most space to encode
+    fn widest(self) -> Self {
+        Self {
+            id: Id::MAX,
+            status: Status::Leaving,
+            port_health: PortHealth::from_unhealthy_bits(u64::MAX),
+            priority: u32::MAX,
+            sees: u32::MAX,
+            ..self
+        }
+    }
+}
+
+/// The state of an instance, see [`discovery::drain`](crate::discovery::drain).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Status {
+    /// Running normally
+    #[default]
+    Active,
+    /// Shutting down soon, still reachable but should not be given new work
+    Draining,
+    /// Left the cluster, charts receiving this forget the instance
+    Leaving,
+}
+
+/// How an [`Entry`] got into the chart. An instance that is merged and later
+/// heard from over the network is marked as [`Network`](Source::Network).
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum Source {
+    /// Received on a discovery socket, either broadcast to the multicast
+    /// group or sent directly as a [reply](ChartBuilder::with_reply_policy)
+    #[default]
+    Network,
+    /// Added using [`Chart::merge_from`]
+    Merged,
 }
 
 /// A chart entry representing a discovered node. The msg is an array of
 /// ports or a custom struct if you used [`custom_msg`](ChartBuilder::custom_msg()).
 ///
-/// You probably do not want to use one of the [iterator methods](iter) instead
-#[derive(Debug, Clone)]
+/// You probably want to use one of the [iterator methods](Chart::addr_vec) instead
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Entry<Msg: Debug + Clone> {
     pub ip: IpAddr,
     pub msg: Msg,
+    pub status: Status,
+    /// the [group](ChartBuilder::with_group) the instance is in
+    pub group: Option<u16>,
+    /// the sub-protocols the instance supports
+    pub capabilities: Capabilities,
+    /// how the instance was learned of
+    pub source: Source,
+    /// the key value pairs the instance shares, see [`Chart::put_meta`]
+    pub meta: Metadata,
+    /// which of the instance's ports are healthy, see [`Chart::set_port_health`]
+    pub port_health: PortHealth,
+    /// how much the instance wants to coordinate, see [`ChartBuilder::with_priority`]
+    pub priority: u32,
+}
+
+impl<Msg: Debug + Clone> Entry<Msg> {
+    #[cfg(test)]
+    pub(crate) fn new(ip: IpAddr, msg: Msg) -> Self {
+        Self {
+            ip,
+            msg,
+            status: Status::Active,
+            group: None,
+            capabilities: Capabilities::OURS,
+            source: Source::Network,
+            meta: Metadata::default(),
+            port_health: PortHealth::default(),
+            priority: 0,
+        }
+    }
+}
+
+/// A discovery socket and the multicast groups it joined
+#[derive(Debug)]
+pub(crate) struct Socket {
+    sock: Transport,
+    groups: Vec<IpAddr>,
+    /// when our own discovery msg last arrived back on this socket
+    last_echo: watch::Sender<Option<Instant>>,
+}
+
+impl Socket {
+    pub(crate) fn new(sock: Arc<UdpSocket>, groups: Vec<IpAddr>) -> Self {
+        Self {
+            sock: Transport::Udp(sock),
+            groups,
+            last_echo: watch::Sender::new(None),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn unix(sock: UnixSocket) -> Self {
+        Self {
+            sock: Transport::Unix(sock),
+            groups: Vec::new(),
+            last_echo: watch::Sender::new(None),
+        }
+    }
+
+    /// drop datagrams received from across a
+    /// [`Partition`](crate::testing::Partition) once the chart is added to one
+    #[cfg(feature = "testing")]
+    pub(crate) fn partitioned(self, cut: Cut) -> Self {
+        Self {
+            sock: Transport::Partitioned(Box::new(self.sock), cut),
+            ..self
+        }
+    }
+
+    /// where to send a broadcast
+    fn broadcast_targets(&self) -> Vec<SocketAddr> {
+        #[cfg(unix)]
+        if let Transport::Unix(sock) = self.sock.inner() {
+            return sock.peers();
+        }
+        let port = self.port();
+        self.groups
+            .iter()
+            .map(|group| SocketAddr::from((*group, port)))
+            .collect()
+    }
+
+    fn port(&self) -> u16 {
+        self.sock.local_addr().map_or(0, |addr| addr.port())
+    }
+
+    fn echoed_since(&self, since: Instant) -> bool {
+        self.last_echo.borrow().is_some_and(|echo| echo >= since)
+    }
+
+    /// wait for our own discovery msg to arrive after `since`
+    async fn echo_after(&self, since: Instant) {
+        let mut last_echo = self.last_echo.subscribe();
+        last_echo
+            .wait_for(|echo| echo.is_some_and(|echo| echo >= since))
+            .await
+            .expect("sender is owned by self");
+    }
+
+    /// leave and join all groups again
+    fn rejoin(&self) -> io::Result<()> {
+        let Transport::Udp(sock) = self.sock.inner() else {
+            return Ok(());
+        };
+        // fails if the membership was dropped, that is why we rejoin
+        for group in &self.groups {
+            match group {
+                IpAddr::V4(group) => {
+                    let interface = Ipv4Addr::UNSPECIFIED;
+                    let _ig_err = sock.leave_multicast_v4(*group, interface);
+                    sock.join_multicast_v4(*group, interface)?;
+                }
+                IpAddr::V6(group) => {
+                    let _ig_err = sock.leave_multicast_v6(group, 0);
+                    sock.join_multicast_v6(group, 0)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 /// The chart keeping track of the discoverd nodes. That a node appears in the
@@ -57,67 +358,1022 @@ pub struct Entry<Msg: Debug + Clone> {
 #[derive(Debug, Clone)]
 pub struct Chart<const N: usize, T: Debug + Clone + Serialize> {
     header: u64,
-    service_id: Id,
-    msg: [T; N],
-    sock: Arc<UdpSocket>,
+    /// only changes if our random id is re-rolled after a collision
+    service_id: Arc<AtomicU64>,
+    /// re-roll our id if another instance uses it until this moment, see
+    /// [`ChartBuilder::reroll_id_on_collision`]
+    #[cfg_attr(not(feature = "rand"), allow(dead_code))]
+    reroll_until: Option<Instant>,
+    /// see [`ChartBuilder::with_id_rng`]
+    #[cfg(feature = "rand")]
+    id_rng: Option<IdRng>,
+    /// can change, see [`Chart::set_service_port`]
+    msg: Arc<std::sync::RwLock<[T; N]>>,
+    endpoints: Arc<Vec<Socket>>,
     interval: Interval,
-    map: Arc<std::sync::Mutex<HashMap<Id, Entry<[T; N]>>>>,
+    map: Arc<std::sync::Mutex<Map<[T; N]>>>,
+    /// which senders of messages with our id are on this host
+    host: Arc<HostIps>,
+    /// number of instances in the chart including self
+    size: Arc<watch::Sender<usize>>,
     broadcast: broadcast::Sender<(Id, Entry<[T; N]>)>,
+    events: broadcast::Sender<Event>,
+    conflict_policy: ConflictPolicy,
+    reply_policy: ReplyPolicy,
+    /// whether we receive our own discovery messages
+    multicast_loop: bool,
+    /// discovering over both IPv4 and IPv6
+    dual_stack: bool,
+    /// the group we announce, see [`ChartBuilder::with_group`]
+    group: Option<u16>,
+    wire_format: WireFormat,
+    status: Arc<std::sync::Mutex<Status>>,
+    counters: Arc<counters::Cells>,
+    goodbyes: u8,
+    /// forget instances not heard from for this many times their interval
+    expiry: Option<u32>,
+    /// ignore senders of repeated malformed messages
+    quarantine: Option<Arc<std::sync::Mutex<Quarantine>>>,
+    /// hysteresis for instances that expire and come back repeatedly
+    debounce: Option<Arc<std::sync::Mutex<Debounce>>>,
+    /// see [`Chart::report_unreachable`]
+    unreachable: Arc<Mutex<Unreachable>>,
+    /// see [`Chart::size_trend`]
+    trend: Arc<Mutex<Ewma>>,
+    /// limits the datagrams processed per window over all endpoints
+    budget: Option<Arc<Budget>>,
+    /// see [`Chart::put_meta`]
+    meta: Arc<Mutex<Metadata>>,
+    /// bitfield of our unhealthy ports, see [`Chart::set_port_health`]
+    port_health: Arc<AtomicU64>,
+    /// see [`Chart::observed_headers`]
+    observer: Option<Arc<std::sync::Mutex<Observer>>>,
+    /// see [`Chart::add_inbound_middleware`]
+    inbound: Arc<std::sync::RwLock<Inbound<[T; N]>>>,
+    /// see [`Chart::add_outbound_decorator`]
+    outbound: Arc<std::sync::RwLock<Outbound<[T; N]>>>,
+    /// newcomers must prove they know the shared key before being charted
+    #[cfg(feature = "challenge")]
+    challenges: Option<Arc<std::sync::Mutex<Challenges<[T; N]>>>>,
+    /// see [`ChartBuilder::with_packet_tap`]
+    #[cfg(feature = "packet-tap")]
+    tap: Option<Tap>,
+    /// see [`ChartBuilder::with_fault_injection`]
+    #[cfg(feature = "fault-injection")]
+    faults: Option<Arc<Faults>>,
+    /// see [`Partition`](crate::testing::Partition)
+    #[cfg(feature = "testing")]
+    cut: Cut,
+    /// see [`Chart::discovery_buf`]
+    encoded: Arc<Mutex<Option<Messages>>>,
+    /// see [`ChartBuilder::with_ttl`]
+    ttl: Option<Duration>,
+    /// see [`Chart::set_priority`]
+    priority: Arc<AtomicU32>,
+    /// see [`Event::AsymmetricVisibility`]
+    visibility: Arc<Mutex<Visibility>>,
+    /// not announcing, see [`Chart::suspend`]
+    suspended: Arc<AtomicBool>,
+    /// see [`ChartBuilder::with_service_port_zero_autoassign`]
+    autoassign: bool,
+    /// not announcing until every placeholder port is assigned, see
+    /// [`Chart::set_service_port`]
+    awaiting_ports: Arc<AtomicBool>,
+    /// see [`Chart::freeze`]
+    freeze: Arc<freeze::Gate>,
+    /// see [`Chart::is_maintained`]
+    drivers: Arc<AtomicUsize>,
+    /// set once the chart is [closed](Chart::close)
+    closed: Arc<watch::Sender<bool>>,
+}
+
+/// Our encoded discovery msg for each [`Status`]
+type Messages = Arc<[Box<[u8]>; 3]>;
+
+/// Our encoded discovery msg for one status, see [`Chart::discovery_buf`]
+#[derive(Debug, Clone)]
+pub(crate) enum Encoded {
+    /// reused until the id changes
+    Cached(Messages, usize),
+    /// encoded for this send only, see [`Chart::add_outbound_decorator`]
+    Decorated(Arc<[u8]>),
+}
+
+impl Deref for Encoded {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            Encoded::Cached(messages, status) => &messages[*status],
+            Encoded::Decorated(buf) => buf,
+        }
+    }
+}
+
+/// What changed in the chart, events for it are sent once the map lock is
+/// released.
+#[derive(Debug)]
+enum Update<Msg: Debug + Clone> {
+    Unchanged,
+    /// our own discovery msg came back
+    Echo,
+    /// a message with our id from `ip`, whether that is our own echo is
+    /// checked once the map lock is released
+    OwnId(IpAddr),
+    Invalid(DiscoveryError),
+    New(Id, Entry<Msg>),
+    StatusChanged {
+        id: Id,
+        status: Status,
+    },
+    PriorityChanged {
+        id: Id,
+        priority: u32,
+    },
+    IdConflict {
+        id: Id,
+        charted: IpAddr,
+        other: IpAddr,
+    },
+    Left {
+        id: Id,
+    },
+    /// sent too many malformed messages, now ignored
+    Quarantined {
+        ip: IpAddr,
+        strikes: u32,
+    },
+    /// another instance uses our id
+    IdCollision {
+        id: Id,
+        ip: IpAddr,
+        new_id: Option<Id>,
+    },
+    /// first message from an instance with a different number of values
+    MismatchedN {
+        id: Id,
+        ip: IpAddr,
+        expected: usize,
+        got: usize,
+    },
 }
 
 impl<const N: usize, T: Serialize + Debug + Clone> Chart<N, T> {
+    /// The [`Status`] this instance announces
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn our_status(&self) -> Status {
+        *self.status.lock().unwrap()
+    }
+
+    #[must_use]
+    fn discovery_msg(&self) -> DiscoveryMsg<N, T> {
+        DiscoveryMsg {
+            header: self.header,
+            id: self.id(),
+            status: self.our_status(),
+            interval_ms: u32::try_from(self.interval.max().as_millis()).unwrap_or(u32::MAX),
+            group: self.group,
+            capabilities: Capabilities::OURS,
+            msg: self.msg.read().unwrap().clone(),
+            meta: self.meta.lock().unwrap().clone(),
+            port_health: self.our_port_health(),
+            ttl_ms: self
+                .ttl
+                .map(|ttl| u32::try_from(ttl.as_millis()).unwrap_or(u32::MAX)),
+            priority: self.our_priority(),
+            sees: u32::try_from(*self.size.borrow()).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// whether `msg` can be encoded whatever the fields that change while
+    /// running are set to, see [`BincodeOptions::limit`]
+    pub(crate) fn check_fits(&self, msg: DiscoveryMsg<N, T>) -> Result<(), Error>
+    where
+        T: 'static,
+    {
+        if self.wire_format.fits(&msg.widest()) {
+            Ok(())
+        } else {
+            Err(Error::MessageTooLarge {
+                limit: self.wire_format.limit().unwrap_or(u64::MAX),
+            })
+        }
+    }
+
+    #[cfg(test)]
     fn insert(&self, id: Id, entry: Entry<[T; N]>) -> bool {
-        let old_key = {
+        let update = {
             let mut map = self.map.lock().unwrap();
-            map.insert(id, entry.clone())
+            let update = self.apply(&mut map, id, entry);
+            self.update_size(&map);
+            update
         };
-        if old_key.is_none() {
-            // errors if there are no active recievers which is
-            // the default and not a problem
-            let _ig_err = self.broadcast.send((id, entry));
-            true
-        } else {
-            false
+        self.publish(update)
+    }
+
+    /// call with the map still locked so sizes are published in order
+    fn update_size(&self, map: &Map<[T; N]>) {
+        let new = map.len() + 1;
+        if self
+            .size
+            .send_if_modified(|size| std::mem::replace(size, new) != new)
+        {
+            self.counters.size(new);
+            self.trend.lock().unwrap().update(new, Instant::now());
+            self.interval.membership_changed();
+            // our discovery msg includes the size
+            *self.encoded.lock().unwrap() = None;
         }
     }
 
-    #[tracing::instrument(skip(self, buf))]
-    fn process_buf<'de>(&self, buf: &'de [u8], addr: SocketAddr) -> bool
-    where
-        T: Serialize + DeserializeOwned + Debug,
-    {
-        let DiscoveryMsg::<N, T> { header, id, msg } = bincode::deserialize(buf).unwrap();
+    fn apply(&self, map: &mut Map<[T; N]>, id: Id, mut entry: Entry<[T; N]>) -> Update<[T; N]> {
+        if map.is_rejected(&id) {
+            return Update::Unchanged;
+        }
+        if map.is_full_for(&id) {
+            trace!("store is full, not charting instance {id}");
+            return Update::Unchanged;
+        }
+        match map.get(&id).map(|old| old.ip) {
+            Some(charted) if self.dual_stack && charted.is_ipv6() != entry.ip.is_ipv6() => {
+                // the same instance reached over the other address family
+                Update::Unchanged
+            }
+            Some(charted) if charted != entry.ip => {
+                let other = entry.ip;
+                match self.conflict_policy {
+                    ConflictPolicy::KeepFirst => (),
+                    ConflictPolicy::KeepLatest => {
+                        map.insert(id, entry);
+                    }
+                    ConflictPolicy::RejectBoth => map.reject(id),
+                }
+                Update::IdConflict { id, charted, other }
+            }
+            charted => {
+                // a reordered older message does not roll back the metadata
+                if let Some(old) = charted.and(map.get(&id)) {
+                    if old.meta.version() > entry.meta.version() {
+                        entry.meta = old.meta.clone();
+                    }
+                }
+                match map.insert(id, entry.clone()) {
+                    None => Update::New(id, entry),
+                    Some(old) if old.status != entry.status => Update::StatusChanged {
+                        id,
+                        status: entry.status,
+                    },
+                    Some(old) if old.priority != entry.priority => Update::PriorityChanged {
+                        id,
+                        priority: entry.priority,
+                    },
+                    Some(_) => Update::Unchanged,
+                }
+            }
+        }
+    }
+
+    /// our id, see [`our_id`](Chart::our_id)
+    fn id(&self) -> Id {
+        self.service_id.load(Ordering::Relaxed)
+    }
+
+    /// a message with our id arrived from `ip`, if that is not our own echo
+    /// another instance uses our id
+    fn own_id_from(&self, map: &mut Map<[T; N]>, ip: IpAddr) -> Update<[T; N]> {
+        match self.host.cached(ip) {
+            Some(true) => Update::Echo,
+            Some(false) => self.id_collision(map, ip),
+            None => Update::OwnId(ip),
+        }
+    }
+
+    /// [`own_id_from`](Self::own_id_from) for an ip not yet known to be on
+    /// this host or not, call without the map locked
+    fn resolve_own_id(&self, ip: IpAddr) -> Update<[T; N]> {
+        if self.host.is_ours(ip) {
+            return Update::Echo;
+        }
+        let mut map = self.map.lock().unwrap();
+        self.id_collision(&mut map, ip)
+    }
+
+    /// the instance at `ip` uses our id, reported once per ip
+    fn id_collision(&self, map: &mut Map<[T; N]>, ip: IpAddr) -> Update<[T; N]> {
+        if !map.own_id_from(ip) {
+            return Update::Unchanged;
+        }
+        let id = self.id();
+        Update::IdCollision {
+            id,
+            ip,
+            new_id: self.reroll_id(map),
+        }
+    }
+
+    /// switch to another id, call with the map locked
+    fn set_id(&self, map: &mut Map<[T; N]>, id: Id) {
+        self.service_id.store(id, Ordering::Relaxed);
+        *self.encoded.lock().unwrap() = None;
+        map.forget_own_id_senders();
+    }
+
+    /// pick a new random id if that is still allowed
+    #[cfg(feature = "rand")]
+    fn reroll_id(&self, map: &mut Map<[T; N]>) -> Option<Id> {
+        if self.reroll_until.is_none_or(|until| Instant::now() > until) {
+            return None;
+        }
+        let new_id = id_rng::random_id(self.id_rng.as_ref());
+        self.set_id(map, new_id);
+        Some(new_id)
+    }
+
+    #[cfg(not(feature = "rand"))]
+    fn reroll_id(&self, _: &mut Map<[T; N]>) -> Option<Id> {
+        None
+    }
+
+    /// forget an instance that said goodbye
+    fn apply_leaving(&self, map: &mut Map<[T; N]>, id: Id, ip: IpAddr) -> Update<[T; N]> {
+        match map.get(&id) {
+            Some(entry) if entry.ip == ip => {
+                map.remove(&id);
+                Update::Left { id }
+            }
+            _ => Update::Unchanged,
+        }
+    }
+
+    /// send out notifications and events, returns true if a new instance was
+    /// charted
+    fn publish(&self, update: Update<[T; N]>) -> bool {
+        // sending errors if there are no active recievers which is
+        // the default and not a problem
+        let event = match update {
+            Update::Unchanged | Update::Echo | Update::OwnId(_) => return false,
+            Update::New(id, entry) => {
+                let _ig_err = self.broadcast.send((id, entry));
+                return true;
+            }
+            Update::StatusChanged { id, status } => Event::StatusChanged { id, status },
+            Update::PriorityChanged { id, priority } => Event::PriorityChanged { id, priority },
+            Update::IdConflict { id, charted, other } => {
+                warn!("instance at {other} announced id {id} already charted for {charted}");
+                Event::IdConflict { id, charted, other }
+            }
+            Update::Left { id } => Event::Left { id },
+            Update::Invalid(err) => {
+                warn!("{err}");
+                Event::Error(err)
+            }
+            Update::Quarantined { ip, strikes } => {
+                let duration = self.quarantine_duration();
+                warn!("{ip} sent {strikes} malformed messages, ignoring it for {duration:?}");
+                Event::Quarantined {
+                    ip,
+                    strikes,
+                    duration,
+                }
+            }
+            Update::IdCollision { id, ip, new_id } => {
+                error!("instance at {ip} uses our id {id}, we switched to: {new_id:?}");
+                Event::IdCollision { id, ip, new_id }
+            }
+            Update::MismatchedN {
+                id,
+                ip,
+                expected,
+                got,
+            } => {
+                warn!("instance {id} at {ip} sends {got} values while we expect {expected}");
+                Event::MismatchedN {
+                    id,
+                    ip,
+                    expected,
+                    got,
+                }
+            }
+        };
+        let _ig_err = self.events.send(event);
+        false
+    }
+
+    fn quarantine_duration(&self) -> Duration {
+        self.quarantine
+            .as_ref()
+            .map_or(Duration::ZERO, |q| q.lock().unwrap().duration())
+    }
+
+    fn report(&self, err: DiscoveryError) {
+        warn!("{err}");
+        let _ig_err = self.events.send(Event::Error(err));
+    }
+
+    /// transient errors are reported, others end discovery on the socket
+    fn recv_failed(&self, endpoint: &Socket, err: &io::Error) -> Result<(), DiscoveryError> {
+        use io::ErrorKind as Kind;
+        let port = endpoint.port();
+        let kind = err.kind();
+        match kind {
+            // unicast replies to a host that is gone trigger ICMP errors
+            Kind::ConnectionRefused
+            | Kind::ConnectionReset
+            | Kind::HostUnreachable
+            | Kind::NetworkUnreachable
+            | Kind::Interrupted
+            | Kind::TimedOut => {
+                self.report(DiscoveryError::Recv { port, kind });
+                Ok(())
+            }
+            _ => {
+                let err = DiscoveryError::SocketClosed { port, kind };
+                self.report(err.clone());
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Serialize + DeserializeOwned + Debug + Clone,
+{
+    /// process a batch of received datagrams holding the map lock only once.
+    /// The addresses of newly charted instances are pushed onto `uncharted`.
+    /// Returns true if our own discovery msg was among the datagrams.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn process_bufs<'a>(
+        &self,
+        datagrams: impl Iterator<Item = (&'a [u8], SocketAddr)>,
+        uncharted: &mut Vec<SocketAddr>,
+    ) -> bool {
+        let updates: Vec<_> = {
+            let waiting = std::time::Instant::now();
+            let mut map = self.map.lock().unwrap();
+            self.counters.lock_wait(waiting.elapsed());
+            let mut quarantine = self.quarantine.as_deref().map(|q| q.lock().unwrap());
+            let mut observer = self.observer.as_deref().map(|o| o.lock().unwrap());
+            let now = Instant::now();
+            let updates = datagrams
+                .filter_map(|(buf, addr)| {
+                    if let Some(observer) = observer.as_mut() {
+                        observer.record(self.wire_format, buf, addr);
+                    }
+                    let Some(quarantine) = quarantine.as_mut() else {
+                        return Some((self.process_buf(&mut map, buf, addr), addr));
+                    };
+                    if quarantine.ignores(addr.ip(), now) {
+                        self.counters.quarantined(1);
+                        return None;
+                    }
+                    let update = match self.process_buf(&mut map, buf, addr) {
+                        Update::Invalid(err) => match quarantine.strike(addr.ip(), now) {
+                            Some(strikes) => Update::Quarantined {
+                                ip: addr.ip(),
+                                strikes,
+                            },
+                            None => Update::Invalid(err),
+                        },
+                        update => update,
+                    };
+                    Some((update, addr))
+                })
+                .collect();
+            self.update_size(&map);
+            updates
+        };
+        let mut echoed = false;
+        for (update, addr) in updates {
+            let update = match update {
+                Update::OwnId(ip) => self.resolve_own_id(ip),
+                update => update,
+            };
+            echoed |= matches!(update, Update::Echo);
+            if self.publish(update) {
+                uncharted.push(addr);
+            }
+        }
+        echoed
+    }
+
+    fn process_buf(&self, map: &mut Map<[T; N]>, buf: &[u8], addr: SocketAddr) -> Update<[T; N]> {
+        #[cfg(feature = "challenge")]
+        if let Some(update) = self.process_challenge(map, buf, addr) {
+            return update;
+        }
+        let peeked = self.wire_format.peek(buf);
+        if let Some(prefix) = &peeked {
+            if prefix.header != self.header {
+                return Update::Unchanged;
+            }
+            if prefix.id == self.id() {
+                return self.own_id_from(map, addr.ip());
+            }
+            self.counters.heard(1);
+            // heartbeat from a known instance, nothing changed
+            if prefix.status != Status::Leaving
+                && map.heartbeat(prefix.id, addr.ip(), map::fingerprint(buf))
+            {
+                self.counters.skipped(1);
+                return Update::Unchanged;
+            }
+        }
+
+        let DiscoveryMsg::<N, T> {
+            header,
+            id,
+            status,
+            interval_ms,
+            group,
+            capabilities,
+            msg,
+            meta,
+            port_health,
+            ttl_ms,
+            priority,
+            sees,
+        } = match self.wire_format.decode(buf) {
+            Ok(msg) => {
+                self.counters.decoded(1);
+                msg
+            }
+            Err(DecodeError::MismatchedN { id, expected, got }) => {
+                self.counters.invalid(1);
+                if !map.mismatch(id) {
+                    return Update::Unchanged;
+                }
+                return Update::MismatchedN {
+                    id,
+                    ip: addr.ip(),
+                    expected,
+                    got,
+                };
+            }
+            Err(err) => {
+                self.counters.invalid(1);
+                return Update::Invalid(DiscoveryError::Parse {
+                    from: addr,
+                    reason: err.to_string(),
+                });
+            }
+        };
         if header != self.header {
-            return false;
+            return Update::Unchanged;
+        }
+        if id == self.id() {
+            return self.own_id_from(map, addr.ip());
+        }
+        if peeked.is_none() {
+            self.counters.heard(1);
+        }
+        let mut entry = Entry {
+            ip: addr.ip(),
+            msg,
+            status,
+            group,
+            capabilities,
+            source: Source::Network,
+            meta,
+            port_health,
+            priority,
+        };
+        let interval = Duration::from_millis(u64::from(interval_ms));
+        let meta = Meta {
+            id,
+            from: addr,
+            received: Instant::now(),
+            interval,
+        };
+        if self.inbound.read().unwrap().run(&mut entry, &meta) == Action::Reject {
+            trace!("middleware rejected msg from instance {id}");
+            return Update::Unchanged;
+        }
+        // middleware may have rewritten the ip
+        let ip = entry.ip;
+        if entry.status == Status::Leaving {
+            return self.apply_leaving(map, id, ip);
+        }
+        if map.get(&id).is_none() && self.holds_rejoin(id, interval) {
+            return Update::Unchanged;
         }
-        if id == self.service_id {
+        if map.get(&id).is_none() && self.ignores_unreachable(id) {
+            return Update::Unchanged;
+        }
+        #[cfg(feature = "challenge")]
+        if let Some(challenges) = &self.challenges {
+            // also when charted under another ip, or anyone could take over an id
+            if map.get(&id).is_none_or(|charted| charted.ip != ip) && !map.is_rejected(&id) {
+                let mut challenges = challenges.lock().unwrap();
+                let now = Instant::now();
+                challenges.challenge(self.header, self.id(), id, addr, entry, now);
+                return Update::Unchanged;
+            }
+        }
+        let update = self.apply(map, id, entry);
+        if capabilities.contains(Capabilities::VISIBILITY) {
+            self.visibility
+                .lock()
+                .unwrap()
+                .report(id, sees, meta.received);
+        }
+        let ttl = ttl_ms.map(|ms| Duration::from_millis(u64::from(ms)));
+        map.set_seen(id, ip, addr.ip(), map::fingerprint(buf), interval, ttl);
+        update
+    }
+
+    /// whether instance `id` expired recently and should not be charted
+    /// again yet, see [`ChartBuilder::with_debounce`]
+    fn holds_rejoin(&self, id: Id, interval: Duration) -> bool {
+        let Some(debounce) = &self.debounce else {
             return false;
+        };
+        let held = debounce.lock().unwrap().holds(id, interval, Instant::now());
+        if held {
+            trace!("not charting instance {id} again yet, it expired recently");
+        }
+        held
+    }
+
+    /// answer challenges and chart instances that answered ours, `None` if
+    /// `buf` is not part of a challenge
+    #[cfg(feature = "challenge")]
+    fn process_challenge(
+        &self,
+        map: &mut Map<[T; N]>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Option<Update<[T; N]>> {
+        let challenges = self.challenges.as_deref()?;
+        let msg = challenge::parse(buf, self.header)?;
+        let mut challenges = challenges.lock().unwrap();
+        let update = match msg {
+            challenge @ challenge::Msg::Challenge { .. } => {
+                challenges.respond(self.header, self.id(), challenge, addr, host::is_local);
+                Update::Unchanged
+            }
+            challenge::Msg::Response { id, nonce, mac } => {
+                match challenges.verify(self.header, self.id(), id, addr.ip(), nonce, mac) {
+                    Some(entry) => self.apply(map, id, entry),
+                    None => Update::Unchanged,
+                }
+            }
+        };
+        Some(update)
+    }
+
+    /// send the challenges and responses that queued up, unicast to the
+    /// instance they are meant for unless they need to be multicast
+    #[cfg(feature = "challenge")]
+    async fn send_challenges(&self, endpoint: &Socket) {
+        let Some(challenges) = &self.challenges else {
+            return;
+        };
+        let outgoing = challenges.lock().unwrap().take_outgoing();
+        for (buf, to) in outgoing {
+            let Some(to) = to else {
+                self.multicast(&buf).await;
+                continue;
+            };
+            match self.send_to(endpoint, &buf, to).await {
+                Ok(_) => {
+                    self.counters.sent(1);
+                    #[cfg(feature = "packet-tap")]
+                    self.tap(Direction::Sent, &buf, to);
+                }
+                Err(err) => self.report(DiscoveryError::Send {
+                    port: endpoint.port(),
+                    to,
+                    kind: err.kind(),
+                }),
+            }
+        }
+    }
+
+    /// every datagram is sent through here so faults can be injected
+    async fn send_to(&self, endpoint: &Socket, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &self.faults {
+            tokio::time::sleep(faults.delay()).await;
+            if faults.lose() {
+                return Ok(buf.len());
+            }
+        }
+        endpoint.sock.send_to(buf, to).await
+    }
+
+    /// send our discovery msg to all multicast groups right away, does
+    /// nothing while waiting for service ports to be
+    /// [assigned](Chart::set_service_port), peers would chart the placeholder
+    pub(crate) async fn broadcast_now(&self) {
+        if self.awaiting_ports() {
+            return;
+        }
+        let buf = self.discovery_buf();
+        self.multicast(&buf).await;
+    }
+
+    async fn multicast(&self, buf: &[u8]) {
+        for endpoint in self.endpoints.iter() {
+            for to in endpoint.broadcast_targets() {
+                match self.send_to(endpoint, buf, to).await {
+                    Ok(_) => {
+                        self.counters.sent(1);
+                        #[cfg(feature = "packet-tap")]
+                        self.tap(Direction::Sent, buf, to);
+                    }
+                    Err(err) => self.report(DiscoveryError::Send {
+                        port: endpoint.port(),
+                        to,
+                        kind: err.kind(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// broadcast at the minimal interval again and ramp down from there
+    pub(crate) fn restart_rampdown(&self) {
+        self.interval.restart();
+    }
+
+    /// send our discovery msg directly to the discovery port on each of
+    /// `ips`, they reply if they have not charted us yet
+    pub(crate) async fn probe(&self, ips: &[IpAddr]) {
+        if self.awaiting_ports() {
+            return;
+        }
+        let buf = self.discovery_buf();
+        for endpoint in self.endpoints.iter() {
+            let Transport::Udp(sock) = endpoint.sock.inner() else {
+                // every local instance already gets our broadcasts
+                continue;
+            };
+            let Ok(local) = sock.local_addr() else {
+                continue;
+            };
+            for ip in ips.iter().filter(|ip| ip.is_ipv4() == local.is_ipv4()) {
+                let to = SocketAddr::new(*ip, local.port());
+                match self.send_to(endpoint, &buf, to).await {
+                    Ok(_) => {
+                        self.counters.sent(1);
+                        #[cfg(feature = "packet-tap")]
+                        self.tap(Direction::Sent, &buf, to);
+                    }
+                    Err(err) => self.report(DiscoveryError::Send {
+                        port: local.port(),
+                        to,
+                        kind: err.kind(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Send our discovery msg to `addr` right away, for example to handshake
+    /// with a known peer or to bridge networks multicast does not cross.
+    /// `addr` is usually the discovery port of an instance. An instance that
+    /// did not chart us yet does so and replies, depending on its
+    /// [reply policy](crate::ChartBuilder::with_reply_policy).
+    ///
+    /// # Note
+    /// No reply is sent if the instance is about to broadcast anyway, which
+    /// does not reach us across networks. To bridge networks announce from
+    /// both sides.
+    ///
+    /// Does nothing while waiting for service ports to be
+    /// [assigned](Chart::set_service_port).
+    ///
+    /// # Errors
+    /// Returns an error if sending fails or if there is no UDP discovery
+    /// socket for the address family of `addr`, for example when using
+    /// [`LocalDiscovery::Unix`].
+    pub async fn announce_to(&self, addr: SocketAddr) -> io::Result<()> {
+        let endpoint = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| matches!(endpoint.sock.inner(), Transport::Udp(_)))
+            .find(|endpoint| {
+                let local = endpoint.sock.local_addr();
+                local.is_ok_and(|local| local.is_ipv4() == addr.is_ipv4())
+            })
+            .ok_or(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no udp discovery socket for the address family",
+            ))?;
+        if self.awaiting_ports() {
+            return Ok(());
+        }
+        let buf = self.discovery_buf();
+        self.send_to(endpoint, &buf, addr).await?;
+        self.counters.sent(1);
+        #[cfg(feature = "packet-tap")]
+        self.tap(Direction::Sent, &buf, addr);
+        Ok(())
+    }
+
+    /// Check that multicast works by sending our discovery msg and waiting
+    /// for it to arrive back on every discovery socket. Fail fast using this
+    /// instead of waiting forever in [`found_everyone`](crate::discovery::found_everyone)
+    /// on networks that drop multicast.
+    ///
+    /// # Errors
+    /// Returns [`SelfTestError::NoEcho`] if our msg did not arrive within
+    /// one second or [`SelfTestError::LoopbackDisabled`] if multicast loopback
+    /// is [disabled](crate::ChartBuilder::with_multicast_loop).
+    ///
+    /// # Note
+    /// [`maintain`](crate::discovery::maintain) must be running, it receives
+    /// the messages.
+    pub async fn self_test(&self) -> Result<(), SelfTestError> {
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        if !self.multicast_loop {
+            return Err(SelfTestError::LoopbackDisabled);
         }
-        self.insert(id, Entry { ip: addr.ip(), msg })
+        let start = Instant::now();
+        self.broadcast_now().await;
+        for endpoint in self.endpoints.iter() {
+            if endpoint.broadcast_targets().is_empty() {
+                continue;
+            }
+            if tokio::time::timeout(TIMEOUT, endpoint.echo_after(start))
+                .await
+                .is_err()
+            {
+                return Err(SelfTestError::NoEcho {
+                    port: endpoint.port(),
+                    timeout: TIMEOUT,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Leave the cluster and stop discovery. Our goodbye is sent multiple
+    /// times spread out over `grace` so peers get it even if some packets are
+    /// lost. Once all are sent [`maintain`](crate::discovery::maintain) and
+    /// [`sniff`](crate::discovery::sniff) return. The discovery sockets close
+    /// when the last clone of the chart is dropped.
+    ///
+    /// How many goodbyes are sent is set with
+    /// [`ChartBuilder::with_goodbye_count`](crate::ChartBuilder::with_goodbye_count).
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn close(self, grace: Duration) {
+        self.say_goodbye(grace).await;
+        self.closed.send_replace(true);
+        info!("closed, said goodbye to the cluster");
+    }
+
+    /// broadcast the configured number of goodbyes spread out over `spread`
+    pub(crate) async fn say_goodbye(&self, spread: Duration) {
+        self.set_status(Status::Leaving);
+        let goodbyes = self.goodbyes.max(1);
+        let spacing = spread / u32::from(goodbyes);
+        for sent in 1..=goodbyes {
+            self.broadcast_now().await;
+            if sent < goodbyes {
+                tokio::time::sleep(spacing).await;
+            }
+        }
+    }
+
+    /// Stop announcing, for example right before the system goes to sleep.
+    /// A goodbye is sent so peers forget this instance instead of keeping a
+    /// ghost entry while it naps. Discoveries are still charted.
+    ///
+    /// Call [`resume`](Self::resume) on wake up. Wake up is also detected by
+    /// [`maintain`](crate::discovery::maintain), it then broadcasts more often
+    /// for a while so peers find us again quickly.
+    pub async fn suspend(&self) {
+        self.suspended.store(true, Ordering::Relaxed);
+        self.set_status(Status::Leaving);
+        self.broadcast_now().await;
+    }
+
+    /// Start announcing again after [`suspend`](Self::suspend)
+    pub async fn resume(&self) {
+        self.set_status(Status::Active);
+        self.suspended.store(false, Ordering::Relaxed);
+        self.broadcast_now().await;
+    }
+
+    /// not announcing, either [suspended](Self::suspend) or waiting for
+    /// service ports to be [assigned](Chart::set_service_port)
+    fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::Relaxed) || self.awaiting_ports()
+    }
+
+    /// resolves once the chart is closed
+    pub(crate) async fn closed(&self) {
+        let mut closed = self.closed.subscribe();
+        let _ig_err = closed.wait_for(|closed| *closed).await;
+    }
+
+    /// send our discovery msg directly to a newly discovered instance
+    async fn reply(&self, endpoint: &Socket, buf: &[u8], to: SocketAddr) {
+        match self.send_to(endpoint, buf, to).await {
+            Ok(_) => {
+                self.counters.sent(1);
+                self.interval.replied();
+                #[cfg(feature = "packet-tap")]
+                self.tap(Direction::Sent, buf, to);
+            }
+            Err(err) => self.report(DiscoveryError::Send {
+                port: endpoint.port(),
+                to,
+                kind: err.kind(),
+            }),
+        }
+    }
+
+    /// reply to the newcomers whose [delay](ReplyPolicy::DelayedRandom) is
+    /// over, unless we broadcast in the mean time or are about to
+    async fn send_delayed(&mut self, endpoint: &Socket, delayed: &mut reply::Delayed) {
+        let due = delayed.take_due(self.interval.last_broadcast());
+        if due.is_empty() || self.broadcast_soon() {
+            return;
+        }
+        if self.is_suspended() || *self.closed.borrow() {
+            return;
+        }
+        let buf = self.discovery_buf();
+        for to in due {
+            self.reply(endpoint, &buf, to).await;
+        }
+    }
+
+    /// our discovery msg for the current status, only the status (and
+    /// rarely the id) changes so it is encoded once for each on first use.
+    /// Unless there are outbound decorators, then it is encoded every time.
+    #[must_use]
+    pub(crate) fn discovery_buf(&self) -> Encoded {
+        let outbound = self.outbound.read().unwrap();
+        if !outbound.is_empty() {
+            let mut msg = self.discovery_msg();
+            outbound.run(&mut msg.msg);
+            // decorators are checked when added but could grow the message later
+            match self.wire_format.encode(&msg) {
+                Ok(buf) => return Encoded::Decorated(buf.into()),
+                Err(err) => warn!("sending our message undecorated, decorated: {err}"),
+            }
+        }
+        let mut encoded = self.encoded.lock().unwrap();
+        let encoded = encoded.get_or_insert_with(|| {
+            Arc::new(
+                [Status::Active, Status::Draining, Status::Leaving].map(|status| {
+                    let msg = DiscoveryMsg {
+                        status,
+                        ..self.discovery_msg()
+                    };
+                    self.wire_format
+                        .encode(&msg)
+                        .expect("checked to fit when building and by everything growing it")
+                        .into_boxed_slice()
+                }),
+            )
+        });
+        Encoded::Cached(Arc::clone(encoded), self.our_status() as usize)
     }
 }
 
-/// The array of ports set for this chart instance, set in `ChartBuilder::with_service_ports`.
+/// The array of ports set for this chart instance, set in `ChartBuilder::with_service_ports`
+/// or [`Chart::set_service_port`].
+// lock poisoning happens only on crash in another thread, in which
+// case panicing here is expected
+#[allow(clippy::missing_panics_doc)]
 impl<const N: usize> Chart<N, Port> {
     #[must_use]
-    pub fn our_service_ports(&self) -> &[u16] {
-        &self.msg
+    pub fn our_service_ports(&self) -> [u16; N] {
+        *self.msg.read().unwrap()
     }
 }
 
-/// The port set for this chart instance, set in `ChartBuilder::with_service_port`.
+/// The port set for this chart instance, set in `ChartBuilder::with_service_port`
+/// or [`Chart::set_service_port`].
+#[allow(clippy::missing_panics_doc)]
 impl Chart<1, Port> {
     #[must_use]
     pub fn our_service_port(&self) -> u16 {
-        self.msg[0]
+        self.msg.read().unwrap()[0]
+    }
+}
+
+/// The endpoints set for this chart instance, set in `ChartBuilder::service_endpoints`.
+#[allow(clippy::missing_panics_doc)]
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    #[must_use]
+    pub fn our_endpoints(&self) -> [E; N] {
+        self.msg.read().unwrap().clone()
     }
 }
 
 /// The msg struct for this chart instance, set in `ChartBuilder::custom_msg`.
+#[allow(clippy::missing_panics_doc)]
 impl<T: Debug + Clone + Serialize> Chart<1, T> {
     #[must_use]
-    pub fn our_msg(&self) -> &T {
-        &self.msg[0]
+    pub fn our_msg(&self) -> T {
+        self.msg.read().unwrap()[0].clone()
     }
 }
 
@@ -167,17 +1423,158 @@ impl<const N: usize, T: Debug + Clone + Serialize + DeserializeOwned> Chart<N, T
     /// ```
     #[must_use]
     pub fn notify(&self) -> Notify<N, T> {
-        Notify(self.broadcast.subscribe())
+        Notify {
+            discoveries: self.broadcast.subscribe(),
+            counters: Some(self.counters.clone()),
+            group: None,
+            closed: Some(self.closed.subscribe()),
+        }
+    }
+
+    /// Like [`notify`](Self::notify) but only returns instances that are in
+    /// `group`, see [`ChartBuilder::with_group`].
+    #[must_use]
+    pub fn notify_group(&self, group: u16) -> Notify<N, T> {
+        Notify {
+            group: Some(group),
+            ..self.notify()
+        }
+    }
+
+    /// All instances in `group`, see [`ChartBuilder::with_group`]
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn peers_in_group(&self, group: u16) -> Vec<(Id, Entry<[T; N]>)> {
+        let map = self.map.lock().unwrap();
+        map.iter()
+            .filter(|(_, entry)| entry.group == Some(group))
+            .map(|(id, entry)| (*id, entry.clone()))
+            .collect()
+    }
+
+    /// The group this instance announces, see [`ChartBuilder::with_group`]
+    #[must_use]
+    pub fn our_group(&self) -> Option<u16> {
+        self.group
+    }
+
+    /// The sub-protocols this instance supports
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::OURS
+    }
+
+    /// The sub-protocols both we and instance `id` support, `None` if `id`
+    /// is not in the chart. Only use a sub-protocol with a peer if it is in
+    /// here.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn shared_capabilities(&self, id: Id) -> Option<Capabilities> {
+        let map = self.map.lock().unwrap();
+        map.get(&id)
+            .map(|entry| entry.capabilities & Capabilities::OURS)
+    }
+
+    /// Snapshot of the discovery hot path [`Counters`], useful to measure the
+    /// overhead of discovery in your environment.
+    #[must_use]
+    pub fn counters(&self) -> Counters {
+        self.counters.snapshot()
+    }
+
+    /// Subscribe to [`Event`]s, buffering up to 256 of them.
+    #[must_use]
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
     }
 
     /// forget a node removing it from the map. If it is discovered again notify 
-    /// subscribers will get a notification (again)
+    /// subscribers will get a notification (again). An [`Event::Forgotten`]
+    /// is sent if it was charted.
     ///
     /// # Note
-    /// This has no effect if the node has not yet been discoverd
+    /// This has no effect if the node has not yet been discoverd. If the id was
+    /// rejected by [`ConflictPolicy::RejectBoth`] it will be accepted again.
     #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
     pub fn forget(&self, id: Id) {
-        self.map.lock().unwrap().remove(&id);
+        let forgotten = {
+            let mut map = self.map.lock().unwrap();
+            let forgotten = map.remove(&id).is_some();
+            map.allow(id);
+            self.update_size(&map);
+            forgotten
+        };
+        if forgotten {
+            let _ig_err = self.events.send(Event::Forgotten { id });
+        }
+    }
+
+    /// Forget every instance except those in `keep`, for example to reset
+    /// the chart while keeping the seed nodes. Happens under a single lock so
+    /// no discovery is lost in between. An [`Event::Forgotten`] is sent for
+    /// each dropped instance. Like with [`forget`](Chart::forget) they are
+    /// charted again once they are discovered again.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn forget_all_except(&self, keep: &[Id]) {
+        let forgotten: Vec<Id> = {
+            let mut map = self.map.lock().unwrap();
+            let forgotten: Vec<Id> = map
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| !keep.contains(id))
+                .collect();
+            for id in &forgotten {
+                map.remove(id);
+                map.allow(*id);
+            }
+            self.update_size(&map);
+            forgotten
+        };
+        for id in forgotten {
+            let _ig_err = self.events.send(Event::Forgotten { id });
+        }
+    }
+
+    /// Add instances learned of in another way then multicast, for example
+    /// from a config service, a file or the [`snapshot`](Chart::snapshot) of
+    /// a chart on another host. Instances are added as if they were
+    /// discovered: [`notify`](Chart::notify) subscribers hear of new ones and
+    /// conflicts are resolved using the [`ConflictPolicy`]. Our own id and
+    /// instances that are [`Leaving`](Status::Leaving) are skipped. Returns
+    /// the number of newly charted instances.
+    ///
+    /// # Note
+    /// Merged instances are only [expired](ChartBuilder::with_expiry) once
+    /// they have been heard from over multicast.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn merge_from(&self, entries: impl IntoIterator<Item = (Id, Entry<[T; N]>)>) -> usize {
+        let updates: Vec<_> = {
+            let mut map = self.map.lock().unwrap();
+            let updates = entries
+                .into_iter()
+                .filter(|(id, entry)| *id != self.id() && entry.status != Status::Leaving)
+                .map(|(id, entry)| {
+                    // do not hide that a charted instance was heard from
+                    let source = map
+                        .get(&id)
+                        .map_or(Source::Merged, |charted| charted.source);
+                    self.apply(&mut map, id, Entry { source, ..entry })
+                })
+                .collect();
+            self.update_size(&map);
+            updates
+        };
+        updates
+            .into_iter()
+            .map(|update| self.publish(update))
+            .filter(|new| *new)
+            .count()
     }
 
     /// number of instances discoverd including self
@@ -189,79 +1586,924 @@ impl<const N: usize, T: Debug + Clone + Serialize + DeserializeOwned> Chart<N, T
         self.map.lock().unwrap().len() + 1
     }
 
+    /// Number of instances heard from within `max_age`, including self.
+    /// Unlike [`size`](Self::size) this leaves out instances that went silent
+    /// but did not [expire](ChartBuilder::with_expiry) yet and
+    /// [merged](Self::merge_from) instances we never heard from. Use this for
+    /// quorum decisions.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn active_size(&self, max_age: Duration) -> usize {
+        let now = Instant::now();
+        let map = self.map.lock().unwrap();
+        map.iter()
+            .filter(|(id, _)| map.heard_within(id, now, max_age))
+            .count()
+            + 1
+    }
+
+    /// like [`entries_if_size`](Self::entries_if_size) but only counts and
+    /// returns instances heard from within `max_age`
+    pub(crate) fn active_entries_if_size(
+        &self,
+        size: usize,
+        max_age: Duration,
+    ) -> Option<Vec<(Id, Entry<[T; N]>)>> {
+        let now = Instant::now();
+        let map = self.map.lock().unwrap();
+        let active: Vec<_> = map
+            .iter()
+            .filter(|(id, _)| map.heard_within(id, now, max_age))
+            .map(|(id, e)| (*id, e.clone()))
+            .collect();
+        (active.len() + 1 >= size).then_some(active)
+    }
+
+    /// all charted entries if the chart, including self, holds at least
+    /// `size` instances. Taken under a single lock so the result is consistent.
+    pub(crate) fn entries_if_size(&self, size: usize) -> Option<Vec<(Id, Entry<[T; N]>)>> {
+        let map = self.map.lock().unwrap();
+        (map.len() + 1 >= size).then(|| map.iter().map(|(id, e)| (*id, e.clone())).collect())
+    }
+
+    /// Wait until at least `size` instances, including self, are in the
+    /// chart. Returns right away if that is already the case.
+    ///
+    /// If discovery is not running a few seconds into the wait an
+    /// [`Event::Error`] with [`DiscoveryError::NotMaintained`] is sent.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use instance_chart::{discovery, ChartBuilder};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// # let shutdown = tokio::time::sleep(Duration::from_millis(10));
+    /// let chart = ChartBuilder::new()
+    ///     .with_id(1)
+    ///     .with_service_port(8042)
+    /// #   .with_discovery_port(43813)
+    ///     .finish()?;
+    /// let _ = tokio::spawn(discovery::maintain(chart.clone()));
+    ///
+    /// tokio::select! {
+    ///     () = chart.await_size(3) => println!("cluster complete"),
+    ///     () = shutdown => println!("shutting down"),
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub async fn await_size(&self, size: usize) {
+        let mut sizes = self.size.subscribe();
+        tokio::select! {
+            _ = sizes.wait_for(|current| *current >= size) => (),
+            () = self.report_if_not_maintained() => (),
+        }
+    }
+
+    /// A stream of the number of instances in the chart, including self. The
+    /// current size is yielded first, then a new item whenever it changes.
+    /// Sizes might be skipped if the stream is not polled often enough.
+    pub fn size_stream(&self) -> impl Stream<Item = usize> + Send + Unpin + 'static {
+        WatchStream::new(self.size.subscribe())
+    }
+
+    /// The id of the instance discovered on `ip`. Use this to map the peer
+    /// address of an incoming connection back to an instance.
+    ///
+    /// # Note
+    /// Returns None if no instance or more then one instance was discovered
+    /// on `ip`. When multiple instances share a host use
+    /// [`entries_on_host`](Self::entries_on_host) instead.
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn id_by_ip(&self, ip: IpAddr) -> Option<Id> {
+        match self.map.lock().unwrap().ids_on(&ip) {
+            [id] => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// the address the instance with `id` was discovered on
+    #[cfg(feature = "dns")]
+    pub(crate) fn ip_of(&self, id: Id) -> Option<IpAddr> {
+        self.map.lock().unwrap().get(&id).map(|entry| entry.ip)
+    }
+
+    /// All instances discovered on the host with address `ip`
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn entries_on_host(&self, ip: IpAddr) -> Vec<(Id, Entry<[T; N]>)> {
+        let map = self.map.lock().unwrap();
+        map.ids_on(&ip)
+            .iter()
+            .filter_map(|id| map.get(id).map(|entry| (*id, entry.clone())))
+            .collect()
+    }
+
+    pub(crate) fn set_status(&self, status: Status) {
+        *self.status.lock().unwrap() = status;
+    }
+
     /// The id set for this chart instance
     #[must_use]
     pub fn our_id(&self) -> Id {
-        self.service_id
+        self.id()
     }
 
     /// The port this instance is using for discovery
+    ///
+    /// # Note
+    /// When using [multiple endpoints](ChartBuilder::with_discovery_endpoints)
+    /// this is the port of the first.
     #[allow(clippy::missing_panics_doc)] // socket is set during building
     #[must_use]
     pub fn discovery_port(&self) -> u16 {
-        self.sock.local_addr().unwrap().port()
+        self.endpoints[0].sock.local_addr().unwrap().port()
     }
 
+    /// The receive buffer size the OS granted the discovery socket, see
+    /// [`ChartBuilder::with_recv_buffer_size`]
+    ///
+    /// # Note
+    /// When using [multiple endpoints](ChartBuilder::with_discovery_endpoints)
+    /// this is the buffer of the first.
+    #[allow(clippy::missing_panics_doc)] // socket is open
     #[must_use]
-    fn discovery_msg(&self) -> DiscoveryMsg<N, T> {
-        DiscoveryMsg {
-            header: self.header,
-            id: self.service_id,
-            msg: self.msg.clone(),
-        }
+    pub fn recv_buffer_size(&self) -> usize {
+        self.endpoints[0]
+            .sock
+            .sock_ref()
+            .recv_buffer_size()
+            .unwrap()
+    }
+
+    /// The send buffer size the OS granted the discovery socket, see
+    /// [`ChartBuilder::with_send_buffer_size`]
+    ///
+    /// # Note
+    /// When using [multiple endpoints](ChartBuilder::with_discovery_endpoints)
+    /// this is the buffer of the first.
+    #[allow(clippy::missing_panics_doc)] // socket is open
+    #[must_use]
+    pub fn send_buffer_size(&self) -> usize {
+        self.endpoints[0]
+            .sock
+            .sock_ref()
+            .send_buffer_size()
+            .unwrap()
+    }
+
+    /// Time left until this instance broadcasts its discovery message again.
+    /// Zero before [`discovery::maintain`](crate::discovery::maintain) has
+    /// started broadcasting.
+    #[must_use]
+    pub fn next_broadcast_in(&self) -> Duration {
+        self.interval.until_scheduled()
+    }
+
+    /// The current time in between broadcasts without the random jitter
+    /// applied. Grows from [`min_interval`](Self::min_interval) to
+    /// [`max_interval`](Self::max_interval) over the
+    /// [`rampdown`](ChartBuilder::with_rampdown).
+    #[must_use]
+    pub fn broadcast_interval(&self) -> Duration {
+        self.interval.current()
     }
 
+    /// The interval broadcasting starts at, see [`ChartBuilder::with_rampdown`]
     #[must_use]
-    fn discovery_buf(&self) -> Vec<u8> {
-        let msg = self.discovery_msg();
-        bincode::serialize(&msg).unwrap()
+    pub fn min_interval(&self) -> Duration {
+        self.interval.min()
+    }
+
+    /// The interval broadcasting settles at, see [`ChartBuilder::with_rampdown`]
+    #[must_use]
+    pub fn max_interval(&self) -> Duration {
+        self.interval.max()
+    }
+
+    /// How long it takes the interval to grow from its minimum to its
+    /// maximum, see [`ChartBuilder::with_rampdown`]
+    #[must_use]
+    pub fn rampdown(&self) -> Duration {
+        self.interval.rampdown()
     }
 
     #[must_use]
     fn broadcast_soon(&mut self) -> bool {
-        let next = self.interval.next();
-        next.until() < Duration::from_millis(100)
+        self.interval.until_next() < Duration::from_millis(100)
+    }
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub(crate) async fn handle_incoming<const N: usize, T>(
+    chart: Chart<N, T>,
+) -> Result<(), DiscoveryError>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
+{
+    // aborts the other receivers when dropped
+    let mut receivers = JoinSet::new();
+    for idx in 0..chart.endpoints.len() {
+        receivers.spawn(handle_incoming_on(chart.clone(), idx));
     }
+    while let Some(res) = receivers.join_next().await {
+        match res {
+            Ok(res) => res?,
+            Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+            Err(_cancelled) => (),
+        }
+    }
+    Ok(())
+}
+
+/// like [`handle_incoming`] but receives on every endpoint from the current
+/// task instead of spawning one per endpoint
+pub(crate) async fn handle_incoming_inline<const N: usize, T>(
+    chart: Chart<N, T>,
+) -> Result<(), DiscoveryError>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
+{
+    let mut receivers: Vec<_> = (0..chart.endpoints.len())
+        .map(|idx| Box::pin(handle_incoming_on(chart.clone(), idx)))
+        .collect();
+    // receivers only return if their socket stops working
+    std::future::poll_fn(|cx| {
+        for receiver in &mut receivers {
+            if let std::task::Poll::Ready(res) = receiver.as_mut().poll(cx) {
+                return std::task::Poll::Ready(res);
+            }
+        }
+        std::task::Poll::Pending
+    })
+    .await
 }
 
-#[tracing::instrument]
-pub(crate) async fn handle_incoming<const N: usize, T>(mut chart: Chart<N, T>)
+/// largest discovery message that is received in full
+const MAX_DATAGRAM: usize = 1024;
+
+/// maximum number of queued datagrams processed under one lock
+const MAX_BATCH: usize = 64;
+
+async fn handle_incoming_on<const N: usize, T>(
+    mut chart: Chart<N, T>,
+    idx: usize,
+) -> Result<(), DiscoveryError>
 where
-    T: Debug + Clone + Serialize + DeserializeOwned,
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
 {
+    // allocated once, datagrams that queued up while we where busy are
+    // processed together
+    let mut bufs = vec![[0u8; MAX_DATAGRAM]; MAX_BATCH];
+    let mut received: Vec<(usize, SocketAddr)> = Vec::with_capacity(MAX_BATCH);
+    let mut uncharted = Vec::new();
+    let mut rng = Jitter::new();
+    let mut delayed = reply::Delayed::default();
+    let endpoints = chart.endpoints.clone();
+    let endpoint = &endpoints[idx];
+    let freeze = Arc::clone(&chart.freeze);
     loop {
-        let mut buf = [0; 1024];
-        let (_len, addr) = chart.sock.recv_from(&mut buf).await.unwrap();
-        trace!("got msg from: {addr:?}");
-        let was_uncharted = chart.process_buf(&buf, addr);
-        if was_uncharted && !chart.broadcast_soon() {
-            chart
-                .sock
-                .send_to(&chart.discovery_buf(), addr)
-                .await
-                .unwrap();
+        let deferred = received.len();
+        if let Some(budget) = chart.budget.as_deref().filter(|_| !received.is_empty()) {
+            // deferred datagrams are processed first once there is budget
+            budget.replenished().await;
+        } else {
+            let datagram = tokio::select! {
+                datagram = endpoint.sock.recv_from(&mut bufs[0]) => datagram,
+                () = delayed.next_due() => {
+                    chart.send_delayed(endpoint, &mut delayed).await;
+                    continue;
+                }
+            };
+            match datagram {
+                Ok(datagram) => received.push(datagram),
+                Err(err) => {
+                    chart.recv_failed(endpoint, &err)?;
+                    continue;
+                }
+            }
+        }
+        while received.len() < MAX_BATCH {
+            match endpoint.sock.try_recv_from(&mut bufs[received.len()]) {
+                Ok(datagram) => received.push(datagram),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    chart.recv_failed(endpoint, &err)?;
+                    break;
+                }
+            }
+        }
+        trace!("got {} msgs", received.len() - deferred);
+        #[cfg(feature = "packet-tap")]
+        for (buf, (len, addr)) in bufs[deferred..].iter().zip(&received[deferred..]) {
+            chart.tap(Direction::Received, &buf[..*len], *addr);
+        }
+        chart.counters.received((received.len() - deferred) as u64);
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &chart.faults {
+            tokio::time::sleep(faults.delay()).await;
+        }
+
+        let allowed = match &chart.budget {
+            Some(budget) => budget.take(received.len(), Instant::now()),
+            None => received.len(),
+        };
+        let datagrams = bufs
+            .iter()
+            .zip(&received[..allowed])
+            .map(|(buf, (len, addr))| (&buf[..*len], *addr));
+        #[cfg(feature = "fault-injection")]
+        let datagrams = datagrams.filter(|_| chart.faults.as_ref().is_none_or(|f| !f.lose()));
+        let mut probed = Vec::new();
+        let datagrams = datagrams.filter(|(buf, addr)| {
+            let probe = probe::is_probe(buf, chart.header);
+            if probe {
+                probed.push(*addr);
+            }
+            !probe
+        });
+        let unfrozen = freeze.pass().await;
+        if chart.process_bufs(datagrams, &mut uncharted) {
+            endpoint.last_echo.send_replace(Some(Instant::now()));
+        }
+        drop(unfrozen);
+        #[cfg(feature = "challenge")]
+        chart.send_challenges(endpoint).await;
+        for to in probed {
+            chart.answer_probe(endpoint, to).await;
+        }
+        let replies = chart.reply_policy != ReplyPolicy::Never;
+        if replies && !uncharted.is_empty() && !chart.is_suspended() && !chart.broadcast_soon() {
+            let buf = chart.discovery_buf();
+            for to in &uncharted {
+                match chart.reply_policy.delay(&mut rng) {
+                    None => (),
+                    Some(Duration::ZERO) => chart.reply(endpoint, &buf, *to).await,
+                    Some(delay) => delayed.push(*to, delay, chart.interval.last_broadcast()),
+                }
+            }
+        }
+        uncharted.clear();
+
+        let over_budget = received.len() - allowed;
+        if over_budget > 0 {
+            trace!("packet budget exhausted, deferring {over_budget} msgs");
+            chart.counters.deferred(over_budget as u64);
+            bufs[..received.len()].rotate_left(allowed);
         }
+        received.drain(..allowed);
     }
 }
 
-#[tracing::instrument]
+/// Some drivers silently drop multicast group membership, for example after a
+/// suspend and resume. We receive our own broadcasts, if those stop arriving
+/// on an endpoint its groups are joined again. Does nothing if multicast
+/// loopback is disabled.
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub(crate) async fn rejoin_watchdog<const N: usize, T>(chart: Chart<N, T>)
+where
+    T: 'static + Debug + Serialize + DeserializeOwned + Clone,
+{
+    if !chart.multicast_loop {
+        return std::future::pending().await;
+    }
+    // several broadcasts should have happened in between checks
+    let period = chart.interval.max() * 5;
+    let mut previous_check = Instant::now();
+    let mut frozen_for = chart.freeze.frozen_for();
+    loop {
+        tokio::time::sleep(period).await;
+        // our msgs are not processed while frozen
+        if chart.is_frozen() {
+            continue;
+        }
+        let now_frozen_for = chart.freeze.frozen_for();
+        let since = previous_check + (now_frozen_for - frozen_for);
+        frozen_for = now_frozen_for;
+        for endpoint in chart.endpoints.iter() {
+            if endpoint.groups.is_empty() || endpoint.echoed_since(since) {
+                continue;
+            }
+            let port = endpoint.port();
+            warn!("own discovery msgs stopped arriving on port {port}, rejoining multicast groups");
+            match endpoint.rejoin() {
+                Ok(()) => {
+                    let _ig_err = chart.events.send(Event::Rejoined { port });
+                }
+                Err(e) => warn!("could not rejoin multicast groups on port {port}: {e:?}"),
+            }
+        }
+        previous_check = Instant::now();
+    }
+}
+
+/// forget instances that have not been heard from for a while, see
+/// [`ChartBuilder::with_expiry`]
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub(crate) async fn expire_stale<const N: usize, T>(chart: Chart<N, T>)
+where
+    T: 'static + Debug + Serialize + DeserializeOwned + Clone,
+{
+    // instances can set a ttl even if expiry is disabled
+    let factor = chart.expiry;
+    // the interval panics on a zero period
+    let period = (chart.interval.max() / 2).max(Duration::from_millis(1));
+    let mut check = tokio::time::interval(period);
+    loop {
+        check.tick().await;
+        let unfrozen = chart.freeze.pass().await;
+        let (expired, asymmetric, we_see) = {
+            let mut map = chart.map.lock().unwrap();
+            let mut debounce = chart.debounce.as_deref().map(|d| d.lock().unwrap());
+            let grace = debounce.as_ref().map_or(Duration::ZERO, |d| d.leave());
+            let now = Instant::now();
+            let expired = map.expired(now, factor, grace);
+            for id in &expired {
+                map.remove(id);
+                if let Some(debounce) = debounce.as_mut() {
+                    debounce.expired(*id, now);
+                }
+            }
+            chart.update_size(&map);
+            let ours = u32::try_from(map.len() + 1).unwrap_or(u32::MAX);
+            let mut visibility = chart.visibility.lock().unwrap();
+            let charted = |id: &Id| map.get(id).is_some();
+            let asymmetric = visibility.check(charted, ours, chart.interval.max() * 2, now);
+            (expired, asymmetric, ours)
+        };
+        drop(unfrozen);
+        for id in expired {
+            info!("forgetting instance {id}, not heard from in a while");
+            let _ig_err = chart.events.send(Event::Expired { id });
+        }
+        for (id, sees) in asymmetric {
+            warn!("instance {id} sees {sees} instances while we see {we_see}");
+            let _ig_err = chart
+                .events
+                .send(Event::AsymmetricVisibility { id, sees, we_see });
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument)]
 pub(crate) async fn broadcast_periodically<const N: usize, T>(
     mut chart: Chart<N, T>,
 ) where
-    T: Debug + Serialize + DeserializeOwned + Clone,
+    T: 'static + Debug + Serialize + DeserializeOwned + Clone,
 {
+    let mut wake = wake::WakeDetector::new();
     loop {
-        trace!("sending discovery msg");
-        broadcast(&chart.sock, chart.discovery_port(), &chart.discovery_buf()).await;
+        if let Some(slept) = wake.check() {
+            info!("system was suspended for {slept:?}, announcing again");
+            chart.interval.restart();
+        }
+        if chart.interval.suppress_broadcast() {
+            trace!("skipping discovery msg, just replied to a newcomer");
+        } else if !chart.is_suspended() {
+            trace!("sending discovery msg");
+            chart.broadcast_now().await;
+        }
         chart.interval.sleep_till_next().await;
     }
 }
 
-#[tracing::instrument]
-async fn broadcast(sock: &Arc<UdpSocket>, port: u16, msg: &[u8]) {
-    let multiaddr = Ipv4Addr::from([224, 0, 0, 251]);
-    let _len = sock
-        .send_to(msg, (multiaddr, port))
-        .await
-        .unwrap_or_else(|e| panic!("broadcast failed with port: {port}, error: {e:?}"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::{entry, test_msg};
+    use std::net::Ipv4Addr;
+
+    fn datagram(chart: &Chart<1, u16>, id: Id) -> (Vec<u8>, SocketAddr) {
+        let msg = test_msg(chart.header, id, [8000u16]);
+        let buf = chart.wire_format.encode(&msg).unwrap();
+        (buf, SocketAddr::from(([10, 0, 0, id as u8], 8080)))
+    }
+
+    #[tokio::test]
+    async fn invalid_msg_is_reported() {
+        let chart = Chart::test(entry).await;
+        let mut events = chart.events();
+        let addr = SocketAddr::from(([10, 0, 0, 1], 8080));
+        chart.process_bufs([(&[1u8, 2, 3][..], addr)].into_iter(), &mut Vec::new());
+
+        let Ok(Event::Error(DiscoveryError::Parse { from, .. })) = events.try_recv() else {
+            panic!("expected a parse error event");
+        };
+        assert_eq!(from, addr);
+        assert_eq!(chart.counters().invalid, 1);
+    }
+
+    #[tokio::test]
+    async fn malformed_sender_is_quarantined() {
+        let mut chart = Chart::test(entry).await;
+        let duration = Duration::from_secs(10);
+        chart.quarantine = Some(Arc::new(std::sync::Mutex::new(Quarantine::new(
+            2, duration,
+        ))));
+        let mut events = chart.events();
+        let addr = SocketAddr::from(([10, 0, 0, 1], 8080));
+        let garbage = [(&[1u8, 2, 3][..], addr); 4];
+        chart.process_bufs(garbage.into_iter(), &mut Vec::new());
+
+        assert!(matches!(events.try_recv(), Ok(Event::Error(_))));
+        let quarantined = Event::Quarantined {
+            ip: addr.ip(),
+            strikes: 2,
+            duration,
+        };
+        assert_eq!(events.try_recv(), Ok(quarantined));
+        assert!(events.try_recv().is_err());
+        assert_eq!(chart.counters().quarantined, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_instance_rejoins_after_debounce() {
+        let mut chart = Chart::test(entry).await;
+        let debounce = Debounce::new(Duration::ZERO, Duration::from_secs(3));
+        chart.debounce = Some(Arc::new(std::sync::Mutex::new(debounce)));
+        chart
+            .debounce
+            .as_deref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .expired(100, Instant::now());
+
+        let (buf, addr) = datagram(&chart, 100);
+        for _ in 0..3 {
+            chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+            assert!(chart.snapshot().get(100).is_none());
+            tokio::time::advance(Duration::from_secs(1)).await;
+        }
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+        assert!(chart.snapshot().get(100).is_some());
+    }
+
+    #[tokio::test]
+    async fn inbound_middleware_rewrites_and_vetoes() {
+        let chart = Chart::test(entry).await;
+        chart.add_inbound_middleware(|entry, meta| {
+            entry.ip = IpAddr::V4(Ipv4Addr::new(192, 168, 0, meta.id as u8));
+            Action::Accept
+        });
+        chart.add_inbound_middleware(|_, meta| match meta.id {
+            101 => Action::Reject,
+            _ => Action::Accept,
+        });
+        let datagrams = [datagram(&chart, 100), datagram(&chart, 101)];
+        let batch = datagrams.iter().map(|(buf, addr)| (buf.as_slice(), *addr));
+        chart.process_bufs(batch, &mut Vec::new());
+
+        let snapshot = chart.snapshot();
+        let rewritten = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 100));
+        assert_eq!(snapshot.get(100).map(|e| e.ip), Some(rewritten));
+        assert!(snapshot.get(101).is_none());
+
+        // heartbeats of the rewritten entry still take the fast path
+        let batch = datagrams.iter().map(|(buf, addr)| (buf.as_slice(), *addr));
+        chart.process_bufs(batch, &mut Vec::new());
+        assert_eq!(chart.counters().skipped, 1);
+    }
+
+    #[tokio::test]
+    async fn outbound_decorator_runs_every_send() {
+        let chart = Chart::test(entry).await;
+        let sends = Arc::new(AtomicU64::new(0));
+        let counted = Arc::clone(&sends);
+        chart
+            .add_outbound_decorator(move |msg| {
+                msg[0] = 9000 + counted.fetch_add(1, Ordering::Relaxed) as u16;
+            })
+            .unwrap();
+
+        // the first call checks the decorated message fits
+        for expected in [9001, 9002] {
+            let buf = chart.discovery_buf();
+            let msg = chart.wire_format.decode::<1, u16>(&buf).unwrap();
+            assert_eq!(msg.msg, [expected]);
+        }
+        assert_eq!(*chart.msg.read().unwrap(), entry(0).1.msg);
+    }
+
+    #[tokio::test]
+    async fn growth_past_bincode_limit_refused() {
+        let mut chart = Chart::test(entry).await;
+        let options = BincodeOptions {
+            int_encoding: IntEncoding::Varint,
+            ..BincodeOptions::default()
+        };
+        chart.wire_format = WireFormat::Bincode(options);
+        // takes a single byte as varint, ports above 250 take three
+        chart.set_service_port(0, 250).await.unwrap();
+        let widest = chart.discovery_msg().widest();
+        let limit = chart.wire_format.encode(&widest).unwrap().len();
+        chart.wire_format = WireFormat::Bincode(BincodeOptions {
+            limit: Some(limit as u64),
+            ..options
+        });
+
+        let res = chart.set_service_port(0, 8000).await;
+        assert!(matches!(res, Err(Error::MessageTooLarge { .. })));
+        assert_eq!(chart.our_service_ports(), [250]);
+        let res = chart.add_outbound_decorator(|msg| msg[0] = 8000);
+        assert!(matches!(res, Err(Error::MessageTooLarge { .. })));
+        let res = chart.put_meta("key", "value");
+        assert_eq!(res, Err(crate::MetaError::TooLarge { max: limit }));
+
+        chart.set_id(&mut chart.map.lock().unwrap(), Id::MAX);
+        chart.set_priority(u32::MAX);
+        let msg = chart.wire_format.decode::<1, u16>(&chart.discovery_buf());
+        assert_eq!(msg.unwrap().id, Id::MAX);
+    }
+
+    #[tokio::test]
+    async fn meta_is_replicated_newest_wins() {
+        let sender = Chart::test(entry).await;
+        sender.service_id.store(100, Ordering::Relaxed);
+        let receiver = Chart::test(entry).await;
+        let from = SocketAddr::from(([10, 0, 0, 100], 8080));
+
+        sender.put_meta("build", "abc123").unwrap();
+        let older = sender.discovery_buf();
+        sender.put_meta("build", "def456").unwrap();
+        let newer = sender.discovery_buf();
+        for buf in [&newer, &older] {
+            receiver.process_bufs([(&buf[..], from)].into_iter(), &mut Vec::new());
+        }
+
+        let meta = receiver.meta_of(100).unwrap();
+        assert_eq!(meta.get("build"), Some("def456"));
+        assert_eq!(meta, sender.our_meta());
+    }
+
+    #[tokio::test]
+    async fn meta_must_fit_in_a_datagram() {
+        let chart = Chart::test(entry).await;
+        let err = chart.put_meta("big", "x".repeat(MAX_DATAGRAM));
+        assert_eq!(err, Err(crate::MetaError::TooLarge { max: MAX_DATAGRAM }));
+        assert!(chart.our_meta().is_empty());
+    }
+
+    #[tokio::test]
+    async fn own_msg_is_an_echo() {
+        let chart = Chart::test(entry).await;
+        let mut uncharted = Vec::new();
+        let (buf, addr) = datagram(&chart, 100);
+        assert!(!chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut uncharted));
+        let (buf, _) = datagram(&chart, chart.id());
+        let local = SocketAddr::from(([127, 0, 0, 1], 8080));
+        assert!(chart.process_bufs([(buf.as_slice(), local)].into_iter(), &mut uncharted));
+        assert_eq!(uncharted.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn id_collision_is_reported_once() {
+        let chart = Chart::test(entry).await;
+        let mut events = chart.events();
+        let (buf, addr) = datagram(&chart, chart.id());
+        for _ in 0..2 {
+            assert!(!chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new()));
+        }
+
+        let collision = Event::IdCollision {
+            id: 0,
+            ip: addr.ip(),
+            new_id: None,
+        };
+        assert_eq!(events.try_recv(), Ok(collision));
+        assert!(events.try_recv().is_err());
+        assert_eq!(chart.id(), 0);
+    }
+
+    #[cfg(feature = "rand")]
+    #[tokio::test]
+    async fn reroll_id_on_collision() {
+        let mut chart = Chart::test(entry).await;
+        chart.reroll_until = Some(Instant::now() + Duration::from_secs(1));
+        let mut events = chart.events();
+        let (buf, addr) = datagram(&chart, chart.id());
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+
+        let Ok(Event::IdCollision {
+            new_id: Some(new_id),
+            ..
+        }) = events.try_recv()
+        else {
+            panic!("expected a re-rolled id");
+        };
+        assert_eq!(chart.id(), new_id);
+        let prefix = chart.wire_format.peek(&chart.discovery_buf()).unwrap();
+        assert_eq!(prefix.id, new_id);
+    }
+
+    #[tokio::test]
+    async fn only_shared_capabilities() {
+        let chart = Chart::test(entry).await;
+        let msg = DiscoveryMsg {
+            capabilities: Capabilities::from_bits(0x8000) | Capabilities::PROBES,
+            ..test_msg(chart.header, 100, [8000u16])
+        };
+        let buf = chart.wire_format.encode(&msg).unwrap();
+        let addr = SocketAddr::from(([10, 0, 0, 100], 8080));
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+
+        assert_eq!(chart.shared_capabilities(100), Some(Capabilities::empty()));
+        let (buf, addr) = datagram(&chart, 101);
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+        assert_eq!(chart.shared_capabilities(101), Some(Capabilities::OURS));
+        assert_eq!(chart.shared_capabilities(102), None);
+    }
+
+    #[tokio::test]
+    async fn batch_charts_newcomers_once() {
+        let chart = Chart::test(entry).await;
+        let mut datagrams: Vec<_> = (100..105).map(|id| datagram(&chart, id)).collect();
+        datagrams.push(datagram(&chart, 100));
+        datagrams.push(datagram(&chart, chart.id()));
+
+        let mut uncharted = Vec::new();
+        let batch = datagrams.iter().map(|(buf, addr)| (buf.as_slice(), *addr));
+        chart.process_bufs(batch, &mut uncharted);
+        assert_eq!(uncharted.len(), 5);
+        assert_eq!(chart.size(), 10 + 5);
+
+        uncharted.clear();
+        let batch = datagrams.iter().map(|(buf, addr)| (buf.as_slice(), *addr));
+        chart.process_bufs(batch, &mut uncharted);
+        assert!(uncharted.is_empty());
+
+        let counters = chart.counters();
+        assert_eq!(counters.decoded, 5);
+        assert_eq!(counters.skipped, 1 + 6);
+    }
+
+    #[tokio::test]
+    async fn merge_notifies_new() {
+        let chart = Chart::test(entry).await;
+        let mut notify = chart.notify();
+        let (_, mut leaving) = entry(43);
+        leaving.status = Status::Leaving;
+        let entries = [
+            entry(1),
+            entry(42),
+            (chart.id(), entry(44).1),
+            (43, leaving),
+        ];
+
+        assert_eq!(chart.merge_from(entries), 1);
+        assert_eq!(chart.size(), 11);
+        let (id, ip, _) = notify.recv().await.unwrap();
+        assert_eq!((id, ip), (42, entry(42).1.ip));
+        assert!(notify.discoveries.try_recv().is_err());
+        assert_eq!(chart.stats().merged, 1);
+    }
+
+    #[tokio::test]
+    async fn notify_full_entry() {
+        let chart = Chart::test(entry).await;
+        let mut notify = chart.notify();
+        let (_, mut draining) = entry(42);
+        draining.status = Status::Draining;
+        chart.merge_from([(42, draining.clone())]);
+
+        let (id, entry) = notify.recv_entry().await.unwrap();
+        assert_eq!(id, 42);
+        assert_eq!(entry.ip, draining.ip);
+        assert_eq!(entry.status, Status::Draining);
+        assert_eq!(entry.source, Source::Merged);
+    }
+
+    #[tokio::test]
+    async fn notify_from_id_set() {
+        let chart = Chart::test(entry).await;
+        let mut notify = chart.notify();
+        let ids = std::collections::HashSet::from([43, 44]);
+
+        let recv = tokio::spawn(async move { notify.recv_from(&ids).await });
+        chart.merge_from([entry(42)]);
+        tokio::task::yield_now().await;
+        chart.merge_from([entry(43)]);
+        let (id, found) = recv.await.unwrap().unwrap();
+        assert_eq!((id, found.ip), (43, entry(43).1.ip));
+    }
+
+    #[tokio::test]
+    async fn notify_closed_with_chart() {
+        let chart = Chart::test(entry).await;
+        let mut notify = chart.notify();
+        chart.merge_from([entry(42)]);
+        chart.clone().close(Duration::ZERO).await;
+
+        assert!(!notify.is_closed(), "discovery still buffered");
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+        let closed = broadcast::error::RecvError::Closed;
+        assert_eq!(notify.recv().await, Err(closed));
+        assert!(notify.is_closed());
+    }
+
+    #[tokio::test]
+    async fn forget_all_except_keep() {
+        let chart = Chart::test(entry).await;
+        let mut events = chart.events();
+        chart.forget_all_except(&[3, 5, 42]);
+
+        let mut charted: Vec<_> = chart.snapshot().iter().map(|(id, _)| id).collect();
+        charted.sort_unstable();
+        assert_eq!(charted, [3, 5]);
+        assert_eq!(chart.size(), 3);
+
+        let mut forgotten = Vec::new();
+        while let Ok(Event::Forgotten { id }) = events.try_recv() {
+            forgotten.push(id);
+        }
+        forgotten.sort_unstable();
+        assert_eq!(forgotten, [1, 2, 4, 6, 7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_removed_and_ignored() {
+        let mut chart = Chart::test(entry).await;
+        chart.unreachable = Arc::new(Mutex::new(Unreachable::new(2, Duration::from_secs(60))));
+        let mut events = chart.events();
+
+        assert!(!chart.report_unreachable(3));
+        chart.report_reachable(3);
+        assert!(!chart.report_unreachable(3));
+        assert!(chart.report_unreachable(3));
+        assert!(chart.snapshot().get(3).is_none());
+        assert_eq!(
+            events.try_recv(),
+            Ok(Event::Unreachable { id: 3, failures: 2 })
+        );
+
+        let (buf, addr) = datagram(&chart, 3);
+        chart.process_bufs([(&buf[..], addr)].into_iter(), &mut Vec::new());
+        assert!(chart.snapshot().get(3).is_none());
+        assert!(!chart.report_unreachable(42), "not charted");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn active_size_leaves_out_silent() {
+        let chart = Chart::test(entry).await;
+        let (buf, addr) = datagram(&chart, 42);
+        chart.process_bufs([(&buf[..], addr)].into_iter(), &mut Vec::new());
+        let max_age = Duration::from_secs(1);
+        assert_eq!(chart.size(), 11);
+        assert_eq!(chart.active_size(max_age), 2);
+        assert!(chart.active_entries_if_size(3, max_age).is_none());
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(chart.active_size(max_age), 1);
+        assert_eq!(chart.size(), 11);
+    }
+
+    #[tokio::test]
+    async fn merged_heard_from_network() {
+        let chart = Chart::test(entry).await;
+        let (buf, addr) = datagram(&chart, 42);
+        let entry = Entry::new(addr.ip(), [8000]);
+        chart.merge_from([(42, entry)]);
+        let source = |chart: &Chart<1, u16>| chart.map.lock().unwrap().get(&42).unwrap().source;
+        assert_eq!(source(&chart), Source::Merged);
+
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+        assert_eq!(source(&chart), Source::Network);
+        assert_eq!(chart.stats().merged, 0);
+    }
+
+    #[tokio::test]
+    async fn size_follows_chart() {
+        use tokio_stream::StreamExt;
+
+        let chart = Chart::test(entry).await;
+        let mut sizes = chart.size_stream();
+        assert_eq!(sizes.next().await, Some(10));
+
+        chart.insert(42, entry(42).1);
+        assert_eq!(sizes.next().await, Some(11));
+        chart.await_size(11).await;
+
+        chart.forget(42);
+        chart.forget(1);
+        assert_eq!(sizes.next().await, Some(9));
+    }
+
+    #[tokio::test]
+    async fn discovery_msg_encoded_once() {
+        let chart = Chart::test(entry).await;
+        let active = chart.discovery_buf();
+        assert!(std::ptr::eq(&*active, &*chart.discovery_buf()));
+
+        chart.set_status(Status::Draining);
+        let draining = chart.discovery_buf();
+        assert!(!std::ptr::eq(&*active, &*draining));
+        let prefix = chart.wire_format.peek(&draining).unwrap();
+        assert_eq!(prefix.status, Status::Draining);
+    }
 }
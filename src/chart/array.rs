@@ -0,0 +1,135 @@
+//! (De)serialize arrays of any length, serde only implements its traits for
+//! arrays of up to 32 elements. Arrays are encoded as tuples, the same as serde
+//! does for short arrays. Use [`counted`] to also encode the length.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+#[cfg_attr(not(feature = "protobuf"), allow(dead_code))] // used by the protobuf payload
+pub(crate) fn serialize<S, T, const N: usize>(
+    array: &[T; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let mut tuple = serializer.serialize_tuple(N)?;
+    for element in array {
+        tuple.serialize_element(element)?;
+    }
+    tuple.end()
+}
+
+#[cfg_attr(not(feature = "protobuf"), allow(dead_code))] // used by the protobuf payload
+pub(crate) fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+}
+
+/// Encodes arrays as sequences, which are prefixed with their length. That
+/// lets the receiver tell an array of the wrong length apart from garbage.
+pub(crate) mod counted {
+    use super::{ArrayVisitor, Deserializer, PhantomData, Serializer};
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Serialize};
+
+    pub(crate) fn serialize<S, T, const N: usize>(
+        array: &[T; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        let mut seq = serializer.serialize_seq(Some(N))?;
+        for element in array {
+            seq.serialize_element(element)?;
+        }
+        seq.end()
+    }
+
+    pub(crate) fn deserialize<'de, D, T, const N: usize>(
+        deserializer: D,
+    ) -> Result<[T; N], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        deserializer.deserialize_seq(ArrayVisitor(PhantomData))
+    }
+}
+
+struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+impl<'de, T, const N: usize> Visitor<'de> for ArrayVisitor<T, N>
+where
+    T: Deserialize<'de>,
+{
+    type Value = [T; N];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an array of length {N}")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut elements = Vec::with_capacity(N);
+        while elements.len() < N {
+            match seq.next_element()? {
+                Some(element) => elements.push(element),
+                None => return Err(de::Error::invalid_length(elements.len(), &self)),
+            }
+        }
+        // tuples always have exactly N elements, sequences could be longer
+        if seq.next_element::<T>()?.is_some() {
+            return Err(de::Error::invalid_length(N + 1, &self));
+        }
+        Ok(elements
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("exactly N elements were collected")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wrapper(#[serde(with = "super")] [u16; 40]);
+
+    #[test]
+    fn matches_serde_tuple_encoding() {
+        let short: [u16; 3] = [1, 2, 3];
+        #[derive(Serialize)]
+        struct Short(#[serde(with = "super")] [u16; 3]);
+        assert_eq!(
+            bincode::serialize(&Short(short)).unwrap(),
+            bincode::serialize(&short).unwrap()
+        );
+
+        let long = Wrapper(std::array::from_fn(|i| i as u16));
+        let buf = bincode::serialize(&long).unwrap();
+        assert_eq!(bincode::deserialize::<Wrapper>(&buf).unwrap(), long);
+    }
+
+    #[test]
+    fn counted_rejects_other_lengths() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Counted<const N: usize>(#[serde(with = "super::counted")] [u16; N]);
+
+        let buf = bincode::serialize(&Counted([1, 2, 3])).unwrap();
+        assert_eq!(&buf[..8], &3u64.to_le_bytes());
+        assert_eq!(
+            bincode::deserialize::<Counted<3>>(&buf).unwrap(),
+            Counted([1, 2, 3])
+        );
+        assert!(bincode::deserialize::<Counted<2>>(&buf).is_err());
+        assert!(bincode::deserialize::<Counted<4>>(&buf).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::Debug;
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+
+use super::{Chart, DiscoveryMsg, Port};
+use crate::Error;
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// whether we are not announcing until every placeholder port is
+    /// assigned, see [`ChartBuilder::with_service_port_zero_autoassign`](crate::ChartBuilder::with_service_port_zero_autoassign)
+    pub(crate) fn awaiting_ports(&self) -> bool {
+        self.awaiting_ports.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Chart<N, Port> {
+    /// Advertise `port` as our service port at `idx` from now on, for
+    /// example once a listener bound to port `0` knows the port the OS gave
+    /// it. Peers see the change with our next broadcast or reply.
+    ///
+    /// Filling in the last placeholder port, see
+    /// [`ChartBuilder::with_service_port_zero_autoassign`](crate::ChartBuilder::with_service_port_zero_autoassign),
+    /// announces this instance right away and then often for a while so
+    /// peers find it quickly.
+    ///
+    /// # Note
+    /// Unlike when building the chart `port` is not checked against the
+    /// discovery port or the other service ports.
+    ///
+    /// # Errors
+    /// Returns [`Error::MessageTooLarge`] if our discovery message would no
+    /// longer fit within the [bincode limit](crate::BincodeOptions::limit),
+    /// the port is left unchanged then.
+    ///
+    /// # Panics
+    /// If `idx` is not smaller then the number of ports
+    pub async fn set_service_port(&self, idx: usize, port: u16) -> Result<(), Error> {
+        assert!(idx < N, "there are only {N} service ports");
+        let ours = self.discovery_msg();
+        let mut msg = ours.msg;
+        msg[idx] = port;
+        self.check_fits(DiscoveryMsg { msg, ..ours })?;
+        let awaiting = {
+            let mut ports = self.msg.write().unwrap();
+            ports[idx] = port;
+            self.autoassign && ports.contains(&0)
+        };
+        *self.encoded.lock().unwrap() = None;
+        if self.awaiting_ports.swap(awaiting, Ordering::Relaxed) && !awaiting {
+            info!("service ports assigned, announcing");
+            self.restart_rampdown();
+            self.broadcast_now().await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry_with;
+    use crate::{Entry, Id};
+
+    fn entry(n: u8) -> (Id, Entry<[u16; 2]>) {
+        entry_with(n, [0, 0])
+    }
+
+    #[tokio::test]
+    async fn announces_once_assigned() {
+        let mut chart = Chart::test(entry).await;
+        chart.autoassign = true;
+        chart.awaiting_ports.store(true, Ordering::Relaxed);
+        let placeholders = chart.discovery_buf();
+
+        chart.set_service_port(1, 9000).await.unwrap();
+        assert!(chart.is_suspended());
+        chart.set_service_port(0, 8000).await.unwrap();
+        assert!(!chart.is_suspended());
+
+        assert_eq!(chart.our_service_ports(), [8000, 9000]);
+        let msg = chart.wire_format.decode::<2, u16>(&chart.discovery_buf());
+        assert_eq!(msg.unwrap().msg, [8000, 9000]);
+        assert!(!std::ptr::eq(&*placeholders, &*chart.discovery_buf()));
+    }
+}
@@ -0,0 +1,68 @@
+//! Exposes the hot path to the benchmarks, not part of the public API.
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::ops::Deref;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{Capabilities, Chart, DiscoveryMsg, Metadata, PortHealth, Status, WireFormat};
+use crate::Id;
+
+/// encode a discovery message carrying `ports`
+pub fn encode<const N: usize>(format: WireFormat, ports: [u16; N]) -> Vec<u8> {
+    let msg = DiscoveryMsg {
+        header: 0,
+        id: 0,
+        status: Status::Active,
+        interval_ms: 1000,
+        group: None,
+        capabilities: Capabilities::OURS,
+        msg: ports,
+        meta: Metadata::default(),
+        port_health: PortHealth::default(),
+        ttl_ms: None,
+        priority: 0,
+        sees: 1,
+    };
+    format.encode(&msg).unwrap()
+}
+
+/// decode a discovery message carrying `N` ports
+pub fn decode<const N: usize>(format: WireFormat, buf: &[u8]) -> [u16; N] {
+    format.decode::<N, u16>(buf).unwrap().msg
+}
+
+/// the discovery message an instance with `id` sharing the same message as
+/// `chart` would send
+pub fn heartbeat<const N: usize, T>(chart: &Chart<N, T>, id: Id) -> Vec<u8>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let msg = DiscoveryMsg {
+        id,
+        ..chart.discovery_msg()
+    };
+    chart.wire_format.encode(&msg).unwrap()
+}
+
+/// our own discovery message as it is sent every broadcast and reply
+pub fn discovery_buf<const N: usize, T>(chart: &Chart<N, T>) -> impl Deref<Target = [u8]>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    chart.discovery_buf()
+}
+
+/// process datagrams as if they were received, returns the number of newly
+/// charted instances
+pub fn process<const N: usize, T>(chart: &Chart<N, T>, datagrams: &[(Vec<u8>, SocketAddr)]) -> usize
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let mut uncharted = Vec::new();
+    let datagrams = datagrams.iter().map(|(buf, addr)| (buf.as_slice(), *addr));
+    chart.process_bufs(datagrams, &mut uncharted);
+    uncharted.len()
+}
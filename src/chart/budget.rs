@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Limits the number of datagrams processed per window, shared by all
+/// discovery sockets. Protects the runtime from a multicast storm or a
+/// misbehaving peer.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    packets: usize,
+    per: Duration,
+    /// start of the current window and datagrams processed in it
+    used: Mutex<(Instant, usize)>,
+}
+
+impl Budget {
+    pub(crate) fn new(packets: usize, per: Duration) -> Self {
+        Self {
+            packets: packets.max(1),
+            per,
+            used: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// take up to `n` datagrams from the budget, returns how many may be
+    /// processed now
+    pub(crate) fn take(&self, n: usize, now: Instant) -> usize {
+        let mut used = self.used.lock().unwrap();
+        let (start, count) = &mut *used;
+        if now.duration_since(*start) >= self.per {
+            *start = now;
+            *count = 0;
+        }
+        let allowed = n.min(self.packets - *count);
+        *count += allowed;
+        allowed
+    }
+
+    /// wait until the budget is replenished
+    pub(crate) async fn replenished(&self) {
+        let (start, _) = *self.used.lock().unwrap();
+        tokio::time::sleep_until(start + self.per).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_every_window() {
+        let budget = Budget::new(10, Duration::from_secs(1));
+        let now = Instant::now();
+        assert_eq!(budget.take(6, now), 6);
+        assert_eq!(budget.take(6, now), 4);
+        assert_eq!(budget.take(6, now), 0);
+
+        budget.replenished().await;
+        assert_eq!(Instant::now(), now + Duration::from_secs(1));
+        assert_eq!(budget.take(6, Instant::now()), 6);
+    }
+}
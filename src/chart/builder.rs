@@ -1,19 +1,42 @@
-use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io;
 use std::marker::PhantomData;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::Error;
+use crate::{BindDiagnosis, Error};
 
-use super::{interval, Chart, Id};
-use rand::rngs::OsRng;
-use rand::RngCore;
+#[cfg(feature = "challenge")]
+use super::challenge::Challenges;
+use super::clock::{Clock, SharedClock};
+use super::counters::Cells;
+use super::metrics::{ChartMetrics, Metrics};
+#[cfg(feature = "packet-tap")]
+use super::tap::{Direction, Tap};
+#[cfg(feature = "testing")]
+use super::Cut;
+#[cfg(feature = "fault-injection")]
+use super::Faults;
+#[cfg(feature = "rand")]
+use super::IdRng;
+#[cfg(unix)]
+use super::UnixSocket;
+use super::{
+    interval, probe, BincodeOptions, Budget, Chart, ConflictPolicy, Debounce, Endpoint, Ewma, Id,
+    LocalDiscovery, Map, Quarantine, ReplyPolicy, Socket, Status, Unreachable, WireFormat,
+};
 use serde::Serialize;
 use tokio::net::UdpSocket;
-use tokio::sync::broadcast;
-use tracing::info;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+
+mod config;
+pub use config::ChartConfig;
+mod dynamic;
+pub use dynamic::DynamicBuilder;
 
 #[derive(Debug, Default)]
 pub struct Yes;
@@ -21,17 +44,23 @@ pub struct Yes;
 pub struct No;
 
 pub trait ToAssign: core::fmt::Debug {}
-pub trait Assigned: ToAssign {}
-pub trait NotAssigned: ToAssign {}
 
 impl ToAssign for Yes {}
 impl ToAssign for No {}
 
-impl Assigned for Yes {}
-impl NotAssigned for No {}
-
-const DEFAULT_HEADER: u64 = 6_687_164_552_036_412_667;
-const DEFAULT_PORT: u16 = 8080;
+/// The header used unless one is set with [`ChartBuilder::with_header`]
+pub const DEFAULT_HEADER: u64 = 6_687_164_552_036_412_667;
+/// The port used unless one is set with [`ChartBuilder::with_discovery_port`]
+pub const DEFAULT_DISCOVERY_PORT: u16 = 8080;
+/// The broadcast interval discovery starts at, see [`ChartBuilder::with_rampdown`]
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(100);
+/// The broadcast interval discovery settles at, see [`ChartBuilder::with_rampdown`]
+pub const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the interval takes to go from its minimum to its maximum, see
+/// [`ChartBuilder::with_rampdown`]
+pub const DEFAULT_RAMPDOWN: Duration = Duration::from_secs(10);
+const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const DEFAULT_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
 
 pub type Port = u16;
 
@@ -46,11 +75,54 @@ where
 {
     header: u64,
     service_id: Option<Id>,
+    /// the id was set by [`with_random_id`](Self::with_random_id)
+    random_id: bool,
+    reroll: Option<Duration>,
+    #[cfg(feature = "rand")]
+    id_rng: Option<IdRng>,
     discovery_port: u16,
+    discovery_range: Option<RangeInclusive<u16>>,
+    discovery_endpoints: Option<Vec<(Ipv4Addr, u16)>>,
+    /// see [`with_bound_socket`](Self::with_bound_socket)
+    bound_socket: Option<Arc<UdpSocket>>,
     service_port: Option<u16>,
     service_ports: [u16; N],
+    autoassign: bool,
     rampdown: interval::Params,
     local: bool,
+    /// see [`LocalDiscovery::Unix`]
+    #[cfg(unix)]
+    unix: bool,
+    multicast_loop: bool,
+    dual_stack: bool,
+    group: Option<u16>,
+    conflict_policy: ConflictPolicy,
+    reply_policy: ReplyPolicy,
+    wire_format: WireFormat,
+    expected_capacity: usize,
+    recv_buffer: Option<usize>,
+    send_buffer: Option<usize>,
+    goodbyes: u8,
+    expiry: Option<u32>,
+    ttl: Option<Duration>,
+    priority: u32,
+    quarantine: Option<(u32, Duration)>,
+    /// leave and rejoin debounce
+    debounce: Option<(Duration, Duration)>,
+    /// reports before an instance is removed and how long it is ignored
+    unreachable: (u32, Duration),
+    /// time constant of the smoothed size
+    size_window: Duration,
+    /// packets processed per window
+    budget: Option<(usize, Duration)>,
+    observe: bool,
+    #[cfg(feature = "challenge")]
+    challenge_key: Option<[u8; 32]>,
+    #[cfg(feature = "packet-tap")]
+    tap: Option<Tap>,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<Arc<Faults>>,
+    metrics: Metrics,
     id_set: PhantomData<IdSet>,
     port_set: PhantomData<PortSet>,
     ports_set: PhantomData<PortsSet>,
@@ -64,11 +136,47 @@ impl<const N: usize> ChartBuilder<N, No, No, No> {
         ChartBuilder {
             header: DEFAULT_HEADER,
             service_id: None,
-            discovery_port: DEFAULT_PORT,
+            random_id: false,
+            reroll: None,
+            #[cfg(feature = "rand")]
+            id_rng: None,
+            discovery_port: DEFAULT_DISCOVERY_PORT,
+            discovery_range: None,
+            discovery_endpoints: None,
+            bound_socket: None,
             service_ports: [0u16; N],
             service_port: None,
+            autoassign: false,
             rampdown: interval::Params::default(),
             local: false,
+            #[cfg(unix)]
+            unix: false,
+            multicast_loop: true,
+            dual_stack: false,
+            group: None,
+            conflict_policy: ConflictPolicy::default(),
+            reply_policy: ReplyPolicy::default(),
+            wire_format: WireFormat::default(),
+            expected_capacity: 0,
+            recv_buffer: None,
+            send_buffer: None,
+            goodbyes: 3,
+            expiry: None,
+            ttl: None,
+            priority: 0,
+            quarantine: None,
+            debounce: None,
+            unreachable: (3, Duration::ZERO),
+            size_window: Duration::from_secs(10),
+            budget: None,
+            observe: false,
+            #[cfg(feature = "challenge")]
+            challenge_key: None,
+            #[cfg(feature = "packet-tap")]
+            tap: None,
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+            metrics: Metrics::default(),
             id_set: PhantomData {},
             port_set: PhantomData {},
             ports_set: PhantomData {},
@@ -82,25 +190,344 @@ where
     PortSet: ToAssign,
     PortsSet: ToAssign,
 {
-    /// Set the [`Id`] for this node, the [`Id`] is the key for this node in the chart
-    /// # Note
-    /// Always needed, you can not build without an [`Id`] set. The [`Id`] must be __unique__
-    #[must_use]
-    pub fn with_id(self, id: Id) -> ChartBuilder<N, Yes, PortSet, PortsSet> {
+    // change the type-state keeping all the set options
+    fn retype<NewIdSet, NewPortSet, NewPortsSet>(
+        self,
+    ) -> ChartBuilder<N, NewIdSet, NewPortSet, NewPortsSet>
+    where
+        NewIdSet: ToAssign,
+        NewPortSet: ToAssign,
+        NewPortsSet: ToAssign,
+    {
         ChartBuilder {
             header: self.header,
+            random_id: self.random_id,
+            reroll: self.reroll,
+            #[cfg(feature = "rand")]
+            id_rng: self.id_rng,
             discovery_port: self.discovery_port,
-            service_id: Some(id),
+            discovery_range: self.discovery_range,
+            discovery_endpoints: self.discovery_endpoints,
+            bound_socket: self.bound_socket,
+            service_id: self.service_id,
             service_port: self.service_port,
             service_ports: self.service_ports,
+            autoassign: self.autoassign,
             rampdown: self.rampdown,
             local: self.local,
+            #[cfg(unix)]
+            unix: self.unix,
+            multicast_loop: self.multicast_loop,
+            dual_stack: self.dual_stack,
+            group: self.group,
+            conflict_policy: self.conflict_policy,
+            reply_policy: self.reply_policy,
+            wire_format: self.wire_format,
+            expected_capacity: self.expected_capacity,
+            recv_buffer: self.recv_buffer,
+            send_buffer: self.send_buffer,
+            goodbyes: self.goodbyes,
+            expiry: self.expiry,
+            ttl: self.ttl,
+            priority: self.priority,
+            quarantine: self.quarantine,
+            debounce: self.debounce,
+            unreachable: self.unreachable,
+            size_window: self.size_window,
+            budget: self.budget,
+            observe: self.observe,
+            #[cfg(feature = "challenge")]
+            challenge_key: self.challenge_key,
+            #[cfg(feature = "packet-tap")]
+            tap: self.tap,
+            #[cfg(feature = "fault-injection")]
+            faults: self.faults,
+            metrics: self.metrics,
             id_set: PhantomData {},
             port_set: PhantomData {},
             ports_set: PhantomData {},
         }
     }
 
+    /// `swept` is the discovery port found by sweeping the
+    /// [range](Self::with_discovery_port_in_range) ahead of time, if not the
+    /// range is swept while building
+    // with IdSet set to Yes service_id is always Some
+    fn build<Msg>(&self, msg: [Msg; N], swept: Option<u16>) -> Result<Chart<N, Msg>, Error>
+    where
+        Msg: 'static + Debug + Serialize + Clone,
+    {
+        if self.local && !self.multicast_loop {
+            return Err(Error::LoopbackRequired);
+        }
+        let endpoints = match &self.bound_socket {
+            Some(sock) => vec![bound_endpoint(sock.clone())?],
+            #[cfg(unix)]
+            None if self.unix => vec![self.unix_endpoint()?],
+            None => self.open_endpoints(swept)?,
+        };
+        #[cfg(feature = "testing")]
+        let cut = Cut::default();
+        #[cfg(feature = "testing")]
+        let endpoints = endpoints
+            .into_iter()
+            .map(|endpoint| endpoint.partitioned(cut.clone()))
+            .collect();
+        let chart = Chart {
+            header: self.header,
+            service_id: Arc::new(AtomicU64::new(self.service_id.unwrap())),
+            reroll_until: self
+                .reroll
+                .filter(|_| self.random_id)
+                .map(|window| Instant::now() + window),
+            #[cfg(feature = "rand")]
+            id_rng: self.id_rng.clone(),
+            msg: Arc::new(std::sync::RwLock::new(msg)),
+            endpoints: Arc::new(endpoints),
+            map: Arc::new(Mutex::new(Map::with_capacity(self.expected_capacity))),
+            host: Arc::default(),
+            size: Arc::new(watch::Sender::new(1)),
+            interval: self.rampdown.clone().into(),
+            broadcast: broadcast::channel(256).0,
+            events: broadcast::channel(256).0,
+            conflict_policy: self.conflict_policy,
+            reply_policy: self.reply_policy.clone(),
+            multicast_loop: self.multicast_loop,
+            dual_stack: self.dual_stack,
+            group: self.group,
+            wire_format: self.wire_format,
+            status: Arc::new(Mutex::new(Status::default())),
+            counters: Arc::new(Cells::new(self.metrics.clone())),
+            goodbyes: self.goodbyes,
+            expiry: self.expiry,
+            ttl: self.ttl,
+            priority: Arc::new(AtomicU32::new(self.priority)),
+            visibility: Arc::default(),
+            quarantine: self
+                .quarantine
+                .map(|(threshold, duration)| Quarantine::new(threshold, duration))
+                .map(|q| Arc::new(Mutex::new(q))),
+            debounce: self
+                .debounce
+                .map(|(leave, rejoin)| Debounce::new(leave, rejoin))
+                .map(|d| Arc::new(Mutex::new(d))),
+            unreachable: Arc::new(Mutex::new(Unreachable::new(
+                self.unreachable.0,
+                self.unreachable.1,
+            ))),
+            trend: Arc::new(Mutex::new(Ewma::new(self.size_window, 1, Instant::now()))),
+            budget: self
+                .budget
+                .map(|(packets, per)| Arc::new(Budget::new(packets, per))),
+            observer: self.observe.then(Arc::default),
+            meta: Arc::default(),
+            port_health: Arc::default(),
+            inbound: Arc::default(),
+            outbound: Arc::default(),
+            #[cfg(feature = "challenge")]
+            challenges: self
+                .challenge_key
+                .map(|key| Arc::new(Mutex::new(Challenges::new(key)))),
+            #[cfg(feature = "packet-tap")]
+            tap: self.tap.clone(),
+            #[cfg(feature = "fault-injection")]
+            faults: self.faults.clone(),
+            #[cfg(feature = "testing")]
+            cut,
+            encoded: Arc::default(),
+            suspended: Arc::default(),
+            autoassign: self.autoassign,
+            awaiting_ports: Arc::default(),
+            freeze: Arc::default(),
+            drivers: Arc::default(),
+            closed: Arc::new(watch::Sender::new(false)),
+        };
+        chart.check_fits(chart.discovery_msg())?;
+        Ok(chart)
+    }
+
+    fn open_endpoints(&self, swept: Option<u16>) -> Result<Vec<Socket>, Error> {
+        let discovery_port = match (swept, self.sweep_range()) {
+            (Some(port), _) => port,
+            (None, Some(range)) => self.sweep(range)?,
+            (None, None) => self.discovery_port,
+        };
+        let mut endpoints: Vec<(IpAddr, u16)> = self
+            .endpoints(discovery_port)
+            .into_iter()
+            .map(|(group, port)| (group.into(), port))
+            .collect();
+        if self.dual_stack {
+            let ports: Vec<_> = endpoints.iter().map(|(_, port)| *port).collect();
+            endpoints.extend(
+                ports
+                    .into_iter()
+                    .map(|port| (DEFAULT_GROUP_V6.into(), port)),
+            );
+        }
+        open_endpoints(
+            &endpoints,
+            self.local,
+            self.multicast_loop,
+            self.recv_buffer,
+            self.send_buffer,
+        )
+    }
+
+    #[cfg(unix)]
+    fn unix_endpoint(&self) -> Result<Socket, Error> {
+        let sock =
+            UnixSocket::bind(self.discovery_port, self.multicast_loop).map_err(Error::Construct)?;
+        let socket = Socket::unix(sock);
+        let sock = socket.sock.sock_ref();
+        if let Some(bytes) = self.recv_buffer {
+            sock.set_recv_buffer_size(bytes)
+                .map_err(Error::SetBufferSize)?;
+        }
+        if let Some(bytes) = self.send_buffer {
+            sock.set_send_buffer_size(bytes)
+                .map_err(Error::SetBufferSize)?;
+        }
+        Ok(socket)
+    }
+
+    /// [`build`](Self::build) with service ports, does not probe for another
+    /// chart instance holding the discovery port as that blocks
+    fn build_ports(&self, ports: [Port; N]) -> Result<Chart<N, Port>, Error> {
+        self.finish_build(self.build(ports, None), None, ports)
+    }
+
+    /// [`build`](Self::build) but if another chart instance on this host
+    /// holds the discovery port that is returned instead of the bind error.
+    /// Sweeps and probes for that instance without blocking the runtime.
+    async fn build_ports_async(&self, ports: [Port; N]) -> Result<Chart<N, Port>, Error> {
+        let swept = match self.sweep_range() {
+            Some(range) => {
+                let candidates = self.sweep_candidates(range.clone());
+                let (header, wire_format, shared) = (self.header, self.wire_format, self.local);
+                let sweep = move || sweep_ports(candidates, header, wire_format, shared);
+                let port = tokio::task::spawn_blocking(sweep).await.ok().flatten();
+                Some(port_found(port, &range)?)
+            }
+            None => None,
+        };
+        let res = self.build(ports, swept);
+        let running = match held_exclusively(&res) {
+            Some(port) => {
+                let (header, wire_format) = (self.header, self.wire_format);
+                let probe = move || probe::running_locally(header, wire_format, port);
+                let id = tokio::task::spawn_blocking(probe).await.ok().flatten();
+                id.map(|id| (port, id))
+            }
+            None => None,
+        };
+        self.finish_build(res, running, ports)
+    }
+
+    /// report the instance `running` on this host instead of the bind error
+    fn finish_build(
+        &self,
+        res: Result<Chart<N, Port>, Error>,
+        running: Option<(u16, Id)>,
+        ports: [Port; N],
+    ) -> Result<Chart<N, Port>, Error> {
+        if let Some((port, id)) = running {
+            return Err(Error::AlreadyRunningLocally { port, id });
+        }
+        res.inspect(|chart| {
+            let awaiting = self.autoassign && ports.contains(&0);
+            chart.awaiting_ports.store(awaiting, Ordering::Relaxed);
+        })
+    }
+
+    /// [`build`](Self::build) retrying recoverable errors, waits `backoff`
+    /// after the first failed attempt doubling it every next attempt
+    async fn build_with_retry(
+        &self,
+        ports: [Port; N],
+        attempts: u32,
+        mut backoff: Duration,
+    ) -> Result<Chart<N, Port>, Error> {
+        let mut attempt = 1;
+        loop {
+            match self.build_ports_async(ports).await {
+                Err(err) if err.is_recoverable() && attempt < attempts => {
+                    warn!("building chart failed (attempt {attempt}/{attempts}): {err}, retrying in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// the range to sweep for a discovery port, if any
+    fn sweep_range(&self) -> Option<RangeInclusive<u16>> {
+        #[cfg(unix)]
+        if self.unix {
+            return None;
+        }
+        self.discovery_range
+            .clone()
+            .filter(|_| self.discovery_endpoints.is_none() && self.bound_socket.is_none())
+    }
+
+    /// the first port in `range` usable for discovery, blocks the calling
+    /// thread while sweeping
+    fn sweep(&self, range: RangeInclusive<u16>) -> Result<u16, Error> {
+        let candidates = self.sweep_candidates(range.clone());
+        let port = sweep_ports(candidates, self.header, self.wire_format, self.local);
+        port_found(port, &range)
+    }
+
+    /// the ports in `range` that are not service ports
+    fn sweep_candidates(&self, range: RangeInclusive<u16>) -> Vec<u16> {
+        range
+            .filter(|port| *port != 0)
+            .filter(|port| self.service_port != Some(*port) && !self.service_ports.contains(port))
+            .collect()
+    }
+
+    fn check_ports(&self, service_ports: &[u16]) -> Result<(), Error> {
+        let endpoints = if let Some(sock) = &self.bound_socket {
+            let port = sock.local_addr().map_or(0, |addr| addr.port());
+            vec![(DEFAULT_GROUP, port)]
+        } else if self.discovery_range.is_some() && self.discovery_endpoints.is_none() {
+            // a port from the range is never a service port
+            Vec::new()
+        } else {
+            self.endpoints(self.discovery_port)
+        };
+        for (i, port) in service_ports.iter().enumerate() {
+            if endpoints.iter().any(|(_, discovery)| discovery == port) {
+                return Err(Error::ServicePortIsDiscoveryPort(*port));
+            }
+            let placeholder = self.autoassign && *port == 0;
+            if service_ports[..i].contains(port) && !placeholder {
+                return Err(Error::DuplicateServicePort(*port));
+            }
+        }
+        Ok(())
+    }
+
+    fn endpoints(&self, discovery_port: u16) -> Vec<(Ipv4Addr, u16)> {
+        self.discovery_endpoints
+            .clone()
+            .unwrap_or_else(|| vec![(DEFAULT_GROUP, discovery_port)])
+    }
+
+    /// Set the [`Id`] for this node, the [`Id`] is the key for this node in the chart
+    /// # Note
+    /// Always needed, you can not build without an [`Id`] set. The [`Id`] must be __unique__
+    #[must_use]
+    pub fn with_id(self, id: Id) -> ChartBuilder<N, Yes, PortSet, PortsSet> {
+        let mut builder = self.retype();
+        builder.service_id = Some(id);
+        builder.random_id = false;
+        builder
+    }
+
     /// Use a true random number from a reliable source of randomness as an [`Id`].
     /// # Note
     /// I recommend setting the [`Id`] in a deterministic way if possible, it makes debugging a lot
@@ -108,23 +535,62 @@ where
     /// number, the chance of this is unrealistically small.
     ///
     /// It is *extreemly* unlikely though possible that this fails. This happens if the systems source of random is configured incorrectly.
+    /// Collisions are reported as [`Event::IdCollision`](crate::Event::IdCollision), see
+    /// [`reroll_id_on_collision`](Self::reroll_id_on_collision).
+    ///
+    /// Only available with the `rand` feature (enabled by default).
     #[must_use]
+    #[cfg(feature = "rand")]
     pub fn with_random_id(self) -> ChartBuilder<N, Yes, PortSet, PortsSet> {
-        let mut rng = OsRng::default();
-        let id = rng.next_u64();
+        let id = super::id_rng::random_id(None);
         info!("Using random id: {id}");
-        ChartBuilder {
-            header: self.header,
-            discovery_port: self.discovery_port,
-            service_id: Some(id),
-            service_port: self.service_port,
-            service_ports: self.service_ports,
-            rampdown: self.rampdown,
-            local: self.local,
-            id_set: PhantomData {},
-            port_set: PhantomData {},
-            ports_set: PhantomData {},
-        }
+        let mut builder = self.retype();
+        builder.service_id = Some(id);
+        builder.random_id = true;
+        builder.id_rng = None;
+        builder
+    }
+
+    /// Like [`with_random_id`](Self::with_random_id) but the [`Id`] comes from
+    /// `rng`, so do new ids picked by
+    /// [`reroll_id_on_collision`](Self::reroll_id_on_collision). Pass a seeded
+    /// rng to get the same ids on every run, for example to compare logs of
+    /// tests that failed in CI.
+    ///
+    /// Only available with the `rand` feature (enabled by default).
+    ///
+    /// # Note
+    /// Instances using the same seed get the same id, give each its own.
+    #[must_use]
+    #[cfg(feature = "rand")]
+    pub fn with_id_rng(
+        self,
+        rng: impl rand::RngCore + Send + 'static,
+    ) -> ChartBuilder<N, Yes, PortSet, PortsSet> {
+        let rng = IdRng::new(rng);
+        let id = rng.next_id();
+        info!("Using random id: {id}");
+        let mut builder = self.retype();
+        builder.service_id = Some(id);
+        builder.random_id = true;
+        builder.id_rng = Some(rng);
+        builder
+    }
+    /// Pick a new [random id](Self::with_random_id) if another instance turns
+    /// out to use ours within `window` after building. After that peers
+    /// probably depend on our id and it is kept. Either way an
+    /// [`Event::IdCollision`](crate::Event::IdCollision) is sent. Has no
+    /// effect unless the id is set using [`with_random_id`](Self::with_random_id).
+    ///
+    /// Only available with the `rand` feature (enabled by default).
+    #[must_use]
+    #[cfg(feature = "rand")]
+    pub fn reroll_id_on_collision(
+        mut self,
+        window: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.reroll = Some(window);
+        self
     }
     /// Set a `port` for use by your application. This will appear to the other
     /// nodes in the Chart.
@@ -134,18 +600,9 @@ where
     /// [`custom_msg`](Self::custom_msg).
     #[must_use]
     pub fn with_service_port(self, port: u16) -> ChartBuilder<N, IdSet, Yes, No> {
-        ChartBuilder {
-            header: self.header,
-            discovery_port: self.discovery_port,
-            service_id: self.service_id,
-            service_port: Some(port),
-            service_ports: self.service_ports,
-            rampdown: self.rampdown,
-            local: self.local,
-            id_set: PhantomData {},
-            port_set: PhantomData {},
-            ports_set: PhantomData {},
-        }
+        let mut builder = self.retype();
+        builder.service_port = Some(port);
+        builder
     }
     /// Set mutiple `ports` for use by your application. This will appear to the other
     /// nodes in the Chart.
@@ -155,18 +612,39 @@ where
     /// [`custom_msg`](Self::custom_msg).
     #[must_use]
     pub fn with_service_ports(self, ports: [u16; N]) -> ChartBuilder<N, IdSet, No, Yes> {
-        ChartBuilder {
-            header: self.header,
-            discovery_port: self.discovery_port,
-            service_id: self.service_id,
-            service_port: None,
-            service_ports: ports,
-            rampdown: self.rampdown,
-            local: self.local,
-            id_set: PhantomData {},
-            port_set: PhantomData {},
-            ports_set: PhantomData {},
-        }
+        let mut builder = self.retype();
+        builder.service_port = None;
+        builder.service_ports = ports;
+        builder
+    }
+    /// Allow service ports set to `0` as placeholders, for example for a
+    /// listener that binds to port `0` and gets a port from the OS only after
+    /// the chart is built. Fill them in with [`Chart::set_service_port`],
+    /// until every placeholder is filled in this instance does not announce
+    /// itself. It does chart the instances it hears from.
+    ///
+    /// Without this a `0` port is advertised as is.
+    #[must_use]
+    pub fn with_service_port_zero_autoassign(
+        mut self,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.autoassign = true;
+        self
+    }
+    /// apply the settings in `config`, see [`ChartConfig`]
+    /// # Errors
+    /// returns [`Error::InvalidRampdown`] if the config's `min_interval` is
+    /// larger then its `max_interval` or `max_interval` is zero
+    pub fn with_config(
+        self,
+        config: &ChartConfig,
+    ) -> Result<ChartBuilder<N, IdSet, PortSet, PortsSet>, Error> {
+        let builder = self
+            .with_header(config.header)
+            .with_discovery_port(config.discovery_port)
+            .try_with_rampdown(config.min_interval, config.max_interval, config.rampdown)?
+            .local_discovery(config.local_discovery);
+        Ok(builder)
     }
     /// set a custom header number. The header is used to identify your application's chart
     /// from others multicast traffic when deployed your should set this to a [random](https://www.random.org) number.
@@ -177,35 +655,149 @@ where
     }
     /// set custom port for discovery. With [local discovery] enabled this port needs to be
     /// free and unused on all nodes it is not free the multicast traffic caused by this library
-    /// might corrupt network data of other applications. The default port is 8080.
+    /// might corrupt network data of other applications. The default port is
+    /// [`DEFAULT_DISCOVERY_PORT`], 8080.
     /// # Warning
     /// Not all ports seem to pass multicast traffic, you might need to experiment a bit.
     #[must_use]
     pub fn with_discovery_port(mut self, port: u16) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
         self.discovery_port = port;
+        self.discovery_range = None;
+        self
+    }
+    /// use the first port in `range` that is not in use on this host and on
+    /// which our multicast traffic arrives back. With
+    /// [`local_discovery`](Self::local_discovery) a port shared with a
+    /// [maintained](crate::discovery::maintain) chart instance using our
+    /// [header](Self::with_header) is used too, so instances of a test
+    /// cluster on one host sweeping the same range end up on the same port.
+    /// Use [`Chart::discovery_port`] to find out which port was picked, for
+    /// example to pass it to other instances. Meant for tests that run in
+    /// parallel on busy machines. Replaces the port set with
+    /// [`with_discovery_port`](Self::with_discovery_port), has no effect if
+    /// [discovery endpoints](Self::with_discovery_endpoints) are set.
+    ///
+    /// # Note
+    /// Trying a port takes up to 100ms. [`finish_with_retry`](Self::finish_with_retry)
+    /// sweeps on a blocking thread, the other ways to build block the calling
+    /// thread while sweeping. Fails with [`Error::NoUsablePort`] if no port
+    /// in the range is usable.
+    #[must_use]
+    pub fn with_discovery_port_in_range(
+        mut self,
+        range: RangeInclusive<u16>,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.discovery_range = Some(range);
+        self
+    }
+
+    /// Discover over a socket you already set up instead of opening one.
+    /// Announcements are multicast to `224.0.0.251` (or `ff02::fb` for an
+    /// IPv6 socket) on the port the socket is bound to. This replaces the
+    /// [discovery port](Self::with_discovery_port),
+    /// [range](Self::with_discovery_port_in_range) and
+    /// [endpoints](Self::with_discovery_endpoints). The socket options set by
+    /// [`dual_stack`](Self::dual_stack), [`local_discovery`](Self::local_discovery),
+    /// [`with_multicast_loop`](Self::with_multicast_loop) and the buffer sizes
+    /// are not applied, configure those on the socket yourself.
+    ///
+    /// The socket must:
+    /// - be bound to the discovery port, on the unspecified address
+    ///   (`0.0.0.0` or `::`) to receive multicast,
+    /// - have joined the multicast group above,
+    /// - have multicast loop enabled, unless disabled here too using
+    ///   [`with_multicast_loop`](Self::with_multicast_loop),
+    /// - have `SO_REUSEPORT` set if other instances on this host share the port.
+    ///
+    /// A multicast TTL (hops) above one is needed to reach other subnets, by
+    /// default the chart uses four. The chart reads every datagram arriving on
+    /// the socket, datagrams of other protocols are reported as
+    /// [invalid](crate::DiscoveryError::Parse).
+    #[must_use]
+    pub fn with_bound_socket(
+        mut self,
+        sock: UdpSocket,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.bound_socket = Some(Arc::new(sock));
+        self
+    }
+
+    /// listen and announce on multiple multicast `group` and `port` pairs at
+    /// once. The instances found on any of them end up in the same chart. This
+    /// replaces the port set with [`with_discovery_port`](Self::with_discovery_port),
+    /// the default group is `224.0.0.251`.
+    /// # Panics
+    /// panics if no endpoints are passed
+    #[must_use]
+    pub fn with_discovery_endpoints(
+        mut self,
+        endpoints: &[(Ipv4Addr, u16)],
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        assert!(
+            !endpoints.is_empty(),
+            "need at least one discovery endpoint"
+        );
+        self.discovery_endpoints = Some(endpoints.to_vec());
         self
     }
     /// set duration between discovery broadcasts, decreases linearly from `max` to `min`
     /// over `rampdown` period.
     /// # Panics
-    /// panics if min is larger then max
+    /// panics if min is larger then max or max is zero, use
+    /// [`try_with_rampdown`](Self::try_with_rampdown)
+    /// if the durations come from user input.
     #[must_use]
     pub fn with_rampdown(
+        self,
+        min: Duration,
+        max: Duration,
+        rampdown: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.try_with_rampdown(min, max, rampdown)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// set duration between discovery broadcasts, decreases linearly from `max` to `min`
+    /// over `rampdown` period.
+    /// # Errors
+    /// returns [`Error::InvalidRampdown`] if min is larger then max or max
+    /// is zero
+    pub fn try_with_rampdown(
         mut self,
         min: Duration,
         max: Duration,
         rampdown: Duration,
+    ) -> Result<ChartBuilder<N, IdSet, PortSet, PortsSet>, Error> {
+        if min > max || max.is_zero() {
+            return Err(Error::InvalidRampdown { min, max });
+        }
+        self.rampdown = interval::Params {
+            rampdown,
+            min,
+            max,
+            ..self.rampdown
+        };
+        Ok(self)
+    }
+
+    /// Record every datagram arriving on the discovery sockets, also those
+    /// with another [header](Self::with_header), see
+    /// [`Chart::observed_headers`]. Meant for debugging, finding out which
+    /// other application uses the discovery port and group. Defaults to
+    /// false.
+    #[must_use]
+    pub fn observe_all_headers(
+        mut self,
+        is_enabled: bool,
     ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
-        assert!(
-            min <= max,
-            "minimum duration: {min:?} must be smaller or equal to the maximum: {max:?}"
-        );
-        self.rampdown = interval::Params { rampdown, min, max };
+        self.observe = is_enabled;
         self
     }
 
     #[must_use]
     /// set whether discovery is enabled within the same host. Defaults to false.
+    /// Pass `true` or [`LocalDiscovery::Udp`] to share the discovery port with
+    /// instances on this host.
     ///
     /// # Warning
     /// When this is enabled you might not be warned if the `discovery port` is in use by another application.
@@ -213,15 +805,446 @@ where
     /// corruption in the application if it can not handle this.
     /// `ChartBuilder` will still fail if the `discovery port` is already bound to a multicast adress
     /// without `SO_REUSEADDR` set.
+    ///
+    /// # Unix sockets
+    /// With [`LocalDiscovery::Unix`] only instances on this host are
+    /// discovered, over unix datagram sockets instead of UDP. Every instance
+    /// using the same [discovery port](Self::with_discovery_port) places its
+    /// socket in the directory `instance-chart-<port>` in `XDG_RUNTIME_DIR`,
+    /// or the temporary directory if that is not set. A broadcast is sent to
+    /// every socket in there. The directory is made accessible only by its
+    /// owner, building fails if it belongs to another user. This does not
+    /// depend on `SO_REUSEPORT` so it behaves the same on every OS, use it for
+    /// large single machine test clusters.
+    ///
+    /// Instances are then charted with the loopback address.
+    /// [Multiple endpoints](Self::with_discovery_endpoints),
+    /// [dual stack](Self::dual_stack) and [port sweeping](Self::with_discovery_port_in_range)
+    /// do not apply.
     pub fn local_discovery(
+        mut self,
+        mode: impl Into<LocalDiscovery>,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        let mode = mode.into();
+        self.local = mode == LocalDiscovery::Udp;
+        #[cfg(unix)]
+        {
+            self.unix = mode == LocalDiscovery::Unix;
+        }
+        self
+    }
+
+    /// set whether we receive our own discovery messages. Defaults to true.
+    /// With one instance per host receiving our own messages is pure
+    /// overhead, turn it off to save on that.
+    ///
+    /// # Note
+    /// Without loopback [`Chart::self_test`] can not work and lost multicast
+    /// group membership is not detected and repaired. Building fails with
+    /// [`Error::LoopbackRequired`] if [local discovery](Self::local_discovery)
+    /// is enabled.
+    #[must_use]
+    pub fn with_multicast_loop(
+        mut self,
+        is_enabled: bool,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.multicast_loop = is_enabled;
+        self
+    }
+
+    /// also discover over IPv6 so hosts that only have IPv6 join the chart.
+    /// For every discovery port an IPv6 socket is opened that joins the link
+    /// local group `ff02::fb`. Instances reachable over both are charted once
+    /// under the address they were first discovered on.
+    #[must_use]
+    pub fn dual_stack(mut self, is_enabled: bool) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.dual_stack = is_enabled;
+        self
+    }
+
+    /// announce that this instance is in `group`, for example the shard it
+    /// serves. Instances in every group are charted, use
+    /// [`Chart::peers_in_group`] and [`Chart::notify_group`] to look at a
+    /// single group. By default an instance is in no group.
+    #[must_use]
+    pub fn with_group(mut self, group: u16) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.group = Some(group);
+        self
+    }
+
+    /// set what happens when an instance announces an [`Id`] that is already
+    /// in the chart under another address. Defaults to
+    /// [`KeepLatest`](ConflictPolicy::KeepLatest). An
+    /// [`IdConflict`](crate::Event::IdConflict) event is emitted regardless of the policy.
+    #[must_use]
+    pub fn with_conflict_policy(
+        mut self,
+        policy: ConflictPolicy,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// set whether and when we reply directly to newly discovered instances,
+    /// defaults to [`Always`](ReplyPolicy::Always). In large clusters use
+    /// [`Probabilistic`](ReplyPolicy::Probabilistic) or
+    /// [`DelayedRandom`](ReplyPolicy::DelayedRandom) so newcomers do not get
+    /// a reply from every instance at once. Use [`Never`](ReplyPolicy::Never)
+    /// to turn replying off entirely and rely on periodic broadcasts.
+    #[must_use]
+    pub fn with_reply_policy(
+        mut self,
+        policy: ReplyPolicy,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.reply_policy = policy;
+        self
+    }
+
+    /// skip a scheduled broadcast if we replied to a newcomer within the last
+    /// half interval, the newcomer already has our msg. Reduces traffic when
+    /// many instances start at once. Two broadcasts in a row are never
+    /// skipped so charted instances keep hearing from us. Defaults to false.
+    #[must_use]
+    pub fn suppress_redundant_broadcasts(
         mut self,
         is_enabled: bool,
     ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
-        self.local = is_enabled;
+        self.rampdown.suppress = is_enabled;
+        self
+    }
+
+    /// Ramp down from the minimum interval again whenever an instance joins
+    /// or leaves. Once the interval has reached the maximum a change in the
+    /// cluster is then spread quickly, at the cost of a burst of broadcasts
+    /// from every instance. Defaults to false.
+    #[must_use]
+    pub fn restart_rampdown_on_change(
+        mut self,
+        is_enabled: bool,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.rampdown.restart_on_change = is_enabled;
+        self
+    }
+
+    /// set how discovery messages are encoded, defaults to
+    /// [`Bincode`](WireFormat::Bincode) with default [options](BincodeOptions).
+    /// All instances must use the same format.
+    #[must_use]
+    pub fn with_wire_format(
+        mut self,
+        format: WireFormat,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.wire_format = format;
+        self
+    }
+
+    /// use the bincode [`WireFormat`] with custom options, for example to
+    /// match an existing deployment or to shrink messages using varint encoding.
+    ///
+    /// # Note
+    /// Building fails with [`Error::MessageTooLarge`] if the message to share
+    /// does not fit within [`limit`](BincodeOptions::limit). Once running,
+    /// setters that would grow it past the limit return an error instead.
+    #[must_use]
+    pub fn with_bincode_options(
+        mut self,
+        options: BincodeOptions,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.wire_format = WireFormat::Bincode(options);
+        self
+    }
+
+    /// pre-allocate room for `capacity` instances. For large clusters this
+    /// prevents the chart from growing (and rehashing) while discovery is
+    /// under way.
+    #[must_use]
+    pub fn with_expected_capacity(
+        mut self,
+        capacity: usize,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.expected_capacity = capacity;
+        self
+    }
+
+    /// request a receive buffer of `bytes` for the discovery sockets. A larger
+    /// buffer prevents announcements from being dropped when many instances
+    /// (re)start at once. The OS may grant a different size, on Linux it is
+    /// doubled and capped by `net.core.rmem_max`. Check what was granted using
+    /// [`Chart::recv_buffer_size`].
+    #[must_use]
+    pub fn with_recv_buffer_size(
+        mut self,
+        bytes: usize,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.recv_buffer = Some(bytes);
+        self
+    }
+
+    /// request a send buffer of `bytes` for the discovery sockets. The OS may
+    /// grant a different size, check it using [`Chart::send_buffer_size`].
+    #[must_use]
+    pub fn with_send_buffer_size(
+        mut self,
+        bytes: usize,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.send_buffer = Some(bytes);
+        self
+    }
+
+    /// forget instances not heard from for `factor` times the interval in
+    /// between broadcasts they advertise. Instances advertise the maximum
+    /// interval of their [rampdown](Self::with_rampdown) so this works even
+    /// if instances use different settings. By default instances are never
+    /// forgotten unless they say goodbye.
+    ///
+    /// An [`Event::Expired`](crate::Event::Expired) is sent for every forgotten
+    /// instance.
+    #[must_use]
+    pub fn with_expiry(mut self, factor: u32) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.expiry = Some(factor);
+        self
+    }
+
+    /// Ask the other instances to forget this one once they have not heard
+    /// from it for `ttl`, overriding their [expiry](Self::with_expiry).
+    /// Instances that will not live long, for example a batch job, can use
+    /// this to disappear promptly even if their goodbye gets lost. Receivers
+    /// honor it even without expiry enabled, they check for expired
+    /// instances every half of their maximum broadcast interval.
+    ///
+    /// # Note
+    /// Pick `ttl` a few times longer then the [maximum broadcast
+    /// interval](Self::with_rampdown) or this instance keeps being forgotten
+    /// and rediscovered.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Advertise how much this instance wants to coordinate, for example to
+    /// pick a leader or the node running a singleton job. Every instance
+    /// picks the same one using [`Chart::highest_priority_peer`]: the
+    /// highest priority wins, ties go to the lowest [`Id`]. Change it later
+    /// using [`Chart::set_priority`]. Defaults to `0`.
+    #[must_use]
+    pub fn with_priority(mut self, priority: u32) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.priority = priority;
+        self
+    }
+
+    /// smooth over instances that flap between expiring and being
+    /// discovered again on a noisy network. An instance is only
+    /// [expired](Self::with_expiry) once it has been silent for an extra
+    /// `leave`. Once expired it is only charted again, and
+    /// [notified](Chart::notify) about, after it has been heard from
+    /// steadily for `rejoin`. Hearing nothing for longer then twice the
+    /// interval it advertises restarts that wait. Instances that expired
+    /// more then ten times `rejoin` ago are charted right away.
+    ///
+    /// Has no effect without [`with_expiry`](Self::with_expiry). Instances
+    /// that [say goodbye](crate::Status::Leaving) are not held back.
+    #[must_use]
+    pub fn with_debounce(
+        mut self,
+        leave: Duration,
+        rejoin: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.debounce = Some((leave, rejoin));
+        self
+    }
+
+    /// smooth the [size trend](Chart::size_trend) over `window`. After
+    /// `window` the smoothed size moved about two thirds of the way to a new
+    /// size. Defaults to ten seconds, zero disables smoothing.
+    #[must_use]
+    pub fn with_size_window(
+        mut self,
+        window: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.size_window = window;
+        self
+    }
+
+    /// remove an instance from the chart once the application
+    /// [reported](Chart::report_unreachable) it unreachable `threshold`
+    /// times without reporting it [reachable](Chart::report_reachable) in
+    /// between. Its messages are then ignored for `ignore_for`, otherwise it
+    /// is charted again with its next broadcast. By default an instance is
+    /// removed after three reports and not ignored afterwards.
+    #[must_use]
+    pub fn with_unreachable_threshold(
+        mut self,
+        threshold: u32,
+        ignore_for: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.unreachable = (threshold, ignore_for);
+        self
+    }
+
+    /// ignore a host for `duration` once it sent `threshold` malformed
+    /// messages within `duration`. Keeps a broken neighbor from dominating
+    /// the receive loop and the logs. An
+    /// [`Event::Quarantined`](crate::Event::Quarantined) is sent when a host
+    /// is quarantined. By default no host is ever ignored.
+    #[must_use]
+    pub fn with_quarantine(
+        mut self,
+        threshold: u32,
+        duration: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.quarantine = Some((threshold, duration));
+        self
+    }
+
+    /// process at most `packets` discovery datagrams every `per`, summed
+    /// over all discovery sockets. Datagrams over budget are left for the
+    /// next window, those that do not fit in the socket's
+    /// [receive buffer](Self::with_recv_buffer_size) in the mean time are
+    /// dropped by the OS. Protects the application's runtime from a
+    /// misbehaving peer or a multicast storm on the discovery port. Deferred
+    /// datagrams are counted in [`Counters::deferred`](crate::Counters::deferred).
+    /// By default every datagram is processed right away.
+    ///
+    /// Set the budget well above what the cluster normally sends, a chart
+    /// that falls behind learns of new instances late.
+    #[must_use]
+    pub fn with_packet_budget(
+        mut self,
+        packets: usize,
+        per: Duration,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.budget = Some((packets, per));
+        self
+    }
+
+    /// Only chart instances that prove they know `key`. On first sighting an
+    /// instance is sent a challenge, it is charted and
+    /// [notified](Chart::notify) about once it answers correctly. This costs
+    /// a round trip but prevents hosts that do not know the key from
+    /// joining the chart or taking over the [`Id`] of an instance. The answer
+    /// is bound to the address the instance announced itself from, a host
+    /// without the key can not relay it from another instance.
+    ///
+    /// All instances must use the same key and have this enabled.
+    ///
+    /// Only available with the `challenge` feature.
+    #[cfg(feature = "challenge")]
+    #[must_use]
+    pub fn with_challenge(mut self, key: [u8; 32]) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.challenge_key = Some(key);
+        self
+    }
+
+    /// Call `tap` with every discovery datagram sent or received, for
+    /// example to log the traffic when tcpdump is not available. Received
+    /// datagrams are passed on before they are parsed, including malformed
+    /// ones.
+    ///
+    /// Only available with the `packet-tap` feature.
+    ///
+    /// # Note
+    /// `tap` is called from the discovery hot path, it should return
+    /// quickly.
+    #[cfg(feature = "packet-tap")]
+    #[must_use]
+    pub fn with_packet_tap(
+        mut self,
+        tap: impl Fn(Direction, &[u8], SocketAddr) + Send + Sync + 'static,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.tap = Some(Tap(Arc::new(tap)));
+        self
+    }
+
+    /// Simulate a bad network for testing: discovery datagrams are dropped
+    /// with probability `loss_rate`, clamped to the range 0..=1, both when
+    /// sending and when receiving. Sending a datagram and handling received
+    /// ones each take a random time within `delay`. This exercises the real
+    /// code path, unlike dropping packets in a test double.
+    ///
+    /// Only available with the `fault-injection` feature, do not enable it
+    /// outside tests.
+    ///
+    /// # Note
+    /// Between two instances with fault injection a datagram can be lost and
+    /// delayed on both ends. The delay holds up whatever is sending or
+    /// receiving, datagrams are not reordered. Datagrams received together
+    /// share one delay.
+    #[cfg(feature = "fault-injection")]
+    #[must_use]
+    pub fn with_fault_injection(
+        mut self,
+        loss_rate: f32,
+        delay: std::ops::Range<Duration>,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.faults = Some(Arc::new(Faults::new(loss_rate, delay)));
+        self
+    }
+
+    /// forward discovery metrics to `metrics`, see [`ChartMetrics`]. By
+    /// default they are only available through [`Chart::counters`].
+    #[must_use]
+    pub fn with_metrics(
+        mut self,
+        metrics: impl ChartMetrics + 'static,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.metrics = Metrics(Arc::new(metrics));
+        self
+    }
+
+    /// drive the broadcast schedule from `clock` instead of the tokio timer,
+    /// see [`Clock`].
+    #[must_use]
+    pub fn with_clock(
+        mut self,
+        clock: impl Clock + 'static,
+    ) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.rampdown.clock = SharedClock(Arc::new(clock));
+        self
+    }
+
+    /// how many times [`Chart::close`] sends a goodbye, defaults to 3. More
+    /// make it less likely peers miss the goodbye on lossy networks.
+    #[must_use]
+    pub fn with_goodbye_count(mut self, count: u8) -> ChartBuilder<N, IdSet, PortSet, PortsSet> {
+        self.goodbyes = count;
         self
     }
 }
 
+impl<const N: usize> ChartBuilder<N, Yes, No, No> {
+    /// build a chart sharing how this instance can be reached instead of only
+    /// service ports. For example a [`SocketAddr`], a URL or your own type,
+    /// see [`Endpoint`].
+    ///
+    /// # Errors
+    /// This errors if the discovery port could not be opened. see: [`Self::with_discovery_port`].
+    ///
+    /// # Example
+    /// ```rust
+    ///use std::error::Error;
+    ///use instance_chart::{discovery, ChartBuilder};
+    ///
+    ///#[tokio::main]
+    ///async fn main() -> Result<(), Box<dyn Error>> {
+    ///   let chart = ChartBuilder::new()
+    ///       .with_id(1)
+    ///       .with_discovery_port(43812)
+    ///       .service_endpoints([String::from("https://example.org/api")])?;
+    ///   let maintain = discovery::maintain(chart.clone());
+    ///   let _ = tokio::spawn(maintain); // maintain task will run forever
+    ///   let urls = chart.addr_vec();
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn service_endpoints<E: Endpoint + 'static>(
+        self,
+        endpoints: [E; N],
+    ) -> Result<Chart<N, E>, Error> {
+        self.build(endpoints, None)
+    }
+}
+
 impl ChartBuilder<1, Yes, No, No> {
     /// build a chart with a custom msg instead of a service port. The message can
     /// be any struct that implements `Debug`, `Clone`, `serde::Serialize` and `serde::Deserialize`
@@ -259,18 +1282,9 @@ impl ChartBuilder<1, Yes, No, No> {
     #[allow(clippy::missing_panics_doc)] // with generic IdSet and PortSet set service_id must be set
     pub fn custom_msg<Msg>(self, msg: Msg) -> Result<Chart<1, Msg>, Error>
     where
-        Msg: Debug + Serialize + Clone,
+        Msg: 'static + Debug + Serialize + Clone,
     {
-        let sock = open_socket(self.discovery_port, self.local)?;
-        Ok(Chart {
-            header: self.header,
-            service_id: self.service_id.unwrap(),
-            msg: [msg],
-            sock: Arc::new(sock),
-            map: Arc::new(Mutex::new(HashMap::new())),
-            interval: self.rampdown.into(),
-            broadcast: broadcast::channel(256).0,
-        })
+        self.build([msg], None)
     }
 }
 
@@ -279,6 +1293,7 @@ impl ChartBuilder<1, Yes, Yes, No> {
     ///
     /// # Errors
     /// This errors if the discovery port could not be opened. see: [`Self::with_discovery_port`].
+    /// Or if the service port is also used for discovery.
     ///
     /// # Example
     /// ```rust
@@ -303,20 +1318,34 @@ impl ChartBuilder<1, Yes, Yes, No> {
     ///   Ok(())
     /// }
     /// ```
-
     // with generic IdSet, PortSet set service_id and service_port are always Some
     #[allow(clippy::missing_panics_doc)]
     pub fn finish(self) -> Result<Chart<1, Port>, Error> {
-        let sock = open_socket(self.discovery_port, self.local)?;
-        Ok(Chart {
-            header: self.header,
-            service_id: self.service_id.unwrap(),
-            msg: [self.service_port.unwrap()],
-            sock: Arc::new(sock),
-            map: Arc::new(Mutex::new(HashMap::new())),
-            interval: self.rampdown.into(),
-            broadcast: broadcast::channel(256).0,
-        })
+        let port = self.service_port.unwrap();
+        self.check_ports(&[port])?;
+        self.build_ports([port])
+    }
+
+    /// Like [`finish`](Self::finish) but retries up to `attempts` times if
+    /// building fails with a [recoverable](Error::is_recoverable) error. For
+    /// example when the previous process has not yet released the discovery
+    /// port or the network is not up yet. Waits `backoff` after the first
+    /// failure, doubling it after every next.
+    ///
+    /// # Errors
+    /// Returns the last error if all attempts failed or the first error
+    /// that is not recoverable. If another instance on this host holds the
+    /// discovery port that is not recoverable, see
+    /// [`Error::AlreadyRunningLocally`].
+    #[allow(clippy::missing_panics_doc)]
+    pub async fn finish_with_retry(
+        self,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Chart<1, Port>, Error> {
+        let port = self.service_port.unwrap();
+        self.check_ports(&[port])?;
+        self.build_with_retry([port], attempts, backoff).await
     }
 }
 
@@ -325,6 +1354,7 @@ impl<const N: usize> ChartBuilder<N, Yes, No, Yes> {
     ///
     /// # Errors
     /// This errors if the discovery port could not be opened. see: [`Self::with_discovery_port`].
+    /// Or if a service port is also used for discovery or set multiple times.
     ///
     /// # Example
     /// ```rust
@@ -349,49 +1379,306 @@ impl<const N: usize> ChartBuilder<N, Yes, No, Yes> {
     ///   Ok(())
     /// }
     /// ```
-
     // with generic IdSet, PortSets set service_id and service_ports are always Some
     #[allow(clippy::missing_panics_doc)]
     pub fn finish(self) -> Result<Chart<N, Port>, Error> {
-        let sock = open_socket(self.discovery_port, self.local)?;
-        Ok(Chart {
-            header: self.header,
-            service_id: self.service_id.unwrap(),
-            msg: self.service_ports,
-            sock: Arc::new(sock),
-            map: Arc::new(Mutex::new(HashMap::new())),
-            interval: self.rampdown.into(),
-            broadcast: broadcast::channel(256).0,
+        let ports = self.service_ports;
+        self.check_ports(&ports)?;
+        self.build_ports(ports)
+    }
+
+    /// Like [`finish`](Self::finish) but retries up to `attempts` times if
+    /// building fails with a [recoverable](Error::is_recoverable) error. For
+    /// example when the previous process has not yet released the discovery
+    /// port or the network is not up yet. Waits `backoff` after the first
+    /// failure, doubling it after every next.
+    ///
+    /// # Errors
+    /// Returns the last error if all attempts failed or the first error
+    /// that is not recoverable. If another instance on this host holds the
+    /// discovery port that is not recoverable, see
+    /// [`Error::AlreadyRunningLocally`].
+    pub async fn finish_with_retry(
+        self,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Chart<N, Port>, Error> {
+        let ports = self.service_ports;
+        self.check_ports(&ports)?;
+        self.build_with_retry(ports, attempts, backoff).await
+    }
+}
+
+/// open one socket per distinct port joining all groups for that port
+fn open_endpoints(
+    endpoints: &[(IpAddr, u16)],
+    local_discovery: bool,
+    multicast_loop: bool,
+    recv_buffer: Option<usize>,
+    send_buffer: Option<usize>,
+) -> Result<Vec<Socket>, Error> {
+    // one socket per port and address family
+    let mut groups_by_port: Vec<(u16, Vec<IpAddr>)> = Vec::new();
+    for (group, port) in endpoints {
+        let same_socket = |(p, groups): &&mut (u16, Vec<IpAddr>)| {
+            p == port && groups[0].is_ipv6() == group.is_ipv6()
+        };
+        match groups_by_port.iter_mut().find(same_socket) {
+            Some((_, groups)) if groups.contains(group) => (),
+            Some((_, groups)) => groups.push(*group),
+            None => groups_by_port.push((*port, vec![*group])),
+        }
+    }
+
+    groups_by_port
+        .into_iter()
+        .map(|(port, groups)| {
+            let sock = open_socket(
+                port,
+                &groups,
+                local_discovery,
+                multicast_loop,
+                recv_buffer,
+                send_buffer,
+            )?;
+            Ok(Socket::new(Arc::new(sock), groups))
         })
+        .collect()
+}
+
+/// the endpoint for a socket passed to [`ChartBuilder::with_bound_socket`]
+fn bound_endpoint(sock: Arc<UdpSocket>) -> Result<Socket, Error> {
+    let group = match sock.local_addr().map_err(Error::Construct)? {
+        SocketAddr::V4(_) => IpAddr::V4(DEFAULT_GROUP),
+        SocketAddr::V6(_) => IpAddr::V6(DEFAULT_GROUP_V6),
+    };
+    Ok(Socket::new(sock, vec![group]))
+}
+
+/// set up a socket bound by someone else, for example systemd, like
+/// [`open_socket`] would so it meets the requirements of
+/// [`ChartBuilder::with_bound_socket`]
+#[cfg(all(unix, feature = "systemd"))]
+pub(crate) fn prepare_bound(sock: socket2::Socket) -> io::Result<UdpSocket> {
+    match sock.local_addr()?.as_socket() {
+        Some(SocketAddr::V4(_)) => {
+            sock.set_broadcast(true)?;
+            sock.set_multicast_loop_v4(true)?;
+            sock.set_ttl(4)?;
+            sock.join_multicast_v4(&DEFAULT_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        }
+        Some(SocketAddr::V6(_)) => {
+            sock.set_multicast_loop_v6(true)?;
+            sock.set_multicast_hops_v6(4)?;
+            sock.join_multicast_v6(&DEFAULT_GROUP_V6, 0)?;
+        }
+        None => return Err(io::ErrorKind::InvalidInput.into()),
     }
+    sock.set_nonblocking(true)?;
+    UdpSocket::from_std(sock.into())
 }
 
-fn open_socket(port: u16, local_discovery: bool) -> Result<UdpSocket, Error> {
+fn bind_error(error: io::Error, port: u16) -> Error {
+    if error.kind() == io::ErrorKind::PermissionDenied && port < 1024 {
+        return Error::PrivilegedPort { error, port };
+    }
+    Error::Bind {
+        diagnosis: diagnose_bind(&error, port),
+        error,
+        port,
+    }
+}
+
+fn diagnose_bind(error: &io::Error, port: u16) -> BindDiagnosis {
+    use socket2::{Domain, Socket, Type};
+
+    match error.kind() {
+        io::ErrorKind::AddrInUse => {
+            // succeeds only if all sockets on the port allow sharing it
+            let shared = Socket::new(Domain::IPV4, Type::DGRAM, None)
+                .and_then(|probe| {
+                    probe.set_reuse_port(true)?;
+                    probe.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())
+                })
+                .is_ok();
+            if shared {
+                BindDiagnosis::InUseShared
+            } else {
+                BindDiagnosis::InUseExclusively
+            }
+        }
+        io::ErrorKind::PermissionDenied => BindDiagnosis::PermissionDenied,
+        io::ErrorKind::AddrNotAvailable | io::ErrorKind::Unsupported => {
+            BindDiagnosis::AddressUnavailable
+        }
+        _ => BindDiagnosis::Unknown,
+    }
+}
+
+/// the discovery port if building failed because something on this host holds
+/// it without sharing it
+fn held_exclusively<T>(res: &Result<T, Error>) -> Option<u16> {
+    match res {
+        Err(Error::Bind {
+            port,
+            diagnosis: BindDiagnosis::InUseExclusively,
+            ..
+        }) => Some(*port),
+        _ => None,
+    }
+}
+
+/// the first of the `candidates` that is a [`usable_port`], blocks for up to
+/// 100ms for every port tried
+fn sweep_ports(
+    candidates: Vec<u16>,
+    header: u64,
+    wire_format: WireFormat,
+    shared: bool,
+) -> Option<u16> {
+    candidates
+        .into_iter()
+        .find(|port| usable_port(header, wire_format, *port, shared))
+}
+
+/// the port a sweep of `range` found or [`Error::NoUsablePort`]
+fn port_found(port: Option<u16>, range: &RangeInclusive<u16>) -> Result<u16, Error> {
+    let (start, end) = (*range.start(), *range.end());
+    port.inspect(|port| info!("using discovery port {port}"))
+        .ok_or(Error::NoUsablePort { start, end })
+}
+
+/// Whether `port` can be used for discovery: multicast sent to it arrives
+/// back and it is unused on this host. With `shared` it may also be in use
+/// by a [maintained](crate::discovery::maintain) chart instance with our
+/// `header` that allows sharing the port.
+fn usable_port(header: u64, wire_format: WireFormat, port: u16, shared: bool) -> bool {
+    use std::time::Instant;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    let probe = probe::encode(header);
+    let check = || -> io::Result<bool> {
+        let (listener, in_use) = match bind_multicast(port, false) {
+            Ok(sock) => (sock, false),
+            Err(_) if shared => (bind_multicast(port, true)?, true),
+            Err(err) => return Err(err),
+        };
+        // a chart instance on the port answers the probe to the sender
+        let sender = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        sender.send_to(&probe, (DEFAULT_GROUP, port))?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mut buf = [0u8; super::MAX_DATAGRAM];
+        let passes = recv_until(&listener, &mut buf, deadline, |msg| msg == probe)?;
+        if !passes || !in_use {
+            return Ok(passes);
+        }
+        recv_until(&sender, &mut buf, deadline, |msg| {
+            wire_format
+                .sender(msg)
+                .is_some_and(|(sender_header, _)| sender_header == header)
+        })
+    };
+    check().unwrap_or(false)
+}
+
+/// a socket on `port` in the default group, only binds if nothing else uses
+/// the port unless `shared`
+fn bind_multicast(port: u16, shared: bool) -> io::Result<std::net::UdpSocket> {
+    use socket2::{Domain, Socket, Type};
+
+    let sock = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
+    sock.set_reuse_port(shared)?;
+    sock.set_multicast_loop_v4(true)?;
+    sock.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    sock.join_multicast_v4(&DEFAULT_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    Ok(sock.into())
+}
+
+/// whether a datagram for which `wanted` is true arrives on `sock` before
+/// the `deadline`
+fn recv_until(
+    sock: &std::net::UdpSocket,
+    buf: &mut [u8],
+    deadline: std::time::Instant,
+    wanted: impl Fn(&[u8]) -> bool,
+) -> io::Result<bool> {
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            return Ok(false);
+        }
+        sock.set_read_timeout(Some(left))?;
+        match sock.recv(buf) {
+            Ok(len) if wanted(&buf[..len]) => return Ok(true),
+            Ok(_) => continue,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// all `groups` must be of the same address family
+fn open_socket(
+    port: u16,
+    groups: &[IpAddr],
+    local_discovery: bool,
+    multicast_loop: bool,
+    recv_buffer: Option<usize>,
+    send_buffer: Option<usize>,
+) -> Result<UdpSocket, Error> {
     use socket2::{Domain, SockAddr, Socket, Type};
     use Error::{
-        Bind, Construct, JoinMulticast, SetBroadcast, SetMulticast, SetNonBlocking, SetReuse,
-        SetTTL, ToTokio,
+        Construct, JoinMulticast, SetBroadcast, SetBufferSize, SetMulticast, SetNonBlocking,
+        SetReuse, SetTTL, ToTokio,
     };
 
     assert_ne!(port, 0);
 
+    let ipv6 = groups.first().is_some_and(IpAddr::is_ipv6);
     let interface = Ipv4Addr::from([0, 0, 0, 0]);
-    let multiaddr = Ipv4Addr::from([224, 0, 0, 251]);
+    let domain = if ipv6 { Domain::IPV6 } else { Domain::IPV4 };
 
-    let sock = Socket::new(Domain::IPV4, Type::DGRAM, None).map_err(Construct)?;
+    let sock = Socket::new(domain, Type::DGRAM, None).map_err(Construct)?;
 
     if local_discovery {
         sock.set_reuse_port(true).map_err(SetReuse)?; // allow binding to a port already in use
     }
-    sock.set_broadcast(true).map_err(SetBroadcast)?; // enable udp broadcasting
-    sock.set_multicast_loop_v4(true).map_err(SetMulticast)?; // send broadcast to self
-    sock.set_ttl(4).map_err(SetTTL)?; // deliver to other subnetworks
+    if ipv6 {
+        sock.set_only_v6(true).map_err(SetMulticast)?; // do not clash with the IPv4 socket
+        sock.set_multicast_loop_v6(multicast_loop)
+            .map_err(SetMulticast)?; // send broadcast to self
+        sock.set_multicast_hops_v6(4).map_err(SetTTL)?; // deliver to other subnetworks
+    } else {
+        sock.set_broadcast(true).map_err(SetBroadcast)?; // enable udp broadcasting
+        sock.set_multicast_loop_v4(multicast_loop)
+            .map_err(SetMulticast)?; // send broadcast to self
+        sock.set_ttl(4).map_err(SetTTL)?; // deliver to other subnetworks
+    }
+    if let Some(bytes) = recv_buffer {
+        sock.set_recv_buffer_size(bytes).map_err(SetBufferSize)?;
+    }
+    if let Some(bytes) = send_buffer {
+        sock.set_send_buffer_size(bytes).map_err(SetBufferSize)?;
+    }
 
-    let address = SocketAddr::from((interface, port));
+    let address = if ipv6 {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))
+    } else {
+        SocketAddr::from((interface, port))
+    };
     let address = SockAddr::from(address);
-    sock.bind(&address).map_err(|error| Bind { error, port })?;
-    sock.join_multicast_v4(&multiaddr, &interface)
+    sock.bind(&address)
+        .map_err(|error| bind_error(error, port))?;
+    for group in groups {
+        match group {
+            IpAddr::V4(group) => sock.join_multicast_v4(group, &interface),
+            IpAddr::V6(group) => sock.join_multicast_v6(group, 0),
+        }
         .map_err(JoinMulticast)?;
+    }
 
     let sock = std::net::UdpSocket::from(sock);
     sock.set_nonblocking(true).map_err(SetNonBlocking)?;
@@ -399,6 +1686,294 @@ fn open_socket(port: u16, local_discovery: bool) -> Result<UdpSocket, Error> {
     Ok(sock)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_port_is_discovery_port() {
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(DEFAULT_DISCOVERY_PORT)
+            .finish();
+        assert!(matches!(
+            res,
+            Err(Error::ServicePortIsDiscoveryPort(DEFAULT_DISCOVERY_PORT))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn id_from_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let id = |seed| {
+            ChartBuilder::<1, _, _, _>::new()
+                .with_id_rng(StdRng::seed_from_u64(seed))
+                .service_id
+        };
+        assert_eq!(id(1), id(1));
+        assert_ne!(id(1), id(2));
+    }
+
+    fn reserved_port(shared: bool) -> (socket2::Socket, u16) {
+        use socket2::{Domain, Socket, Type};
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+        sock.set_reuse_port(shared).unwrap();
+        sock.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())
+            .unwrap();
+        let port = sock.local_addr().unwrap().as_socket().unwrap().port();
+        (sock, port)
+    }
+
+    #[test]
+    fn diagnose_port_in_use() {
+        let (_sock, port) = reserved_port(false);
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_port(port)
+            .finish();
+        let Err(Error::Bind { diagnosis, .. }) = res else {
+            panic!("should fail to bind");
+        };
+        assert_eq!(diagnosis, BindDiagnosis::InUseExclusively);
+    }
+
+    #[test]
+    fn diagnose_port_shared() {
+        let (_sock, port) = reserved_port(true);
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_port(port)
+            .finish();
+        let Err(Error::Bind { diagnosis, .. }) = res else {
+            panic!("should fail to bind");
+        };
+        assert_eq!(diagnosis, BindDiagnosis::InUseShared);
+    }
+
+    #[test]
+    fn privileged_port_denied() {
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            bind_error(denied(), 80),
+            Error::PrivilegedPort { port: 80, .. }
+        ));
+        assert!(matches!(
+            bind_error(denied(), 8080),
+            Error::Bind {
+                diagnosis: BindDiagnosis::PermissionDenied,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn sweep_discovery_ports() {
+        let _taken = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 43821)).unwrap();
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(43822)
+            .with_discovery_port_in_range(43821..=43830)
+            .finish()
+            .unwrap();
+        assert_eq!(chart.discovery_port(), 43823);
+
+        let res = ChartBuilder::new()
+            .with_id(1)
+            .with_service_port(8042)
+            .with_discovery_port_in_range(43821..=43821)
+            .finish();
+        assert!(matches!(
+            res,
+            Err(Error::NoUsablePort {
+                start: 43821,
+                end: 43821
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn local_instances_sweep_to_the_same_port() {
+        let builder = |id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042)
+                .local_discovery(true)
+                .with_discovery_port_in_range(43831..=43840)
+        };
+        let first = builder(1)
+            .finish_with_retry(1, Duration::ZERO)
+            .await
+            .unwrap();
+        tokio::spawn(crate::discovery::maintain(first.clone()));
+        let second = builder(2)
+            .finish_with_retry(1, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(first.discovery_port(), second.discovery_port());
+    }
+
+    #[tokio::test]
+    async fn retry_until_port_released() {
+        let (sock, port) = reserved_port(false);
+        let builder = || {
+            ChartBuilder::new()
+                .with_id(0)
+                .with_service_port(8042)
+                .with_discovery_port(port)
+        };
+        let res = builder()
+            .finish_with_retry(2, Duration::from_millis(10))
+            .await;
+        assert!(res.unwrap_err().is_recoverable());
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(sock);
+        });
+        builder()
+            .finish_with_retry(10, Duration::from_millis(20))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn detect_instance_running_locally() {
+        let builder = |id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042)
+                .with_discovery_port(43844)
+        };
+        let running = builder(1).finish().unwrap();
+        let mut events = running.events();
+        tokio::spawn(crate::discovery::maintain(running.clone()));
+
+        // the sync finish does not block on probing
+        assert!(matches!(builder(2).finish(), Err(Error::Bind { .. })));
+        let res = builder(2)
+            .finish_with_retry(5, Duration::from_secs(1))
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::AlreadyRunningLocally { port: 43844, id: 1 })
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(running.size(), 1, "the probe should not be charted");
+        assert!(
+            events.try_recv().is_err(),
+            "the probe should not cause events"
+        );
+    }
+
+    #[test]
+    fn invalid_rampdown() {
+        let res = ChartBuilder::<1, _, _, _>::new().try_with_rampdown(
+            Duration::from_secs(2),
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+        );
+        assert!(matches!(res, Err(Error::InvalidRampdown { .. })));
+
+        let res = ChartBuilder::<1, _, _, _>::new().try_with_rampdown(
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert!(matches!(res, Err(Error::InvalidRampdown { .. })));
+    }
+
+    #[test]
+    fn invalid_config() {
+        let mut config = ChartConfig::default();
+        config.min_interval = config.max_interval * 2;
+        let res = ChartBuilder::<1, _, _, _>::new().with_config(&config);
+        assert!(matches!(res, Err(Error::InvalidRampdown { .. })));
+    }
+
+    #[test]
+    fn duplicate_service_ports() {
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_ports([1, 2, 1])
+            .finish();
+        assert!(matches!(res, Err(Error::DuplicateServicePort(1))));
+    }
+
+    #[tokio::test]
+    async fn message_over_bincode_limit() {
+        let options = BincodeOptions {
+            limit: Some(16),
+            ..BincodeOptions::default()
+        };
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .local_discovery(true)
+            .with_bincode_options(options)
+            .finish();
+        assert!(matches!(res, Err(Error::MessageTooLarge { limit: 16 })));
+    }
+
+    #[tokio::test]
+    async fn placeholder_ports_may_repeat() {
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .with_service_ports([0, 0])
+            .with_service_port_zero_autoassign()
+            .local_discovery(true)
+            .finish()
+            .unwrap();
+        assert!(chart.awaiting_ports());
+    }
+
+    #[test]
+    fn loopback_needed_for_local_discovery() {
+        let res = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .local_discovery(true)
+            .with_multicast_loop(false)
+            .finish();
+        assert!(matches!(res, Err(Error::LoopbackRequired)));
+    }
+
+    #[tokio::test]
+    async fn loopback_disabled() {
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_port(43810)
+            .with_multicast_loop(false)
+            .finish()
+            .unwrap();
+        let sock = chart.endpoints[0].sock.sock_ref();
+        assert!(!sock.multicast_loop_v4().unwrap());
+        assert!(matches!(
+            chart.self_test().await,
+            Err(crate::SelfTestError::LoopbackDisabled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn buffer_sizes() {
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_port(43819)
+            .with_recv_buffer_size(32 * 1024)
+            .with_send_buffer_size(16 * 1024)
+            .finish()
+            .unwrap();
+        assert!(chart.recv_buffer_size() >= 32 * 1024);
+        assert!(chart.send_buffer_size() >= 16 * 1024);
+    }
+}
+
 #[cfg(test)]
 mod compiles {
     use super::*;
@@ -434,4 +2009,15 @@ mod compiles {
             .unwrap();
         let _ = chart.our_msg();
     }
+
+    #[tokio::test]
+    async fn service_endpoints() {
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .local_discovery(true)
+            .service_endpoints([SocketAddr::from(([10, 0, 0, 1], 80))])
+            .unwrap();
+        let _ = chart.our_endpoints();
+        let _ = chart.get_addr(1);
+    }
 }
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use super::{
+    DEFAULT_DISCOVERY_PORT, DEFAULT_HEADER, DEFAULT_MAX_INTERVAL, DEFAULT_MIN_INTERVAL,
+    DEFAULT_RAMPDOWN,
+};
+
+/// The most commonly changed discovery settings as plain values, for
+/// example to share them between an application and its tests or tooling.
+/// Start from one of the profiles, adjust the fields and apply them using
+/// [`ChartBuilder::with_config`](crate::ChartBuilder::with_config).
+///
+/// # Examples
+/// ```rust
+/// # use std::error::Error;
+/// # use instance_chart::{ChartBuilder, ChartConfig};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let mut config = ChartConfig::default_localhost_testing();
+/// config.discovery_port = 43851;
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+///     .with_config(&config)?
+///     .finish()?;
+/// assert_eq!(chart.discovery_port(), 43851);
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChartConfig {
+    /// see [`ChartBuilder::with_discovery_port`](crate::ChartBuilder::with_discovery_port)
+    pub discovery_port: u16,
+    /// see [`ChartBuilder::with_header`](crate::ChartBuilder::with_header)
+    pub header: u64,
+    /// the broadcast interval to start at, see
+    /// [`ChartBuilder::with_rampdown`](crate::ChartBuilder::with_rampdown)
+    pub min_interval: Duration,
+    /// the broadcast interval to settle at
+    pub max_interval: Duration,
+    /// how long it takes to get from the minimum to the maximum interval
+    pub rampdown: Duration,
+    /// see [`ChartBuilder::local_discovery`](crate::ChartBuilder::local_discovery)
+    pub local_discovery: bool,
+}
+
+impl Default for ChartConfig {
+    fn default() -> Self {
+        Self::default_lan()
+    }
+}
+
+impl ChartConfig {
+    /// The defaults of [`ChartBuilder`](crate::ChartBuilder), for instances
+    /// spread over a local network
+    #[must_use]
+    pub fn default_lan() -> Self {
+        Self {
+            discovery_port: DEFAULT_DISCOVERY_PORT,
+            header: DEFAULT_HEADER,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            rampdown: DEFAULT_RAMPDOWN,
+            local_discovery: false,
+        }
+    }
+
+    /// For tests running every instance on this host: local discovery is
+    /// enabled and broadcasting ramps down ten times faster. Give every
+    /// test its own `discovery_port` so parallel tests do not find each
+    /// other.
+    #[must_use]
+    pub fn default_localhost_testing() -> Self {
+        Self {
+            min_interval: DEFAULT_MIN_INTERVAL / 10,
+            max_interval: DEFAULT_MAX_INTERVAL / 10,
+            rampdown: DEFAULT_RAMPDOWN / 10,
+            local_discovery: true,
+            ..Self::default_lan()
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use std::fmt::Debug;
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{ChartBuilder, ChartConfig, No, Port};
+use crate::chart::{
+    BincodeOptions, Chart, ChartMetrics, Clock, ConflictPolicy, Id, LocalDiscovery, ReplyPolicy,
+    WireFormat,
+};
+use crate::Error;
+
+/// A [`ChartBuilder`] that checks whether everything needed is set when
+/// building instead of at compile time. Use this when the configuration is
+/// only known at runtime. Create one with [`ChartBuilder::dynamic`].
+///
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// use instance_chart::{discovery, ChartBuilder};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let ports: Vec<u16> = vec![8042, 8043]; // for example from a config file
+/// let chart = ChartBuilder::<2, _, _, _>::dynamic()
+///     .with_id(1)
+/// #   .with_discovery_port(43811)
+///     .with_service_ports(&ports)
+///     .finish()?;
+/// let maintain = discovery::maintain(chart.clone());
+/// let _ = tokio::spawn(maintain); // maintain task will run forever
+/// #   Ok(())
+/// # }
+/// ```
+pub struct DynamicBuilder<const N: usize> {
+    inner: ChartBuilder<N, No, No, No>,
+    id: Option<Id>,
+    /// the id was set by [`with_random_id`](Self::with_random_id)
+    random_id: bool,
+    service_ports: Option<Vec<Port>>,
+    /// checked when building so an empty list does not panic
+    discovery_endpoints: Option<Vec<(Ipv4Addr, u16)>>,
+}
+
+impl<const N: usize> ChartBuilder<N, No, No, No> {
+    /// Create a builder that checks at runtime instead of compile time
+    /// whether the [`Id`] and service ports are set. See [`DynamicBuilder`].
+    #[must_use]
+    pub fn dynamic() -> DynamicBuilder<N> {
+        DynamicBuilder {
+            inner: ChartBuilder::new(),
+            id: None,
+            random_id: false,
+            service_ports: None,
+            discovery_endpoints: None,
+        }
+    }
+}
+
+impl<const N: usize> DynamicBuilder<N> {
+    /// see [`ChartBuilder::with_id`]
+    #[must_use]
+    pub fn with_id(mut self, id: Id) -> Self {
+        self.id = Some(id);
+        self.random_id = false;
+        self
+    }
+
+    /// see [`ChartBuilder::with_random_id`]
+    #[must_use]
+    #[cfg(feature = "rand")]
+    pub fn with_random_id(mut self) -> Self {
+        let id = crate::chart::id_rng::random_id(None);
+        info!("Using random id: {id}");
+        self.id = Some(id);
+        self.random_id = true;
+        self.inner.id_rng = None;
+        self
+    }
+
+    /// see [`ChartBuilder::with_id_rng`]
+    #[must_use]
+    #[cfg(feature = "rand")]
+    pub fn with_id_rng(mut self, rng: impl rand::RngCore + Send + 'static) -> Self {
+        let rng = crate::chart::IdRng::new(rng);
+        let id = rng.next_id();
+        info!("Using random id: {id}");
+        self.id = Some(id);
+        self.random_id = true;
+        self.inner.id_rng = Some(rng);
+        self
+    }
+
+    /// see [`ChartBuilder::reroll_id_on_collision`]
+    #[must_use]
+    #[cfg(feature = "rand")]
+    pub fn reroll_id_on_collision(mut self, window: Duration) -> Self {
+        self.inner = self.inner.reroll_id_on_collision(window);
+        self
+    }
+
+    /// see [`ChartBuilder::with_service_port`]
+    #[must_use]
+    pub fn with_service_port(self, port: Port) -> Self {
+        self.with_service_ports(&[port])
+    }
+
+    /// see [`ChartBuilder::with_service_ports`]. Building fails with
+    /// [`Error::ServicePortCount`] unless exactly `N` ports are passed.
+    #[must_use]
+    pub fn with_service_ports(mut self, ports: &[Port]) -> Self {
+        self.service_ports = Some(ports.to_vec());
+        self
+    }
+
+    /// see [`ChartBuilder::with_service_port_zero_autoassign`]
+    #[must_use]
+    pub fn with_service_port_zero_autoassign(mut self) -> Self {
+        self.inner = self.inner.with_service_port_zero_autoassign();
+        self
+    }
+
+    /// see [`ChartBuilder::with_config`]
+    /// # Errors
+    /// returns [`Error::InvalidRampdown`] if the config's `min_interval` is
+    /// larger then its `max_interval` or `max_interval` is zero
+    pub fn with_config(mut self, config: &ChartConfig) -> Result<Self, Error> {
+        self.inner = self.inner.with_config(config)?;
+        Ok(self)
+    }
+
+    /// see [`ChartBuilder::with_header`]
+    #[must_use]
+    pub fn with_header(mut self, header: u64) -> Self {
+        self.inner = self.inner.with_header(header);
+        self
+    }
+
+    /// see [`ChartBuilder::with_discovery_port`]
+    #[must_use]
+    pub fn with_discovery_port(mut self, port: u16) -> Self {
+        self.inner = self.inner.with_discovery_port(port);
+        self
+    }
+
+    /// see [`ChartBuilder::with_discovery_port_in_range`]
+    #[must_use]
+    pub fn with_discovery_port_in_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.inner = self.inner.with_discovery_port_in_range(range);
+        self
+    }
+
+    /// see [`ChartBuilder::with_discovery_endpoints`], building fails with
+    /// [`Error::NoDiscoveryEndpoints`] if no endpoints are passed
+    #[must_use]
+    pub fn with_discovery_endpoints(mut self, endpoints: &[(Ipv4Addr, u16)]) -> Self {
+        self.discovery_endpoints = Some(endpoints.to_vec());
+        self
+    }
+
+    /// see [`ChartBuilder::with_bound_socket`]
+    #[must_use]
+    pub fn with_bound_socket(mut self, sock: tokio::net::UdpSocket) -> Self {
+        self.inner = self.inner.with_bound_socket(sock);
+        self
+    }
+
+    /// see [`ChartBuilder::try_with_rampdown`]
+    /// # Errors
+    /// returns [`Error::InvalidRampdown`] if min is larger then max or max
+    /// is zero
+    pub fn with_rampdown(
+        mut self,
+        min: Duration,
+        max: Duration,
+        rampdown: Duration,
+    ) -> Result<Self, Error> {
+        self.inner = self.inner.try_with_rampdown(min, max, rampdown)?;
+        Ok(self)
+    }
+
+    /// see [`ChartBuilder::local_discovery`]
+    #[must_use]
+    pub fn local_discovery(mut self, mode: impl Into<LocalDiscovery>) -> Self {
+        self.inner = self.inner.local_discovery(mode);
+        self
+    }
+
+    /// see [`ChartBuilder::observe_all_headers`]
+    #[must_use]
+    pub fn observe_all_headers(mut self, is_enabled: bool) -> Self {
+        self.inner = self.inner.observe_all_headers(is_enabled);
+        self
+    }
+
+    /// see [`ChartBuilder::with_multicast_loop`]
+    #[must_use]
+    pub fn with_multicast_loop(mut self, is_enabled: bool) -> Self {
+        self.inner = self.inner.with_multicast_loop(is_enabled);
+        self
+    }
+
+    /// see [`ChartBuilder::dual_stack`]
+    #[must_use]
+    pub fn dual_stack(mut self, is_enabled: bool) -> Self {
+        self.inner = self.inner.dual_stack(is_enabled);
+        self
+    }
+
+    /// see [`ChartBuilder::with_group`]
+    #[must_use]
+    pub fn with_group(mut self, group: u16) -> Self {
+        self.inner = self.inner.with_group(group);
+        self
+    }
+
+    /// see [`ChartBuilder::with_conflict_policy`]
+    #[must_use]
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.inner = self.inner.with_conflict_policy(policy);
+        self
+    }
+
+    /// see [`ChartBuilder::with_reply_policy`]
+    #[must_use]
+    pub fn with_reply_policy(mut self, policy: ReplyPolicy) -> Self {
+        self.inner = self.inner.with_reply_policy(policy);
+        self
+    }
+
+    /// see [`ChartBuilder::suppress_redundant_broadcasts`]
+    #[must_use]
+    pub fn suppress_redundant_broadcasts(mut self, is_enabled: bool) -> Self {
+        self.inner = self.inner.suppress_redundant_broadcasts(is_enabled);
+        self
+    }
+
+    /// see [`ChartBuilder::restart_rampdown_on_change`]
+    #[must_use]
+    pub fn restart_rampdown_on_change(mut self, is_enabled: bool) -> Self {
+        self.inner = self.inner.restart_rampdown_on_change(is_enabled);
+        self
+    }
+
+    /// see [`ChartBuilder::with_wire_format`]
+    #[must_use]
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.inner = self.inner.with_wire_format(format);
+        self
+    }
+
+    /// see [`ChartBuilder::with_bincode_options`]
+    #[must_use]
+    pub fn with_bincode_options(mut self, options: BincodeOptions) -> Self {
+        self.inner = self.inner.with_bincode_options(options);
+        self
+    }
+
+    /// see [`ChartBuilder::with_expected_capacity`]
+    #[must_use]
+    pub fn with_expected_capacity(mut self, capacity: usize) -> Self {
+        self.inner = self.inner.with_expected_capacity(capacity);
+        self
+    }
+
+    /// see [`ChartBuilder::with_recv_buffer_size`]
+    #[must_use]
+    pub fn with_recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.inner = self.inner.with_recv_buffer_size(bytes);
+        self
+    }
+
+    /// see [`ChartBuilder::with_send_buffer_size`]
+    #[must_use]
+    pub fn with_send_buffer_size(mut self, bytes: usize) -> Self {
+        self.inner = self.inner.with_send_buffer_size(bytes);
+        self
+    }
+
+    /// see [`ChartBuilder::with_expiry`]
+    #[must_use]
+    pub fn with_expiry(mut self, factor: u32) -> Self {
+        self.inner = self.inner.with_expiry(factor);
+        self
+    }
+
+    /// see [`ChartBuilder::with_ttl`]
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.inner = self.inner.with_ttl(ttl);
+        self
+    }
+
+    /// see [`ChartBuilder::with_priority`]
+    #[must_use]
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.inner = self.inner.with_priority(priority);
+        self
+    }
+
+    /// see [`ChartBuilder::with_quarantine`]
+    #[must_use]
+    pub fn with_quarantine(mut self, threshold: u32, duration: Duration) -> Self {
+        self.inner = self.inner.with_quarantine(threshold, duration);
+        self
+    }
+
+    /// see [`ChartBuilder::with_debounce`]
+    #[must_use]
+    pub fn with_debounce(mut self, leave: Duration, rejoin: Duration) -> Self {
+        self.inner = self.inner.with_debounce(leave, rejoin);
+        self
+    }
+
+    /// see [`ChartBuilder::with_size_window`]
+    #[must_use]
+    pub fn with_size_window(mut self, window: Duration) -> Self {
+        self.inner = self.inner.with_size_window(window);
+        self
+    }
+
+    /// see [`ChartBuilder::with_unreachable_threshold`]
+    #[must_use]
+    pub fn with_unreachable_threshold(mut self, threshold: u32, ignore_for: Duration) -> Self {
+        self.inner = self.inner.with_unreachable_threshold(threshold, ignore_for);
+        self
+    }
+
+    /// see [`ChartBuilder::with_packet_budget`]
+    #[must_use]
+    pub fn with_packet_budget(mut self, packets: usize, per: Duration) -> Self {
+        self.inner = self.inner.with_packet_budget(packets, per);
+        self
+    }
+
+    /// see [`ChartBuilder::with_challenge`]
+    #[cfg(feature = "challenge")]
+    #[must_use]
+    pub fn with_challenge(mut self, key: [u8; 32]) -> Self {
+        self.inner = self.inner.with_challenge(key);
+        self
+    }
+
+    /// see [`ChartBuilder::with_packet_tap`]
+    #[cfg(feature = "packet-tap")]
+    #[must_use]
+    pub fn with_packet_tap(
+        mut self,
+        tap: impl Fn(crate::Direction, &[u8], std::net::SocketAddr) + Send + Sync + 'static,
+    ) -> Self {
+        self.inner = self.inner.with_packet_tap(tap);
+        self
+    }
+
+    /// see [`ChartBuilder::with_fault_injection`]
+    #[cfg(feature = "fault-injection")]
+    #[must_use]
+    pub fn with_fault_injection(
+        mut self,
+        loss_rate: f32,
+        delay: std::ops::Range<Duration>,
+    ) -> Self {
+        self.inner = self.inner.with_fault_injection(loss_rate, delay);
+        self
+    }
+
+    /// see [`ChartBuilder::with_metrics`]
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: impl ChartMetrics + 'static) -> Self {
+        self.inner = self.inner.with_metrics(metrics);
+        self
+    }
+
+    /// see [`ChartBuilder::with_clock`]
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.inner = self.inner.with_clock(clock);
+        self
+    }
+
+    /// see [`ChartBuilder::with_goodbye_count`]
+    #[must_use]
+    pub fn with_goodbye_count(mut self, count: u8) -> Self {
+        self.inner = self.inner.with_goodbye_count(count);
+        self
+    }
+
+    /// build a chart sharing the service ports
+    ///
+    /// # Errors
+    /// Returns [`Error::IdNotSet`] or [`Error::ServicePortsNotSet`] if either
+    /// was not set, [`Error::ServicePortCount`] if not exactly `N` service
+    /// ports where set and [`Error::NoDiscoveryEndpoints`] if an empty list of
+    /// discovery endpoints was set. Otherwise fails like
+    /// [`ChartBuilder::finish`].
+    pub fn finish(self) -> Result<Chart<N, Port>, Error> {
+        let id = self.id.ok_or(Error::IdNotSet)?;
+        let inner = with_endpoints(self.inner, self.discovery_endpoints)?;
+        let ports = self.service_ports.ok_or(Error::ServicePortsNotSet)?;
+        let ports: [Port; N] =
+            ports
+                .try_into()
+                .map_err(|ports: Vec<Port>| Error::ServicePortCount {
+                    expected: N,
+                    got: ports.len(),
+                })?;
+        let mut inner = inner.with_id(id);
+        inner.random_id = self.random_id;
+        inner.with_service_ports(ports).finish()
+    }
+}
+
+impl DynamicBuilder<1> {
+    /// build a chart sharing a custom msg, see [`ChartBuilder::custom_msg`]
+    ///
+    /// # Errors
+    /// Returns [`Error::IdNotSet`] if no [`Id`] was set and
+    /// [`Error::ServicePortsWithCustomMsg`] if service ports where set.
+    /// Returns [`Error::NoDiscoveryEndpoints`] if an empty list of discovery
+    /// endpoints was set. Otherwise fails like [`ChartBuilder::custom_msg`].
+    pub fn custom_msg<Msg>(self, msg: Msg) -> Result<Chart<1, Msg>, Error>
+    where
+        Msg: 'static + Debug + Serialize + Clone,
+    {
+        let id = self.id.ok_or(Error::IdNotSet)?;
+        if self.service_ports.is_some() {
+            return Err(Error::ServicePortsWithCustomMsg);
+        }
+        let mut inner = with_endpoints(self.inner, self.discovery_endpoints)?.with_id(id);
+        inner.random_id = self.random_id;
+        inner.custom_msg(msg)
+    }
+}
+
+/// apply the discovery endpoints, checked here as
+/// [`ChartBuilder::with_discovery_endpoints`] panics on an empty list
+fn with_endpoints<const N: usize>(
+    inner: ChartBuilder<N, No, No, No>,
+    endpoints: Option<Vec<(Ipv4Addr, u16)>>,
+) -> Result<ChartBuilder<N, No, No, No>, Error> {
+    match endpoints {
+        Some(endpoints) if endpoints.is_empty() => Err(Error::NoDiscoveryEndpoints),
+        Some(endpoints) => Ok(inner.with_discovery_endpoints(&endpoints)),
+        None => Ok(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_id() {
+        let res = ChartBuilder::<1, _, _, _>::dynamic()
+            .with_service_port(8042)
+            .finish();
+        assert!(matches!(res, Err(Error::IdNotSet)));
+    }
+
+    #[test]
+    fn no_discovery_endpoints() {
+        let res = ChartBuilder::<1, _, _, _>::dynamic()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_endpoints(&[])
+            .finish();
+        assert!(matches!(res, Err(Error::NoDiscoveryEndpoints)));
+        let res = ChartBuilder::<1, _, _, _>::dynamic()
+            .with_id(0)
+            .with_discovery_endpoints(&[])
+            .custom_msg(42u32);
+        assert!(matches!(res, Err(Error::NoDiscoveryEndpoints)));
+    }
+
+    #[test]
+    fn missing_ports() {
+        let res = ChartBuilder::<1, _, _, _>::dynamic().with_id(0).finish();
+        assert!(matches!(res, Err(Error::ServicePortsNotSet)));
+    }
+
+    #[test]
+    fn wrong_port_count() {
+        let res = ChartBuilder::<3, _, _, _>::dynamic()
+            .with_id(0)
+            .with_service_ports(&[1, 2])
+            .finish();
+        assert!(matches!(
+            res,
+            Err(Error::ServicePortCount {
+                expected: 3,
+                got: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn ports_with_custom_msg() {
+        let res = ChartBuilder::<1, _, _, _>::dynamic()
+            .with_id(0)
+            .with_service_port(8042)
+            .custom_msg("hi");
+        assert!(matches!(res, Err(Error::ServicePortsWithCustomMsg)));
+    }
+
+    #[tokio::test]
+    async fn builds() {
+        let chart = ChartBuilder::<2, _, _, _>::dynamic()
+            .with_id(0)
+            .with_service_ports(&[1, 2])
+            .local_discovery(true)
+            .finish()
+            .unwrap();
+        assert_eq!(chart.our_service_ports(), [1, 2]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::{Chart, Entry, Event, Id};
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Call `callback` for every newly discovered instance. The callback runs
+    /// on a task spawned on the current tokio runtime, it stops once the chart
+    /// is [closed](Chart::close) or when the returned handle is aborted.
+    ///
+    /// # Note
+    /// Only discoveries after this is called are passed to the callback. If
+    /// the callback is slow and more then 256 discoveries queue up the
+    /// oldest are skipped.
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime
+    pub fn on_discovery(
+        &self,
+        callback: impl Fn(Id, Entry<[T; N]>) + Send + Sync + 'static,
+    ) -> JoinHandle<()> {
+        let discoveries = self.broadcast.subscribe();
+        self.spawn_callback(discoveries, move |(id, entry)| callback(id, entry))
+    }
+
+    /// Call `callback` with the [`Id`] of every instance removed from the
+    /// chart because it [left](Event::Left), [expired](Event::Expired), was
+    /// [unreachable](Event::Unreachable) or was [forgotten](Event::Forgotten).
+    /// The callback runs on a task spawned on the current tokio runtime, it
+    /// stops once the chart is [closed](Chart::close) or when the returned
+    /// handle is aborted.
+    ///
+    /// # Note
+    /// Instances removed due to an [`IdConflict`](Event::IdConflict) are not
+    /// passed to the callback.
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime
+    pub fn on_removal(&self, callback: impl Fn(Id) + Send + Sync + 'static) -> JoinHandle<()> {
+        let events = self.events.subscribe();
+        self.spawn_callback(events, move |event| match event {
+            Event::Left { id }
+            | Event::Expired { id }
+            | Event::Forgotten { id }
+            | Event::Unreachable { id, .. } => callback(id),
+            _ => (),
+        })
+    }
+
+    fn spawn_callback<M: Clone + Send + 'static>(
+        &self,
+        mut rx: broadcast::Receiver<M>,
+        callback: impl Fn(M) + Send + Sync + 'static,
+    ) -> JoinHandle<()> {
+        let chart = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    msg = rx.recv() => msg,
+                    () = chart.closed() => return,
+                };
+                match msg {
+                    Ok(msg) => callback(msg),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("callback too slow, skipped {n} updates");
+                        chart.counters.lagged(n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn called_on_discovery_and_removal() {
+        let chart = Chart::test(entry).await;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let discovered = seen.clone();
+        chart.on_discovery(move |id, _| discovered.lock().unwrap().push(id));
+        let removed = seen.clone();
+        chart.on_removal(move |id| removed.lock().unwrap().push(id + 100));
+        tokio::task::yield_now().await;
+
+        chart.insert(42, entry(42).1);
+        let _ = chart.events.send(Event::Left { id: 42 });
+        let _ = chart.events.send(Event::Rejoined { port: 8080 });
+        chart.forget(1);
+        chart.forget(1);
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort_unstable();
+        assert_eq!(seen, [42, 101, 142]);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+
+use serde::{Deserialize, Serialize};
+
+/// The sub-protocols an instance supports, sent along with every discovery
+/// message. Use [`Chart::shared_capabilities`](crate::Chart::shared_capabilities)
+/// to find out which ones both sides support before using one with a peer.
+/// That way new sub-protocols can be rolled out one instance at the time.
+///
+/// Flags this version does not know about are kept as is.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Capabilities(u16);
+
+impl Capabilities {
+    /// Forgets instances that say goodbye, see
+    /// [`Status::Leaving`](crate::Status::Leaving)
+    pub const GOODBYE: Capabilities = Capabilities(1);
+    /// Reserved for exchanging digests of the chart
+    pub const GOSSIP_DIGEST: Capabilities = Capabilities(1 << 1);
+    /// Reserved for directly probing instances
+    pub const PROBES: Capabilities = Capabilities(1 << 2);
+    /// Shares [metadata](crate::Chart::put_meta)
+    pub const METADATA: Capabilities = Capabilities(1 << 3);
+    /// Shares the [health of its ports](crate::Chart::set_port_health)
+    pub const PORT_HEALTH: Capabilities = Capabilities(1 << 4);
+    /// Advertises how long to stay charted, see
+    /// [`ChartBuilder::with_ttl`](crate::ChartBuilder::with_ttl)
+    pub const TTL: Capabilities = Capabilities(1 << 5);
+    /// Advertises a [priority](crate::Chart::set_priority)
+    pub const PRIORITY: Capabilities = Capabilities(1 << 6);
+    /// Shares how many instances it charted, see
+    /// [`Event::AsymmetricVisibility`](crate::Event::AsymmetricVisibility)
+    pub const VISIBILITY: Capabilities = Capabilities(1 << 7);
+
+    /// What this version of the crate supports
+    pub(crate) const OURS: Capabilities = Capabilities(
+        Capabilities::GOODBYE.0
+            | Capabilities::METADATA.0
+            | Capabilities::PORT_HEALTH.0
+            | Capabilities::TTL.0
+            | Capabilities::PRIORITY.0
+            | Capabilities::VISIBILITY.0,
+    );
+
+    /// No capabilities, what instances running older versions advertise
+    #[must_use]
+    pub const fn empty() -> Self {
+        Capabilities(0)
+    }
+
+    /// The raw bitfield as sent on the wire
+    #[must_use]
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Capabilities from a raw bitfield, unknown flags are kept
+    #[must_use]
+    pub const fn from_bits(bits: u16) -> Self {
+        Capabilities(bits)
+    }
+
+    /// Whether all flags in `other` are set
+    #[must_use]
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Capabilities(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMED: [(Capabilities, &str); 8] = [
+            (Capabilities::GOODBYE, "GOODBYE"),
+            (Capabilities::GOSSIP_DIGEST, "GOSSIP_DIGEST"),
+            (Capabilities::PROBES, "PROBES"),
+            (Capabilities::METADATA, "METADATA"),
+            (Capabilities::PORT_HEALTH, "PORT_HEALTH"),
+            (Capabilities::TTL, "TTL"),
+            (Capabilities::PRIORITY, "PRIORITY"),
+            (Capabilities::VISIBILITY, "VISIBILITY"),
+        ];
+        let mut set = f.debug_set();
+        let mut unknown = self.0;
+        for (flag, name) in NAMED {
+            if self.contains(flag) {
+                set.entry(&format_args!("{name}"));
+                unknown &= !flag.0;
+            }
+        }
+        if unknown != 0 {
+            set.entry(&format_args!("{unknown:#x}"));
+        }
+        set.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_flags_are_kept() {
+        let theirs = Capabilities::from_bits(0x8001);
+        assert!(theirs.contains(Capabilities::GOODBYE));
+        assert!(!theirs.contains(Capabilities::GOODBYE | Capabilities::PROBES));
+        assert_eq!(theirs & Capabilities::OURS, Capabilities::GOODBYE);
+        assert_eq!(theirs.bits(), 0x8001);
+        assert_eq!(format!("{theirs:?}"), "{GOODBYE, 0x8000}");
+    }
+}
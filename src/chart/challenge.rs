@@ -0,0 +1,478 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::Entry;
+use crate::Id;
+
+const CHALLENGE: u8 = 1;
+const RESPONSE: u8 = 2;
+/// marker, kind, id, nonce and mac. Challenges carry the challenger's id, the
+/// challenged address and how to answer where responses carry the mac, so both
+/// have the same length and can not be used to amplify traffic.
+const LEN: usize = 8 + 1 + 8 + 16 + 32;
+/// do not challenge the same instance more often
+const RETRY: Duration = Duration::from_secs(1);
+/// forget challenges that were not answered within this time
+const STALE: Duration = Duration::from_secs(10);
+const MAX_PENDING: usize = 1024;
+/// the most challenged ips to remember whether they are on this host
+const MAX_LOCAL: usize = 64;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Msg {
+    /// the instance with id `target` at `ip` should prove to instance
+    /// `challenger` that it knows the key. Answered by multicast if it was
+    /// multicast.
+    Challenge {
+        target: Id,
+        nonce: [u8; 16],
+        challenger: Id,
+        ip: IpAddr,
+        multicast: bool,
+    },
+    /// proof that instance `id` knows the key
+    Response {
+        id: Id,
+        nonce: [u8; 16],
+        mac: [u8; 32],
+    },
+}
+
+struct Pending<Msg: fmt::Debug + Clone> {
+    nonce: [u8; 16],
+    ip: IpAddr,
+    /// inserted into the chart once the challenge is answered
+    entry: Entry<Msg>,
+    sent: Instant,
+}
+
+/// Newly discovered instances must answer a challenge before they are
+/// charted. The challenge contains a nonce the instance must return
+/// authenticated with the key shared by all instances.
+pub(crate) struct Challenges<Msg: fmt::Debug + Clone> {
+    key: [u8; 32],
+    /// nonces are derived from these, the key keeps them unpredictable
+    seed: u64,
+    counter: u64,
+    pending: HashMap<Id, Pending<Msg>>,
+    /// whether a challenged ip is on this host, finding out takes a syscall
+    local: HashMap<IpAddr, bool>,
+    /// challenges and responses waiting to be sent and where to, `None`
+    /// multicasts them
+    outgoing: Vec<(Vec<u8>, Option<SocketAddr>)>,
+}
+
+impl<Msg: fmt::Debug + Clone> fmt::Debug for Challenges<Msg> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Challenges")
+            .field("pending", &self.pending.len())
+            .field("outgoing", &self.outgoing.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// distinguishes challenges from discovery messages which start with the
+/// header
+fn marker(header: u64) -> [u8; 8] {
+    (!header).to_le_bytes()
+}
+
+fn encode(header: u64, kind: u8, id: Id, nonce: [u8; 16], tail: [u8; 32]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(LEN);
+    buf.extend_from_slice(&marker(header));
+    buf.push(kind);
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&nonce);
+    buf.extend_from_slice(&tail);
+    buf
+}
+
+/// ipv4 addresses are mapped so both families take 16 bytes
+fn ip_bytes(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+        IpAddr::V6(v6) => v6.octets(),
+    }
+}
+
+/// what a challenge carries in place of a mac
+fn challenge_tail(challenger: Id, ip: IpAddr, multicast: bool) -> [u8; 32] {
+    let mut tail = [0; 32];
+    tail[..8].copy_from_slice(&challenger.to_le_bytes());
+    tail[8..24].copy_from_slice(&ip_bytes(ip));
+    tail[24] = u8::from(multicast);
+    tail
+}
+
+/// decode a challenge or response, `None` if `buf` is neither
+pub(crate) fn parse(buf: &[u8], header: u64) -> Option<Msg> {
+    if buf.len() != LEN || buf[..8] != marker(header) {
+        return None;
+    }
+    let id = Id::from_le_bytes(buf[9..17].try_into().expect("slice is 8 long"));
+    let nonce = buf[17..33].try_into().expect("slice is 16 long");
+    let tail: [u8; 32] = buf[33..].try_into().expect("slice is 32 long");
+    match buf[8] {
+        CHALLENGE => Some(Msg::Challenge {
+            target: id,
+            nonce,
+            challenger: Id::from_le_bytes(tail[..8].try_into().expect("slice is 8 long")),
+            ip: IpAddr::V6(Ipv6Addr::from(
+                <[u8; 16]>::try_from(&tail[8..24]).expect("slice is 16 long"),
+            ))
+            .to_canonical(),
+            multicast: tail[24] == 1,
+        }),
+        RESPONSE => Some(Msg::Response {
+            id,
+            nonce,
+            mac: tail,
+        }),
+        _ => None,
+    }
+}
+
+impl<Msg: fmt::Debug + Clone> Challenges<Msg> {
+    pub(crate) fn new(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            seed: RandomState::new().build_hasher().finish(),
+            counter: 0,
+            pending: HashMap::new(),
+            local: HashMap::new(),
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// binds the responder's address and the challenger so a response can
+    /// not be relayed on behalf of an instance elsewhere
+    fn mac(
+        &self,
+        header: u64,
+        id: Id,
+        nonce: [u8; 16],
+        challenger: Id,
+        ip: IpAddr,
+    ) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(&header.to_le_bytes());
+        hasher.update(&id.to_le_bytes());
+        hasher.update(&nonce);
+        hasher.update(&challenger.to_le_bytes());
+        hasher.update(&ip_bytes(ip));
+        hasher.finalize()
+    }
+
+    fn nonce(&mut self) -> [u8; 16] {
+        self.counter += 1;
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(b"nonce");
+        hasher.update(&self.seed.to_le_bytes());
+        hasher.update(&self.counter.to_le_bytes());
+        let hash = hasher.finalize();
+        hash.as_bytes()[..16].try_into().expect("hash is 32 long")
+    }
+
+    /// challenge instance `id` at the address it announced itself from
+    /// unless we just did, `entry` is returned once it answers. Other
+    /// instances on that host may share the port and get the challenge
+    /// instead, so if it goes unanswered the retry is multicast.
+    pub(crate) fn challenge(
+        &mut self,
+        header: u64,
+        our_id: Id,
+        id: Id,
+        addr: SocketAddr,
+        entry: Entry<Msg>,
+        now: Instant,
+    ) {
+        let ip = addr.ip();
+        if let Some(pending) = self.pending.get_mut(&id) {
+            if pending.ip == ip && now.duration_since(pending.sent) < RETRY {
+                pending.entry = entry;
+                return;
+            }
+        }
+        if self.pending.len() >= MAX_PENDING && !self.pending.contains_key(&id) {
+            self.pending
+                .retain(|_, pending| now.duration_since(pending.sent) < STALE);
+        }
+        if self.pending.len() >= MAX_PENDING && !self.pending.contains_key(&id) {
+            // a flood of fresh ids, make room by giving up on the oldest
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.sent)
+                .map(|(id, _)| *id)
+                .expect("pending is full");
+            self.pending.remove(&oldest);
+        }
+        let (nonce, multicast) = match self.pending.get(&id) {
+            Some(pending) if pending.ip == ip => (pending.nonce, true),
+            _ => (self.nonce(), false),
+        };
+        let tail = challenge_tail(our_id, ip, multicast);
+        let to = (!multicast).then_some(addr);
+        self.outgoing
+            .push((encode(header, CHALLENGE, id, nonce, tail), to));
+        let pending = Pending {
+            nonce,
+            ip,
+            entry,
+            sent: now,
+        };
+        self.pending.insert(id, pending);
+    }
+
+    /// answer a challenge that arrived `from` the challenger if it is meant
+    /// for us. `is_ours` tells whether the challenged ip is on this host, a
+    /// challenge relayed from somewhere else names the relay's ip. Its answer
+    /// is remembered.
+    pub(crate) fn respond(
+        &mut self,
+        header: u64,
+        our_id: Id,
+        challenge: self::Msg,
+        from: SocketAddr,
+        is_ours: impl FnOnce(IpAddr) -> bool,
+    ) {
+        let self::Msg::Challenge {
+            target,
+            nonce,
+            challenger,
+            ip,
+            multicast,
+        } = challenge
+        else {
+            return;
+        };
+        if target != our_id {
+            return;
+        }
+        if self.local.len() >= MAX_LOCAL && !self.local.contains_key(&ip) {
+            self.local.clear();
+        }
+        if *self.local.entry(ip).or_insert_with(|| is_ours(ip)) {
+            let mac = self.mac(header, our_id, nonce, challenger, ip);
+            let response = encode(header, RESPONSE, our_id, nonce, *mac.as_bytes());
+            self.outgoing.push((response, (!multicast).then_some(from)));
+        }
+    }
+
+    /// the entry for `id` if it answered the challenge we sent it
+    pub(crate) fn verify(
+        &mut self,
+        header: u64,
+        our_id: Id,
+        id: Id,
+        ip: IpAddr,
+        nonce: [u8; 16],
+        mac: [u8; 32],
+    ) -> Option<Entry<Msg>> {
+        let pending = self.pending.get(&id)?;
+        // blake3::Hash compares in constant time
+        let valid = pending.ip == ip
+            && pending.nonce == nonce
+            && self.mac(header, id, nonce, our_id, ip) == blake3::Hash::from(mac);
+        valid.then(|| self.pending.remove(&id).expect("checked above").entry)
+    }
+
+    pub(crate) fn take_outgoing(&mut self) -> Vec<(Vec<u8>, Option<SocketAddr>)> {
+        std::mem::take(&mut self.outgoing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    const ADDR: SocketAddr = SocketAddr::new(IP, 8080);
+    const CHALLENGER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8080);
+    const RELAY: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)), 8080);
+
+    /// instance 1 challenges instance 2 which announced itself from `from`
+    fn send_challenge(challenger: &mut Challenges<[u16; 1]>, from: SocketAddr) -> Vec<u8> {
+        let entry = Entry::new(from.ip(), [8042]);
+        challenger.challenge(42, 1, 2, from, entry, Instant::now());
+        let [(challenge, to)] = challenger.take_outgoing().try_into().unwrap();
+        assert_eq!(to, Some(from), "challenges go to the announcing address");
+        challenge
+    }
+
+    /// instance 2 at `own` answers a challenge that came from `from`
+    fn answer(
+        challenged: &mut Challenges<[u16; 1]>,
+        challenge: &[u8],
+        from: SocketAddr,
+        own: IpAddr,
+    ) -> Option<Msg> {
+        let challenge = parse(challenge, 42).unwrap();
+        challenged.respond(42, 2, challenge, from, |ip| ip == own);
+        let outgoing = challenged.take_outgoing();
+        let [(response, to)] = outgoing.try_into().ok()?;
+        assert_eq!(to, Some(from), "responses go back to the challenger");
+        parse(&response, 42)
+    }
+
+    /// instance 2 at `IP` answers a multicast challenge
+    fn answer_multicast(challenged: &mut Challenges<[u16; 1]>, challenge: &[u8]) -> Option<Msg> {
+        let challenge = parse(challenge, 42).unwrap();
+        challenged.respond(42, 2, challenge, CHALLENGER, |ip| ip == IP);
+        let [(response, to)] = challenged.take_outgoing().try_into().ok()?;
+        assert_eq!(to, None, "multicast challenges are answered by multicast");
+        parse(&response, 42)
+    }
+
+    fn exchange(
+        challenger: &mut Challenges<[u16; 1]>,
+        challenged: &mut Challenges<[u16; 1]>,
+    ) -> Option<Entry<[u16; 1]>> {
+        let challenge = send_challenge(challenger, ADDR);
+        let Some(Msg::Response { id, nonce, mac }) = answer(challenged, &challenge, CHALLENGER, IP)
+        else {
+            panic!("not a response");
+        };
+        challenger.verify(42, 1, id, IP, nonce, mac)
+    }
+
+    #[test]
+    fn shared_key() {
+        let mut a = Challenges::new([1; 32]);
+        let mut b = Challenges::new([1; 32]);
+        assert_eq!(
+            exchange(&mut a, &mut b).map(|entry| entry.msg),
+            Some([8042])
+        );
+        assert!(a.pending.is_empty());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let mut a = Challenges::new([1; 32]);
+        let mut b = Challenges::new([2; 32]);
+        assert!(exchange(&mut a, &mut b).is_none());
+    }
+
+    #[test]
+    fn not_for_us() {
+        let mut b: Challenges<[u16; 1]> = Challenges::new([1; 32]);
+        let challenge = Msg::Challenge {
+            target: 3,
+            nonce: [0; 16],
+            challenger: 1,
+            ip: IP,
+            multicast: false,
+        };
+        b.respond(42, 2, challenge, CHALLENGER, |_| true);
+        assert!(b.take_outgoing().is_empty());
+        assert_eq!(parse(&[0; LEN], 42), None);
+    }
+
+    #[test]
+    fn relayed_response_rejected() {
+        let mut a = Challenges::new([1; 32]);
+        let mut b = Challenges::new([1; 32]);
+        // the relay announced itself as instance 2
+        let challenge = send_challenge(&mut a, RELAY);
+
+        // the real instance 2 does not answer for the relay's ip
+        assert!(answer(&mut b, &challenge, RELAY, IP).is_none());
+
+        // if the relay names the real ip instead the mac binds that ip
+        let Some(Msg::Challenge { target, nonce, .. }) = parse(&challenge, 42) else {
+            panic!("not a challenge");
+        };
+        let forged = encode(42, CHALLENGE, target, nonce, challenge_tail(1, IP, false));
+        let Some(Msg::Response { id, nonce, mac }) = answer(&mut b, &forged, RELAY, IP) else {
+            panic!("not a response");
+        };
+        assert!(a.verify(42, 1, id, RELAY.ip(), nonce, mac).is_none());
+
+        // nor can a response to another challenger be passed on, here
+        // instance 2 is on the relay's host
+        let mut b = Challenges::new([1; 32]);
+        let forged = encode(
+            42,
+            CHALLENGE,
+            target,
+            nonce,
+            challenge_tail(5, RELAY.ip(), false),
+        );
+        let Some(Msg::Response { id, nonce, mac }) = answer(&mut b, &forged, RELAY, RELAY.ip())
+        else {
+            panic!("not a response");
+        };
+        assert!(a.verify(42, 1, id, RELAY.ip(), nonce, mac).is_none());
+    }
+
+    #[test]
+    fn ipv6_round_trips() {
+        let ip = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2));
+        let buf = encode(42, CHALLENGE, 2, [0; 16], challenge_tail(1, ip, false));
+        let Some(Msg::Challenge { ip: parsed, .. }) = parse(&buf, 42) else {
+            panic!("not a challenge");
+        };
+        assert_eq!(parsed, ip);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_limited() {
+        let mut a = Challenges::new([1; 32]);
+        a.challenge(42, 1, 2, ADDR, Entry::new(IP, [8042]), Instant::now());
+        a.challenge(42, 1, 2, ADDR, Entry::new(IP, [8042]), Instant::now());
+        assert_eq!(a.take_outgoing().len(), 1);
+        tokio::time::advance(RETRY).await;
+        a.challenge(42, 1, 2, ADDR, Entry::new(IP, [8042]), Instant::now());
+        let [(challenge, to)] = a.take_outgoing().try_into().unwrap();
+        let Some(Msg::Challenge {
+            nonce, multicast, ..
+        }) = parse(&challenge, 42)
+        else {
+            panic!("not a challenge");
+        };
+        assert_eq!(nonce, a.pending[&2].nonce, "retries reuse the nonce");
+        assert!(multicast && to.is_none(), "retries are multicast");
+
+        let mut b = Challenges::new([1; 32]);
+        let Some(Msg::Response { id, nonce, mac }) = answer_multicast(&mut b, &challenge) else {
+            panic!("not a response");
+        };
+        assert!(a.verify(42, 1, id, IP, nonce, mac).is_some());
+    }
+
+    #[test]
+    fn pending_bounded() {
+        let mut a = Challenges::new([1; 32]);
+        let now = Instant::now();
+        for id in 0..MAX_PENDING as Id + 10 {
+            a.challenge(42, 1, id, ADDR, Entry::new(IP, [8042]), now);
+        }
+        assert_eq!(a.pending.len(), MAX_PENDING);
+        assert!(a.pending.contains_key(&(MAX_PENDING as Id + 9)));
+        assert_eq!(a.take_outgoing().len(), MAX_PENDING + 10);
+    }
+
+    #[test]
+    fn locality_remembered() {
+        let mut b: Challenges<[u16; 1]> = Challenges::new([1; 32]);
+        let challenge = send_challenge(&mut Challenges::new([1; 32]), ADDR);
+        let mut checks = 0;
+        for _ in 0..3 {
+            let challenge = parse(&challenge, 42).unwrap();
+            b.respond(42, 2, challenge, CHALLENGER, |_| {
+                checks += 1;
+                true
+            });
+        }
+        assert_eq!(checks, 1);
+        assert_eq!(b.take_outgoing().len(), 3);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{Chart, DiscoveryMsg, Event, Jitter, Status};
+use crate::Id;
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    /// Switch to the lowest id no other instance uses, giving small human
+    /// friendly ids without a central allocator. Listens for `listen` to
+    /// learn the ids in use, then proposes the lowest free one. If another
+    /// instance turns out to use it within `listen` this instance goes back
+    /// to its original id, waits a random part of `listen` and tries again.
+    /// Returns the claimed id.
+    ///
+    /// Build the chart with an id outside the small range handed out, for
+    /// example a [random one](crate::ChartBuilder::with_random_id), and do
+    /// not combine this with
+    /// [`reroll_id_on_collision`](crate::ChartBuilder::reroll_id_on_collision).
+    /// Each switch says goodbye under the old id so peers forget it.
+    ///
+    /// # Note
+    /// [`maintain`](crate::discovery::maintain) must be running. Pick
+    /// `listen` a few times larger then the
+    /// [maximum broadcast interval](Chart::max_interval) of the other
+    /// instances, instances that are not heard from within it can end up
+    /// with the same id. Those collisions are still reported as
+    /// [`Event::IdCollision`]. Instances on the same host can not tell
+    /// another's messages apart from their own echo, start those one at the
+    /// time.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn claim_lowest_id(&self, listen: Duration) -> Id {
+        let original = self.id();
+        let mut jitter = Jitter::new();
+        tokio::time::sleep(listen).await;
+        loop {
+            let snapshot = self.snapshot();
+            let candidate = (0..)
+                .find(|id| snapshot.get(*id).is_none())
+                .expect("there are fewer then u64::MAX instances");
+            let mut events = self.events();
+            self.switch_id(candidate).await;
+
+            let collision = async {
+                loop {
+                    match events.recv().await {
+                        Ok(Event::IdCollision { id, .. }) if id == candidate => return,
+                        Err(RecvError::Closed) => std::future::pending().await,
+                        _ => (),
+                    }
+                }
+            };
+            let collided = tokio::time::timeout(listen, collision).await.is_ok();
+            if !collided && self.snapshot().get(candidate).is_none() {
+                info!("claimed id {candidate}");
+                return candidate;
+            }
+
+            // whoever retries first gets the id, the other sees it charted
+            info!("id {candidate} is taken, retrying");
+            self.switch_id(original).await;
+            tokio::time::sleep(listen.mul_f32(jitter.next_unit())).await;
+        }
+    }
+
+    /// say goodbye under our current id and announce ourselves using `id`
+    async fn switch_id(&self, id: Id) {
+        let goodbye = DiscoveryMsg {
+            status: Status::Leaving,
+            ..self.discovery_msg()
+        };
+        let goodbye = self
+            .wire_format
+            .encode(&goodbye)
+            .expect("checked to fit when building and by everything growing it");
+        self.multicast(&goodbye).await;
+
+        self.set_id(&mut self.map.lock().unwrap(), id);
+        self.broadcast_now().await;
+    }
+}
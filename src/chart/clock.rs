@@ -0,0 +1,66 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::time::Instant;
+
+/// The time source the broadcast schedule follows. Install one using
+/// [`ChartBuilder::with_clock`](crate::ChartBuilder::with_clock).
+///
+/// The default, [`TokioClock`], follows the tokio timer. That already allows
+/// fast-forwarding the rampdown in tests that run with a paused runtime (see
+/// `tokio::time::pause`), implement this to drive the schedule from a
+/// simulation instead.
+///
+/// # Example
+/// Run the broadcast schedule at double speed.
+/// ```rust
+/// use instance_chart::Clock;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use tokio::time::Instant;
+///
+/// #[derive(Debug)]
+/// struct Fast {
+///     start: Instant,
+/// }
+///
+/// impl Clock for Fast {
+///     fn now(&self) -> Instant {
+///         self.start + Instant::now().duration_since(self.start) * 2
+///     }
+///     fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+///         let real = self.start + deadline.saturating_duration_since(self.start) / 2;
+///         Box::pin(tokio::time::sleep_until(real))
+///     }
+/// }
+/// ```
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// the current time
+    fn now(&self) -> Instant;
+    /// completes once [`now`](Clock::now) has passed `deadline`
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// The default [`Clock`], uses the tokio timer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SharedClock(pub(crate) Arc<dyn Clock>);
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self(Arc::new(TokioClock))
+    }
+}
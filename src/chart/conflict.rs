@@ -0,0 +1,69 @@
+/// What to do when an instance announces an [`Id`](crate::Id) that is already
+/// charted under a different address. Set using
+/// [`ChartBuilder::with_conflict_policy`](crate::ChartBuilder::with_conflict_policy).
+///
+/// # Note
+/// Instances on the same host share an address, conflicts between them can
+/// not be detected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the instance that was charted first, ignore the newcomer
+    KeepFirst,
+    /// Replace the charted instance with the one that announced last
+    #[default]
+    KeepLatest,
+    /// Remove the id from the chart and ignore both instances until the id is
+    /// [forgotten](crate::Chart::forget)
+    RejectBoth,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::{entry, entry_with};
+    use crate::chart::Entry;
+    use crate::{Chart, Event};
+
+    /// claims id 2 from another ip
+    fn imposter() -> Entry<[u16; 1]> {
+        entry_with(42, [9000]).1
+    }
+
+    async fn conflicting_insert(policy: ConflictPolicy) -> (Chart<1, u16>, Event) {
+        let mut chart = Chart::test(entry).await;
+        chart.conflict_policy = policy;
+        let mut events = chart.events();
+        assert!(!chart.insert(2, imposter()));
+        (chart, events.try_recv().unwrap())
+    }
+
+    #[tokio::test]
+    async fn keep_first() {
+        let (chart, event) = conflicting_insert(ConflictPolicy::KeepFirst).await;
+        assert_eq!(chart.map.lock().unwrap().get(&2).unwrap().ip, entry(2).1.ip);
+        assert_eq!(
+            event,
+            Event::IdConflict {
+                id: 2,
+                charted: entry(2).1.ip,
+                other: imposter().ip
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn keep_latest() {
+        let (chart, _) = conflicting_insert(ConflictPolicy::KeepLatest).await;
+        assert_eq!(chart.map.lock().unwrap().get(&2).unwrap().ip, imposter().ip);
+    }
+
+    #[tokio::test]
+    async fn reject_both() {
+        let (chart, _) = conflicting_insert(ConflictPolicy::RejectBoth).await;
+        assert!(chart.map.lock().unwrap().get(&2).is_none());
+        assert!(!chart.insert(2, entry(2).1));
+
+        chart.forget(2);
+        assert!(chart.insert(2, entry(2).1));
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::metrics::Metrics;
+
+/// Counts of what the discovery hot path did, get a snapshot using
+/// [`Chart::counters()`](crate::Chart::counters). Counting starts when the
+/// chart is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Counters {
+    /// datagrams received on any discovery socket
+    pub received: u64,
+    /// discovery messages from other instances, our own echoes are not
+    /// counted
+    pub heard: u64,
+    /// heartbeats from known instances dropped before decoding
+    pub skipped: u64,
+    /// datagrams that were fully decoded
+    pub decoded: u64,
+    /// datagrams that could not be decoded
+    pub invalid: u64,
+    /// datagrams dropped because the sender is quarantined
+    pub quarantined: u64,
+    /// times a datagram was left for a later window because the
+    /// [packet budget](crate::ChartBuilder::with_packet_budget) ran out
+    pub deferred: u64,
+    /// discovery messages sent, both multicast and replies to newcomers
+    pub sent: u64,
+    /// discoveries and events skipped because a [`Notify`](crate::Notify) or
+    /// [callback](crate::Chart::on_discovery) fell more then 256 behind
+    pub lagged: u64,
+    /// total time the receive path waited to lock the chart
+    pub lock_wait: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Cells {
+    received: AtomicU64,
+    heard: AtomicU64,
+    skipped: AtomicU64,
+    decoded: AtomicU64,
+    invalid: AtomicU64,
+    quarantined: AtomicU64,
+    deferred: AtomicU64,
+    sent: AtomicU64,
+    lagged: AtomicU64,
+    lock_wait_nanos: AtomicU64,
+    metrics: Metrics,
+}
+
+macro_rules! add_fn {
+    ($name:ident) => {
+        pub(crate) fn $name(&self, n: u64) {
+            self.$name.fetch_add(n, Ordering::Relaxed);
+            self.metrics.0.counter(stringify!($name), n);
+        }
+    };
+}
+
+impl Cells {
+    pub(crate) fn new(metrics: Metrics) -> Self {
+        Self {
+            metrics,
+            ..Self::default()
+        }
+    }
+
+    add_fn!(received);
+    add_fn!(heard);
+    add_fn!(skipped);
+    add_fn!(decoded);
+    add_fn!(invalid);
+    add_fn!(quarantined);
+    add_fn!(deferred);
+    add_fn!(sent);
+    add_fn!(lagged);
+
+    pub(crate) fn lock_wait(&self, waited: Duration) {
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.lock_wait_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.metrics.0.counter("lock_wait_ns", nanos);
+    }
+
+    pub(crate) fn size(&self, size: usize) {
+        self.metrics.0.gauge("size", size as u64);
+    }
+
+    pub(crate) fn snapshot(&self) -> Counters {
+        Counters {
+            received: self.received.load(Ordering::Relaxed),
+            heard: self.heard.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            decoded: self.decoded.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+            quarantined: self.quarantined.load(Ordering::Relaxed),
+            deferred: self.deferred.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            lock_wait: Duration::from_nanos(self.lock_wait_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::Id;
+
+/// Hysteresis for instances that flap between expiring and being discovered
+/// again. Expiring waits an extra `leave`, an instance that expired must be
+/// heard from steadily for `rejoin` before it is charted again.
+#[derive(Debug)]
+pub(crate) struct Debounce {
+    leave: Duration,
+    rejoin: Duration,
+    /// when instances expired
+    expired: HashMap<Id, Instant>,
+    /// when expired instances were first and last heard from again
+    rejoining: HashMap<Id, (Instant, Instant)>,
+}
+
+impl Debounce {
+    pub(crate) fn new(leave: Duration, rejoin: Duration) -> Self {
+        Self {
+            leave,
+            rejoin,
+            expired: HashMap::new(),
+            rejoining: HashMap::new(),
+        }
+    }
+
+    /// extra time an instance has to be silent before it expires
+    pub(crate) fn leave(&self) -> Duration {
+        self.leave
+    }
+
+    /// how long ago an instance must have expired before it is charted
+    /// right away again
+    fn horizon(&self) -> Duration {
+        self.rejoin * 10
+    }
+
+    /// remember `id` expired, forgets instances that expired long ago
+    pub(crate) fn expired(&mut self, id: Id, now: Instant) {
+        let horizon = self.horizon();
+        self.expired
+            .retain(|_, at| now.duration_since(*at) < horizon);
+        self.rejoining.retain(|id, _| self.expired.contains_key(id));
+        self.expired.insert(id, now);
+        self.rejoining.remove(&id);
+    }
+
+    /// true if `id` expired recently and has not yet been heard from
+    /// steadily for long enough to chart it again. Hearing nothing for
+    /// longer then twice the `interval` it advertises restarts the wait.
+    pub(crate) fn holds(&mut self, id: Id, interval: Duration, now: Instant) -> bool {
+        let Some(expired) = self.expired.get(&id) else {
+            return false;
+        };
+        if now.duration_since(*expired) >= self.horizon() {
+            self.expired.remove(&id);
+            self.rejoining.remove(&id);
+            return false;
+        }
+
+        let (first, last) = self.rejoining.entry(id).or_insert((now, now));
+        if now.duration_since(*last) > interval * 2 {
+            *first = now;
+        }
+        *last = now;
+        if now.duration_since(*first) < self.rejoin {
+            return true;
+        }
+        self.expired.remove(&id);
+        self.rejoining.remove(&id);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    #[tokio::test(start_paused = true)]
+    async fn rejoin_after_steady_contact() {
+        let mut debounce = Debounce::new(Duration::ZERO, Duration::from_secs(5));
+        let start = Instant::now();
+        assert!(!debounce.holds(1, INTERVAL, start));
+
+        debounce.expired(1, start);
+        for secs in 0..5 {
+            let now = start + Duration::from_secs(secs);
+            assert!(debounce.holds(1, INTERVAL, now));
+        }
+        assert!(!debounce.holds(1, INTERVAL, start + Duration::from_secs(5)));
+        // charted again, no longer held
+        assert!(!debounce.holds(1, INTERVAL, start + Duration::from_secs(5)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silence_restarts_wait() {
+        let mut debounce = Debounce::new(Duration::ZERO, Duration::from_secs(5));
+        let start = Instant::now();
+        debounce.expired(1, start);
+        assert!(debounce.holds(1, INTERVAL, start));
+        for secs in 3..8 {
+            let now = start + Duration::from_secs(secs);
+            assert!(debounce.holds(1, INTERVAL, now));
+        }
+        assert!(!debounce.holds(1, INTERVAL, start + Duration::from_secs(8)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn forgets_old_expiries() {
+        let mut debounce = Debounce::new(Duration::ZERO, Duration::from_secs(5));
+        let start = Instant::now();
+        debounce.expired(1, start);
+        assert!(!debounce.holds(1, INTERVAL, start + Duration::from_secs(50)));
+    }
+}
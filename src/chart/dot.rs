@@ -0,0 +1,102 @@
+use std::fmt::{Debug, Write};
+use tokio::time::Instant;
+
+use serde::Serialize;
+
+use super::{Chart, Status};
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Describe this instance and the peers it discovered as a
+    /// [Graphviz](https://graphviz.org) graph in the dot language. Every peer
+    /// is labeled with its id, address, message, [`Status`] and how long ago
+    /// we last heard from it. Draining peers are drawn dashed and leaving ones
+    /// dotted.
+    ///
+    /// Useful as an artifact when an integration test fails, render it with
+    /// `dot -Tsvg chart.dot -o chart.svg`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use instance_chart::ChartBuilder;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let chart = ChartBuilder::new()
+    ///     .with_id(1)
+    ///     .with_service_port(8042)
+    ///     .local_discovery(true)
+    ///     .finish()?;
+    /// std::fs::write(std::env::temp_dir().join("chart.dot"), chart.to_dot())?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let now = Instant::now();
+        let our_id = self.id();
+        let mut dot = String::from("digraph chart {\n");
+        let our_label = escape(&format!("{our_id} (self)\n{:?}", self.msg.read().unwrap()));
+        let _ = writeln!(dot, "    \"{our_id}\" [label=\"{our_label}\", shape=box];");
+
+        let map = self.map.lock().unwrap();
+        let mut peers: Vec<_> = map.iter().collect();
+        peers.sort_unstable_by_key(|(id, _)| **id);
+        for (id, entry) in peers {
+            let seen = map.last_seen(id).map_or_else(
+                || String::from("never heard from"),
+                |at| format!("seen {:.1}s ago", now.duration_since(at).as_secs_f32()),
+            );
+            let label = format!(
+                "{id}\n{}\n{:?}\n{:?}, {seen}",
+                entry.ip, entry.msg, entry.status
+            );
+            let style = match entry.status {
+                Status::Active => "solid",
+                Status::Draining => "dashed",
+                Status::Leaving => "dotted",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{id}\" [label=\"{}\", style={style}];",
+                escape(&label)
+            );
+            let _ = writeln!(dot, "    \"{our_id}\" -> \"{id}\";");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// make `s` safe to use within a quoted dot string
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{Entry, Id};
+
+    #[tokio::test]
+    async fn peers_in_graph() {
+        fn gen_kv(n: u8) -> (Id, Entry<[u16; 1]>) {
+            let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+            (n.into(), Entry::new(ip, [8000 + u16::from(n)]))
+        }
+        let chart = Chart::test(gen_kv).await;
+        let dot = chart.to_dot();
+        assert!(dot.starts_with("digraph chart {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("\"0\" [label=\"0 (self)"));
+        for (id, entry) in (1..10).map(gen_kv) {
+            assert!(dot.contains(&format!("\"{id}\" [label=\"{id}\\n{}", entry.ip)));
+            assert!(dot.contains(&format!("\"0\" -> \"{id}\";")));
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// How an instance can be reached, shared with the other instances when they
+/// discover it. The address accessors on [`Chart`](crate::Chart), for example
+/// [`get_addr`](crate::Chart::get_addr) and [`addr_vec`](crate::Chart::addr_vec),
+/// are available for any chart sharing an `Endpoint`.
+///
+/// Implemented for:
+/// - a port (`u16`), resolves to the ip the instance was discovered on plus the port
+/// - a [`SocketAddr`], used as is
+/// - a [`String`], for example a DNS name or URL, used as is
+///
+/// # Example
+/// ```rust
+/// use instance_chart::Endpoint;
+/// use serde::{Deserialize, Serialize};
+/// use std::net::IpAddr;
+///
+/// #[derive(Debug, Clone, Serialize, Deserialize)]
+/// struct Http {
+///     port: u16,
+///     path: String,
+/// }
+///
+/// impl Endpoint for Http {
+///     type Addr = String;
+///     fn resolve(&self, ip: IpAddr) -> String {
+///         format!("http://{ip}:{}/{}", self.port, self.path)
+///     }
+/// }
+/// ```
+pub trait Endpoint: Debug + Clone + Serialize + DeserializeOwned {
+    /// what the endpoint resolves to
+    type Addr;
+    /// the address to reach the endpoint, given the `ip` the instance was
+    /// discovered on
+    fn resolve(&self, ip: IpAddr) -> Self::Addr;
+}
+
+impl Endpoint for u16 {
+    type Addr = SocketAddr;
+    fn resolve(&self, ip: IpAddr) -> SocketAddr {
+        SocketAddr::new(ip, *self)
+    }
+}
+
+impl Endpoint for SocketAddr {
+    type Addr = SocketAddr;
+    fn resolve(&self, _: IpAddr) -> SocketAddr {
+        *self
+    }
+}
+
+impl Endpoint for String {
+    type Addr = String;
+    fn resolve(&self, _: IpAddr) -> String {
+        self.clone()
+    }
+}
@@ -0,0 +1,81 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::chart::Status;
+use crate::{DiscoveryError, Id};
+
+/// Something noteworthy that happened while maintaining the chart. Subscribe
+/// using [`Chart::events()`](crate::Chart::events).
+///
+/// New discoveries are not events, use [`Chart::notify()`](crate::Chart::notify) for those.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum Event {
+    /// An instance announced an [`Id`] that is already in the chart under
+    /// another address. How this is resolved depends on the
+    /// [`ConflictPolicy`](crate::ConflictPolicy).
+    IdConflict {
+        id: Id,
+        /// address the id was charted under
+        charted: IpAddr,
+        /// address of the instance that announced the same id
+        other: IpAddr,
+    },
+    /// A charted instance announced a new [`Status`]
+    StatusChanged { id: Id, status: Status },
+    /// A charted instance announced a new priority, see
+    /// [`ChartBuilder::with_priority`](crate::ChartBuilder::with_priority)
+    PriorityChanged { id: Id, priority: u32 },
+    /// A charted instance said goodbye and was removed from the chart
+    Left { id: Id },
+    /// A charted instance was not heard from for too long and was removed from
+    /// the chart, see [`ChartBuilder::with_expiry`](crate::ChartBuilder::with_expiry)
+    Expired { id: Id },
+    /// A charted instance was removed using [`Chart::forget`](crate::Chart::forget)
+    /// or [`Chart::forget_all_except`](crate::Chart::forget_all_except)
+    Forgotten { id: Id },
+    /// Our own discovery messages stopped arriving on the discovery `port`.
+    /// The multicast groups were left and joined again to recover.
+    Rejoined { port: u16 },
+    /// The host at `ip` sent `strikes` malformed messages in a short time,
+    /// everything it sends is ignored for `duration`. See
+    /// [`ChartBuilder::with_quarantine`](crate::ChartBuilder::with_quarantine).
+    Quarantined {
+        ip: IpAddr,
+        strikes: u32,
+        duration: Duration,
+    },
+    /// The instance at `ip` announces our `id`. Only detected if multicast
+    /// loopback is enabled. Our id is re-rolled to `new_id` if
+    /// [`ChartBuilder::reroll_id_on_collision`](crate::ChartBuilder::reroll_id_on_collision)
+    /// is set and we are still within the window it allows for that.
+    IdCollision {
+        id: Id,
+        ip: IpAddr,
+        new_id: Option<Id>,
+    },
+    /// The instance `id` at `ip` sends a different number of service ports
+    /// (or custom messages) then this chart was built for. Its messages are
+    /// dropped. Sent once for every such instance.
+    MismatchedN {
+        id: Id,
+        ip: IpAddr,
+        expected: usize,
+        got: usize,
+    },
+    /// The application [reported](crate::Chart::report_unreachable) the
+    /// instance unreachable `failures` times, it was removed from the chart
+    Unreachable { id: Id, failures: u32 },
+    /// The charted instance `id` sees less then half of the instances we
+    /// see, both counts include the instance itself. Often one-way multicast
+    /// filtering: its messages reach us but ours do not reach it. Sent once
+    /// the instance has been charted for twice our
+    /// [maximum broadcast interval](crate::ChartBuilder::with_rampdown), then
+    /// again only after it recovered.
+    AsymmetricVisibility { id: Id, sees: u32, we_see: u32 },
+    /// Something went wrong while discovering, see [`DiscoveryError`]
+    Error(DiscoveryError),
+}
@@ -0,0 +1,57 @@
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::interval::Jitter;
+
+/// Simulated packet loss and latency, see
+/// [`ChartBuilder::with_fault_injection`](crate::ChartBuilder::with_fault_injection)
+#[derive(Debug)]
+pub(crate) struct Faults {
+    /// probability a sent or received datagram is dropped
+    loss_rate: f32,
+    /// how long sending a datagram or handling received ones takes
+    delay: Range<Duration>,
+    rng: Mutex<Jitter>,
+}
+
+impl Faults {
+    pub(crate) fn new(loss_rate: f32, delay: Range<Duration>) -> Self {
+        Self {
+            loss_rate: loss_rate.clamp(0.0, 1.0),
+            delay,
+            rng: Mutex::new(Jitter::new()),
+        }
+    }
+
+    /// whether a datagram should be dropped, drawn separately for sending
+    /// and receiving
+    pub(crate) fn lose(&self) -> bool {
+        self.rng.lock().unwrap().next_unit() < self.loss_rate
+    }
+
+    /// how long to wait before sending a datagram or handling a batch of
+    /// received ones
+    pub(crate) fn delay(&self) -> Duration {
+        let spread = self.delay.end.saturating_sub(self.delay.start);
+        self.delay.start + spread.mul_f32(self.rng.lock().unwrap().next_unit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_and_delay_within_bounds() {
+        let delay = Duration::from_millis(10)..Duration::from_millis(20);
+        let faults = Faults::new(0.5, delay.clone());
+        let lost = (0..1000).filter(|_| faults.lose()).count();
+        assert!((400..600).contains(&lost), "lost: {lost}");
+        assert!((0..100).all(|_| (delay.start..=delay.end).contains(&faults.delay())));
+
+        let faults = Faults::new(2.0, Duration::ZERO..Duration::ZERO);
+        assert!(faults.lose());
+        assert_eq!(faults.delay(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,41 @@
+//! Entries and discovery messages shared by the unit tests
+
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr};
+
+use super::{Capabilities, DiscoveryMsg, Entry, Metadata, PortHealth, Status};
+use crate::Id;
+
+/// instance `n` at `10.0.0.n` sharing service port 8042, pass it to
+/// [`Chart::test`](crate::Chart::test) for a chart with instances 1 to 9
+pub(crate) fn entry(n: u8) -> (Id, Entry<[u16; 1]>) {
+    entry_with(n, [8042])
+}
+
+/// like [`entry`] but sharing `msg`
+pub(crate) fn entry_with<const N: usize, T>(n: u8, msg: [T; N]) -> (Id, Entry<[T; N]>)
+where
+    T: Debug + Clone,
+{
+    let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+    (n.into(), Entry::new(ip, msg))
+}
+
+/// an active instance announcing `msg` with the capabilities of this
+/// version, override fields using struct update syntax
+pub(crate) fn test_msg<const N: usize, T>(header: u64, id: Id, msg: [T; N]) -> DiscoveryMsg<N, T> {
+    DiscoveryMsg {
+        header,
+        id,
+        status: Status::Active,
+        interval_ms: 1000,
+        group: None,
+        capabilities: Capabilities::OURS,
+        msg,
+        meta: Metadata::default(),
+        port_health: PortHealth::default(),
+        ttl_ms: None,
+        priority: 0,
+        sees: 1,
+    }
+}
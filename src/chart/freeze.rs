@@ -0,0 +1,136 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{Mutex, OwnedRwLockWriteGuard, RwLock, RwLockReadGuard};
+use tokio::time::Instant;
+
+use super::Chart;
+
+/// Keeps discovery from changing the chart while frozen, see
+/// [`Chart::freeze`]
+#[derive(Debug, Default)]
+pub(crate) struct Gate {
+    lock: Arc<RwLock<()>>,
+    /// held while frozen, with when the freeze started
+    frozen: Mutex<Option<(OwnedRwLockWriteGuard<()>, Instant)>>,
+    /// how long the chart was frozen in total, not counting a freeze that
+    /// is still going on
+    frozen_for: std::sync::Mutex<Duration>,
+}
+
+impl Gate {
+    /// Waits while the chart is frozen, discovery may change the chart as
+    /// long as the returned guard lives
+    pub(crate) async fn pass(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().await
+    }
+
+    /// How long the chart was frozen in total, not counting a freeze that
+    /// is still going on
+    pub(crate) fn frozen_for(&self) -> Duration {
+        *self.frozen_for.lock().unwrap()
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Stop discovery from changing the chart until
+    /// [`unfreeze`](Self::unfreeze) is called, for example while taking a
+    /// backup that must be consistent with the membership. Discovery
+    /// messages that arrive in the mean time wait in the socket's receive
+    /// buffer and are processed once unfrozen. Instances do not
+    /// [expire](crate::ChartBuilder::with_expiry) while frozen. This
+    /// instance keeps announcing itself.
+    ///
+    /// Returns once messages that were being processed are applied. Changes
+    /// made through the chart itself, such as [`merge_from`](Self::merge_from),
+    /// still apply.
+    ///
+    /// # Note
+    /// If the receive buffer fills up messages are lost, set its size with
+    /// [`ChartBuilder::with_recv_buffer_size`](crate::ChartBuilder::with_recv_buffer_size)
+    /// if you freeze for long.
+    pub async fn freeze(&self) {
+        let mut frozen = self.freeze.frozen.lock().await;
+        if frozen.is_none() {
+            let guard = Arc::clone(&self.freeze.lock).write_owned().await;
+            *frozen = Some((guard, Instant::now()));
+            info!("chart frozen");
+        }
+    }
+
+    /// Let discovery change the chart again after [`freeze`](Self::freeze),
+    /// messages that arrived in the mean time are processed right away.
+    pub async fn unfreeze(&self) {
+        let Some((guard, since)) = self.freeze.frozen.lock().await.take() else {
+            return;
+        };
+        // time stood still for the chart, move when we last heard from
+        // instances forward so they do not expire before the buffered
+        // messages are processed
+        let frozen = since.elapsed();
+        self.map.lock().unwrap().shift_seen(frozen);
+        *self.freeze.frozen_for.lock().unwrap() += frozen;
+        drop(guard);
+        info!("chart unfrozen");
+    }
+
+    /// Whether the chart is [frozen](Self::freeze)
+    #[must_use]
+    pub fn is_frozen(&self) -> bool {
+        self.freeze.lock.try_read().is_err()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chart::fixtures::entry;
+
+    #[tokio::test]
+    async fn frozen_gate_waits() {
+        let chart = Chart::test(entry).await;
+        chart.freeze().await;
+        chart.freeze().await;
+        assert!(chart.is_frozen());
+
+        let gate = Arc::clone(&chart.freeze);
+        let passing = tokio::spawn(async move {
+            let _pass = gate.pass().await;
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!passing.is_finished());
+
+        chart.unfreeze().await;
+        assert!(!chart.is_frozen());
+        passing.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn nothing_expires_while_frozen() {
+        let mut chart = Chart::test(entry).await;
+        chart.expiry = Some(3);
+        let interval = chart.interval.max();
+        {
+            let mut map = chart.map.lock().unwrap();
+            for n in 1..10 {
+                let (id, entry) = entry(n);
+                map.set_seen(id, entry.ip, entry.ip, 0, interval, None);
+            }
+        }
+        let mut events = chart.events();
+        tokio::spawn(crate::chart::expire_stale(chart.clone()));
+
+        chart.freeze().await;
+        tokio::time::sleep(interval * 10).await;
+        chart.unfreeze().await;
+        tokio::time::sleep(interval).await;
+
+        assert_eq!(chart.size(), 10);
+        assert!(events.try_recv().is_err());
+        assert!(chart.freeze.frozen_for() >= interval * 10);
+    }
+}
@@ -1,11 +1,13 @@
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+
 use crate::Id;
-use std::net::SocketAddr;
 
-use super::builder::Port;
-use super::{Chart, Entry};
+use super::{Chart, Endpoint, Entry};
 
-impl<const N: usize> Chart<N, Port> {
-    /// Get all the `SocketAddr`'s for a given node's `Id`
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    /// Get all the addresses for a given node's `Id`, see [`Endpoint`]
     ///
     /// # Note
     /// returns None if the node was not in the Chart
@@ -39,21 +41,81 @@ impl<const N: usize> Chart<N, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[must_use]
-    pub fn get_addr_list(&self, id: Id) -> Option<[SocketAddr; N]> {
+    pub fn get_addr_list(&self, id: Id) -> Option<[E::Addr; N]> {
         assert_ne!(self.our_id(), id, "Can not call with our own id");
         let map = self.map.lock().unwrap();
-        let Entry { ip, msg: ports } = map.get(&id)?;
-        let arr = ports.map(|p| SocketAddr::new(*ip, p));
+        let Entry {
+            ip, msg: endpoints, ..
+        } = map.get(&id)?;
+        let arr = endpoints.each_ref().map(|e| e.resolve(*ip));
         Some(arr)
     }
+
+    /// Like [`get_addr_list`](Self::get_addr_list) but if the node is not in
+    /// the Chart yet waits up to `timeout` for it to be discovered. A
+    /// discovery in between looking it up and starting to wait is not missed.
+    ///
+    /// # Note
+    /// returns None if the node was not discovered within `timeout` or the
+    /// chart was [closed](Chart::close)
+    ///
+    /// # Panics
+    /// This function panics when called with the `Id` of the chart instance
+    /// it is called on
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use instance_chart::{discovery, ChartBuilder};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let chart = ChartBuilder::new()
+    ///     .with_id(1)
+    /// #   .with_discovery_port(43843)
+    ///     .with_service_ports([8042, 8043])
+    ///     .finish()?;
+    /// let _ = tokio::spawn(discovery::maintain(chart.clone()));
+    /// let timeout = Duration::from_millis(10);
+    /// if let Some([rpc, _]) = chart.get_or_wait(2, timeout).await {
+    ///     println!("node 2 takes calls on {rpc}");
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub async fn get_or_wait(&self, id: Id, timeout: Duration) -> Option<[E::Addr; N]> {
+        // subscribe before the lookup so nothing is discovered unnoticed
+        let mut discovered = self.notify();
+        if let Some(addrs) = self.get_addr_list(id) {
+            return Some(addrs);
+        }
+
+        let wait = async {
+            loop {
+                match discovered.recv_entry().await {
+                    Ok((new, Entry { ip, msg, .. })) if new == id => {
+                        return Some(msg.each_ref().map(|e| e.resolve(ip)));
+                    }
+                    Ok(_) => (),
+                    Err(RecvError::Lagged(_)) => {
+                        if let Some(addrs) = self.get_addr_list(id) {
+                            return Some(addrs);
+                        }
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        };
+        tokio::time::timeout(timeout, wait).await.ok().flatten()
+    }
 }
 
-impl<const N: usize> Chart<N, Port> {
-    /// Get a nodes nth `SocketAddr`'s given its `Id`
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    /// Get a nodes nth address given its `Id`, see [`Endpoint`]
     ///
     /// # Note
     /// returns None if the node was not in the Chart
@@ -87,21 +149,21 @@ impl<const N: usize> Chart<N, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[must_use]
-    pub fn get_nth_addr<const IDX: usize>(&self, id: Id) -> Option<SocketAddr> {
+    pub fn get_nth_addr<const IDX: usize>(&self, id: Id) -> Option<E::Addr> {
         assert_ne!(self.our_id(), id, "Can not call with our own id");
         let map = self.map.lock().unwrap();
-        let Entry { ip, msg: ports } = map.get(&id)?;
-        let port = ports[IDX];
-        Some(SocketAddr::new(*ip, port))
+        let Entry {
+            ip, msg: endpoints, ..
+        } = map.get(&id)?;
+        Some(endpoints[IDX].resolve(*ip))
     }
 }
 
-impl Chart<1, Port> {
-    /// Get a nodes `SocketAddr`'s given its `Id`
+impl<E: Endpoint> Chart<1, E> {
+    /// Get a nodes address given its `Id`, see [`Endpoint`]
     ///
     /// # Note
     /// returns None if the node was not in the Chart
@@ -135,15 +197,18 @@ impl Chart<1, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[must_use]
-    pub fn get_addr(&self, id: Id) -> Option<SocketAddr> {
+    pub fn get_addr(&self, id: Id) -> Option<E::Addr> {
         assert_ne!(self.our_id(), id, "Can not call with our own id");
         let map = self.map.lock().unwrap();
-        let Entry { ip, msg: [port] } = map.get(&id)?;
-        Some(SocketAddr::new(*ip, *port))
+        let Entry {
+            ip,
+            msg: [endpoint],
+            ..
+        } = map.get(&id)?;
+        Some(endpoint.resolve(*ip))
     }
 }
 
@@ -152,13 +217,14 @@ mod tests {
     use crate::chart::Entry;
     use crate::{Chart, Id};
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::Duration;
 
     #[tokio::test]
     async fn get_addr_list() {
         fn test_kv(n: u8) -> (Id, Entry<[u16; 1]>) {
             let ip = IpAddr::V4(Ipv4Addr::new(n, 0, 0, 1));
             let port = 8000 + n as u16;
-            (n as u64, Entry { ip, msg: [port] })
+            (n as u64, Entry::new(ip, [port]))
         }
 
         let chart = Chart::test(test_kv).await;
@@ -174,13 +240,7 @@ mod tests {
         let port1 = 8000 + n as u16;
         let port2 = 7000 + n as u16;
         let port3 = 6000 + n as u16;
-        (
-            n as u64,
-            Entry {
-                ip,
-                msg: [port1, port2, port3],
-            },
-        )
+        (n as u64, Entry::new(ip, [port1, port2, port3]))
     }
 
     #[tokio::test]
@@ -191,4 +251,35 @@ mod tests {
         let correct = SocketAddr::new(entry.ip, entry.msg[2]);
         assert_eq!(addr, correct)
     }
+
+    #[tokio::test]
+    async fn get_addr_custom_endpoint() {
+        fn test_kv(n: u8) -> (Id, Entry<[String; 1]>) {
+            let ip = IpAddr::V4(Ipv4Addr::new(n, 0, 0, 1));
+            (n as u64, Entry::new(ip, [format!("node{n}.example.org")]))
+        }
+
+        let chart = Chart::test(test_kv).await;
+        assert_eq!(chart.get_addr(2).unwrap(), "node2.example.org");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn get_or_wait() {
+        let chart = Chart::test(entry_3ports).await;
+        let timeout = Duration::from_secs(1);
+        assert_eq!(chart.get_or_wait(2, timeout).await, chart.get_addr_list(2));
+        assert_eq!(chart.get_or_wait(42, timeout).await, None);
+
+        let (id, entry) = entry_3ports(42);
+        let discover = {
+            let chart = chart.clone();
+            async move {
+                tokio::time::sleep(timeout / 2).await;
+                chart.insert(id, entry);
+            }
+        };
+        let (addrs, ()) = tokio::join!(chart.get_or_wait(42, timeout), discover);
+        assert_eq!(addrs, chart.get_addr_list(42));
+        assert!(addrs.is_some());
+    }
 }
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Chart, Endpoint, Entry, Snapshot};
+use crate::Id;
+
+/// Which of an instance's service ports (or custom messages) are healthy,
+/// set using [`Chart::set_port_health`]. Ports are healthy unless marked
+/// otherwise, only the first 64 can be marked.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PortHealth(u64);
+
+impl PortHealth {
+    /// Whether the port at `idx` is healthy
+    #[must_use]
+    pub const fn is_healthy(self, idx: usize) -> bool {
+        idx >= 64 || self.0 & (1 << idx) == 0
+    }
+
+    /// Whether no port is marked unhealthy
+    #[must_use]
+    pub const fn all_healthy(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The raw bitfield of unhealthy ports as sent on the wire
+    #[must_use]
+    pub const fn unhealthy_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Health from a raw bitfield of unhealthy ports
+    #[must_use]
+    pub const fn from_unhealthy_bits(bits: u64) -> Self {
+        PortHealth(bits)
+    }
+}
+
+impl fmt::Debug for PortHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unhealthy: Vec<_> = (0..64).filter(|idx| !self.is_healthy(*idx)).collect();
+        f.debug_struct("PortHealth")
+            .field("unhealthy", &unhealthy)
+            .finish()
+    }
+}
+
+impl<const N: usize, T: fmt::Debug + Clone + Serialize> Chart<N, T> {
+    /// Advertise whether our service port (or custom message) at `idx` is
+    /// healthy. Peers see the change with our next broadcast or reply and can
+    /// skip the port using for example [`healthy_nth_addr_vec`](Chart::healthy_nth_addr_vec).
+    ///
+    /// # Panics
+    /// If `idx` is not smaller then both the number of ports and 64
+    pub fn set_port_health(&self, idx: usize, healthy: bool) {
+        assert!(idx < N.min(64), "only the first 64 ports can be marked");
+        let bit = 1 << idx;
+        let before = if healthy {
+            self.port_health.fetch_and(!bit, Ordering::Relaxed)
+        } else {
+            self.port_health.fetch_or(bit, Ordering::Relaxed)
+        };
+        let was_healthy = before & bit == 0;
+        if was_healthy != healthy {
+            *self.encoded.lock().unwrap() = None;
+        }
+    }
+
+    /// The port health we advertise, see [`set_port_health`](Chart::set_port_health)
+    #[must_use]
+    pub fn our_port_health(&self) -> PortHealth {
+        PortHealth(self.port_health.load(Ordering::Relaxed))
+    }
+}
+
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    /// Like [`nth_addr_vec`](Chart::nth_addr_vec) but leaves out instances
+    /// that marked their nth port unhealthy
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn healthy_nth_addr_vec<const IDX: usize>(&self) -> Vec<(Id, E::Addr)> {
+        self.map
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.port_health.is_healthy(IDX))
+            .map(|(id, Entry { ip, msg, .. })| (*id, msg[IDX].resolve(*ip)))
+            .collect()
+    }
+
+    /// Like [`get_nth_addr`](Chart::get_nth_addr) but returns `None` if the
+    /// instance marked its nth port unhealthy
+    ///
+    /// # Panics
+    /// This function panics when called with the `Id` of the chart instance
+    /// it is called on
+    #[must_use]
+    pub fn get_healthy_nth_addr<const IDX: usize>(&self, id: Id) -> Option<E::Addr> {
+        assert_ne!(self.our_id(), id, "Can not call with our own id");
+        let map = self.map.lock().unwrap();
+        let Entry {
+            ip,
+            msg,
+            port_health,
+            ..
+        } = map.get(&id)?;
+        port_health.is_healthy(IDX).then(|| msg[IDX].resolve(*ip))
+    }
+}
+
+impl<const N: usize, E: Endpoint> Snapshot<N, E> {
+    /// Iterate over each discovered instance's nth address, leaving out
+    /// instances that marked that port unhealthy
+    pub fn healthy_nth_addrs<const IDX: usize>(&self) -> impl Iterator<Item = (Id, E::Addr)> + '_ {
+        self.iter()
+            .filter(|(_, entry)| entry.port_health.is_healthy(IDX))
+            .map(|(id, Entry { ip, msg, .. })| (id, msg[IDX].resolve(*ip)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn entry_2ports(n: u8) -> (Id, Entry<[u16; 2]>) {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+        let mut entry = Entry::new(ip, [8042, 8043]);
+        if n == 2 {
+            entry.port_health = PortHealth::from_unhealthy_bits(0b10);
+        }
+        (n.into(), entry)
+    }
+
+    #[tokio::test]
+    async fn unhealthy_ports_skipped() {
+        let chart = Chart::test(entry_2ports).await;
+        assert_eq!(chart.healthy_nth_addr_vec::<0>().len(), 9);
+        assert_eq!(chart.healthy_nth_addr_vec::<1>().len(), 8);
+        assert!(chart.get_healthy_nth_addr::<0>(2).is_some());
+        assert_eq!(chart.get_healthy_nth_addr::<1>(2), None);
+        assert_eq!(chart.snapshot().healthy_nth_addrs::<1>().count(), 8);
+
+        let healthy = chart.discovery_buf();
+        chart.set_port_health(1, false);
+        assert!(!chart.our_port_health().is_healthy(1));
+        assert!(!std::ptr::eq(&*healthy, &*chart.discovery_buf()));
+        chart.set_port_health(1, true);
+        assert!(chart.our_port_health().all_healthy());
+    }
+}
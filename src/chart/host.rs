@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// the most ips to remember whether they are on this host
+const MAX_CACHED: usize = 64;
+
+/// whether `ip` is an address of this host, binding to an address fails
+/// unless it is
+pub(crate) fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        // binding link-local addresses needs the interface, assume they are ours
+        IpAddr::V6(v6) if v6.is_unicast_link_local() => true,
+        ip => std::net::UdpSocket::bind((ip, 0)).is_ok(),
+    }
+}
+
+/// Remembers which ips are addresses of this host. Finding out needs a bind
+/// syscall, [`is_ours`](Self::is_ours) must not be called with another lock
+/// held.
+#[derive(Debug, Default)]
+pub(crate) struct HostIps {
+    cache: Mutex<HashMap<IpAddr, bool>>,
+}
+
+impl HostIps {
+    /// whether `ip` is on this host if that is known, never blocks on a syscall
+    pub(crate) fn cached(&self, ip: IpAddr) -> Option<bool> {
+        self.cache.lock().unwrap().get(&ip).copied()
+    }
+
+    /// whether `ip` is on this host, checks using a bind syscall if not cached
+    pub(crate) fn is_ours(&self, ip: IpAddr) -> bool {
+        if let Some(local) = self.cached(ip) {
+            return local;
+        }
+        let local = is_local(ip);
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED {
+            cache.clear();
+        }
+        cache.insert(ip, local);
+        local
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn cache_is_bounded() {
+        let host = HostIps::default();
+        assert!(host.is_ours(Ipv4Addr::LOCALHOST.into()));
+        for i in 0..=255 {
+            let ip = Ipv4Addr::new(192, 0, 2, i).into();
+            let local = host.is_ours(ip);
+            assert_eq!(host.cached(ip), Some(local));
+        }
+        assert!(host.cache.lock().unwrap().len() <= MAX_CACHED);
+    }
+}
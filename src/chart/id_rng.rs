@@ -0,0 +1,50 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use rand::RngCore;
+
+use super::Id;
+
+/// Where random ids come from, see
+/// [`ChartBuilder::with_id_rng`](crate::ChartBuilder::with_id_rng)
+#[derive(Clone)]
+pub(crate) struct IdRng(Arc<Mutex<dyn RngCore + Send>>);
+
+impl fmt::Debug for IdRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdRng")
+    }
+}
+
+impl IdRng {
+    pub(crate) fn new(rng: impl RngCore + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(rng)))
+    }
+
+    pub(crate) fn next_id(&self) -> Id {
+        self.0.lock().unwrap().next_u64()
+    }
+}
+
+/// a random id from `rng` or if there is none from the OS
+pub(crate) fn random_id(rng: Option<&IdRng>) -> Id {
+    rng.map_or_else(|| rand::rngs::OsRng.next_u64(), IdRng::next_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn seeded_is_reproducible() {
+        let ids = || {
+            let rng = IdRng::new(StdRng::seed_from_u64(42));
+            [random_id(Some(&rng)), random_id(Some(&rng))]
+        };
+        assert_eq!(ids(), ids());
+        assert_ne!(ids()[0], ids()[1]);
+    }
+}
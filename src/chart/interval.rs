@@ -1,88 +1,199 @@
-use rand::{Rng, SeedableRng};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::time::{sleep_until, Instant};
+use tokio::time::Instant;
+
+use super::builder::{DEFAULT_MAX_INTERVAL, DEFAULT_MIN_INTERVAL, DEFAULT_RAMPDOWN};
+use super::clock::SharedClock;
 
 #[derive(Debug, Clone)]
 pub struct Params {
     pub rampdown: Duration,
     pub min: Duration,
     pub max: Duration,
+    /// skip a broadcast if we just replied to a newcomer
+    pub suppress: bool,
+    /// ramp down again when an instance joins or leaves
+    pub restart_on_change: bool,
+    pub clock: SharedClock,
 }
 
 impl Default for Params {
     fn default() -> Self {
         Params {
-            rampdown: Duration::from_secs(10),
-            min: Duration::from_millis(100),
-            max: Duration::from_secs(1),
+            rampdown: DEFAULT_RAMPDOWN,
+            min: DEFAULT_MIN_INTERVAL,
+            max: DEFAULT_MAX_INTERVAL,
+            suppress: false,
+            restart_on_change: false,
+            clock: SharedClock::default(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Interval {
-    rng: rand::rngs::SmallRng,
-    start: Instant,
+    rng: Jitter,
+    clock: SharedClock,
+    start: Arc<Mutex<Instant>>,
     rampdown: Duration,
     min: Duration,
     max: Duration,
     last_broadcast: Arc<Mutex<Option<Instant>>>,
+    /// when the broadcast we are sleeping towards is due
+    scheduled: Arc<Mutex<Option<Instant>>>,
+    /// when we last replied directly to a newcomer
+    last_reply: Arc<Mutex<Option<Instant>>>,
+    suppress: bool,
+    /// whether the previous broadcast was skipped
+    suppressed: bool,
+    restart_on_change: bool,
 }
 
 impl From<Params> for Interval {
     fn from(p: Params) -> Self {
-        assert!(p.min < p.max);
+        assert!(p.min <= p.max);
         Interval {
             min: p.min,
             max: p.max,
             rampdown: p.rampdown,
-            rng: rand::rngs::SmallRng::from_entropy(),
-            start: Instant::now(),
+            rng: Jitter::new(),
+            start: Arc::new(Mutex::new(p.clock.0.now())),
             last_broadcast: Arc::new(Mutex::new(None)),
+            scheduled: Arc::new(Mutex::new(None)),
+            last_reply: Arc::new(Mutex::new(None)),
+            suppress: p.suppress,
+            suppressed: false,
+            restart_on_change: p.restart_on_change,
+            clock: p.clock,
         }
     }
 }
 
 impl Interval {
-    pub fn now(&mut self) -> Duration {
-        if self.start.elapsed() > self.rampdown {
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.clock.0.now().saturating_duration_since(since)
+    }
+    /// how far the interval has grown from the minimum, scaled by `factor`
+    fn ramp(&self, factor: f32) -> Duration {
+        let x = self.elapsed(*self.start.lock().unwrap());
+        if x >= self.rampdown {
             return self.max;
         }
         let dy = self.max - self.min;
         let dx = self.rampdown;
         let slope = dy.as_secs_f32() / dx.as_secs_f32();
-        let x = self.start.elapsed();
-        let rand = self.rng.gen_range(0.9..1.1);
-        self.min + x.mul_f32(slope).mul_f32(rand)
+        self.min + x.mul_f32(slope).mul_f32(factor)
+    }
+    pub fn now(&mut self) -> Duration {
+        let rand = self.rng.next_factor();
+        self.ramp(rand)
+    }
+    /// the current interval without jitter
+    pub fn current(&self) -> Duration {
+        self.ramp(1.0)
+    }
+    /// ramp down again from the minimal interval
+    pub fn restart(&self) {
+        *self.start.lock().unwrap() = self.clock.0.now();
+    }
+    /// an instance joined or left, restarts the rampdown if configured to
+    pub fn membership_changed(&self) {
+        if self.restart_on_change {
+            self.restart();
+        }
+    }
+    /// the shortest time in between broadcasts
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+    /// the longest time in between broadcasts
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+    /// how long it takes to go from the min to the max interval
+    pub fn rampdown(&self) -> Duration {
+        self.rampdown
     }
     pub async fn sleep_till_next(&mut self) {
-        sleep_until(self.next()).await;
-        *self.last_broadcast.lock().unwrap() = Some(Instant::now());
+        let next = self.next();
+        *self.scheduled.lock().unwrap() = Some(next);
+        self.clock.0.sleep_until(next).await;
+        *self.last_broadcast.lock().unwrap() = Some(self.clock.0.now());
+    }
+    /// remember we just sent our discovery msg directly to a newcomer
+    pub fn replied(&self) {
+        *self.last_reply.lock().unwrap() = Some(self.clock.0.now());
+    }
+    /// whether the broadcast that is due now can be skipped. That is the
+    /// case if suppression is enabled and we replied to a newcomer within the
+    /// last half interval, the newcomer already has our msg. Never skips
+    /// twice in a row so charted instances keep hearing from us.
+    pub fn suppress_broadcast(&mut self) -> bool {
+        let last_reply = *self.last_reply.lock().unwrap();
+        let recent = last_reply.is_some_and(|at| self.elapsed(at) < self.now() / 2);
+        self.suppressed = self.suppress && recent && !self.suppressed;
+        self.suppressed
+    }
+    /// when we last broadcasted, `None` if we never did
+    pub fn last_broadcast(&self) -> Option<Instant> {
+        *self.last_broadcast.lock().unwrap()
     }
     pub fn next(&mut self) -> Instant {
         let last = *self.last_broadcast.lock().unwrap();
         match last {
             Some(last) => last + self.now(),
-            None => Instant::now(),
+            None => self.clock.0.now(),
         }
     }
+    /// time left until the next broadcast is due
+    pub fn until_next(&mut self) -> Duration {
+        self.next().saturating_duration_since(self.clock.0.now())
+    }
+    /// time left until the broadcast that is scheduled is due, zero if none
+    /// is scheduled yet
+    pub fn until_scheduled(&self) -> Duration {
+        let scheduled = *self.scheduled.lock().unwrap();
+        scheduled.map_or(Duration::ZERO, |at| {
+            at.saturating_duration_since(self.clock.0.now())
+        })
+    }
 }
 
-pub trait Until {
-    fn until(&self) -> Duration;
-}
+/// Cheap xorshift generator, the jitter only needs to keep instances from
+/// broadcasting in lockstep
+#[derive(Debug, Clone)]
+pub(crate) struct Jitter(u64);
+
+impl Jitter {
+    pub(crate) fn new() -> Self {
+        // the std hasher is randomly keyed for every process
+        let seed = RandomState::new().build_hasher().finish();
+        Jitter(seed | 1)
+    }
+
+    /// a number in the range 0..1
+    pub(crate) fn next_unit(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
 
-impl Until for tokio::time::Instant {
-    fn until(&self) -> Duration {
-        self.saturating_duration_since(Instant::now())
+    /// a factor in the range 0.9..1.1
+    fn next_factor(&mut self) -> f32 {
+        0.9 + self.next_unit() * 0.2
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chart::clock::Clock;
     use more_asserts::*;
+    use std::future::Future;
+    use std::pin::Pin;
     use tokio::time::sleep_until;
 
     impl Interval {
@@ -91,6 +202,7 @@ mod tests {
                 min: Duration::from_secs(0),
                 max: Duration::from_secs(1),
                 rampdown: Duration::from_secs(1),
+                ..Params::default()
             }
             .into()
         }
@@ -102,10 +214,100 @@ mod tests {
         let mut interval = Interval::test();
 
         for i in 1..=10 {
-            call_next = call_next + Duration::from_secs_f32(0.1);
+            call_next += Duration::from_secs_f32(0.1);
             sleep_until(call_next).await;
             let correct = Duration::from_secs_f32(0.1 * (i as f32)).as_millis();
             assert_lt!(u128::abs_diff(interval.now().as_millis(), correct), i * 20);
         }
     }
+
+    #[derive(Debug)]
+    struct Manual(Mutex<Instant>);
+
+    impl Clock for Manual {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+        fn sleep_until(&self, _: Instant) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    #[test]
+    fn rampdown_follows_clock() {
+        let start = Instant::now();
+        let clock = Arc::new(Manual(Mutex::new(start)));
+        let mut interval: Interval = Params {
+            rampdown: Duration::from_secs(10),
+            min: Duration::from_secs(1),
+            max: Duration::from_secs(5),
+            suppress: false,
+            restart_on_change: false,
+            clock: SharedClock(clock.clone()),
+        }
+        .into();
+        assert_eq!(interval.now(), Duration::from_secs(1));
+        assert_eq!(interval.until_next(), Duration::ZERO);
+
+        *clock.0.lock().unwrap() = start + Duration::from_secs(5);
+        assert_eq!(interval.current(), Duration::from_secs(3));
+        *clock.0.lock().unwrap() = start + Duration::from_secs(10);
+        assert_eq!(interval.now(), Duration::from_secs(5));
+        interval.restart();
+        assert_eq!(interval.now(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn scheduled_broadcast() {
+        let clock = Arc::new(Manual(Mutex::new(Instant::now())));
+        let mut interval: Interval = Params {
+            min: Duration::from_secs(1),
+            clock: SharedClock(clock.clone()),
+            ..Params::default()
+        }
+        .into();
+        assert_eq!(interval.until_scheduled(), Duration::ZERO);
+        interval.sleep_till_next().await;
+        assert_eq!(interval.until_scheduled(), Duration::ZERO);
+        interval.sleep_till_next().await;
+        assert_eq!(interval.until_scheduled(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn restart_on_membership_change() {
+        let start = Instant::now();
+        let clock = Arc::new(Manual(Mutex::new(start)));
+        let params = |restart_on_change| Params {
+            restart_on_change,
+            clock: SharedClock(clock.clone()),
+            ..Params::default()
+        };
+        let fixed: Interval = params(false).into();
+        let restarting: Interval = params(true).into();
+
+        *clock.0.lock().unwrap() = start + Duration::from_secs(20);
+        fixed.membership_changed();
+        restarting.membership_changed();
+        assert_eq!(fixed.current(), fixed.max());
+        assert_eq!(restarting.current(), restarting.min());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn suppress_after_reply() {
+        let mut interval: Interval = Params {
+            suppress: true,
+            ..Params::default()
+        }
+        .into();
+        assert!(!interval.suppress_broadcast());
+
+        interval.replied();
+        assert!(interval.suppress_broadcast());
+        interval.replied();
+        assert!(!interval.suppress_broadcast(), "never skip twice in a row");
+
+        interval.replied();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!interval.suppress_broadcast(), "reply was too long ago");
+    }
 }
@@ -0,0 +1,144 @@
+use std::fmt::Debug;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use super::{Chart, Entry, Event, Id};
+
+/// One line of the log
+#[derive(Serialize)]
+#[serde(bound = "Entry<Msg>: Serialize")]
+struct Line<'a, Msg: Debug + Clone> {
+    /// milliseconds since the unix epoch
+    time_ms: u128,
+    /// the instance writing the log
+    chart: Id,
+    #[serde(flatten)]
+    record: Record<'a, Msg>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case", bound = "Entry<Msg>: Serialize")]
+enum Record<'a, Msg: Debug + Clone> {
+    Discovered { id: Id, entry: &'a Entry<Msg> },
+    Event(&'a Event),
+}
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Send + Sync,
+    Entry<[T; N]>: Serialize,
+{
+    /// Write every newly discovered instance and every [`Event`] to `out` as
+    /// a line of JSON. Each line has the time in milliseconds since the unix
+    /// epoch, our id and either the discovered instance or the event:
+    /// ```text
+    /// {"time_ms":1700000000000,"chart":1,"discovered":{"id":2,"entry":{"ip":"10.0.0.2",..}}}
+    /// {"time_ms":1700000002000,"chart":1,"event":{"Left":{"id":2}}}
+    /// ```
+    /// Writing happens on a task spawned on the current tokio runtime, it
+    /// stops once the chart is [closed](Chart::close), writing fails or when
+    /// the returned handle is aborted. `out` is flushed after every line.
+    ///
+    /// Only available with the `json-log` feature.
+    ///
+    /// # Note
+    /// Writes block the task, pass something fast such as a file. If more
+    /// then 256 discoveries or events queue up the oldest are skipped.
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime
+    pub fn log_json(&self, mut out: impl io::Write + Send + 'static) -> JoinHandle<()> {
+        let mut discoveries = self.broadcast.subscribe();
+        let mut events = self.events.subscribe();
+        let chart = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let res = tokio::select! {
+                    res = discoveries.recv() => res.map(|(id, entry)| {
+                        chart.write_line(&mut out, Record::Discovered { id, entry: &entry })
+                    }),
+                    res = events.recv() => res.map(|event| {
+                        chart.write_line(&mut out, Record::Event(&event))
+                    }),
+                    () = chart.closed() => return,
+                };
+                match res {
+                    Ok(Ok(())) => (),
+                    Ok(Err(err)) => {
+                        warn!("stopped json log, could not write: {err}");
+                        return;
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("json log too slow, skipped {n} lines");
+                        chart.counters.lagged(n);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+
+    fn write_line(&self, out: &mut impl io::Write, record: Record<'_, [T; N]>) -> io::Result<()> {
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let line = Line {
+            time_ms,
+            chart: self.our_id(),
+            record,
+        };
+        serde_json::to_writer(&mut *out, &line)?;
+        out.write_all(b"\n")?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::chart::fixtures::entry;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_discoveries_and_events() {
+        let chart = Chart::test(entry).await;
+        let out = Shared::default();
+        chart.log_json(out.clone());
+        tokio::task::yield_now().await;
+
+        chart.insert(42, entry(42).1);
+        tokio::task::yield_now().await;
+        let _ = chart.events.send(Event::Left { id: 42 });
+        tokio::task::yield_now().await;
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["discovered"]["id"], 42);
+        assert_eq!(lines[0]["discovered"]["entry"]["ip"], "10.0.0.42");
+        assert_eq!(lines[1]["event"]["Left"]["id"], 42);
+        assert!(lines.iter().all(|line| line["time_ms"].is_u64()));
+    }
+}
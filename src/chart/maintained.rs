@@ -0,0 +1,81 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::Chart;
+use crate::DiscoveryError;
+
+/// How long waiting on the chart may go on without anything running
+/// discovery before that is reported
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Marks the chart as maintained until dropped, held by everything that
+/// drives discovery
+#[derive(Debug)]
+pub(crate) struct Maintained(Arc<AtomicUsize>);
+
+impl Drop for Maintained {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    pub(crate) fn maintained(&self) -> Maintained {
+        self.drivers.fetch_add(1, Ordering::Relaxed);
+        Maintained(Arc::clone(&self.drivers))
+    }
+
+    /// Whether discovery is running for this chart, that is
+    /// [`maintain`](crate::discovery::maintain),
+    /// [`sniff`](crate::discovery::sniff) or a
+    /// [`Driver`](crate::discovery::Driver) is working on it.
+    ///
+    /// Without discovery the chart never changes and waiting on it, for
+    /// example using [`found_everyone`](crate::discovery::found_everyone),
+    /// takes forever.
+    #[must_use]
+    pub fn is_maintained(&self) -> bool {
+        self.drivers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Never returns. Reports [`DiscoveryError::NotMaintained`] if nothing
+    /// runs discovery once the grace period is over. Race with a wait.
+    pub(crate) async fn report_if_not_maintained(&self) {
+        tokio::time::sleep(GRACE).await;
+        if !self.is_maintained() {
+            self.report(DiscoveryError::NotMaintained { waited: GRACE });
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry;
+    use crate::Event;
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_unmaintained_is_reported() {
+        let chart = Chart::test(entry).await;
+        let mut events = chart.events();
+        let wait = tokio::time::timeout(2 * GRACE, chart.await_size(100)).await;
+        assert!(wait.is_err());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(Event::Error(DiscoveryError::NotMaintained { .. }))
+        ));
+
+        let guard = chart.maintained();
+        assert!(chart.is_maintained());
+        let wait = tokio::time::timeout(2 * GRACE, chart.await_size(100)).await;
+        assert!(wait.is_err());
+        assert!(events.try_recv().is_err());
+        drop(guard);
+        assert!(!chart.is_maintained());
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::store::{ChartStore, Store};
+use super::Entry;
+use crate::Id;
+
+/// Hashes an [`Id`] with a single multiply, much cheaper than the default
+/// SipHash. Ids are not secret, hash flooding protection buys us nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        // constant from FxHash, spreads sequential ids over all buckets
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+type IdBuildHasher = BuildHasherDefault<IdHasher>;
+pub(crate) type Entries<Msg> = HashMap<Id, Entry<Msg>, IdBuildHasher>;
+
+/// cheap fingerprint of a raw discovery message
+pub(crate) fn fingerprint(buf: &[u8]) -> u64 {
+    let mut hasher = IdHasher::default();
+    hasher.write(buf);
+    hasher.write_u64(buf.len() as u64);
+    hasher.finish()
+}
+
+/// the most addresses to remember another instance with our id used
+const MAX_OWN_ID_FROM: usize = 64;
+
+/// The discovered entries keyed by [`Id`] with a secondary index on the ip
+/// they were discovered on. Multiple instances can share an ip when they run
+/// on the same host.
+#[derive(Debug)]
+pub(crate) struct Map<Msg: Debug + Clone> {
+    /// the entries themselves, in memory unless a [`ChartStore`] was set
+    store: Store<Msg>,
+    by_ip: HashMap<IpAddr, Vec<Id>>,
+    /// ids that are ignored due to [`ConflictPolicy::RejectBoth`](crate::ConflictPolicy::RejectBoth)
+    rejected: HashSet<Id, IdBuildHasher>,
+    /// ids that sent a different number of values then we expect
+    mismatched: HashSet<Id, IdBuildHasher>,
+    /// addresses not on this host that sent messages with our id, the
+    /// collision was reported for these
+    own_id_from: HashSet<IpAddr>,
+    seen: HashMap<Id, Seen, IdBuildHasher>,
+}
+
+/// When and what we last heard from an entry
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    /// fingerprint of the last raw message the entry was updated from
+    fingerprint: u64,
+    /// the ip the entry was charted under when it was updated
+    ip: IpAddr,
+    /// the ip the message came from, middleware may chart the entry under
+    /// another
+    from: IpAddr,
+    at: Instant,
+    /// the longest the instance waits in between broadcasts
+    interval: Duration,
+    /// how long the instance asked to stay charted without being heard from
+    ttl: Option<Duration>,
+}
+
+impl<Msg: Debug + Clone> Default for Map<Msg> {
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
+}
+
+impl<Msg: Debug + Clone> Map<Msg> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// pre-allocate room for `capacity` entries so the map does not have to
+    /// grow while the lock is held in the receive path
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            store: Store::with_capacity(capacity),
+            by_ip: HashMap::with_capacity(capacity),
+            rejected: HashSet::default(),
+            mismatched: HashSet::default(),
+            own_id_from: HashSet::new(),
+            seen: HashMap::with_capacity_and_hasher(capacity, IdBuildHasher::default()),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: Id, entry: Entry<Msg>) -> Option<Entry<Msg>> {
+        let old = self.store.insert(id, entry);
+        if let Some(old) = &old {
+            self.unindex(id, old.ip);
+        }
+        // a custom store may keep another entry or none at all
+        match self.store.get(&id).map(|kept| kept.ip) {
+            Some(ip) => self.index(id, ip),
+            None => {
+                self.seen.remove(&id);
+            }
+        }
+        old
+    }
+
+    pub(crate) fn remove(&mut self, id: &Id) -> Option<Entry<Msg>> {
+        let old = self.store.remove(id)?;
+        self.seen.remove(id);
+        self.unindex(*id, old.ip);
+        Some(old)
+    }
+
+    /// keep the entries in `store` from now on, the current entries are
+    /// moved into it and anything else it held is removed
+    pub(crate) fn set_store(&mut self, mut store: Box<dyn ChartStore<Msg>>) {
+        let stale: Vec<Id> = store
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| self.store.get(id).is_none())
+            .collect();
+        for id in stale {
+            store.remove(id);
+        }
+        for (id, entry) in self.store.iter() {
+            store.insert(*id, entry.clone());
+        }
+        self.store = Store::Custom(store);
+
+        // the store may not have kept every entry as it was
+        self.by_ip.clear();
+        let kept: Vec<_> = self.store.iter().map(|(id, e)| (*id, e.ip)).collect();
+        for (id, ip) in kept {
+            self.index(id, ip);
+        }
+        let store = &self.store;
+        self.seen.retain(|id, _| store.get(id).is_some());
+    }
+
+    /// true if `id` is not charted and the store has no room for it
+    pub(crate) fn is_full_for(&self, id: &Id) -> bool {
+        self.store.get(id).is_none()
+            && self
+                .store
+                .limit()
+                .is_some_and(|capacity| self.store.len() >= capacity)
+    }
+
+    fn index(&mut self, id: Id, ip: IpAddr) {
+        let ids = self.by_ip.entry(ip).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    fn unindex(&mut self, id: Id, ip: IpAddr) {
+        if let Some(ids) = self.by_ip.get_mut(&ip) {
+            ids.retain(|i| *i != id);
+            if ids.is_empty() {
+                self.by_ip.remove(&ip);
+            }
+        }
+    }
+
+    /// remove the entry and ignore the id until [`allow`](Self::allow) is called
+    pub(crate) fn reject(&mut self, id: Id) {
+        self.remove(&id);
+        self.rejected.insert(id);
+    }
+
+    pub(crate) fn allow(&mut self, id: Id) {
+        self.rejected.remove(&id);
+    }
+
+    /// remember `id` sends a different number of values, returns true
+    /// the first time
+    pub(crate) fn mismatch(&mut self, id: Id) -> bool {
+        self.mismatched.insert(id)
+    }
+
+    /// another instance at `ip` uses our id, returns true if that was not
+    /// yet reported for `ip`
+    pub(crate) fn own_id_from(&mut self, ip: IpAddr) -> bool {
+        if self.own_id_from.len() >= MAX_OWN_ID_FROM && !self.own_id_from.contains(&ip) {
+            self.own_id_from.clear();
+        }
+        self.own_id_from.insert(ip)
+    }
+
+    /// our id changed, instances that used the old one may not use the new
+    pub(crate) fn forget_own_id_senders(&mut self) {
+        self.own_id_from.clear();
+    }
+
+    pub(crate) fn is_rejected(&self, id: &Id) -> bool {
+        self.rejected.contains(id)
+    }
+
+    /// remember when we heard from the entry for `id` on `ip` through a
+    /// message sent `from`, what raw message it was updated from and the
+    /// interval and ttl it advertised. Does nothing if the entry is charted
+    /// under another ip.
+    pub(crate) fn set_seen(
+        &mut self,
+        id: Id,
+        ip: IpAddr,
+        from: IpAddr,
+        fingerprint: u64,
+        interval: Duration,
+        ttl: Option<Duration>,
+    ) {
+        if self.store.get(&id).is_some_and(|e| e.ip == ip) {
+            let at = Instant::now();
+            self.seen.insert(
+                id,
+                Seen {
+                    fingerprint,
+                    ip,
+                    from,
+                    at,
+                    interval,
+                    ttl,
+                },
+            );
+        }
+    }
+
+    /// true if `id` is still charted under the ip it was last updated with,
+    /// that update came `from` the same ip and from a raw message with the
+    /// same fingerprint. If so it is marked as seen just now.
+    pub(crate) fn heartbeat(&mut self, id: Id, from: IpAddr, fingerprint: u64) -> bool {
+        let Some(seen) = self.seen.get_mut(&id) else {
+            return false;
+        };
+        if self.store.get(&id).is_none_or(|e| e.ip != seen.ip) {
+            return false;
+        }
+        if seen.from != from || seen.fingerprint != fingerprint {
+            return false;
+        }
+        seen.at = Instant::now();
+        true
+    }
+
+    /// ids not heard from for longer then the ttl they advertised or else
+    /// `factor` times the interval they advertised, plus `grace`. Without
+    /// `factor` only ids that advertised a ttl expire.
+    pub(crate) fn expired(&self, now: Instant, factor: Option<u32>, grace: Duration) -> Vec<Id> {
+        self.seen
+            .iter()
+            .filter(|(_, seen)| {
+                let limit = seen.ttl.or_else(|| factor.map(|f| seen.interval * f));
+                limit.is_some_and(|limit| now.duration_since(seen.at) > limit + grace)
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// act as if every entry was heard from `by` later then it was, used
+    /// to not count the time the chart was frozen
+    pub(crate) fn shift_seen(&mut self, by: Duration) {
+        let now = Instant::now();
+        for seen in self.seen.values_mut() {
+            seen.at = (seen.at + by).min(now);
+        }
+    }
+
+    /// when we last heard from `id`, None if we never did
+    pub(crate) fn last_seen(&self, id: &Id) -> Option<Instant> {
+        self.seen.get(id).map(|seen| seen.at)
+    }
+
+    /// whether `id` was heard from no longer then `max_age` before `now`
+    pub(crate) fn heard_within(&self, id: &Id, now: Instant, max_age: Duration) -> bool {
+        self.seen
+            .get(id)
+            .is_some_and(|seen| now.duration_since(seen.at) <= max_age)
+    }
+
+    /// number of entries heard from within the interval they advertised
+    pub(crate) fn heard_within_interval(&self, now: Instant) -> usize {
+        self.seen
+            .values()
+            .filter(|seen| now.duration_since(seen.at) <= seen.interval)
+            .count()
+    }
+
+    pub(crate) fn get(&self, id: &Id) -> Option<&Entry<Msg>> {
+        self.store.get(id)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Id, &Entry<Msg>)> {
+        self.store.iter()
+    }
+
+    /// The entries as they are now, later changes to the map do not show up
+    pub(crate) fn snapshot(&self) -> Arc<Entries<Msg>> {
+        self.store.snapshot()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// The ids of all entries discovered on `ip`
+    pub(crate) fn ids_on(&self, ip: &IpAddr) -> &[Id] {
+        self.by_ip.get(ip).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl<Msg: Debug + Clone> FromIterator<(Id, Entry<Msg>)> for Map<Msg> {
+    fn from_iter<I: IntoIterator<Item = (Id, Entry<Msg>)>>(iter: I) -> Self {
+        let mut map = Map::new();
+        for (id, entry) in iter {
+            map.insert(id, entry);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures;
+
+    fn entry(n: u8) -> Entry<[u16; 1]> {
+        fixtures::entry(n).1
+    }
+
+    #[test]
+    fn index_follows_ip_change() {
+        let mut map = Map::new();
+        map.insert(1, entry(1));
+        map.insert(2, entry(1));
+        assert_eq!(map.ids_on(&entry(1).ip), &[1, 2]);
+
+        map.insert(2, entry(2));
+        assert_eq!(map.ids_on(&entry(1).ip), &[1]);
+        assert_eq!(map.ids_on(&entry(2).ip), &[2]);
+
+        map.remove(&1);
+        assert!(map.ids_on(&entry(1).ip).is_empty());
+    }
+
+    #[test]
+    fn fingerprint_tracks_entry() {
+        let interval = Duration::from_secs(1);
+        let mut map = Map::new();
+        map.insert(1, entry(1));
+        map.set_seen(1, entry(1).ip, entry(1).ip, 42, interval, None);
+        assert!(map.heartbeat(1, entry(1).ip, 42));
+        assert!(!map.heartbeat(1, entry(1).ip, 43));
+        assert!(!map.heartbeat(1, entry(2).ip, 42));
+
+        map.set_seen(1, entry(2).ip, entry(2).ip, 43, interval, None);
+        assert!(map.heartbeat(1, entry(1).ip, 42));
+
+        map.remove(&1);
+        map.insert(1, entry(1));
+        assert!(!map.heartbeat(1, entry(1).ip, 42));
+    }
+
+    #[test]
+    fn heartbeat_from_original_sender() {
+        let interval = Duration::from_secs(1);
+        let mut map = Map::new();
+        map.insert(1, entry(1));
+        map.set_seen(1, entry(1).ip, entry(2).ip, 42, interval, None);
+        assert!(map.heartbeat(1, entry(2).ip, 42));
+        assert!(!map.heartbeat(1, entry(1).ip, 42));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expiry_follows_advertised_interval() {
+        let mut map = Map::new();
+        map.insert(1, entry(1));
+        map.insert(2, entry(2));
+        let secs = Duration::from_secs;
+        map.set_seen(1, entry(1).ip, entry(1).ip, 0, secs(1), None);
+        map.set_seen(2, entry(2).ip, entry(2).ip, 0, secs(10), None);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(map.expired(Instant::now(), Some(3), Duration::ZERO), [1]);
+
+        map.heartbeat(1, entry(1).ip, 0);
+        assert!(map
+            .expired(Instant::now(), Some(3), Duration::ZERO)
+            .is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn advertised_ttl_overrides_expiry() {
+        let mut map = Map::new();
+        map.insert(1, entry(1));
+        map.insert(2, entry(2));
+        let interval = Duration::from_secs(1);
+        let ttl = |secs| Some(Duration::from_secs(secs));
+        map.set_seen(1, entry(1).ip, entry(1).ip, 0, interval, ttl(20));
+        map.set_seen(2, entry(2).ip, entry(2).ip, 0, interval, ttl(2));
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(map.expired(Instant::now(), Some(3), Duration::ZERO), [2]);
+        assert_eq!(map.expired(Instant::now(), None, Duration::ZERO), [2]);
+    }
+
+    #[test]
+    fn preallocated() {
+        let mut map = Map::with_capacity(1000);
+        let capacity = |map: &Map<_>| match &map.store {
+            Store::Memory(entries) => entries.capacity(),
+            Store::Custom(_) => unreachable!("no store set"),
+        };
+        let before = capacity(&map);
+        assert!(before >= 1000);
+        for n in 0..1000 {
+            map.insert(n, entry(n as u8));
+        }
+        assert_eq!(capacity(&map), before);
+        assert_eq!(map.get(&999).unwrap().ip, entry((999 % 256) as u8).ip);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        entries: HashMap<Id, Entry<[u16; 1]>>,
+        log: Arc<std::sync::Mutex<Vec<(Id, bool)>>>,
+    }
+
+    impl ChartStore<[u16; 1]> for Recorder {
+        fn get(&self, id: Id) -> Option<&Entry<[u16; 1]>> {
+            self.entries.get(&id)
+        }
+        fn insert(&mut self, id: Id, entry: Entry<[u16; 1]>) -> Option<Entry<[u16; 1]>> {
+            self.log.lock().unwrap().push((id, true));
+            self.entries.insert(id, entry)
+        }
+        fn remove(&mut self, id: Id) -> Option<Entry<[u16; 1]>> {
+            self.log.lock().unwrap().push((id, false));
+            self.entries.remove(&id)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<[u16; 1]>)> + '_> {
+            Box::new(self.entries.iter())
+        }
+        fn len(&self) -> usize {
+            self.entries.len()
+        }
+        fn capacity(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    #[test]
+    fn entries_live_in_store() {
+        let mut map: Map<_> = [(1, entry(1))].into_iter().collect();
+        let mut store = Recorder::default();
+        store.entries.insert(5, entry(5));
+        let log = Arc::clone(&store.log);
+        map.set_store(Box::new(store));
+        assert!(map.get(&5).is_none());
+        assert_eq!(map.get(&1).unwrap().ip, entry(1).ip);
+
+        map.insert(2, entry(2));
+        assert!(map.is_full_for(&3));
+        assert!(!map.is_full_for(&2));
+        map.remove(&1);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.snapshot().keys().collect::<Vec<_>>(), [&2]);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [(5, false), (1, true), (2, true), (1, false)]
+        );
+    }
+}
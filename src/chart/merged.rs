@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{Chart, Entry, Event, Map, Notify};
+use crate::util;
+use crate::Id;
+
+/// the entries of the source charts, shared with the forwarders
+type Maps<Msg> = Arc<Mutex<Vec<Arc<Mutex<Map<Msg>>>>>>;
+
+/// An entry in a [`MergedChart`] together with the names of all the sources
+/// it was discovered by.
+#[derive(Debug, Clone)]
+pub struct MergedEntry<Msg: Debug + Clone> {
+    pub id: Id,
+    /// the entry as found in the first source (in the order they where added) that has it
+    pub entry: Entry<Msg>,
+    pub sources: Vec<String>,
+}
+
+/// Unions the entries of multiple charts, for example when discovering using
+/// different headers or discovery ports. Entries are deduplicated by [`Id`]
+/// and the sources each was found in are kept.
+///
+/// # Examples
+/// ```rust
+/// # use std::error::Error;
+/// # use instance_chart::{discovery, ChartBuilder, MergedChart};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let lan = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+/// #   .with_discovery_port(43790)
+///     .finish()?;
+/// let local = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+///     .with_discovery_port(43791)
+///     .local_discovery(true)
+///     .finish()?;
+/// tokio::spawn(discovery::maintain(lan.clone()));
+/// tokio::spawn(discovery::maintain(local.clone()));
+///
+/// let merged = MergedChart::new()
+///     .with_source("lan", lan)
+///     .with_source("local", local);
+/// let mut node_discoverd = merged.notify();
+/// assert_eq!(merged.size(), 1);
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MergedChart<const N: usize, T: Debug + Clone + Serialize> {
+    sources: Vec<(String, Chart<N, T>)>,
+    broadcast: broadcast::Sender<(Id, Entry<[T; N]>)>,
+    /// ids charted by a source that were notified about
+    notified: Arc<Mutex<HashSet<Id>>>,
+    maps: Maps<[T; N]>,
+    forwarders: Vec<util::Wrapper<()>>,
+}
+
+impl<const N: usize, T> Default for MergedChart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, T> MergedChart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Create a merged chart without any sources
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            broadcast: broadcast::channel(256).0,
+            notified: Arc::new(Mutex::new(HashSet::new())),
+            maps: Arc::default(),
+            forwarders: Vec::new(),
+        }
+    }
+
+    /// Add a chart to merge, the `name` is listed in the
+    /// [`sources`](MergedEntry::sources) of the entries it discovered.
+    ///
+    /// # Panics
+    /// Must be called from within a tokio runtime, a task forwarding
+    /// discoveries is spawned.
+    #[must_use]
+    pub fn with_source(mut self, name: impl Into<String>, chart: Chart<N, T>) -> Self {
+        let discoveries = chart.notify();
+        let events = chart.events();
+        self.maps.lock().unwrap().push(chart.map.clone());
+        self.forwarders.push(util::spawn(forward(
+            discoveries,
+            events,
+            self.broadcast.clone(),
+            self.notified.clone(),
+            self.maps.clone(),
+            chart.map.clone(),
+        )));
+        self.sources.push((name.into(), chart));
+        self
+    }
+
+    /// Wait for discoveries from any of the sources. An [`Id`] is only notified
+    /// the first time any source discovers it, and again once it returns
+    /// after no source charted it any more.
+    #[must_use]
+    pub fn notify(&self) -> Notify<N, T> {
+        Notify {
+            discoveries: self.broadcast.subscribe(),
+            counters: None,
+            group: None,
+            closed: None,
+        }
+    }
+
+    /// number of distinct instances across all sources including the
+    /// instances of the source charts themselves
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn size(&self) -> usize {
+        let mut ids = HashSet::new();
+        for (_, chart) in &self.sources {
+            ids.insert(chart.our_id());
+            ids.extend(chart.map.lock().unwrap().iter().map(|(id, _)| *id));
+        }
+        ids.len()
+    }
+
+    /// Get the entry for an instance and the sources that discovered it
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn get(&self, id: Id) -> Option<MergedEntry<[T; N]>> {
+        let mut merged: Option<MergedEntry<_>> = None;
+        for (name, chart) in &self.sources {
+            let map = chart.map.lock().unwrap();
+            let Some(entry) = map.get(&id) else {
+                continue;
+            };
+            match merged.as_mut() {
+                Some(merged) => merged.sources.push(name.clone()),
+                None => {
+                    merged = Some(MergedEntry {
+                        id,
+                        entry: entry.clone(),
+                        sources: vec![name.clone()],
+                    });
+                }
+            }
+        }
+        merged
+    }
+
+    /// All discovered entries across the sources, deduplicated by [`Id`]
+    /// # Note
+    /// vector order is random
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn entries(&self) -> Vec<MergedEntry<[T; N]>> {
+        let mut merged: HashMap<Id, MergedEntry<_>> = HashMap::new();
+        for (name, chart) in &self.sources {
+            let map = chart.map.lock().unwrap();
+            for (id, entry) in map.iter() {
+                merged
+                    .entry(*id)
+                    .and_modify(|merged| merged.sources.push(name.clone()))
+                    .or_insert_with(|| MergedEntry {
+                        id: *id,
+                        entry: entry.clone(),
+                        sources: vec![name.clone()],
+                    });
+            }
+        }
+        merged.into_values().collect()
+    }
+}
+
+async fn forward<const N: usize, T: Debug + Clone>(
+    mut discoveries: Notify<N, T>,
+    mut events: broadcast::Receiver<Event>,
+    broadcast: broadcast::Sender<(Id, Entry<[T; N]>)>,
+    notified: Arc<Mutex<HashSet<Id>>>,
+    maps: Maps<[T; N]>,
+    own: Arc<Mutex<Map<[T; N]>>>,
+) {
+    let charted = |id: &Id| {
+        let maps = maps.lock().unwrap();
+        maps.iter().any(|map| map.lock().unwrap().get(id).is_some())
+    };
+    let charted_elsewhere = |id: &Id| {
+        let maps = maps.lock().unwrap();
+        maps.iter()
+            .filter(|map| !Arc::ptr_eq(map, &own))
+            .any(|map| map.lock().unwrap().get(id).is_some())
+    };
+    loop {
+        // ends once the source chart is closed
+        tokio::select! {
+            // a discovery always precedes the source forgetting it again
+            biased;
+            discovery = discoveries.recv_entry() => {
+                let (id, entry) = match discovery {
+                    Ok(discovery) => discovery,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+                // not every removal sends an event, for example a conflict,
+                // if no other source charts it the id returned
+                let new = notified.lock().unwrap().insert(id) || !charted_elsewhere(&id);
+                if new {
+                    // errors if there are no active recievers which is
+                    // the default and not a problem
+                    let _ig_err = broadcast.send((id, entry));
+                }
+            }
+            event = events.recv() => match event {
+                Ok(
+                    Event::Left { id }
+                    | Event::Expired { id }
+                    | Event::Forgotten { id }
+                    | Event::Unreachable { id, .. },
+                ) => {
+                    if !charted(&id) {
+                        notified.lock().unwrap().remove(&id);
+                    }
+                }
+                Ok(_) => (),
+                // missed which ids left, check them all
+                Err(RecvError::Lagged(_)) => {
+                    let ids: Vec<Id> = notified.lock().unwrap().iter().copied().collect();
+                    let gone: Vec<Id> = ids.into_iter().filter(|id| !charted(id)).collect();
+                    let mut notified = notified.lock().unwrap();
+                    for id in gone {
+                        notified.remove(&id);
+                    }
+                }
+                Err(RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry;
+    use crate::chart::{DiscoveryMsg, Status};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    fn shifted(n: u8) -> (Id, Entry<[u16; 1]>) {
+        entry(n + 5)
+    }
+
+    #[tokio::test]
+    async fn union_with_provenance() {
+        let a = Chart::test(entry).await;
+        let b = Chart::test(shifted).await;
+        let merged = MergedChart::new().with_source("a", a).with_source("b", b);
+
+        // ids 1..=14 and our own id 0
+        assert_eq!(merged.size(), 15);
+        assert_eq!(merged.entries().len(), 14);
+        assert_eq!(merged.get(2).unwrap().sources, ["a"]);
+        assert_eq!(merged.get(7).unwrap().sources, ["a", "b"]);
+        assert_eq!(merged.get(12).unwrap().sources, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn notify_deduplicates() {
+        let a = Chart::test(entry).await;
+        let b = Chart::test(entry).await;
+        let merged = MergedChart::new()
+            .with_source("a", a.clone())
+            .with_source("b", b.clone());
+        let mut notify = merged.notify();
+
+        a.insert(42, entry(42).1);
+        b.insert(42, entry(42).1);
+        b.insert(43, entry(43).1);
+
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+        assert_eq!(notify.recv().await.unwrap().0, 43);
+    }
+
+    /// instance 42 says goodbye to `chart`
+    fn leave(chart: &Chart<1, u16>) {
+        let msg = DiscoveryMsg {
+            id: 42,
+            status: Status::Leaving,
+            ..chart.discovery_msg()
+        };
+        let buf = chart.wire_format.encode(&msg).unwrap();
+        let addr = SocketAddr::new(entry(42).1.ip, 8080);
+        chart.process_bufs([(buf.as_slice(), addr)].into_iter(), &mut Vec::new());
+    }
+
+    #[tokio::test]
+    async fn notified_again_after_leaving_every_source() {
+        let a = Chart::test(entry).await;
+        let b = Chart::test(entry).await;
+        let merged = MergedChart::new()
+            .with_source("a", a.clone())
+            .with_source("b", b.clone());
+        let mut notify = merged.notify();
+        // lets the forwarders handle the goodbyes
+        let settle = || tokio::time::sleep(Duration::from_millis(20));
+
+        a.insert(42, entry(42).1);
+        b.insert(42, entry(42).1);
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+
+        leave(&a);
+        settle().await;
+        a.insert(42, entry(42).1);
+        a.insert(43, entry(43).1);
+        assert_eq!(
+            notify.recv().await.unwrap().0,
+            43,
+            "42 was still charted by b"
+        );
+
+        leave(&a);
+        leave(&b);
+        settle().await;
+        b.insert(42, entry(42).1);
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+
+        b.forget(42);
+        settle().await;
+        b.insert(42, entry(42).1);
+        assert_eq!(notify.recv().await.unwrap().0, 42, "forgotten by b");
+    }
+
+    #[tokio::test]
+    async fn notified_again_after_silent_removal() {
+        let a = Chart::test(entry).await;
+        let merged = MergedChart::new().with_source("a", a.clone());
+        let mut notify = merged.notify();
+
+        a.insert(42, entry(42).1);
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+
+        // removed without an event
+        a.map.lock().unwrap().remove(&42);
+        a.insert(42, entry(42).1);
+        assert_eq!(notify.recv().await.unwrap().0, 42);
+    }
+}
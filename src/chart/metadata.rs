@@ -0,0 +1,171 @@
+use std::collections::{btree_map, BTreeMap};
+use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Chart, DiscoveryMsg, MAX_DATAGRAM};
+use crate::{Id, MetaError};
+
+/// Key value pairs an instance shares along with its message, set them
+/// using [`Chart::put_meta`]. Each instance is the only writer of its own
+/// metadata, a newer version replaces an older one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Metadata {
+    /// milliseconds since the unix epoch of the last change
+    version: u64,
+    pairs: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// The value for `key` if it is set
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.get(key).map(String::as_str)
+    }
+
+    /// Iterate over the key value pairs ordered by key
+    pub fn iter(&self) -> btree_map::Iter<'_, String, String> {
+        self.pairs.iter()
+    }
+
+    /// Number of key value pairs
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Whether no pairs are set
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// When the metadata last changed in milliseconds since the unix epoch
+    /// according to the clock of the instance that changed it
+    #[must_use]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    #[cfg(feature = "protobuf")]
+    pub(crate) fn from_parts(version: u64, pairs: BTreeMap<String, String>) -> Self {
+        Self { version, pairs }
+    }
+
+    /// move the version forward, also when the clock went back
+    fn bump(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| {
+                u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+            });
+        self.version = now.max(self.version.saturating_add(1));
+    }
+}
+
+impl<'a> IntoIterator for &'a Metadata {
+    type Item = (&'a String, &'a String);
+    type IntoIter = btree_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + serde::de::DeserializeOwned,
+{
+    /// Share `value` under `key` with the other instances, replacing any
+    /// earlier value. Spreads with our next broadcast or reply, peers can
+    /// look it up using [`meta_of`](Chart::meta_of).
+    ///
+    /// # Errors
+    /// Returns [`MetaError::TooLarge`] if our discovery message would no
+    /// longer fit in a datagram or within the
+    /// [bincode limit](crate::BincodeOptions::limit), the metadata is left
+    /// unchanged then.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn put_meta(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), MetaError> {
+        let ours = self.discovery_msg();
+        let mut meta = self.meta.lock().unwrap();
+        let mut changed = meta.clone();
+        changed.pairs.insert(key.into(), value.into());
+        changed.bump();
+
+        let msg = DiscoveryMsg {
+            meta: changed.clone(),
+            ..ours
+        };
+        let max = self.wire_format.limit().map_or(MAX_DATAGRAM, |limit| {
+            usize::try_from(limit).map_or(MAX_DATAGRAM, |limit| limit.min(MAX_DATAGRAM))
+        });
+        match self.wire_format.encode(&msg.widest()) {
+            Ok(buf) if buf.len() <= MAX_DATAGRAM => (),
+            _ => return Err(MetaError::TooLarge { max }),
+        }
+        *meta = changed;
+        *self.encoded.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Stop sharing `key`, returns its value if it was set
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn remove_meta(&self, key: &str) -> Option<String> {
+        let mut meta = self.meta.lock().unwrap();
+        let value = meta.pairs.remove(key)?;
+        meta.bump();
+        *self.encoded.lock().unwrap() = None;
+        Some(value)
+    }
+
+    /// The metadata we share, see [`put_meta`](Chart::put_meta)
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn our_meta(&self) -> Metadata {
+        self.meta.lock().unwrap().clone()
+    }
+
+    /// The metadata instance `id` shares, `None` if it is not charted
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn meta_of(&self, id: Id) -> Option<Metadata> {
+        let map = self.map.lock().unwrap();
+        map.get(&id).map(|entry| entry.meta.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_always_increases() {
+        let mut meta = Metadata {
+            version: u64::MAX - 1,
+            ..Metadata::default()
+        };
+        meta.bump();
+        assert_eq!(meta.version(), u64::MAX);
+
+        let mut meta = Metadata::default();
+        meta.bump();
+        let first = meta.version();
+        meta.bump();
+        assert!(meta.version() > first);
+    }
+}
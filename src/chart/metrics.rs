@@ -0,0 +1,98 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Receives discovery metrics so they can be forwarded to the telemetry stack
+/// you already run (StatsD, OpenTelemetry, Prometheus, ..). Install one using
+/// [`ChartBuilder::with_metrics`](crate::ChartBuilder::with_metrics). Both
+/// methods do nothing by default.
+///
+/// The counters are the same as those in [`Counters`](crate::Counters):
+/// `received`, `heard`, `skipped`, `decoded`, `invalid`, `quarantined`,
+/// `deferred`, `sent`, `lagged` and `lock_wait_ns`. The only gauge is `size`, the number of
+/// instances in the chart including this one.
+///
+/// # Note
+/// The methods are called from the discovery hot path, they should return
+/// quickly.
+///
+/// # Example
+/// ```rust
+/// use instance_chart::ChartMetrics;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// #[derive(Default)]
+/// struct Received(AtomicU64);
+///
+/// impl ChartMetrics for Received {
+///     fn counter(&self, name: &'static str, n: u64) {
+///         if name == "received" {
+///             self.0.fetch_add(n, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait ChartMetrics: Send + Sync {
+    /// the counter `name` increased by `n`
+    fn counter(&self, name: &'static str, n: u64) {
+        let _ = (name, n);
+    }
+    /// the gauge `name` changed to `value`
+    fn gauge(&self, name: &'static str, value: u64) {
+        let _ = (name, value);
+    }
+}
+
+/// The default [`ChartMetrics`], discards everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoMetrics;
+
+impl ChartMetrics for NoMetrics {}
+
+#[derive(Clone)]
+pub(crate) struct Metrics(pub(crate) Arc<dyn ChartMetrics>);
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self(Arc::new(NoMetrics))
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::counters::Cells;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(&'static str, u64)>>);
+
+    impl ChartMetrics for Recorder {
+        fn counter(&self, name: &'static str, n: u64) {
+            self.0.lock().unwrap().push((name, n));
+        }
+        fn gauge(&self, name: &'static str, value: u64) {
+            self.0.lock().unwrap().push((name, value));
+        }
+    }
+
+    #[test]
+    fn forwards_counters() {
+        let recorder = Arc::new(Recorder::default());
+        let cells = Cells::new(Metrics(recorder.clone()));
+        cells.received(3);
+        cells.sent(1);
+        cells.size(7);
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [("received", 3), ("sent", 1), ("size", 7)]
+        );
+        assert_eq!(cells.snapshot().received, 3);
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{Chart, Entry};
+use crate::{Error, Id};
+
+/// What to do with a received entry, returned by
+/// [inbound middleware](Chart::add_inbound_middleware)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Pass the entry on to the next middleware or chart it
+    Accept,
+    /// Drop the message, the entry is not charted or updated
+    Reject,
+}
+
+/// What is known about a received discovery message besides its entry, see
+/// [`Chart::add_inbound_middleware`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Meta {
+    /// the instance that sent the message
+    pub id: Id,
+    /// the address the message came from
+    pub from: SocketAddr,
+    /// when the message was processed
+    pub received: Instant,
+    /// the longest the sender waits in between broadcasts
+    pub interval: Duration,
+}
+
+type Hook<Msg> = dyn Fn(&mut Entry<Msg>, &Meta) -> Action + Send + Sync;
+
+/// The inbound middleware in the order it was added
+pub(crate) struct Inbound<Msg: fmt::Debug + Clone>(Vec<Box<Hook<Msg>>>);
+
+impl<Msg: fmt::Debug + Clone> Default for Inbound<Msg> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<Msg: fmt::Debug + Clone> fmt::Debug for Inbound<Msg> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inbound middleware", self.0.len())
+    }
+}
+
+impl<Msg: fmt::Debug + Clone> Inbound<Msg> {
+    /// run the chain, stops at the first middleware that rejects
+    pub(crate) fn run(&self, entry: &mut Entry<Msg>, meta: &Meta) -> Action {
+        for hook in &self.0 {
+            if hook(entry, meta) == Action::Reject {
+                return Action::Reject;
+            }
+        }
+        Action::Accept
+    }
+}
+
+type Decorator<Msg> = dyn Fn(&mut Msg) + Send + Sync;
+
+/// The outbound decorators in the order they were added
+pub(crate) struct Outbound<Msg>(Vec<Box<Decorator<Msg>>>);
+
+impl<Msg> Default for Outbound<Msg> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<Msg> fmt::Debug for Outbound<Msg> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} outbound decorators", self.0.len())
+    }
+}
+
+impl<Msg> Outbound<Msg> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn run(&self, msg: &mut Msg) {
+        for decorator in &self.0 {
+            decorator(msg);
+        }
+    }
+}
+
+impl<const N: usize, T: fmt::Debug + Clone + Serialize> Chart<N, T> {
+    /// Call `middleware` on every received discovery message, goodbyes
+    /// included, before its entry is charted. Middleware can change the entry, for example to
+    /// rewrite container-internal addresses, or veto it by returning
+    /// [`Action::Reject`]. Middleware runs in the order it was added, the
+    /// chain stops at the first one that rejects.
+    ///
+    /// # Note
+    /// Middleware runs on the receive path with the chart locked, it should
+    /// return quickly and must not call methods on the chart. Messages
+    /// identical to the previous one from the same instance are not decoded
+    /// again, middleware only sees them if it changed the entry's ip.
+    /// Rejecting a message does not remove an instance that is already
+    /// charted, use [`forget`](Chart::forget) for that.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn add_inbound_middleware(
+        &self,
+        middleware: impl Fn(&mut Entry<[T; N]>, &Meta) -> Action + Send + Sync + 'static,
+    ) {
+        let mut inbound = self.inbound.write().unwrap();
+        inbound.0.push(Box::new(middleware));
+    }
+}
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + fmt::Debug + Clone + Serialize + DeserializeOwned,
+{
+    /// Call `decorator` on the message we share right before it is encoded,
+    /// for every broadcast, reply and goodbye. Use it to advertise fields
+    /// that change, like a load metric or a token. Decorators run in the
+    /// order they were added, each on a fresh copy of the message the chart
+    /// was built with.
+    ///
+    /// # Note
+    /// Without decorators our message is encoded once and reused, with any
+    /// it is encoded for every send. Decorators run on the discovery tasks
+    /// and should return quickly. Should the decorated message later no
+    /// longer fit within the [bincode limit](crate::BincodeOptions::limit)
+    /// it is sent undecorated.
+    ///
+    /// # Errors
+    /// Returns [`Error::MessageTooLarge`] if our message decorated by all
+    /// decorators, this one included, does not fit within the bincode limit.
+    /// The decorator is not added then.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn add_outbound_decorator(
+        &self,
+        decorator: impl Fn(&mut [T; N]) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let mut outbound = self.outbound.write().unwrap();
+        let mut msg = self.discovery_msg();
+        outbound.run(&mut msg.msg);
+        decorator(&mut msg.msg);
+        self.check_fits(msg)?;
+        outbound.0.push(Box::new(decorator));
+        Ok(())
+    }
+}
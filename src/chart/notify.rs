@@ -1,14 +1,22 @@
+use super::counters::Cells;
 use super::{Entry, Id};
 
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::sync::broadcast;
-use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::watch;
 
 /// Wait for notifications of new discoveries, buffering up to 256 discoveries, created using
 /// [`Chart::notify()`](crate::Chart::notify).
 ///
+/// Once the chart is [closed](crate::Chart::close) and the buffered discoveries
+/// are received the `recv` methods return [`RecvError::Closed`], see
+/// [`is_closed`](Self::is_closed).
+///
 /// # Examples
 /// ```
 /// # use std::error::Error;
@@ -34,9 +42,15 @@ use tokio::sync::broadcast::error::RecvError;
 /// ```
 ///
 #[derive(Debug)]
-pub struct Notify<const N: usize, T: Debug + Clone>(
-    pub(super) broadcast::Receiver<(Id, Entry<[T; N]>)>,
-);
+pub struct Notify<const N: usize, T: Debug + Clone> {
+    pub(super) discoveries: broadcast::Receiver<(Id, Entry<[T; N]>)>,
+    /// where skipped discoveries are counted, `None` for a merged chart
+    pub(super) counters: Option<Arc<Cells>>,
+    /// only instances in this group are returned
+    pub(super) group: Option<u16>,
+    /// whether the chart was closed, `None` for a merged chart
+    pub(super) closed: Option<watch::Receiver<bool>>,
+}
 
 impl<T: Debug + Clone> Notify<1, T> {
     /// await the next discovered instance. Returns the id and custom messag for new node
@@ -54,6 +68,75 @@ impl<T: Debug + Clone> Notify<1, T> {
 }
 
 impl<const N: usize, T: Debug + Clone> Notify<N, T> {
+    /// Whether the chart was closed and every discovery made before was
+    /// received. If so the `recv` methods return [`RecvError::Closed`] right
+    /// away.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        let closed = self.closed.as_ref().is_some_and(|closed| *closed.borrow());
+        (closed || self.discoveries.is_closed()) && self.discoveries.is_empty()
+    }
+
+    /// the next discovery, [`RecvError::Closed`] once the chart is closed and
+    /// nothing is buffered
+    async fn next(&mut self) -> Result<(Id, Entry<[T; N]>), RecvError> {
+        let Notify {
+            discoveries,
+            closed: Some(closed),
+            ..
+        } = self
+        else {
+            return self.discoveries.recv().await;
+        };
+        tokio::select! {
+            biased;
+            res = discoveries.recv() => res,
+            _ = closed.wait_for(|closed| *closed) => match discoveries.try_recv() {
+                Ok(discovery) => Ok(discovery),
+                Err(TryRecvError::Lagged(n)) => Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => Err(RecvError::Closed),
+            },
+        }
+    }
+
+    /// await the next discovered instance. Returns the id and the complete
+    /// [`Entry`] for the new node when it is discovered. Unlike the other
+    /// `recv` methods this includes everything known about the node, such
+    /// as its [`status`](Entry::status) and [`source`](Entry::source).
+    /// # Errors
+    /// If more the 256 discoveries have been made since this was called this returns
+    /// `RecvError::Lagged`. Once the chart is closed this returns
+    /// `RecvError::Closed`.
+    pub async fn recv_entry(&mut self) -> Result<(Id, Entry<[T; N]>), RecvError> {
+        loop {
+            let (id, entry) = self.next().await.inspect_err(|err| {
+                if let (RecvError::Lagged(n), Some(counters)) = (err, &self.counters) {
+                    counters.lagged(*n);
+                }
+            })?;
+            if self.group.is_none_or(|group| entry.group == Some(group)) {
+                return Ok((id, entry));
+            }
+        }
+    }
+
+    /// Like [`recv_entry`](Self::recv_entry) but skips discoveries of
+    /// instances whose id is not in `ids`, for example when only the voting
+    /// members of a cluster matter.
+    /// # Errors
+    /// If more the 256 discoveries have been made since this was called this returns
+    /// `RecvError::Lagged`, discoveries outside `ids` count towards that too. A
+    /// discovery in `ids` might have been skipped, check the chart to catch
+    /// up. Once the chart is closed this returns `RecvError::Closed`.
+    pub async fn recv_from(&mut self, ids: &HashSet<Id>) -> Result<(Id, Entry<[T; N]>), RecvError> {
+        loop {
+            let (id, entry) = self.recv_entry().await?;
+            if ids.contains(&id) {
+                return Ok((id, entry));
+            }
+        }
+    }
+
     /// await the next discovered instance. Returns the id and custom messages for new node
     /// when it is discovered.
     /// # Note
@@ -63,7 +146,7 @@ impl<const N: usize, T: Debug + Clone> Notify<N, T> {
     /// If more the 256 discoveries have been made since this was called this returns
     /// `RecvError::Lagged`
     pub async fn recv(&mut self) -> Result<(Id, IpAddr, [T; N]), RecvError> {
-        let (id, entry) = self.0.recv().await?;
+        let (id, entry) = self.recv_entry().await?;
         Ok((id, entry.ip, entry.msg))
     }
 
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::net::SocketAddr;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{Chart, WireFormat};
+use crate::Id;
+
+/// Senders remembered at most, later ones are not recorded
+const MAX_SENDERS: usize = 1024;
+
+/// Datagrams from one sender seen on the discovery port, see
+/// [`Chart::observed_headers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Announcement {
+    /// the id in the message, `None` if it is not a discovery message
+    pub id: Option<Id>,
+    /// where the datagrams came from
+    pub from: SocketAddr,
+    /// number of datagrams received
+    pub count: u64,
+    /// size of the last datagram in bytes
+    pub len: usize,
+    /// when the first datagram arrived
+    pub first_seen: Instant,
+    /// when the last datagram arrived
+    pub last_seen: Instant,
+}
+
+/// Records every datagram arriving on the discovery sockets whatever its
+/// header, see [`ChartBuilder::observe_all_headers`](crate::ChartBuilder::observe_all_headers)
+#[derive(Debug, Default)]
+pub(crate) struct Observer {
+    seen: HashMap<(Option<u64>, Option<Id>, SocketAddr), Announcement>,
+}
+
+impl Observer {
+    pub(crate) fn record(&mut self, format: WireFormat, buf: &[u8], from: SocketAddr) {
+        let (header, id) = format.sender(buf).unzip();
+        let now = Instant::now();
+        let full = self.seen.len() >= MAX_SENDERS;
+        match self.seen.get_mut(&(header, id, from)) {
+            Some(seen) => {
+                seen.count += 1;
+                seen.len = buf.len();
+                seen.last_seen = now;
+            }
+            None if full => (),
+            None => {
+                let seen = Announcement {
+                    id,
+                    from,
+                    count: 1,
+                    len: buf.len(),
+                    first_seen: now,
+                    last_seen: now,
+                };
+                self.seen.insert((header, id, from), seen);
+            }
+        }
+    }
+
+    fn by_header(&self) -> BTreeMap<Option<u64>, Vec<Announcement>> {
+        let mut grouped: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for ((header, _, _), seen) in &self.seen {
+            grouped.entry(*header).or_default().push(*seen);
+        }
+        for announcements in grouped.values_mut() {
+            announcements.sort_unstable_by_key(|seen| (seen.first_seen, seen.from));
+        }
+        grouped
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Everything that arrived on the discovery sockets grouped by header,
+    /// including messages from applications using another
+    /// [header](crate::ChartBuilder::with_header). Datagrams that are not
+    /// discovery messages are grouped under `None`. Use this to find out who
+    /// else uses the discovery port and group. Empty unless enabled using
+    /// [`ChartBuilder::observe_all_headers`](crate::ChartBuilder::observe_all_headers).
+    ///
+    /// # Note
+    /// At most 1024 senders are recorded, later ones are left out.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn observed_headers(&self) -> BTreeMap<Option<u64>, Vec<Announcement>> {
+        self.observer
+            .as_deref()
+            .map(|observer| observer.lock().unwrap().by_header())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::test_msg;
+
+    fn announce(format: WireFormat, header: u64, id: Id) -> Vec<u8> {
+        let msg = test_msg(header, id, [8042u16]);
+        format.encode(&msg).unwrap()
+    }
+
+    #[tokio::test]
+    async fn grouped_by_header() {
+        let format = WireFormat::default();
+        let from = SocketAddr::from(([10, 0, 0, 1], 8080));
+        let mut observer = Observer::default();
+        observer.record(format, &announce(format, 1, 7), from);
+        observer.record(format, &announce(format, 1, 7), from);
+        observer.record(format, &announce(format, 2, 8), from);
+        observer.record(format, &[1, 2, 3], from);
+
+        let grouped = observer.by_header();
+        assert_eq!(grouped.len(), 3);
+        assert_eq!(grouped[&Some(1)][0].count, 2);
+        assert_eq!(grouped[&Some(2)][0].id, Some(8));
+        assert_eq!(grouped[&None][0].len, 3);
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+
+use super::{Chart, WireFormat};
+use crate::Id;
+
+/// The groups of a [`Partition`](crate::testing::Partition), `None` while
+/// healed
+pub(crate) type Groups = Arc<RwLock<Option<HashMap<Id, usize>>>>;
+
+/// Drops received datagrams sent from the other side of a
+/// [`Partition`](crate::testing::Partition). Shared by a chart and its
+/// [`Transport::Partitioned`](super::Transport::Partitioned) endpoints, it
+/// drops nothing until the chart is added to a partition.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cut(Arc<RwLock<Option<Sides>>>);
+
+#[derive(Debug)]
+struct Sides {
+    groups: Groups,
+    /// our id, it can change while the chart runs
+    ours: Arc<AtomicU64>,
+    format: WireFormat,
+}
+
+impl Sides {
+    /// whether `ours` does not hear from `from`, an instance in no group
+    /// hears only from itself
+    fn blocks(&self, ours: Id, from: Id) -> bool {
+        let groups = self.groups.read().unwrap();
+        let Some(groups) = &*groups else {
+            return false;
+        };
+        let group = groups.get(&ours);
+        from != ours && (group.is_none() || group != groups.get(&from))
+    }
+}
+
+impl Cut {
+    /// whether the datagram in `buf` was sent from the other side of a
+    /// partition
+    pub(crate) fn cuts_off(&self, buf: &[u8]) -> bool {
+        let sides = self.0.read().unwrap();
+        let Some(sides) = &*sides else {
+            return false;
+        };
+        match sides.format.sender(buf) {
+            Some((_, from)) => sides.blocks(sides.ours.load(Ordering::Relaxed), from),
+            None => false,
+        }
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    pub(crate) fn set_cut(&self, groups: Groups) {
+        *self.cut.0.write().unwrap() = Some(Sides {
+            groups,
+            ours: Arc::clone(&self.service_id),
+            format: self.wire_format,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_across_groups() {
+        let sides = Sides {
+            groups: Arc::default(),
+            ours: Arc::default(),
+            format: WireFormat::default(),
+        };
+        assert!(!sides.blocks(1, 2));
+
+        let groups = HashMap::from([(1, 0), (2, 0), (3, 1)]);
+        *sides.groups.write().unwrap() = Some(groups);
+        assert!(!sides.blocks(1, 2));
+        assert!(sides.blocks(1, 3));
+        assert!(sides.blocks(3, 1));
+        assert!(sides.blocks(4, 1));
+        assert!(sides.blocks(1, 4));
+        assert!(!sides.blocks(4, 4));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt::Debug;
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+
+use super::{Chart, Status};
+use crate::Id;
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Advertise `priority` from now on, see
+    /// [`ChartBuilder::with_priority`](crate::ChartBuilder::with_priority).
+    /// Peers see the change with our next broadcast or reply, they send an
+    /// [`Event::PriorityChanged`](crate::Event::PriorityChanged).
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn set_priority(&self, priority: u32) {
+        if self.priority.swap(priority, Ordering::Relaxed) != priority {
+            *self.encoded.lock().unwrap() = None;
+        }
+    }
+
+    /// The priority we advertise, see [`set_priority`](Chart::set_priority)
+    #[must_use]
+    pub fn our_priority(&self) -> u32 {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    /// The instance that should coordinate: the one with the highest
+    /// [priority](crate::ChartBuilder::with_priority), the lowest [`Id`] if
+    /// several share it. This instance is a candidate too, every instance
+    /// that charted the same instances agrees on the outcome. Instances
+    /// that are not [`Status::Active`] are skipped, `None` is returned if
+    /// none are.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn highest_priority_peer(&self) -> Option<Id> {
+        let ours = (*self.status.lock().unwrap() == Status::Active)
+            .then(|| (self.our_priority(), self.id()));
+        let map = self.map.lock().unwrap();
+        let peers = map
+            .iter()
+            .filter(|(_, entry)| entry.status == Status::Active)
+            .map(|(id, entry)| (entry.priority, *id));
+        peers
+            .chain(ours)
+            .max_by(|(a, a_id), (b, b_id)| a.cmp(b).then(b_id.cmp(a_id)))
+            .map(|(_, id)| id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures;
+    use crate::{Entry, Event};
+
+    fn entry(n: u8) -> (Id, Entry<[u16; 1]>) {
+        let (id, mut entry) = fixtures::entry(n);
+        entry.priority = u32::from(n % 4);
+        (id, entry)
+    }
+
+    #[tokio::test]
+    async fn highest_priority_then_lowest_id() {
+        let chart = Chart::test(entry).await;
+        // priority 3: ids 3 and 7
+        assert_eq!(chart.highest_priority_peer(), Some(3));
+
+        chart.set_priority(3);
+        assert_eq!(chart.highest_priority_peer(), Some(0));
+        chart.set_status(Status::Draining);
+        assert_eq!(chart.highest_priority_peer(), Some(3));
+
+        let mut events = chart.events();
+        let (id, mut changed) = entry(3);
+        changed.priority = 1;
+        chart.insert(id, changed);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            Event::PriorityChanged { id: 3, priority: 1 }
+        );
+        assert_eq!(chart.highest_priority_peer(), Some(7));
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt::Debug;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{Chart, Socket, WireFormat, MAX_DATAGRAM};
+use crate::Id;
+
+/// followed by the header, discovery messages are always longer
+const MARKER: [u8; 8] = *b"ic-probe";
+const LEN: usize = 16;
+
+pub(crate) fn encode(header: u64) -> [u8; LEN] {
+    let mut buf = [0; LEN];
+    buf[..8].copy_from_slice(&MARKER);
+    buf[8..].copy_from_slice(&header.to_le_bytes());
+    buf
+}
+
+/// whether `buf` asks which instance holds the discovery port
+pub(crate) fn is_probe(buf: &[u8], header: u64) -> bool {
+    buf == encode(header)
+}
+
+/// The id of the chart instance on this host answering on discovery `port`,
+/// if any. The probe is answered with a discovery msg without the prober
+/// getting charted. Blocks for up to a few hundred milliseconds.
+pub(crate) fn running_locally(header: u64, wire_format: WireFormat, port: u16) -> Option<Id> {
+    const ATTEMPTS: usize = 3;
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    let probe = encode(header);
+    let sock = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).ok()?;
+    let to = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut buf = [0; MAX_DATAGRAM];
+    for _ in 0..ATTEMPTS {
+        sock.send_to(&probe, to).ok()?;
+        let deadline = Instant::now() + TIMEOUT;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            let Ok(len) = sock
+                .set_read_timeout(Some(left))
+                .and_then(|()| sock.recv(&mut buf))
+            else {
+                break;
+            };
+            let found = wire_format
+                .sender(&buf[..len])
+                .filter(|(sender_header, _)| *sender_header == header)
+                .map(|(_, id)| id);
+            if found.is_some() {
+                return found;
+            }
+        }
+    }
+    None
+}
+
+impl<const N: usize, T> Chart<N, T>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    /// tell an instance on this host probing the discovery port who we are,
+    /// probes from elsewhere are ignored so we can not be used to reflect
+    /// traffic
+    pub(crate) async fn answer_probe(&self, endpoint: &Socket, to: SocketAddr) {
+        if !to.ip().is_loopback() && !self.host.is_ours(to.ip()) {
+            trace!("ignoring probe from {to}, it is not on this host");
+            return;
+        }
+        let buf = self.discovery_buf();
+        match self.send_to(endpoint, &buf, to).await {
+            Ok(_) => self.counters.sent(1),
+            Err(err) => trace!("could not answer probe from {to}: {err}"),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Tracks senders of malformed discovery messages. Once a sender sent
+/// `threshold` malformed messages within `duration` everything it sends is
+/// ignored for `duration`.
+#[derive(Debug)]
+pub(crate) struct Quarantine {
+    threshold: u32,
+    duration: Duration,
+    peers: HashMap<IpAddr, Strikes>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Strikes {
+    count: u32,
+    /// first strike in the current window
+    since: Instant,
+    /// ignored until this moment
+    until: Option<Instant>,
+}
+
+impl Quarantine {
+    pub(crate) fn new(threshold: u32, duration: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            duration,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// true if everything from `ip` should be ignored
+    pub(crate) fn ignores(&mut self, ip: IpAddr, now: Instant) -> bool {
+        match self.peers.get(&ip).and_then(|strikes| strikes.until) {
+            Some(until) if until > now => true,
+            Some(_) => {
+                self.peers.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// count a malformed message from `ip`, returns the number of strikes if
+    /// this put `ip` in quarantine
+    pub(crate) fn strike(&mut self, ip: IpAddr, now: Instant) -> Option<u32> {
+        let duration = self.duration;
+        if self.peers.len() > 1024 {
+            // forget peers that stopped misbehaving
+            self.peers
+                .retain(|_, s| now.duration_since(s.since) < duration || s.until.is_some());
+        }
+        let strikes = self.peers.entry(ip).or_insert(Strikes {
+            count: 0,
+            since: now,
+            until: None,
+        });
+        if now.duration_since(strikes.since) > duration {
+            strikes.count = 0;
+            strikes.since = now;
+        }
+        strikes.count += 1;
+        if strikes.count < self.threshold {
+            return None;
+        }
+        strikes.until = Some(now + duration);
+        Some(strikes.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    #[tokio::test(start_paused = true)]
+    async fn quarantine_after_threshold() {
+        let mut quarantine = Quarantine::new(3, Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(quarantine.strike(IP, now), None);
+        assert_eq!(quarantine.strike(IP, now), None);
+        assert!(!quarantine.ignores(IP, now));
+        assert_eq!(quarantine.strike(IP, now), Some(3));
+        assert!(quarantine.ignores(IP, now));
+
+        let later = now + Duration::from_secs(11);
+        assert!(!quarantine.ignores(IP, later));
+        assert_eq!(quarantine.strike(IP, later), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn strikes_expire() {
+        let mut quarantine = Quarantine::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(quarantine.strike(IP, now), None);
+        let later = now + Duration::from_secs(11);
+        assert_eq!(quarantine.strike(IP, later), None);
+        assert_eq!(quarantine.strike(IP, later), Some(2));
+    }
+}
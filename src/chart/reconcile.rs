@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use super::{Chart, Endpoint, Entry};
+use crate::Id;
+
+/// How the chart differs from a list of instances that should be in it, for
+/// example a static inventory. Create one using [`Chart::reconcile`]. Every
+/// list is sorted by [`Id`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReconcileReport {
+    /// expected instances that are not in the chart
+    pub missing: Vec<Id>,
+    /// charted instances that were not expected
+    pub unexpected: Vec<Id>,
+    /// expected instances charted without the expected address
+    pub mismatched: Vec<AddrMismatch>,
+}
+
+impl ReconcileReport {
+    /// Whether the chart holds exactly the expected instances, at the
+    /// expected addresses
+    #[must_use]
+    pub fn in_sync(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// An instance charted without the address it was expected at, see
+/// [`ReconcileReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrMismatch {
+    pub id: Id,
+    /// where the instance was expected
+    pub expected: SocketAddr,
+    /// the addresses the instance announced
+    pub charted: Vec<SocketAddr>,
+}
+
+impl<const N: usize, E: Endpoint<Addr = SocketAddr>> Chart<N, E> {
+    /// Compare the chart to the instances `expected` in it, for example from
+    /// a static inventory, to detect drift. An instance matches if the
+    /// expected address is one of its addresses, see [`Endpoint`]. This
+    /// instance is left out of the comparison.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn reconcile(&self, expected: &[(Id, SocketAddr)]) -> ReconcileReport {
+        let our_id = self.our_id();
+        let expected: Vec<_> = expected.iter().filter(|(id, _)| *id != our_id).collect();
+        let mut report = ReconcileReport::default();
+
+        let map = self.map.lock().unwrap();
+        for (id, addr) in &expected {
+            let Some(Entry { ip, msg, .. }) = map.get(id) else {
+                report.missing.push(*id);
+                continue;
+            };
+            let charted: Vec<_> = msg.iter().map(|e| e.resolve(*ip)).collect();
+            if !charted.contains(addr) {
+                report.mismatched.push(AddrMismatch {
+                    id: *id,
+                    expected: *addr,
+                    charted,
+                });
+            }
+        }
+        let known: HashSet<Id> = expected.iter().map(|(id, _)| *id).collect();
+        report.unexpected = map
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| !known.contains(id))
+            .collect();
+        drop(map);
+
+        report.missing.sort_unstable();
+        report.unexpected.sort_unstable();
+        report.mismatched.sort_unstable_by_key(|m| m.id);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry_with;
+
+    fn addr(n: u8, port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, n], port))
+    }
+
+    #[tokio::test]
+    async fn reports_drift() {
+        let chart = Chart::test(|n| entry_with(n, [8042, 8043])).await;
+        let mut inventory: Vec<_> = (0..10).map(|n| (n.into(), addr(n, 8043))).collect();
+        assert!(chart.reconcile(&inventory).in_sync());
+
+        inventory.retain(|(id, _)| *id != 4);
+        inventory.push((42, addr(42, 8042)));
+        inventory[2].1 = addr(2, 9000);
+        let report = chart.reconcile(&inventory);
+        assert_eq!(report.missing, [42]);
+        assert_eq!(report.unexpected, [4]);
+        assert_eq!(
+            report.mismatched,
+            [AddrMismatch {
+                id: 2,
+                expected: addr(2, 9000),
+                charted: vec![addr(2, 8042), addr(2, 8043)],
+            }]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::interval::Jitter;
+
+/// Whether and when to reply to a newly discovered instance. A reply lets
+/// the newcomer chart us right away instead of after our next broadcast. In a
+/// large cluster every instance replying at once floods the newcomer. Set
+/// using [`ChartBuilder::with_reply_policy`](crate::ChartBuilder::with_reply_policy).
+///
+/// # Note
+/// No reply is sent if we are about to broadcast anyway.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ReplyPolicy {
+    /// Reply right away
+    #[default]
+    Always,
+    /// Never reply, the newcomer finds us when we broadcast next. Discovery
+    /// then relies on the periodic broadcasts alone: newcomers are slower to
+    /// chart us, at worst by the [maximum interval](crate::Chart::max_interval),
+    /// but no unicast traffic is generated when they announce themselves.
+    /// Consider this when many instances watch the same cluster.
+    Never,
+    /// Reply right away with a probability between 0 and 1. With `n`
+    /// instances `Probabilistic(k / n)` results in about `k` replies.
+    Probabilistic(f32),
+    /// Reply after a random delay within the range. Spreads the replies out
+    /// over time, no reply is sent if we broadcast before the delay is over.
+    DelayedRandom(Range<Duration>),
+}
+
+impl ReplyPolicy {
+    /// how long to wait before replying, `None` if we should not reply
+    pub(crate) fn delay(&self, rng: &mut Jitter) -> Option<Duration> {
+        match self {
+            ReplyPolicy::Always => Some(Duration::ZERO),
+            ReplyPolicy::Never => None,
+            ReplyPolicy::Probabilistic(p) => (rng.next_unit() < *p).then_some(Duration::ZERO),
+            ReplyPolicy::DelayedRandom(range) => {
+                let spread = range.end.saturating_sub(range.start);
+                Some(range.start + spread.mul_f32(rng.next_unit()))
+            }
+        }
+    }
+}
+
+/// Replies waiting out their [delay](ReplyPolicy::DelayedRandom). Kept by
+/// the receive loop instead of spawning a task per reply.
+#[derive(Debug, Default)]
+pub(crate) struct Delayed(Vec<Pending>);
+
+#[derive(Debug)]
+struct Pending {
+    at: Instant,
+    to: SocketAddr,
+    /// our last broadcast when the reply was planned
+    last_broadcast: Option<Instant>,
+}
+
+impl Delayed {
+    pub(crate) fn push(
+        &mut self,
+        to: SocketAddr,
+        delay: Duration,
+        last_broadcast: Option<Instant>,
+    ) {
+        self.0.push(Pending {
+            at: Instant::now() + delay,
+            to,
+            last_broadcast,
+        });
+    }
+
+    /// resolves once the earliest reply is due, never if none are waiting
+    pub(crate) async fn next_due(&self) {
+        match self.0.iter().map(|pending| pending.at).min() {
+            Some(at) => tokio::time::sleep_until(at).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// remove the replies that are due, returns who still needs one: we did
+    /// not broadcast since the reply was planned
+    pub(crate) fn take_due(&mut self, last_broadcast: Option<Instant>) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        self.0.retain(|pending| {
+            if pending.at > now {
+                return true;
+            }
+            if pending.last_broadcast == last_broadcast {
+                due.push(pending.to);
+            }
+            false
+        });
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probabilistic() {
+        let mut rng = Jitter::new();
+        let replies = (0..1000)
+            .filter_map(|_| ReplyPolicy::Probabilistic(0.1).delay(&mut rng))
+            .count();
+        assert!((50..150).contains(&replies), "replies: {replies}");
+        assert!(ReplyPolicy::Probabilistic(0.0).delay(&mut rng).is_none());
+        assert!(ReplyPolicy::Probabilistic(1.0).delay(&mut rng).is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delayed_unless_broadcast() {
+        let mut delayed = Delayed::default();
+        let addr = |port| SocketAddr::from(([10, 0, 0, 1], port));
+        delayed.push(addr(1), Duration::from_millis(10), None);
+        delayed.push(addr(2), Duration::from_millis(20), None);
+        delayed.push(addr(3), Duration::from_millis(20), Some(Instant::now()));
+        assert!(delayed.take_due(None).is_empty());
+
+        delayed.next_due().await;
+        assert_eq!(delayed.take_due(None), [addr(1)]);
+        delayed.next_due().await;
+        assert_eq!(delayed.take_due(None), [addr(2)]);
+        assert!(delayed.0.is_empty(), "broadcast since planning the reply");
+    }
+
+    #[test]
+    fn delay_within_range() {
+        let mut rng = Jitter::new();
+        let range = Duration::from_millis(100)..Duration::from_millis(200);
+        for _ in 0..100 {
+            let delay = ReplyPolicy::DelayedRandom(range.clone())
+                .delay(&mut rng)
+                .unwrap();
+            assert!(range.contains(&delay), "delay: {delay:?}");
+        }
+    }
+}
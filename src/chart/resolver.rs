@@ -0,0 +1,284 @@
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+
+use super::{Chart, Endpoint, Id};
+use crate::ResolveError;
+
+type Role<const N: usize, E> = Arc<dyn Fn(Id, &[E; N]) -> bool + Send + Sync>;
+
+/// Maps logical names to the current addresses of chart members. Create one
+/// using [`Chart::resolver`]. Understood names are:
+/// - `id:<id>`, all addresses of the instance with that [`Id`]
+/// - `role:<role>`, all addresses of the instances in a role added with
+///   [`with_role`](Self::with_role)
+///
+/// Hostnames can not contain a `:`, use `id-<id>` and `role-<role>` in URLs.
+///
+/// Use [`target`](Self::target) wherever [`ToSocketAddrs`] is accepted. With
+/// the `hyper` feature this can be used as the resolver of hyper-util's
+/// `HttpConnector` and with the `reqwest` feature as the dns resolver of a
+/// reqwest `Client`. Then only the ip is used, the port comes from the url.
+///
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use instance_chart::{discovery, ChartBuilder};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_ports([8042, 6379])
+/// #   .with_discovery_port(43814)
+///     .finish()?;
+/// let _ = tokio::spawn(discovery::maintain(chart.clone()));
+///
+/// let resolver = chart
+///     .resolver()
+///     .with_role("cache", |_id, ports| ports[1] == 6379);
+/// let caches = resolver.resolve("role:cache");
+/// let connection = std::net::TcpStream::connect(resolver.target("id:7"));
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ChartResolver<const N: usize, E: Endpoint> {
+    chart: Chart<N, E>,
+    roles: Vec<(String, Role<N, E>)>,
+}
+
+impl<const N: usize, E: Endpoint> fmt::Debug for ChartResolver<N, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let roles: Vec<_> = self.roles.iter().map(|(name, _)| name).collect();
+        f.debug_struct("ChartResolver")
+            .field("chart", &self.chart)
+            .field("roles", &roles)
+            .finish()
+    }
+}
+
+impl<const N: usize, E> Chart<N, E>
+where
+    E: Endpoint<Addr = SocketAddr>,
+{
+    /// A resolver for the addresses of instances in this chart, see [`ChartResolver`]
+    #[must_use]
+    pub fn resolver(&self) -> ChartResolver<N, E> {
+        ChartResolver {
+            chart: self.clone(),
+            roles: Vec::new(),
+        }
+    }
+}
+
+impl<const N: usize, E> ChartResolver<N, E>
+where
+    E: Endpoint<Addr = SocketAddr> + DeserializeOwned,
+{
+    /// Let `role:<name>` resolve to the instances for which `is_member`
+    /// returns true.
+    #[must_use]
+    pub fn with_role(
+        mut self,
+        name: impl Into<String>,
+        is_member: impl Fn(Id, &[E; N]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.roles.push((name.into(), Arc::new(is_member)));
+        self
+    }
+
+    /// The current addresses for `name`.
+    ///
+    /// # Errors
+    /// If the name is not understood, refers to an unknown role or nothing
+    /// in the chart matches it.
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    pub fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, ResolveError> {
+        let invalid = || ResolveError::InvalidName(name.to_owned());
+        let (kind, value) = name.split_once([':', '-']).ok_or_else(invalid)?;
+        let map = self.chart.map.lock().unwrap();
+        let addrs: Vec<_> = match kind {
+            "id" => {
+                let id: Id = value.parse().map_err(|_| invalid())?;
+                map.get(&id)
+                    .into_iter()
+                    .flat_map(|entry| entry.msg.iter().map(|e| e.resolve(entry.ip)))
+                    .collect()
+            }
+            "role" => {
+                let (_, is_member) = self
+                    .roles
+                    .iter()
+                    .find(|(role, _)| role == value)
+                    .ok_or_else(|| ResolveError::UnknownRole(value.to_owned()))?;
+                map.iter()
+                    .filter(|(id, entry)| is_member(**id, &entry.msg))
+                    .flat_map(|(_, entry)| entry.msg.iter().map(|e| e.resolve(entry.ip)))
+                    .collect()
+            }
+            _ => return Err(invalid()),
+        };
+        if addrs.is_empty() {
+            return Err(ResolveError::NotFound(name.to_owned()));
+        }
+        Ok(addrs)
+    }
+
+    /// `name` as something accepted wherever [`ToSocketAddrs`] is
+    #[must_use]
+    pub fn target<'a>(&'a self, name: &'a str) -> Target<'a, N, E> {
+        Target {
+            resolver: self,
+            name,
+        }
+    }
+}
+
+/// A logical name that resolves using a [`ChartResolver`], create one using
+/// [`ChartResolver::target`].
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'a, const N: usize, E: Endpoint> {
+    resolver: &'a ChartResolver<N, E>,
+    name: &'a str,
+}
+
+impl<const N: usize, E> ToSocketAddrs for Target<'_, N, E>
+where
+    E: Endpoint<Addr = SocketAddr> + DeserializeOwned,
+{
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        self.resolver
+            .resolve(self.name)
+            .map(Vec::into_iter)
+            .map_err(io::Error::from)
+    }
+}
+
+#[cfg(feature = "hyper")]
+impl<const N: usize, E> tower_service::Service<hyper_util::client::legacy::connect::dns::Name>
+    for ChartResolver<N, E>
+where
+    E: Endpoint<Addr = SocketAddr> + DeserializeOwned,
+{
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = ResolveError;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: hyper_util::client::legacy::connect::dns::Name) -> Self::Future {
+        std::future::ready(self.resolve(name.as_str()).map(Vec::into_iter))
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl<const N: usize, E> reqwest::dns::Resolve for ChartResolver<N, E>
+where
+    E: 'static + Endpoint<Addr = SocketAddr> + DeserializeOwned + Send + Sync,
+{
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let addrs = ChartResolver::resolve(self, name.as_str())
+            .map(|addrs| Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+            .map_err(Into::into);
+        Box::pin(std::future::ready(addrs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::entry_with;
+    use crate::chart::Entry;
+
+    /// even instances are caches
+    fn entry_2ports(n: u8) -> (Id, Entry<[u16; 2]>) {
+        let cache_port = if n.is_multiple_of(2) { 6379 } else { 6380 };
+        entry_with(n, [8000, cache_port])
+    }
+
+    #[tokio::test]
+    async fn resolve_names() {
+        let chart = Chart::test(entry_2ports).await;
+        let resolver = chart
+            .resolver()
+            .with_role("cache", |_, ports| ports[1] == 6379);
+
+        let ip = entry_2ports(3).1.ip;
+        let expected = [SocketAddr::new(ip, 8000), SocketAddr::new(ip, 6380)];
+        assert_eq!(resolver.resolve("id:3").unwrap(), expected);
+        assert_eq!(resolver.resolve("id-3").unwrap(), expected);
+        let addrs: Vec<_> = resolver.target("id:3").to_socket_addrs().unwrap().collect();
+        assert_eq!(addrs, expected);
+
+        let caches = resolver.resolve("role:cache").unwrap();
+        assert_eq!(caches.len(), 2 * 4);
+
+        assert!(matches!(
+            resolver.resolve("id:42"),
+            Err(ResolveError::NotFound(_))
+        ));
+        assert!(matches!(
+            resolver.resolve("role:web"),
+            Err(ResolveError::UnknownRole(_))
+        ));
+        assert!(matches!(
+            resolver.resolve("7"),
+            Err(ResolveError::InvalidName(_))
+        ));
+    }
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn hyper_resolver() {
+        use hyper_util::client::legacy::connect::{dns::Name, HttpConnector};
+        use std::str::FromStr;
+        use tower_service::Service;
+
+        let chart = Chart::test(entry_2ports).await;
+        let mut resolver = chart.resolver();
+        let _connector = HttpConnector::new_with_resolver(resolver.clone());
+
+        let ip = entry_2ports(3).1.ip;
+        let name = Name::from_str("id-3").unwrap();
+        let addrs: Vec<_> = resolver.call(name).await.unwrap().collect();
+        assert_eq!(
+            addrs,
+            [SocketAddr::new(ip, 8000), SocketAddr::new(ip, 6380)]
+        );
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn reqwest_resolver() {
+        use reqwest::dns::{Name, Resolve};
+        use std::str::FromStr;
+
+        let chart = Chart::test(entry_2ports).await;
+        let resolver = Arc::new(chart.resolver());
+        reqwest::Client::builder()
+            .dns_resolver(resolver.clone())
+            .build()
+            .unwrap();
+
+        let ip = entry_2ports(3).1.ip;
+        let name = Name::from_str("id-3").unwrap();
+        let addrs: Vec<_> = Resolve::resolve(&*resolver, name).await.unwrap().collect();
+        assert_eq!(
+            addrs,
+            [SocketAddr::new(ip, 8000), SocketAddr::new(ip, 6380)]
+        );
+
+        let name = Name::from_str("id-42").unwrap();
+        assert!(Resolve::resolve(&*resolver, name).await.is_err());
+    }
+}
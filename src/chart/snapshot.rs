@@ -0,0 +1,265 @@
+use std::collections::hash_map;
+#[cfg(feature = "schemars")]
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::{Serialize, Serializer};
+
+use super::map::Entries;
+use super::{Chart, Endpoint, Entry};
+use crate::Id;
+
+/// The discovered instances at the moment [`Chart::snapshot`] was called.
+///
+/// Taking a snapshot only clones an `Arc`, with a custom
+/// [`ChartStore`](crate::ChartStore) it clones every entry instead. Iterating
+/// it does not take the chart's lock. Changes to the chart after the
+/// snapshot was taken do not show up in it. The first change while a snapshot
+/// is alive copies the entries, further changes do not until the next
+/// snapshot is taken.
+#[derive(Debug, Clone)]
+pub struct Snapshot<const N: usize, T: Debug + Clone> {
+    entries: Arc<Entries<[T; N]>>,
+}
+
+impl<const N: usize, T: Debug + Clone> Snapshot<N, T> {
+    /// Iterate over each discovered instance's id and entry, order is random
+    pub fn iter(&self) -> Iter<'_, N, T> {
+        Iter(self.entries.iter())
+    }
+
+    /// Like [`iter`](Self::iter) but the iterator owns a handle to the
+    /// snapshot and hands out clones of the entries. Each entry is cloned
+    /// only once it is reached, stopping early skips cloning the rest.
+    #[must_use]
+    pub fn iter_owned(&self) -> IntoIter<N, T> {
+        self.clone().into_iter()
+    }
+
+    /// The entry for instance `id` if it was discovered
+    #[must_use]
+    pub fn get(&self, id: Id) -> Option<&Entry<[T; N]>> {
+        self.entries.get(&id)
+    }
+
+    /// Number of discovered instances, not counting ourselves
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no instances were discovered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<const N: usize, E: Endpoint> Snapshot<N, E> {
+    /// Iterate over each discovered instance's addresses, see
+    /// [`Chart::addr_lists_vec`]
+    pub fn addr_lists(&self) -> impl Iterator<Item = (Id, [E::Addr; N])> + '_ {
+        self.iter()
+            .map(|(id, Entry { ip, msg, .. })| (id, msg.each_ref().map(|e| e.resolve(*ip))))
+    }
+
+    /// Iterate over each discovered instance's nth address, see
+    /// [`Chart::nth_addr_vec`]
+    pub fn nth_addrs<const IDX: usize>(&self) -> impl Iterator<Item = (Id, E::Addr)> + '_ {
+        self.iter()
+            .map(|(id, Entry { ip, msg, .. })| (id, msg[IDX].resolve(*ip)))
+    }
+}
+
+impl<E: Endpoint> Snapshot<1, E> {
+    /// Iterate over each discovered instance's address, see
+    /// [`Chart::addr_vec`]
+    pub fn addrs(&self) -> impl Iterator<Item = (Id, E::Addr)> + '_ {
+        self.nth_addrs::<0>()
+    }
+}
+
+/// Iterator over a [`Snapshot`], created by [`Snapshot::iter`]
+#[derive(Debug, Clone)]
+pub struct Iter<'a, const N: usize, T: Debug + Clone>(hash_map::Iter<'a, Id, Entry<[T; N]>>);
+
+impl<'a, const N: usize, T: Debug + Clone> Iterator for Iter<'a, N, T> {
+    type Item = (Id, &'a Entry<[T; N]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(id, entry)| (*id, entry))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Serializes as a map from id to entry
+impl<const N: usize, T> Serialize for Snapshot<N, T>
+where
+    T: Debug + Clone,
+    Entry<[T; N]>: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<const N: usize, T> schemars::JsonSchema for Snapshot<N, T>
+where
+    T: Debug + Clone,
+    Entry<[T; N]>: schemars::JsonSchema,
+{
+    fn schema_name() -> String {
+        format!("Snapshot_for_{}", Entry::<[T; N]>::schema_name())
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<HashMap<Id, Entry<[T; N]>>>()
+    }
+}
+
+impl<const N: usize, T: Debug + Clone> ExactSizeIterator for Iter<'_, N, T> {}
+
+impl<'a, const N: usize, T: Debug + Clone> IntoIterator for &'a Snapshot<N, T> {
+    type Item = (Id, &'a Entry<[T; N]>);
+    type IntoIter = Iter<'a, N, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Owning iterator over a [`Snapshot`], clones each entry as it is reached.
+/// Created by [`Snapshot::iter_owned`], [`Chart::iter_entries`] or by
+/// iterating a snapshot by value.
+#[derive(Debug, Clone)]
+pub struct IntoIter<const N: usize, T: Debug + Clone> {
+    entries: Arc<Entries<[T; N]>>,
+    /// copying the ids is cheap compared to copying the entries
+    ids: std::vec::IntoIter<Id>,
+}
+
+impl<const N: usize, T: Debug + Clone> Iterator for IntoIter<N, T> {
+    type Item = (Id, Entry<[T; N]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        Some((id, self.entries[&id].clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<const N: usize, T: Debug + Clone> ExactSizeIterator for IntoIter<N, T> {}
+
+impl<const N: usize, T: Debug + Clone> IntoIterator for Snapshot<N, T> {
+    type Item = (Id, Entry<[T; N]>);
+    type IntoIter = IntoIter<N, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let ids: Vec<_> = self.entries.keys().copied().collect();
+        IntoIter {
+            entries: self.entries,
+            ids: ids.into_iter(),
+        }
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Take a [`Snapshot`] of the discovered instances. Unlike the
+    /// [iterator methods](Chart::addr_vec) this does not copy every entry
+    /// and the lock is only held to clone an `Arc`. Prefer this for large
+    /// clusters.
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use instance_chart::{discovery, ChartBuilder};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let chart = ChartBuilder::new()
+    ///     .with_id(1)
+    /// #   .with_discovery_port(43821)
+    ///     .with_service_port(8042)
+    ///     .finish()?;
+    /// let maintain = discovery::maintain(chart.clone());
+    /// let _ = tokio::spawn(maintain); // maintain task will run forever
+    /// for (id, addr) in chart.snapshot().addrs() {
+    ///     println!("instance {id} is at {addr}");
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot<N, T> {
+        Snapshot {
+            entries: self.map.lock().unwrap().snapshot(),
+        }
+    }
+
+    /// Iterate over each discovered instance's id and entry without
+    /// cloning every entry up front, see [`Snapshot::iter_owned`]. Changes
+    /// made after this was called do not show up.
+    #[must_use]
+    pub fn iter_entries(&self) -> IntoIter<N, T> {
+        self.snapshot().into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::chart::fixtures::entry_with;
+
+    fn entry(n: u8) -> (Id, Entry<[u16; 2]>) {
+        entry_with(n, [8000 + u16::from(n), 9000])
+    }
+
+    #[tokio::test]
+    async fn matches_vec_methods() {
+        let chart = Chart::test(entry).await;
+        let snapshot = chart.snapshot();
+        assert_eq!(snapshot.len(), 9);
+
+        let lists: HashSet<_> = snapshot.addr_lists().collect();
+        assert_eq!(lists, chart.addr_lists_vec().into_iter().collect());
+        let nth: HashSet<_> = snapshot.nth_addrs::<1>().collect();
+        assert_eq!(nth, chart.nth_addr_vec::<1>().into_iter().collect());
+    }
+
+    #[tokio::test]
+    async fn unaffected_by_later_changes() {
+        let chart = Chart::test(entry).await;
+        let snapshot = chart.snapshot();
+        chart.map.lock().unwrap().remove(&1);
+
+        assert_eq!(snapshot.len(), 9);
+        assert!(snapshot.get(1).is_some());
+        assert_eq!(chart.snapshot().len(), 8);
+        assert_eq!((&snapshot).into_iter().len(), 9);
+    }
+
+    #[tokio::test]
+    async fn owned_iter_clones_lazily() {
+        let chart = Chart::test(entry).await;
+        let mut entries = chart.iter_entries();
+        assert_eq!(entries.len(), 9);
+        let (id, first) = entries.next().unwrap();
+        assert_eq!(first.msg, [8000 + id as u16, 9000]);
+        chart.map.lock().unwrap().remove(&1);
+        assert_eq!(entries.len(), 8);
+
+        let ids: HashSet<_> = chart.snapshot().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, (2..=9).collect());
+    }
+}
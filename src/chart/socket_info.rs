@@ -0,0 +1,108 @@
+use std::fmt::Debug;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::Serialize;
+
+#[cfg(unix)]
+use super::Transport;
+use super::{Chart, Socket};
+
+/// The options the OS reports for a discovery socket, get them using
+/// [`Chart::socket_info`]. Include these when reporting discovery problems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SocketInfo {
+    pub local_addr: SocketAddr,
+    /// multicast groups joined, on the default interface
+    pub groups: Vec<IpAddr>,
+    /// whether other sockets may bind the same port, needed for
+    /// [local discovery](crate::ChartBuilder::local_discovery)
+    pub reuse_port: bool,
+    /// how many hops our messages travel, the TTL for IPv4 and the multicast
+    /// hop limit for IPv6
+    pub ttl: u32,
+    /// whether we receive our own messages
+    pub multicast_loop: bool,
+    /// the receive buffer size in bytes
+    pub recv_buffer: usize,
+    /// the send buffer size in bytes
+    pub send_buffer: usize,
+}
+
+impl SocketInfo {
+    fn query(socket: &Socket) -> io::Result<Self> {
+        let sock = socket.sock.sock_ref();
+        let local_addr = socket.sock.local_addr()?;
+        #[cfg(unix)]
+        if let Transport::Unix(unix) = socket.sock.inner() {
+            // unix sockets do not leave the host
+            return Ok(Self {
+                local_addr,
+                groups: Vec::new(),
+                reuse_port: false,
+                ttl: 0,
+                multicast_loop: unix.multicast_loop(),
+                recv_buffer: sock.recv_buffer_size()?,
+                send_buffer: sock.send_buffer_size()?,
+            });
+        }
+        let (ttl, multicast_loop) = if local_addr.is_ipv6() {
+            (sock.multicast_hops_v6()?, sock.multicast_loop_v6()?)
+        } else {
+            (sock.ttl()?, sock.multicast_loop_v4()?)
+        };
+        Ok(Self {
+            local_addr,
+            groups: socket.groups.clone(),
+            reuse_port: sock.reuse_port()?,
+            ttl,
+            multicast_loop,
+            recv_buffer: sock.recv_buffer_size()?,
+            send_buffer: sock.send_buffer_size()?,
+        })
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// The address the first discovery socket is bound to
+    #[allow(clippy::missing_panics_doc)] // socket is bound during building
+    #[must_use]
+    pub fn discovery_addr(&self) -> SocketAddr {
+        self.endpoints[0].sock.local_addr().unwrap()
+    }
+
+    /// The socket options as applied by the OS for every discovery socket.
+    /// There is one socket per discovery port and address family.
+    ///
+    /// # Errors
+    /// Returns an error if the OS could not report an option.
+    pub fn socket_info(&self) -> io::Result<Vec<SocketInfo>> {
+        self.endpoints.iter().map(SocketInfo::query).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::ChartBuilder;
+
+    #[tokio::test]
+    async fn reports_options() {
+        let chart = ChartBuilder::new()
+            .with_id(0)
+            .with_service_port(8042)
+            .with_discovery_port(43820)
+            .local_discovery(true)
+            .finish()
+            .unwrap();
+        assert_eq!(chart.discovery_addr().port(), 43820);
+        let [info] = chart.socket_info().unwrap().try_into().unwrap();
+        assert_eq!(info.local_addr, chart.discovery_addr());
+        assert_eq!(info.groups, [Ipv4Addr::new(224, 0, 0, 251)]);
+        assert!(info.reuse_port);
+        assert!(info.multicast_loop);
+        assert_eq!(info.ttl, 4);
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Debug;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{Chart, Counters, Entry, Source, Status};
+
+/// A snapshot of the chart and the work done maintaining it, get one using
+/// [`Chart::stats()`]. Meant for dashboards and debug logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChartStats {
+    /// instances in the chart, not counting this one
+    pub peers: usize,
+    /// peers heard from within the interval they advertise, peers that are
+    /// missing broadcasts are not counted
+    pub recently_seen: usize,
+    /// peers that are [draining](crate::discovery::drain)
+    pub draining: usize,
+    /// peers only known of through [`Chart::merge_from`], never heard from
+    /// over the network
+    pub merged: usize,
+    /// packets in and out, drops by reason and lagging notify receivers
+    pub counters: Counters,
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Collect [`ChartStats`] in one call
+    #[allow(clippy::missing_panics_doc)] // ignore lock poisoning
+    #[must_use]
+    pub fn stats(&self) -> ChartStats {
+        let (peers, recently_seen, draining, merged) = {
+            let map = self.map.lock().unwrap();
+            let count = |pred: fn(&Entry<[T; N]>) -> bool| {
+                map.iter().filter(|(_, entry)| pred(entry)).count()
+            };
+            (
+                map.len(),
+                map.heard_within_interval(Instant::now()),
+                count(|entry| entry.status == Status::Draining),
+                count(|entry| entry.source == Source::Merged),
+            )
+        };
+        ChartStats {
+            peers,
+            recently_seen,
+            draining,
+            merged,
+            counters: self.counters.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chart::map;
+    use crate::{Entry, Id};
+
+    #[tokio::test]
+    async fn snapshot() {
+        fn gen_kv(n: u8) -> (Id, Entry<[u16; 1]>) {
+            let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+            let mut entry = Entry::new(ip, [8042]);
+            if n.is_multiple_of(3) {
+                entry.status = Status::Draining;
+            }
+            (n.into(), entry)
+        }
+        let chart = Chart::test(gen_kv).await;
+        {
+            let mut map = chart.map.lock().unwrap();
+            let (id, entry) = gen_kv(1);
+            map.set_seen(
+                id,
+                entry.ip,
+                entry.ip,
+                map::fingerprint(&[]),
+                Duration::from_secs(60),
+                None,
+            );
+        }
+        chart.counters.received(3);
+
+        let stats = chart.stats();
+        assert_eq!(stats.peers, 9);
+        assert_eq!(stats.recently_seen, 1);
+        assert_eq!(stats.draining, 3);
+        assert_eq!(stats.counters.received, 3);
+    }
+}
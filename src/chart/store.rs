@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::map::Entries;
+use super::{Chart, Entry};
+use crate::Id;
+
+/// Where the chart keeps the discovered instances, install one using
+/// [`Chart::set_store`]. Every read ([`get_nth_addr`](Chart::get_nth_addr),
+/// [`addr_vec`](Chart::addr_vec), [`snapshot`](Chart::snapshot), ..) and
+/// every change goes through the store. Use it to keep a persistent history
+/// of the cluster, or to bound how many instances are charted on a device
+/// with little memory. By default the chart uses an in memory `HashMap`,
+/// which implements this trait too so custom stores can build on it.
+///
+/// # Note
+/// The methods are called with the chart locked from the discovery hot path,
+/// they should return quickly. Hand slow work such as disk writes off to
+/// another thread. With a custom store [`Chart::snapshot`] clones every
+/// entry instead of sharing them.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use instance_chart::{ChartStore, Entry, Id};
+///
+/// /// charts at most `limit` instances and keeps those that left
+/// struct Bounded {
+///     limit: usize,
+///     charted: HashMap<Id, Entry<[u16; 1]>>,
+///     history: Vec<(Id, Entry<[u16; 1]>)>,
+/// }
+///
+/// impl ChartStore<[u16; 1]> for Bounded {
+///     fn get(&self, id: Id) -> Option<&Entry<[u16; 1]>> {
+///         self.charted.get(&id)
+///     }
+///     fn insert(&mut self, id: Id, entry: Entry<[u16; 1]>) -> Option<Entry<[u16; 1]>> {
+///         self.charted.insert(id, entry)
+///     }
+///     fn remove(&mut self, id: Id) -> Option<Entry<[u16; 1]>> {
+///         let old = self.charted.remove(&id)?;
+///         self.history.push((id, old.clone()));
+///         Some(old)
+///     }
+///     fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<[u16; 1]>)> + '_> {
+///         Box::new(self.charted.iter())
+///     }
+///     fn len(&self) -> usize {
+///         self.charted.len()
+///     }
+///     fn capacity(&self) -> Option<usize> {
+///         Some(self.limit)
+///     }
+/// }
+/// ```
+pub trait ChartStore<Msg: Debug + Clone>: Send {
+    /// the entry charted for instance `id`
+    fn get(&self, id: Id) -> Option<&Entry<Msg>>;
+    /// chart `entry` for instance `id`, returns the entry it replaces
+    fn insert(&mut self, id: Id, entry: Entry<Msg>) -> Option<Entry<Msg>>;
+    /// remove instance `id` from the chart, returns its entry
+    fn remove(&mut self, id: Id) -> Option<Entry<Msg>>;
+    /// every charted instance, in any order
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<Msg>)> + '_>;
+    /// number of charted instances
+    fn len(&self) -> usize;
+    /// whether no instances are charted
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The most instances, excluding this one, to chart. Once reached newly
+    /// discovered instances are ignored until others leave. Unbounded by
+    /// default.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<Msg, S> ChartStore<Msg> for HashMap<Id, Entry<Msg>, S>
+where
+    Msg: Debug + Clone + Send,
+    S: BuildHasher + Send,
+{
+    fn get(&self, id: Id) -> Option<&Entry<Msg>> {
+        HashMap::get(self, &id)
+    }
+    fn insert(&mut self, id: Id, entry: Entry<Msg>) -> Option<Entry<Msg>> {
+        HashMap::insert(self, id, entry)
+    }
+    fn remove(&mut self, id: Id) -> Option<Entry<Msg>> {
+        HashMap::remove(self, &id)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<Msg>)> + '_> {
+        Box::new(HashMap::iter(self))
+    }
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+/// The entries of a [`Map`](super::map::Map)
+pub(crate) enum Store<Msg: Debug + Clone> {
+    /// the default, shared with any outstanding snapshot and copied on the
+    /// first write after one is taken
+    Memory(Arc<Entries<Msg>>),
+    Custom(Box<dyn ChartStore<Msg>>),
+}
+
+impl<Msg: Debug + Clone> Store<Msg> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self::Memory(Arc::new(Entries::with_capacity_and_hasher(
+            capacity,
+            Default::default(),
+        )))
+    }
+
+    pub(crate) fn get(&self, id: &Id) -> Option<&Entry<Msg>> {
+        match self {
+            Self::Memory(entries) => entries.get(id),
+            Self::Custom(store) => store.get(*id),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: Id, entry: Entry<Msg>) -> Option<Entry<Msg>> {
+        match self {
+            Self::Memory(entries) => Arc::make_mut(entries).insert(id, entry),
+            Self::Custom(store) => store.insert(id, entry),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &Id) -> Option<Entry<Msg>> {
+        match self {
+            // do not copy the entries if there is nothing to remove
+            Self::Memory(entries) if !entries.contains_key(id) => None,
+            Self::Memory(entries) => Arc::make_mut(entries).remove(id),
+            Self::Custom(store) => store.remove(*id),
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Id, &Entry<Msg>)> {
+        let (memory, custom) = match self {
+            Self::Memory(entries) => (Some(entries.iter()), None),
+            Self::Custom(store) => (None, Some(store.iter())),
+        };
+        memory
+            .into_iter()
+            .flatten()
+            .chain(custom.into_iter().flatten())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Memory(entries) => entries.len(),
+            Self::Custom(store) => store.len(),
+        }
+    }
+
+    /// the most entries to hold, None if unbounded
+    pub(crate) fn limit(&self) -> Option<usize> {
+        match self {
+            Self::Memory(_) => None,
+            Self::Custom(store) => store.capacity(),
+        }
+    }
+
+    /// The entries as they are now, later changes do not show up. Only
+    /// clones an `Arc` for the default store.
+    pub(crate) fn snapshot(&self) -> Arc<Entries<Msg>> {
+        match self {
+            Self::Memory(entries) => Arc::clone(entries),
+            Self::Custom(store) => Arc::new(
+                store
+                    .iter()
+                    .map(|(id, entry)| (*id, entry.clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<Msg: Debug + Clone> Debug for Store<Msg> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory(entries) => f.debug_tuple("Memory").field(entries).finish(),
+            Self::Custom(store) => f.debug_tuple("Custom").field(&store.len()).finish(),
+        }
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Keep the charted instances in `store` from now on, see
+    /// [`ChartStore`]. The instances already charted are moved into it right
+    /// away, anything else it holds is removed. Replaces any store set
+    /// before.
+    ///
+    /// # Note
+    /// If more instances are charted then the store's
+    /// [`capacity`](ChartStore::capacity) they are kept, only new instances
+    /// are ignored.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn set_store(&self, store: impl ChartStore<[T; N]> + 'static) {
+        self.map.lock().unwrap().set_store(Box::new(store));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::chart::fixtures::entry;
+
+    type Entries = HashMap<Id, Entry<[u16; 1]>>;
+
+    struct Bounded(usize, Entries);
+
+    impl ChartStore<[u16; 1]> for Bounded {
+        fn get(&self, id: Id) -> Option<&Entry<[u16; 1]>> {
+            self.1.get(&id)
+        }
+        fn insert(&mut self, id: Id, entry: Entry<[u16; 1]>) -> Option<Entry<[u16; 1]>> {
+            self.1.insert(id, entry)
+        }
+        fn remove(&mut self, id: Id) -> Option<Entry<[u16; 1]>> {
+            self.1.remove(&id)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<[u16; 1]>)> + '_> {
+            Box::new(self.1.iter())
+        }
+        fn len(&self) -> usize {
+            self.1.len()
+        }
+        fn capacity(&self) -> Option<usize> {
+            Some(self.0)
+        }
+    }
+
+    /// keeps the first entry charted for an id, ignoring updates
+    struct FirstSeen(Entries);
+
+    impl ChartStore<[u16; 1]> for FirstSeen {
+        fn get(&self, id: Id) -> Option<&Entry<[u16; 1]>> {
+            self.0.get(&id)
+        }
+        fn insert(&mut self, id: Id, entry: Entry<[u16; 1]>) -> Option<Entry<[u16; 1]>> {
+            let first = self.0.entry(id).or_insert(entry);
+            Some(first.clone())
+        }
+        fn remove(&mut self, id: Id) -> Option<Entry<[u16; 1]>> {
+            self.0.remove(&id)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Id, &Entry<[u16; 1]>)> + '_> {
+            Box::new(self.0.iter())
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[tokio::test]
+    async fn full_store_ignores_new_instances() {
+        let chart = Chart::test(entry).await;
+        chart.set_store(Bounded(9, Entries::new()));
+        let (id, new) = entry(42);
+        assert!(!chart.insert(id, new));
+        assert_eq!(chart.size(), 10);
+
+        let (id, mut known) = entry(3);
+        known.msg = [9000];
+        chart.insert(id, known);
+        assert_eq!(chart.get_nth_addr::<0>(3).unwrap().port(), 9000);
+    }
+
+    #[tokio::test]
+    async fn reads_come_from_store() {
+        let chart = Chart::test(entry).await;
+        chart.set_store(FirstSeen(Entries::new()));
+        let (id, mut known) = entry(3);
+        let first_ip = known.ip;
+        known.msg = [9000];
+        known.ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 99));
+        chart.insert(id, known);
+
+        assert_eq!(chart.get_nth_addr::<0>(3).unwrap().port(), 8042);
+        assert_eq!(chart.snapshot().get(3).unwrap().msg, [8042]);
+        assert_eq!(chart.snapshot().len(), 9);
+        assert_eq!(chart.id_by_ip(first_ip), Some(3));
+        assert_eq!(
+            chart.id_by_ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 99))),
+            None
+        );
+        assert_eq!(chart.entries_on_host(first_ip).len(), 1);
+    }
+}
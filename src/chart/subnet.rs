@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::Serialize;
+
+use super::{Chart, Entry};
+use crate::Id;
+
+/// The instances in one subnet, sorted by id
+type Peers<Msg> = Vec<(Id, Entry<Msg>)>;
+
+/// A network prefix such as `10.0.1.0/24`, see [`Chart::peers_by_subnet`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    /// The subnet with the first `prefix_len` bits of `ip`. The prefix
+    /// length is capped at 32 bits for IPv4 and 128 bits for IPv6.
+    #[must_use]
+    pub fn new(ip: IpAddr, prefix_len: u8) -> Self {
+        match ip {
+            IpAddr::V4(ip) => {
+                let prefix_len = prefix_len.min(32);
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                let addr = Ipv4Addr::from(u32::from(ip) & mask).into();
+                Self { addr, prefix_len }
+            }
+            IpAddr::V6(ip) => {
+                let prefix_len = prefix_len.min(128);
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                let addr = Ipv6Addr::from(u128::from(ip) & mask).into();
+                Self { addr, prefix_len }
+            }
+        }
+    }
+
+    /// The first address in the subnet
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The number of leading bits shared by every address in the subnet
+    #[must_use]
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `ip` is in the subnet
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        ip.is_ipv4() == self.addr.is_ipv4() && Self::new(ip, self.prefix_len) == *self
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl<const N: usize, T: fmt::Debug + Clone + Serialize> Chart<N, T> {
+    /// The instances in the chart grouped by the subnet of the address they
+    /// were discovered on, for example to place replicas on different racks.
+    /// Subnets are the first `prefix_len` bits of the address, see
+    /// [`Subnet::new`]. Each group is sorted by [`Id`]. This instance is
+    /// not included.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn peers_by_subnet(&self, prefix_len: u8) -> BTreeMap<Subnet, Peers<[T; N]>> {
+        let mut subnets: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (id, entry) in self.map.lock().unwrap().iter() {
+            subnets
+                .entry(Subnet::new(entry.ip, prefix_len))
+                .or_default()
+                .push((*id, entry.clone()));
+        }
+        for peers in subnets.values_mut() {
+            peers.sort_unstable_by_key(|(id, _)| *id);
+        }
+        subnets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures;
+
+    fn entry(n: u8) -> (Id, Entry<[u16; 1]>) {
+        let (id, entry) = fixtures::entry(n);
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, n % 3, n));
+        (id, Entry { ip, ..entry })
+    }
+
+    #[tokio::test]
+    async fn grouped_by_prefix() {
+        let chart = Chart::test(entry).await;
+        let subnets = chart.peers_by_subnet(24);
+        let racks: Vec<_> = subnets.keys().map(ToString::to_string).collect();
+        assert_eq!(racks, ["10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/24"]);
+        let ids = |rack: &Vec<(Id, _)>| rack.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(&subnets[&Subnet::new([10, 0, 1, 0].into(), 24)]),
+            [1, 4, 7]
+        );
+
+        let all = chart.peers_by_subnet(8);
+        assert_eq!(all.len(), 1);
+        assert_eq!(all.values().next().unwrap().len(), 9);
+    }
+
+    #[test]
+    fn prefix_is_masked_and_capped() {
+        let subnet = Subnet::new([192, 168, 7, 9].into(), 20);
+        assert_eq!(subnet.to_string(), "192.168.0.0/20");
+        assert!(subnet.contains([192, 168, 15, 1].into()));
+        assert!(!subnet.contains([192, 168, 16, 1].into()));
+        assert!(!subnet.contains(Ipv6Addr::LOCALHOST.into()));
+
+        assert_eq!(
+            Subnet::new([10, 0, 0, 1].into(), 0).to_string(),
+            "0.0.0.0/0"
+        );
+        assert_eq!(Subnet::new([10, 0, 0, 1].into(), 40).prefix_len(), 32);
+        let v6 = Subnet::new("fd00:1:2:3::1".parse().unwrap(), 48);
+        assert_eq!(v6.to_string(), "fd00:1:2::/48");
+    }
+}
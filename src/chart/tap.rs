@@ -0,0 +1,63 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::Chart;
+
+/// Whether a datagram passed to the [packet tap](crate::ChartBuilder::with_packet_tap)
+/// was sent or received
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// we sent the datagram, the address is where it went
+    Sent,
+    /// we received the datagram, the address is where it came from
+    Received,
+}
+
+type Callback = dyn Fn(Direction, &[u8], SocketAddr) + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct Tap(pub(crate) Arc<Callback>);
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tap")
+    }
+}
+
+impl<const N: usize, T: fmt::Debug + Clone + Serialize> Chart<N, T> {
+    /// pass a datagram to the packet tap, if there is one
+    pub(crate) fn tap(&self, direction: Direction, buf: &[u8], addr: SocketAddr) {
+        if let Some(Tap(tap)) = &self.tap {
+            tap(direction, buf, addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::chart::fixtures::entry;
+
+    #[tokio::test]
+    async fn tap_sees_datagrams() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut chart = Chart::test(entry).await;
+        let recorder = Arc::clone(&seen);
+        chart.tap = Some(Tap(Arc::new(move |direction, buf: &[u8], _| {
+            recorder.lock().unwrap().push((direction, buf.len()));
+        })));
+
+        let addr = SocketAddr::from(([10, 0, 0, 1], 8080));
+        chart.tap(Direction::Received, &[1, 2, 3], addr);
+        chart.tap(Direction::Sent, &[1], addr);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(Direction::Received, 3), (Direction::Sent, 1)]
+        );
+    }
+}
@@ -1,16 +1,14 @@
 use crate::Id;
-use std::net::SocketAddr;
 
-use super::builder::Port;
-use super::{Chart, Entry};
+use super::{Chart, Endpoint, Entry};
 
-impl<const N: usize> Chart<N, Port> {
-    /// Returns an vector with each discovered node's socketadresses.
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    /// Returns an vector with each discovered node's addresses, see [`Endpoint`].
     /// # Note
     /// - vector order is random
     /// - only availible for Chart configured with
-    /// [`ChartBuilder::with_service_ports`](crate::ChartBuilder::with_service_ports)
-    /// and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
+    ///   [`ChartBuilder::with_service_ports`](crate::ChartBuilder::with_service_ports)
+    ///   and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
     /// ```rust
     /// # use std::error::Error;
     /// # use instance_chart::{discovery, ChartBuilder};
@@ -28,32 +26,33 @@ impl<const N: usize> Chart<N, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
-    pub fn addr_lists_vec(&self) -> Vec<(Id, [SocketAddr; N])> {
+    pub fn addr_lists_vec(&self) -> Vec<(Id, [E::Addr; N])> {
         self.map
             .lock()
             .unwrap()
             .iter()
             .map(|(id, entry)| {
-                let Entry { ip, msg: ports } = entry;
-                let addr = ports.map(|p| SocketAddr::new(*ip, p));
+                let Entry {
+                    ip, msg: endpoints, ..
+                } = entry;
+                let addr = endpoints.each_ref().map(|e| e.resolve(*ip));
                 (*id, addr)
             })
             .collect()
     }
 }
 
-impl<const N: usize> Chart<N, Port> {
-    /// Returns a vector over each discoverd node's nth-socketadress
+impl<const N: usize, E: Endpoint> Chart<N, E> {
+    /// Returns a vector over each discoverd node's nth address, see [`Endpoint`]
     /// # Note
     /// - vector order is random
     /// - only availible for Chart configured with
-    /// [`ChartBuilder::with_service_ports`](crate::ChartBuilder::with_service_ports)
-    /// and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
+    ///   [`ChartBuilder::with_service_ports`](crate::ChartBuilder::with_service_ports)
+    ///   and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
     ///
     /// # Examples
     /// ```rust
@@ -74,32 +73,32 @@ impl<const N: usize> Chart<N, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
-    pub fn nth_addr_vec<const IDX: usize>(&self) -> Vec<(Id, SocketAddr)> {
+    pub fn nth_addr_vec<const IDX: usize>(&self) -> Vec<(Id, E::Addr)> {
         self.map
             .lock()
             .unwrap()
             .iter()
             .map(|(id, entry)| {
-                let Entry { ip, msg: ports } = entry;
-                let port = ports[IDX];
-                (*id, SocketAddr::new(*ip, port))
+                let Entry {
+                    ip, msg: endpoints, ..
+                } = entry;
+                (*id, endpoints[IDX].resolve(*ip))
             })
             .collect()
     }
 }
 
-impl<'a> Chart<1, Port> {
-    /// Returns a vector over each discoverd nodes's socketadress
+impl<'a, E: Endpoint> Chart<1, E> {
+    /// Returns a vector over each discoverd nodes's address, see [`Endpoint`]
     /// # Note
     /// - vector order is random
     /// - only availible for Chart configured with
-    /// [`ChartBuilder::with_service_port`](crate::ChartBuilder::with_service_port)
-    /// and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
+    ///   [`ChartBuilder::with_service_port`](crate::ChartBuilder::with_service_port)
+    ///   and build using [`ChartBuilder::finish`](crate::ChartBuilder::finish).
     /// ```rust
     /// # use std::error::Error;
     /// # use instance_chart::{discovery, ChartBuilder};
@@ -117,19 +116,22 @@ impl<'a> Chart<1, Port> {
     /// #   Ok(())
     /// # }
     /// ```
-
     // lock poisoning happens only on crash in another thread, in which
     // case panicing here is expected
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
-    pub fn addr_vec(&'a self) -> Vec<(Id, SocketAddr)> {
+    pub fn addr_vec(&'a self) -> Vec<(Id, E::Addr)> {
         self.map
             .lock()
             .unwrap()
             .iter()
             .map(|(id, entry)| {
-                let Entry { ip, msg: [port] } = entry;
-                (*id, SocketAddr::new(*ip, *port))
+                let Entry {
+                    ip,
+                    msg: [endpoint],
+                    ..
+                } = entry;
+                (*id, endpoint.resolve(*ip))
             })
             .collect()
     }
@@ -137,13 +139,19 @@ impl<'a> Chart<1, Port> {
 
 #[cfg(test)]
 mod tests {
-    use crate::chart::{Entry, Interval};
+    #[cfg(feature = "testing")]
+    use crate::chart::Cut;
+    use crate::chart::{
+        ConflictPolicy, Entry, Ewma, Interval, Map, ReplyPolicy, Socket, Status, Unreachable,
+        WireFormat,
+    };
     use crate::{Chart, Id};
     use serde::Serialize;
-    use std::collections::{HashMap, HashSet};
+    use std::collections::HashSet;
     use std::fmt::Debug;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::sync::{Arc, Mutex};
+    use std::time::Duration;
     use tokio::net::UdpSocket;
 
     impl<const N: usize, T: Serialize + Debug + Clone> Chart<N, T> {
@@ -152,15 +160,67 @@ mod tests {
             F: FnMut(u8) -> (Id, Entry<[T; N]>) + Copy,
         {
             let msg = gen_kv(0).1.msg;
-            let map: HashMap<Id, Entry<_>> = (1..10).map(gen_kv).collect();
+            let map: Map<_> = (1..10).map(gen_kv).collect();
+            let size = map.len() + 1;
             Self {
                 header: 0,
-                service_id: 0,
-                msg,
-                sock: Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()),
+                service_id: Arc::default(),
+                reroll_until: None,
+                #[cfg(feature = "rand")]
+                id_rng: None,
+                msg: Arc::new(std::sync::RwLock::new(msg)),
+                endpoints: Arc::new(vec![Socket::new(
+                    Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()),
+                    Vec::new(),
+                )]),
                 interval: Interval::test(),
+                size: Arc::new(tokio::sync::watch::Sender::new(size)),
                 map: Arc::new(Mutex::new(map)),
+                host: Arc::default(),
                 broadcast: tokio::sync::broadcast::channel(1).0,
+                events: tokio::sync::broadcast::channel(16).0,
+                conflict_policy: ConflictPolicy::default(),
+                reply_policy: ReplyPolicy::default(),
+                multicast_loop: true,
+                dual_stack: false,
+                group: None,
+                wire_format: WireFormat::default(),
+                status: Arc::new(Mutex::new(Status::default())),
+                counters: Arc::default(),
+                goodbyes: 3,
+                expiry: None,
+                ttl: None,
+                priority: Arc::default(),
+                visibility: Arc::default(),
+                quarantine: None,
+                debounce: None,
+                unreachable: Arc::new(Mutex::new(Unreachable::new(3, Duration::ZERO))),
+                trend: Arc::new(Mutex::new(Ewma::new(
+                    Duration::from_secs(10),
+                    size,
+                    tokio::time::Instant::now(),
+                ))),
+                budget: None,
+                observer: None,
+                meta: Arc::default(),
+                port_health: Arc::default(),
+                inbound: Arc::default(),
+                outbound: Arc::default(),
+                #[cfg(feature = "challenge")]
+                challenges: None,
+                #[cfg(feature = "packet-tap")]
+                tap: None,
+                #[cfg(feature = "fault-injection")]
+                faults: None,
+                #[cfg(feature = "testing")]
+                cut: Cut::default(),
+                encoded: Arc::default(),
+                suspended: Arc::default(),
+                autoassign: false,
+                awaiting_ports: Arc::default(),
+                freeze: Arc::default(),
+                drivers: Arc::default(),
+                closed: Arc::new(tokio::sync::watch::Sender::new(false)),
             }
         }
     }
@@ -170,7 +230,7 @@ mod tests {
         fn test_kv(n: u8) -> (Id, Entry<[u16; 1]>) {
             let ip = IpAddr::V4(Ipv4Addr::new(n, 0, 0, 1));
             let port = 8000 + n as u16;
-            (n as u64, Entry { ip, msg: [port] })
+            (n as u64, Entry::new(ip, [port]))
         }
 
         let chart = Chart::test(test_kv).await;
@@ -188,13 +248,7 @@ mod tests {
         let port1 = 8000 + n as u16;
         let port2 = 7000 + n as u16;
         let port3 = 6000 + n as u16;
-        (
-            n as u64,
-            Entry {
-                ip,
-                msg: [port1, port2, port3],
-            },
-        )
+        (n as u64, Entry::new(ip, [port1, port2, port3]))
     }
 
     #[tokio::test]
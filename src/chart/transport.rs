@@ -0,0 +1,348 @@
+use std::io;
+#[cfg(unix)]
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+#[cfg(feature = "testing")]
+use super::Cut;
+
+/// How instances on the same host discover each other, see
+/// [`ChartBuilder::local_discovery`](crate::ChartBuilder::local_discovery).
+/// A `bool` converts to [`Udp`](Self::Udp) if true and
+/// [`Disabled`](Self::Disabled) if false.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LocalDiscovery {
+    /// Only instances on other hosts are discovered
+    #[default]
+    Disabled,
+    /// Instances on this host share the discovery port over UDP multicast
+    Udp,
+    /// Only instances on this host are discovered, over unix datagram
+    /// sockets. Only available on unix.
+    #[cfg(unix)]
+    Unix,
+}
+
+impl From<bool> for LocalDiscovery {
+    fn from(is_enabled: bool) -> Self {
+        if is_enabled {
+            LocalDiscovery::Udp
+        } else {
+            LocalDiscovery::Disabled
+        }
+    }
+}
+
+/// What discovery messages travel over, UDP multicast unless local discovery
+/// is set to [`LocalDiscovery::Unix`]
+#[derive(Debug)]
+pub(crate) enum Transport {
+    Udp(Arc<UdpSocket>),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+    /// drops what is received from across a
+    /// [`Partition`](crate::testing::Partition)
+    #[cfg(feature = "testing")]
+    Partitioned(Box<Transport>, Cut),
+}
+
+impl Transport {
+    /// the transport underneath any wrapper
+    pub(crate) fn inner(&self) -> &Transport {
+        match self {
+            #[cfg(feature = "testing")]
+            Transport::Partitioned(inner, _) => inner.inner(),
+            transport => transport,
+        }
+    }
+
+    /// for [`Transport::Unix`] the loopback address and the discovery port
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Transport::Udp(sock) => sock.local_addr(),
+            #[cfg(unix)]
+            Transport::Unix(sock) => Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, sock.port))),
+            #[cfg(feature = "testing")]
+            Transport::Partitioned(inner, _) => inner.local_addr(),
+        }
+    }
+
+    pub(crate) async fn send_to(&self, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+        match self.inner() {
+            Transport::Udp(sock) => sock.send_to(buf, to).await,
+            #[cfg(unix)]
+            Transport::Unix(sock) => sock.send_to(buf, to).await,
+            #[cfg(feature = "testing")]
+            Transport::Partitioned(..) => unreachable!("inner is not wrapped"),
+        }
+    }
+
+    pub(crate) async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            let (len, from) = match self.inner() {
+                Transport::Udp(sock) => sock.recv_from(buf).await?,
+                #[cfg(unix)]
+                Transport::Unix(sock) => {
+                    let (len, from) = sock.sock.recv_from(buf).await?;
+                    (len, sock.peer_addr(&from))
+                }
+                #[cfg(feature = "testing")]
+                Transport::Partitioned(..) => unreachable!("inner is not wrapped"),
+            };
+            if !self.cuts_off(&buf[..len]) {
+                return Ok((len, from));
+            }
+        }
+    }
+
+    pub(crate) fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            let (len, from) = match self.inner() {
+                Transport::Udp(sock) => sock.try_recv_from(buf)?,
+                #[cfg(unix)]
+                Transport::Unix(sock) => {
+                    let (len, from) = sock.sock.try_recv_from(buf)?;
+                    (len, sock.peer_addr(&from))
+                }
+                #[cfg(feature = "testing")]
+                Transport::Partitioned(..) => unreachable!("inner is not wrapped"),
+            };
+            if !self.cuts_off(&buf[..len]) {
+                return Ok((len, from));
+            }
+        }
+    }
+
+    /// whether a wrapper drops the datagram in `buf`
+    #[cfg(feature = "testing")]
+    fn cuts_off(&self, buf: &[u8]) -> bool {
+        match self {
+            Transport::Partitioned(inner, cut) => cut.cuts_off(buf) || inner.cuts_off(buf),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "testing"))]
+    fn cuts_off(&self, _: &[u8]) -> bool {
+        false
+    }
+
+    pub(crate) fn sock_ref(&self) -> socket2::SockRef<'_> {
+        match self.inner() {
+            Transport::Udp(sock) => socket2::SockRef::from(sock),
+            #[cfg(unix)]
+            Transport::Unix(sock) => socket2::SockRef::from(&sock.sock),
+            #[cfg(feature = "testing")]
+            Transport::Partitioned(..) => unreachable!("inner is not wrapped"),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) use unix::UnixSocket;
+
+#[cfg(unix)]
+mod unix {
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use std::{io, process};
+
+    use tokio::net::{unix, UnixDatagram};
+
+    /// list the directory again if the peers were listed longer ago
+    const RELIST: Duration = Duration::from_secs(1);
+
+    /// A unix datagram socket in a directory shared by every instance on this
+    /// host using the same discovery port. Sockets are named after a token,
+    /// to the rest of the chart a peer is the loopback address with its token
+    /// as port. Broadcasting sends to every socket in the directory.
+    #[derive(Debug)]
+    pub(crate) struct UnixSocket {
+        pub(super) sock: UnixDatagram,
+        dir: PathBuf,
+        token: u16,
+        /// the discovery port, names the directory
+        pub(super) port: u16,
+        /// whether broadcasts are also sent to ourself
+        multicast_loop: bool,
+        peers: Arc<Mutex<Peers>>,
+    }
+
+    /// The tokens of the sockets in the directory, listed in the background
+    /// every [`RELIST`] and in between learned from what we receive
+    #[derive(Debug)]
+    struct Peers {
+        tokens: BTreeSet<u16>,
+        listed: Instant,
+        listing: bool,
+    }
+
+    impl UnixSocket {
+        /// The directory instances using discovery `port` share. It is in
+        /// `XDG_RUNTIME_DIR`, which only its user can access, if that is set
+        /// otherwise in the temporary directory.
+        fn dir(port: u16) -> PathBuf {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(format!("instance-chart-{port}"))
+        }
+
+        fn path(&self, token: u16) -> PathBuf {
+            self.dir.join(format!("{token}.sock"))
+        }
+
+        /// bind a socket under the first free token, starting from one derived
+        /// from the process id so instances rarely race for the same one
+        pub(crate) fn bind(port: u16, multicast_loop: bool) -> io::Result<Self> {
+            let dir = Self::dir(port);
+            create_private(&dir)?;
+            #[allow(clippy::cast_possible_truncation)] // wrapping is intended
+            let start = process::id() as u16;
+            for offset in 0..u16::MAX {
+                let token = start.wrapping_add(offset).max(1);
+                let path = dir.join(format!("{token}.sock"));
+                match UnixDatagram::bind(&path) {
+                    Ok(sock) => {
+                        let sock = Self {
+                            sock,
+                            peers: Arc::new(Mutex::new(Peers {
+                                tokens: list(&dir),
+                                listed: Instant::now(),
+                                listing: false,
+                            })),
+                            dir,
+                            token,
+                            port,
+                            multicast_loop,
+                        };
+                        check_owner(&sock.dir, &path)?;
+                        return Ok(sock);
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::AddrInUse => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+            Err(io::ErrorKind::AddrInUse.into())
+        }
+
+        /// the address the rest of the chart knows a peer by, the peer is
+        /// remembered for broadcasting
+        pub(super) fn peer_addr(&self, from: &unix::SocketAddr) -> SocketAddr {
+            let token = from
+                .as_pathname()
+                .and_then(|path| path.file_stem()?.to_str()?.parse().ok())
+                .unwrap_or(0);
+            if token != 0 {
+                self.peers.lock().unwrap().tokens.insert(token);
+            }
+            SocketAddr::from((Ipv4Addr::LOCALHOST, token))
+        }
+
+        pub(crate) fn multicast_loop(&self) -> bool {
+            self.multicast_loop
+        }
+
+        /// The addresses of every socket in the directory. The directory is
+        /// listed again on a blocking thread if the list is outdated, until
+        /// then the outdated list is returned.
+        pub(crate) fn peers(&self) -> Vec<SocketAddr> {
+            let mut peers = self.peers.lock().unwrap();
+            if peers.listed.elapsed() > RELIST && !peers.listing {
+                peers.listing = true;
+                let shared = Arc::clone(&self.peers);
+                let dir = self.dir.clone();
+                tokio::task::spawn_blocking(move || {
+                    let tokens = list(&dir);
+                    let mut peers = shared.lock().unwrap();
+                    peers.tokens = tokens;
+                    peers.listed = Instant::now();
+                    peers.listing = false;
+                });
+            }
+            peers
+                .tokens
+                .iter()
+                .filter(|token| self.multicast_loop || **token != self.token)
+                .map(|token| SocketAddr::from((Ipv4Addr::LOCALHOST, *token)))
+                .collect()
+        }
+
+        /// If the receiver's queue is full the datagram is dropped like UDP
+        /// would. Sockets of instances that crashed stay behind, they are
+        /// removed the first time sending to them fails.
+        pub(super) async fn send_to(&self, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+            let path = self.path(to.port());
+            match self.sock.try_send_to(buf, &path) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    trace!("queue of local instance {to} is full, dropped datagram");
+                    Ok(0)
+                }
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    self.peers.lock().unwrap().tokens.remove(&to.port());
+                    let _ig_err = fs::remove_file(&path);
+                    Err(err)
+                }
+                res => res,
+            }
+        }
+    }
+
+    impl Drop for UnixSocket {
+        fn drop(&mut self) {
+            let _ig_err = fs::remove_file(self.path(self.token));
+        }
+    }
+
+    /// the tokens of the sockets in `dir`
+    fn list(dir: &Path) -> BTreeSet<u16> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return BTreeSet::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.path().file_stem()?.to_str()?.parse().ok())
+            .collect()
+    }
+
+    /// create `dir` accessible only by its owner, an existing `dir` others
+    /// can access is changed to that which fails unless we own it
+    fn create_private(dir: &Path) -> io::Result<()> {
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(err) => return Err(err),
+        }
+        let meta = fs::symlink_metadata(dir)?;
+        if !meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not a directory", dir.display()),
+            ));
+        }
+        if meta.mode() & 0o077 != 0 {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(())
+    }
+
+    /// fails if `dir` is owned by someone else than the owner of our `socket`
+    /// in it
+    fn check_owner(dir: &Path, socket: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(dir)?.uid() != fs::symlink_metadata(socket)?.uid() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is owned by another user", dir.display()),
+            ));
+        }
+        Ok(())
+    }
+}
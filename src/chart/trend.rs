@@ -0,0 +1,110 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::Chart;
+
+/// The size of the chart smoothed over time, get it using
+/// [`Chart::size_trend`]. Meant for autoscalers that should not react to a
+/// single lost or late packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct SizeTrend {
+    /// exponentially weighted moving average of the number of instances,
+    /// including self
+    pub smoothed: f64,
+    /// how fast the smoothed size changes in instances per second, negative
+    /// while it shrinks
+    pub per_second: f64,
+}
+
+/// Exponentially weighted moving average of the chart size. The size only
+/// changes in steps, in between them the average decays towards it.
+#[derive(Debug)]
+pub(crate) struct Ewma {
+    window: Duration,
+    smoothed: f64,
+    size: usize,
+    since: Instant,
+}
+
+impl Ewma {
+    pub(crate) fn new(window: Duration, size: usize, now: Instant) -> Self {
+        Self {
+            window,
+            smoothed: size as f64,
+            size,
+            since: now,
+        }
+    }
+
+    /// the average as it decayed towards the current size by `now`
+    fn smoothed_at(&self, now: Instant) -> f64 {
+        if self.window.is_zero() {
+            return self.size as f64;
+        }
+        let elapsed = now.saturating_duration_since(self.since);
+        let remains = (-elapsed.as_secs_f64() / self.window.as_secs_f64()).exp();
+        self.size as f64 + (self.smoothed - self.size as f64) * remains
+    }
+
+    pub(crate) fn update(&mut self, size: usize, now: Instant) {
+        self.smoothed = self.smoothed_at(now);
+        self.size = size;
+        self.since = now;
+    }
+
+    pub(crate) fn trend(&self, now: Instant) -> SizeTrend {
+        let smoothed = self.smoothed_at(now);
+        let per_second = if self.window.is_zero() {
+            0.0
+        } else {
+            (self.size as f64 - smoothed) / self.window.as_secs_f64()
+        };
+        SizeTrend {
+            smoothed,
+            per_second,
+        }
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// The number of instances in the chart, including self, smoothed over
+    /// the [window](crate::ChartBuilder::with_size_window) and how fast that
+    /// changes. Use this instead of [`size`](Chart::size) to drive autoscaling.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn size_trend(&self) -> SizeTrend {
+        self.trend.lock().unwrap().trend(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn decays_towards_size() {
+        let window = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut ewma = Ewma::new(window, 1, start);
+        ewma.update(11, start);
+        let trend = ewma.trend(start);
+        assert!((trend.smoothed - 1.0).abs() < 1e-9);
+        assert!((trend.per_second - 1.0).abs() < 1e-9);
+
+        let trend = ewma.trend(start + window);
+        let expected = 11.0 - 10.0 / std::f64::consts::E;
+        assert!((trend.smoothed - expected).abs() < 1e-9);
+
+        // a blip barely moves the average
+        let later = start + window * 10;
+        ewma.update(10, later);
+        ewma.update(11, later + Duration::from_millis(100));
+        assert!(ewma.trend(later + Duration::from_millis(100)).smoothed > 10.98);
+    }
+}
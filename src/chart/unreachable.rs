@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{Chart, Event};
+use crate::Id;
+
+/// Failure scores of the instances the application could not reach, see
+/// [`Chart::report_unreachable`]
+#[derive(Debug)]
+pub(crate) struct Unreachable {
+    threshold: u32,
+    ignore_for: Duration,
+    scores: HashMap<Id, u32>,
+    /// removed instances and until when their messages are ignored
+    ignored: HashMap<Id, Instant>,
+}
+
+impl Unreachable {
+    pub(crate) fn new(threshold: u32, ignore_for: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            ignore_for,
+            scores: HashMap::new(),
+            ignored: HashMap::new(),
+        }
+    }
+
+    /// count a failure to reach `id`, returns the number of failures if that
+    /// reached the threshold
+    fn fail(&mut self, id: Id, now: Instant) -> Option<u32> {
+        let score = self.scores.entry(id).or_default();
+        *score += 1;
+        if *score < self.threshold {
+            return None;
+        }
+        let failures = *score;
+        self.scores.remove(&id);
+        if !self.ignore_for.is_zero() {
+            self.ignored.insert(id, now + self.ignore_for);
+        }
+        Some(failures)
+    }
+
+    /// true if messages from `id` should be ignored as it was removed for
+    /// being unreachable a short while ago
+    fn ignores(&mut self, id: Id, now: Instant) -> bool {
+        match self.ignored.get(&id) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                self.ignored.remove(&id);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+impl<const N: usize, T: Debug + Clone + Serialize> Chart<N, T> {
+    /// Tell the chart instance `id` could not be reached, for example because
+    /// connecting to it failed. Once this is reported a number of times, see
+    /// [`ChartBuilder::with_unreachable_threshold`](crate::ChartBuilder::with_unreachable_threshold),
+    /// without a [`report_reachable`](Self::report_reachable) in between the
+    /// instance is removed and an [`Event::Unreachable`] is sent. Returns true
+    /// if this report removed it.
+    ///
+    /// Reports about instances that are not charted are ignored.
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn report_unreachable(&self, id: Id) -> bool {
+        let failures = {
+            let mut map = self.map.lock().unwrap();
+            if map.get(&id).is_none() {
+                return false;
+            }
+            let mut unreachable = self.unreachable.lock().unwrap();
+            // scores of instances that left the chart are meaningless
+            unreachable.scores.retain(|id, _| map.get(id).is_some());
+            let Some(failures) = unreachable.fail(id, Instant::now()) else {
+                return false;
+            };
+            map.remove(&id);
+            self.update_size(&map);
+            failures
+        };
+        warn!("instance {id} was unreachable {failures} times, removed it from the chart");
+        let _ig_err = self.events.send(Event::Unreachable { id, failures });
+        true
+    }
+
+    /// whether `id` was removed for being unreachable and should not be
+    /// charted again yet
+    pub(crate) fn ignores_unreachable(&self, id: Id) -> bool {
+        let ignored = self.unreachable.lock().unwrap().ignores(id, Instant::now());
+        if ignored {
+            trace!("ignoring instance {id}, it was removed for being unreachable");
+        }
+        ignored
+    }
+
+    /// Tell the chart instance `id` was reached, resets the failures counted
+    /// by [`report_unreachable`](Self::report_unreachable)
+    // lock poisoning happens only on crash in another thread, in which
+    // case panicing here is expected
+    #[allow(clippy::missing_panics_doc)]
+    pub fn report_reachable(&self, id: Id) {
+        self.unreachable.lock().unwrap().scores.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn removed_at_threshold_then_ignored() {
+        let mut unreachable = Unreachable::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(unreachable.fail(7, now), None);
+        assert_eq!(unreachable.fail(7, now), Some(2));
+        assert!(unreachable.ignores(7, now));
+        assert_eq!(unreachable.fail(7, now), None, "score starts over");
+
+        let later = now + Duration::from_secs(11);
+        assert!(!unreachable.ignores(7, later));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::Id;
+
+/// How many instances each peer says it charted, to find peers that see far
+/// fewer then we do, see [`Event::AsymmetricVisibility`](crate::Event::AsymmetricVisibility)
+#[derive(Debug, Default)]
+pub(crate) struct Visibility {
+    peers: HashMap<Id, Report>,
+}
+
+#[derive(Debug)]
+struct Report {
+    /// instances the peer charted, including itself
+    sees: u32,
+    /// when we first heard from the peer
+    since: Instant,
+    /// whether an event was sent, not again until the peer recovers
+    flagged: bool,
+}
+
+impl Visibility {
+    /// `id` says it charted `sees` instances
+    pub(crate) fn report(&mut self, id: Id, sees: u32, now: Instant) {
+        self.peers
+            .entry(id)
+            .and_modify(|report| report.sees = sees)
+            .or_insert(Report {
+                sees,
+                since: now,
+                flagged: false,
+            });
+    }
+
+    /// The `charted` peers heard from for at least `grace` that see less
+    /// then half of the `ours` instances we see, with what they see. A peer
+    /// is returned once until it sees at least half again.
+    pub(crate) fn check(
+        &mut self,
+        charted: impl Fn(&Id) -> bool,
+        ours: u32,
+        grace: Duration,
+        now: Instant,
+    ) -> Vec<(Id, u32)> {
+        self.peers.retain(|id, _| charted(id));
+        let mut asymmetric = Vec::new();
+        for (id, report) in &mut self.peers {
+            if report.sees.saturating_mul(2) >= ours {
+                report.flagged = false;
+            } else if !report.flagged && now.duration_since(report.since) >= grace {
+                report.flagged = true;
+                asymmetric.push((*id, report.sees));
+            }
+        }
+        asymmetric.sort_unstable();
+        asymmetric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_once_after_grace() {
+        let grace = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut visibility = Visibility::default();
+        visibility.report(1, 1, start);
+        visibility.report(2, 5, start);
+        visibility.report(3, 1, start + grace);
+
+        let all = |_: &Id| true;
+        assert!(visibility.check(all, 5, grace, start).is_empty());
+        let later = start + grace;
+        assert_eq!(visibility.check(all, 5, grace, later), [(1, 1)]);
+        assert!(visibility.check(all, 5, grace, later).is_empty());
+
+        visibility.report(1, 4, later);
+        assert!(visibility.check(all, 5, grace, later).is_empty());
+        visibility.report(1, 2, later);
+        assert_eq!(visibility.check(|id| *id != 3, 5, grace, later), [(1, 2)]);
+        assert!(!visibility.peers.contains_key(&3));
+    }
+}
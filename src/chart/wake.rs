@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// The monotonic clock does not advance while the system is suspended, the
+/// wall clock does. A difference between the two means we slept.
+#[derive(Debug)]
+pub(crate) struct WakeDetector {
+    mono: Instant,
+    wall: SystemTime,
+}
+
+/// smaller differences could be wall clock adjustments (NTP)
+const THRESHOLD: Duration = Duration::from_secs(2);
+
+impl WakeDetector {
+    pub(crate) fn new() -> Self {
+        Self {
+            mono: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// how long the system was suspended since the last call, if it was
+    pub(crate) fn check(&mut self) -> Option<Duration> {
+        let mono = self.mono.elapsed();
+        let wall = self.wall.elapsed().unwrap_or_default();
+        *self = Self::new();
+        slept(mono, wall)
+    }
+}
+
+fn slept(mono: Duration, wall: Duration) -> Option<Duration> {
+    let slept = wall.checked_sub(mono)?;
+    (slept > THRESHOLD).then_some(slept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_suspend() {
+        let s = Duration::from_secs;
+        assert_eq!(slept(s(1), s(1)), None);
+        assert_eq!(slept(s(1), s(2)), None);
+        assert_eq!(slept(s(5), s(1)), None);
+        assert_eq!(slept(s(1), s(61)), Some(s(60)));
+    }
+}
@@ -0,0 +1,711 @@
+#[cfg(feature = "protobuf")]
+use std::any::Any;
+
+#[cfg(feature = "protobuf")]
+use super::{array, MAX_DATAGRAM};
+use super::{Capabilities, DiscoveryMsg, Metadata, PortHealth, Status};
+use crate::Id;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The protobuf schema for [`WireFormat::Protobuf`]. Use it to generate code
+/// for instances not written in rust.
+#[cfg(feature = "protobuf")]
+pub const PROTO_SCHEMA: &str = include_str!("../../proto/discovery.proto");
+
+/// How discovery messages are encoded on the wire. All instances in a chart
+/// must use the same format, set it using
+/// [`ChartBuilder::with_wire_format`](crate::ChartBuilder::with_wire_format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WireFormat {
+    /// Compact format only readable by this crate
+    Bincode(BincodeOptions),
+    /// Protobuf, the schema is available as [`PROTO_SCHEMA`]. Service ports
+    /// are encoded as a repeated `uint32`. A custom message is stored bincode
+    /// encoded in a bytes field, instances not written in rust can only read
+    /// charts sharing service ports. Messages must fit in one datagram of
+    /// 1024 bytes.
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        WireFormat::Bincode(BincodeOptions::default())
+    }
+}
+
+/// How bincode encodes integers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntEncoding {
+    /// Integers always take up their full size
+    #[default]
+    Fixed,
+    /// Small integers take up less space, shrinks the messages of charts with
+    /// many service ports
+    Varint,
+}
+
+/// Byte order used by bincode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Configures the bincode [`WireFormat`]. The defaults encode like
+/// `bincode::serialize`: fixed size little endian integers without a limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BincodeOptions {
+    pub int_encoding: IntEncoding,
+    pub endian: Endian,
+    /// Maximum size of a message in bytes, larger messages are dropped.
+    /// Defaults to no limit.
+    pub limit: Option<u64>,
+}
+
+/// call `$body` with `$options` bound to the bincode `Options` matching a
+/// [`BincodeOptions`]
+macro_rules! with_options {
+    ($config:expr, $options:ident => $body:expr) => {{
+        let base = bincode::DefaultOptions::new().allow_trailing_bytes();
+        let BincodeOptions {
+            int_encoding,
+            endian,
+            limit,
+        } = $config;
+        match (int_encoding, endian) {
+            (IntEncoding::Fixed, Endian::Little) => {
+                let base = base.with_fixint_encoding().with_little_endian();
+                with_options!(@limit base, limit, $options => $body)
+            }
+            (IntEncoding::Fixed, Endian::Big) => {
+                let base = base.with_fixint_encoding().with_big_endian();
+                with_options!(@limit base, limit, $options => $body)
+            }
+            (IntEncoding::Varint, Endian::Little) => {
+                let base = base.with_varint_encoding().with_little_endian();
+                with_options!(@limit base, limit, $options => $body)
+            }
+            (IntEncoding::Varint, Endian::Big) => {
+                let base = base.with_varint_encoding().with_big_endian();
+                with_options!(@limit base, limit, $options => $body)
+            }
+        }
+    }};
+    (@limit $base:expr, $limit:expr, $options:ident => $body:expr) => {
+        match $limit {
+            Some(limit) => {
+                let $options = $base.with_limit(limit);
+                $body
+            }
+            None => {
+                let $options = $base.with_no_limit();
+                $body
+            }
+        }
+    };
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum DecodeError {
+    #[error("invalid bincode: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[cfg(feature = "protobuf")]
+    #[error("invalid protobuf: {0}")]
+    Protobuf(#[from] prost::DecodeError),
+    #[cfg(feature = "protobuf")]
+    #[error("expected {expected} service ports got: {got:?}")]
+    Ports { expected: usize, got: Vec<u32> },
+    #[error("instance {id} sends {got} values, expected {expected}")]
+    MismatchedN { id: Id, expected: usize, got: usize },
+}
+
+/// The fields in front of the message, decoding only these is cheap and
+/// allocation free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) struct Prefix {
+    pub header: u64,
+    pub id: Id,
+    pub status: Status,
+}
+
+/// The fields up to and including the number of values in the message, the
+/// others are only decoded to get past them
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Count {
+    header: u64,
+    id: Id,
+    status: Status,
+    interval_ms: u32,
+    group: Option<u16>,
+    capabilities: Capabilities,
+    len: u64,
+}
+
+/// An optional part of a bincode discovery message following the fields
+/// every version sends. Its `tag` is the index of the [`Capabilities`] bit
+/// announcing support for it. Unknown extensions are skipped, so new ones
+/// can be rolled out without breaking instances on older versions.
+#[derive(Serialize, Deserialize)]
+struct Extension {
+    tag: u8,
+    data: Vec<u8>,
+}
+
+impl Extension {
+    fn new<O: Options>(
+        options: O,
+        flag: Capabilities,
+        value: &impl Serialize,
+    ) -> bincode::Result<Self> {
+        #[allow(clippy::cast_possible_truncation)] // at most 15
+        let tag = flag.bits().trailing_zeros() as u8;
+        let data = options.serialize(value)?;
+        Ok(Self { tag, data })
+    }
+}
+
+/// the extensions carrying the fields of `msg` that not every version
+/// sends, fields at their default are left out
+fn extensions<O, const N: usize, T>(
+    options: O,
+    msg: &DiscoveryMsg<N, T>,
+) -> bincode::Result<Vec<Extension>>
+where
+    O: Options + Copy,
+{
+    let mut extensions = Vec::new();
+    if msg.meta != Metadata::default() {
+        extensions.push(Extension::new(options, Capabilities::METADATA, &msg.meta)?);
+    }
+    if msg.port_health != PortHealth::default() {
+        let health = &msg.port_health;
+        extensions.push(Extension::new(options, Capabilities::PORT_HEALTH, health)?);
+    }
+    if let Some(ttl_ms) = msg.ttl_ms {
+        extensions.push(Extension::new(options, Capabilities::TTL, &ttl_ms)?);
+    }
+    if msg.priority != 0 {
+        extensions.push(Extension::new(
+            options,
+            Capabilities::PRIORITY,
+            &msg.priority,
+        )?);
+    }
+    extensions.push(Extension::new(
+        options,
+        Capabilities::VISIBILITY,
+        &msg.sees,
+    )?);
+    Ok(extensions)
+}
+
+/// fill in the fields of `msg` carried by `extensions`, unknown ones are
+/// skipped
+fn apply_extensions<O, const N: usize, T>(
+    options: O,
+    msg: &mut DiscoveryMsg<N, T>,
+    extensions: Vec<Extension>,
+) -> bincode::Result<()>
+where
+    O: Options + Copy,
+{
+    for Extension { tag, data } in extensions {
+        let Some(flag) = 1u16.checked_shl(u32::from(tag)) else {
+            continue;
+        };
+        match Capabilities::from_bits(flag) {
+            Capabilities::METADATA => msg.meta = options.deserialize(&data)?,
+            Capabilities::PORT_HEALTH => msg.port_health = options.deserialize(&data)?,
+            Capabilities::TTL => msg.ttl_ms = Some(options.deserialize(&data)?),
+            Capabilities::PRIORITY => msg.priority = options.deserialize(&data)?,
+            Capabilities::VISIBILITY => msg.sees = options.deserialize(&data)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// the fields every version sends followed by the [extensions](Extension)
+fn encode_bincode<O, const N: usize, T>(
+    options: O,
+    msg: &DiscoveryMsg<N, T>,
+) -> bincode::Result<Vec<u8>>
+where
+    O: Options + Copy,
+    T: Serialize,
+{
+    let mut buf = options.serialize(msg)?;
+    options.serialize_into(&mut buf, &extensions(options, msg)?)?;
+    Ok(buf)
+}
+
+/// the length of the message [`encode_bincode`] returns
+fn bincode_len<O, const N: usize, T>(options: O, msg: &DiscoveryMsg<N, T>) -> bincode::Result<u64>
+where
+    O: Options + Copy,
+    T: Serialize,
+{
+    let len =
+        options.serialized_size(msg)? + options.serialized_size(&extensions(options, msg)?)?;
+    Ok(len)
+}
+
+/// decode a message from [`encode_bincode`], messages of versions that
+/// send no extensions are decoded too
+fn decode_bincode<O, const N: usize, T>(
+    options: O,
+    buf: &[u8],
+) -> bincode::Result<DiscoveryMsg<N, T>>
+where
+    O: Options + Copy,
+    T: DeserializeOwned,
+{
+    let mut rest = buf;
+    let mut msg: DiscoveryMsg<N, T> = options.deserialize_from(&mut rest)?;
+    if !rest.is_empty() {
+        let extensions = options.deserialize(rest)?;
+        apply_extensions(options, &mut msg, extensions)?;
+    }
+    Ok(msg)
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
+struct Payload<const N: usize, T>(#[serde(with = "array")] [T; N]);
+
+#[cfg(feature = "protobuf")]
+mod protobuf {
+    use super::Payload;
+    use serde::Serialize;
+    use std::any::Any;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct DiscoveryMsg {
+        #[prost(fixed64, tag = "1")]
+        pub header: u64,
+        #[prost(uint64, tag = "2")]
+        pub id: u64,
+        #[prost(uint32, repeated, tag = "3")]
+        pub ports: Vec<u32>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub custom: Vec<u8>,
+        #[prost(uint32, tag = "5")]
+        pub status: u32,
+        #[prost(uint32, tag = "6")]
+        pub interval_ms: u32,
+        #[prost(uint32, optional, tag = "7")]
+        pub group: Option<u32>,
+        #[prost(uint32, tag = "8")]
+        pub capabilities: u32,
+        #[prost(uint64, tag = "9")]
+        pub meta_version: u64,
+        #[prost(btree_map = "string, string", tag = "10")]
+        pub meta: std::collections::BTreeMap<String, String>,
+        #[prost(uint64, tag = "11")]
+        pub unhealthy_ports: u64,
+        #[prost(uint32, optional, tag = "12")]
+        pub ttl_ms: Option<u32>,
+        #[prost(uint32, tag = "13")]
+        pub priority: u32,
+        #[prost(uint32, tag = "14")]
+        pub sees: u32,
+    }
+
+    impl DiscoveryMsg {
+        /// service ports are sent as is, a custom message is bincode
+        /// encoded as other languages have no generic way to read it
+        pub(super) fn from_msg<const N: usize, T>(
+            msg: &super::DiscoveryMsg<N, T>,
+        ) -> bincode::Result<Self>
+        where
+            T: 'static + Serialize + Clone,
+        {
+            let mut proto = DiscoveryMsg {
+                header: msg.header,
+                id: msg.id,
+                status: status(msg.status),
+                interval_ms: msg.interval_ms,
+                group: msg.group.map(u32::from),
+                capabilities: u32::from(msg.capabilities.bits()),
+                meta_version: msg.meta.version(),
+                meta: msg
+                    .meta
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+                unhealthy_ports: msg.port_health.unhealthy_bits(),
+                ttl_ms: msg.ttl_ms,
+                priority: msg.priority,
+                sees: msg.sees,
+                ..Default::default()
+            };
+            let any: &dyn Any = &msg.msg;
+            if let Some(ports) = any.downcast_ref::<[u16; N]>() {
+                proto.ports = ports.iter().copied().map(u32::from).collect();
+            } else {
+                let payload = Payload(msg.msg.clone());
+                proto.custom = bincode::serialize(&payload)?;
+            }
+            Ok(proto)
+        }
+    }
+
+    pub(super) fn status(status: crate::Status) -> u32 {
+        match status {
+            crate::Status::Active => 0,
+            crate::Status::Draining => 1,
+            crate::Status::Leaving => 2,
+        }
+    }
+
+    pub(super) fn from_status(status: u32) -> crate::Status {
+        match status {
+            1 => crate::Status::Draining,
+            2 => crate::Status::Leaving,
+            _ => crate::Status::Active,
+        }
+    }
+}
+
+impl WireFormat {
+    pub(crate) fn encode<const N: usize, T>(
+        self,
+        msg: &DiscoveryMsg<N, T>,
+    ) -> Result<Vec<u8>, bincode::Error>
+    where
+        T: 'static + Serialize + DeserializeOwned + Clone,
+    {
+        match self {
+            WireFormat::Bincode(config) => {
+                let buf = with_options!(config, options => encode_bincode(options, msg))?;
+                check_limit(config, buf.len())?;
+                Ok(buf)
+            }
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => {
+                use prost::Message;
+                Ok(protobuf::DiscoveryMsg::from_msg(msg)?.encode_to_vec())
+            }
+        }
+    }
+
+    /// whether `msg` encodes within the [`limit`](Self::limit)
+    pub(crate) fn fits<const N: usize, T>(self, msg: &DiscoveryMsg<N, T>) -> bool
+    where
+        T: 'static + Serialize + Clone,
+    {
+        match self {
+            WireFormat::Bincode(config) => {
+                let len = with_options!(config, options => bincode_len(options, msg));
+                len.is_ok_and(|len| config.limit.is_none_or(|limit| len <= limit))
+            }
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => {
+                use prost::Message;
+                protobuf::DiscoveryMsg::from_msg(msg)
+                    .is_ok_and(|proto| proto.encoded_len() <= MAX_DATAGRAM)
+            }
+        }
+    }
+
+    /// the most bytes a message may take, see [`BincodeOptions::limit`].
+    /// Protobuf messages must fit in a single datagram.
+    pub(crate) fn limit(self) -> Option<u64> {
+        match self {
+            WireFormat::Bincode(config) => config.limit,
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => Some(MAX_DATAGRAM as u64),
+        }
+    }
+
+    /// decode only the [`Prefix`], returns `None` if the format does not
+    /// support that or the message is invalid
+    pub(crate) fn peek(self, buf: &[u8]) -> Option<Prefix> {
+        match self {
+            WireFormat::Bincode(config) => {
+                with_options!(config, options => options.deserialize(buf)).ok()
+            }
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => None,
+        }
+    }
+
+    /// the header and id of a discovery message in this format whatever
+    /// its header, `None` if `buf` is not one
+    pub(crate) fn sender(self, buf: &[u8]) -> Option<(u64, Id)> {
+        match self {
+            WireFormat::Bincode(_) => self.peek(buf).map(|prefix| (prefix.header, prefix.id)),
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => {
+                use prost::Message;
+                let proto = protobuf::DiscoveryMsg::decode(buf).ok()?;
+                Some((proto.header, proto.id))
+            }
+        }
+    }
+
+    pub(crate) fn decode<const N: usize, T>(
+        self,
+        buf: &[u8],
+    ) -> Result<DiscoveryMsg<N, T>, DecodeError>
+    where
+        T: 'static + Serialize + DeserializeOwned,
+    {
+        match self {
+            WireFormat::Bincode(config) => {
+                let res = check_limit(config, buf.len())
+                    .and_then(|()| with_options!(config, options => decode_bincode(options, buf)));
+                res.map_err(|err| {
+                    let count = with_options!(config, options => options.deserialize(buf));
+                    match count {
+                        Ok(Count { id, len, .. }) if len != N as u64 => DecodeError::MismatchedN {
+                            id,
+                            expected: N,
+                            got: usize::try_from(len).unwrap_or(usize::MAX),
+                        },
+                        _ => DecodeError::Bincode(err),
+                    }
+                })
+            }
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => {
+                use prost::Message;
+                let proto = protobuf::DiscoveryMsg::decode(buf)?;
+                if is_port::<T>() && proto.ports.len() != N {
+                    return Err(DecodeError::MismatchedN {
+                        id: proto.id,
+                        expected: N,
+                        got: proto.ports.len(),
+                    });
+                }
+                let msg = if is_port::<T>() {
+                    let ports: Option<Vec<u16>> =
+                        proto.ports.iter().map(|p| u16::try_from(*p).ok()).collect();
+                    let ports: [u16; N] =
+                        ports.and_then(|p| p.try_into().ok()).ok_or_else(|| {
+                            DecodeError::Ports {
+                                expected: N,
+                                got: proto.ports.clone(),
+                            }
+                        })?;
+                    let ports: Box<dyn Any> = Box::new(ports);
+                    *ports.downcast::<[T; N]>().expect("T is Port")
+                } else {
+                    bincode::deserialize::<Payload<N, T>>(&proto.custom)?.0
+                };
+                Ok(DiscoveryMsg {
+                    header: proto.header,
+                    id: proto.id,
+                    status: protobuf::from_status(proto.status),
+                    interval_ms: proto.interval_ms,
+                    group: proto.group.and_then(|g| u16::try_from(g).ok()),
+                    // flags beyond 16 bits are not defined yet
+                    #[allow(clippy::cast_possible_truncation)]
+                    capabilities: Capabilities::from_bits(proto.capabilities as u16),
+                    msg,
+                    meta: super::Metadata::from_parts(proto.meta_version, proto.meta),
+                    port_health: super::PortHealth::from_unhealthy_bits(proto.unhealthy_ports),
+                    ttl_ms: proto.ttl_ms,
+                    priority: proto.priority,
+                    sees: proto.sees,
+                })
+            }
+        }
+    }
+}
+
+/// the limit applies to the whole message, the fields and extensions are
+/// encoded separately
+fn check_limit(config: BincodeOptions, len: usize) -> bincode::Result<()> {
+    match config.limit {
+        Some(limit) if len as u64 > limit => Err(Box::new(bincode::ErrorKind::SizeLimit)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "protobuf")]
+fn is_port<T: 'static>() -> bool {
+    std::any::TypeId::of::<T>() == std::any::TypeId::of::<super::Port>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::fixtures::test_msg;
+
+    fn roundtrip<const N: usize, T>(format: WireFormat, msg: [T; N]) -> [T; N]
+    where
+        T: 'static + Serialize + DeserializeOwned + Clone,
+    {
+        let msg = DiscoveryMsg {
+            status: crate::Status::Draining,
+            group: Some(3),
+            port_health: PortHealth::from_unhealthy_bits(0b101),
+            ttl_ms: Some(30_000),
+            priority: 7,
+            sees: 12,
+            ..test_msg(42, 7, msg)
+        };
+        let buf = format.encode(&msg).unwrap();
+        let decoded: DiscoveryMsg<N, T> = format.decode(&buf).unwrap();
+        assert_eq!((decoded.header, decoded.id), (42, 7));
+        assert_eq!(decoded.status, crate::Status::Draining);
+        assert_eq!(decoded.group, Some(3));
+        assert_eq!(decoded.capabilities, Capabilities::OURS);
+        assert_eq!(decoded.port_health.unhealthy_bits(), 0b101);
+        assert_eq!(decoded.ttl_ms, Some(30_000));
+        assert_eq!(decoded.priority, 7);
+        assert_eq!(decoded.sees, 12);
+        decoded.msg
+    }
+
+    #[test]
+    fn bincode() {
+        let format = WireFormat::default();
+        assert_eq!(roundtrip(format, [8042u16, 8043]), [8042, 8043]);
+
+        let options = BincodeOptions {
+            int_encoding: IntEncoding::Varint,
+            endian: Endian::Big,
+            limit: Some(64),
+        };
+        let format = WireFormat::Bincode(options);
+        assert_eq!(roundtrip(format, [8042u16, 8043]), [8042, 8043]);
+    }
+
+    #[test]
+    fn default_encoding_is_stable() {
+        let msg = test_msg(42, 7, [8042u16]);
+        let buf = WireFormat::default().encode(&msg).unwrap();
+        let expected: Vec<u8> = [
+            &[42, 0, 0, 0, 0, 0, 0, 0][..], // header
+            &[7, 0, 0, 0, 0, 0, 0, 0],      // id
+            &[0, 0, 0, 0],                  // status
+            &[232, 3, 0, 0],                // interval_ms
+            &[0],                           // group
+            &[249, 0],                      // capabilities
+            &[1, 0, 0, 0, 0, 0, 0, 0],      // number of ports
+            &[106, 31],                     // port
+            &[1, 0, 0, 0, 0, 0, 0, 0],      // number of extensions
+            &[7],                           // tag: visibility
+            &[4, 0, 0, 0, 0, 0, 0, 0],      // data length
+            &[1, 0, 0, 0],                  // sees
+        ]
+        .concat();
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn extensions_are_optional() {
+        let msg = DiscoveryMsg {
+            capabilities: Capabilities::GOODBYE,
+            ttl_ms: Some(5),
+            priority: 3,
+            sees: 9,
+            ..test_msg(42, 7, [8042u16])
+        };
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_little_endian();
+
+        // an older version sends no extensions
+        let buf = options.serialize(&msg).unwrap();
+        let decoded: DiscoveryMsg<1, u16> = WireFormat::default().decode(&buf).unwrap();
+        assert_eq!(decoded.ttl_ms, None);
+        assert_eq!((decoded.priority, decoded.sees), (0, 0));
+
+        // a newer version sends extensions we do not know
+        let mut buf = options.serialize(&msg).unwrap();
+        let unknown = Extension {
+            tag: 15,
+            data: vec![1, 2, 3],
+        };
+        let priority = Extension::new(options, Capabilities::PRIORITY, &3u32).unwrap();
+        options
+            .serialize_into(&mut buf, &vec![unknown, priority])
+            .unwrap();
+        let decoded: DiscoveryMsg<1, u16> = WireFormat::default().decode(&buf).unwrap();
+        assert_eq!(decoded.priority, 3);
+        assert_eq!(decoded.msg, [8042]);
+    }
+
+    #[test]
+    fn limit_exceeded() {
+        let options = BincodeOptions {
+            limit: Some(8),
+            ..BincodeOptions::default()
+        };
+        let msg = test_msg(42, 7, [8042u16]);
+        assert!(WireFormat::Bincode(options).encode(&msg).is_err());
+    }
+
+    #[test]
+    fn peek_prefix() {
+        let msg = DiscoveryMsg {
+            status: crate::Status::Draining,
+            ..test_msg(42, 7, [8042u16])
+        };
+        let prefix = Prefix {
+            header: 42,
+            id: 7,
+            status: crate::Status::Draining,
+        };
+        for int_encoding in [IntEncoding::Fixed, IntEncoding::Varint] {
+            let format = WireFormat::Bincode(BincodeOptions {
+                int_encoding,
+                ..BincodeOptions::default()
+            });
+            let buf = format.encode(&msg).unwrap();
+            assert_eq!(format.peek(&buf), Some(prefix));
+        }
+        assert_eq!(WireFormat::default().peek(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn mismatched_n() {
+        let msg = test_msg(42, 7, [8042u16, 8043, 8044]);
+        let check = |format: WireFormat| {
+            let buf = format.encode(&msg).unwrap();
+            let err = format.decode::<2, u16>(&buf).unwrap_err();
+            assert!(matches!(
+                err,
+                DecodeError::MismatchedN {
+                    id: 7,
+                    expected: 2,
+                    got: 3
+                }
+            ));
+        };
+        check(WireFormat::default());
+        #[cfg(feature = "protobuf")]
+        check(WireFormat::Protobuf);
+        assert!(matches!(
+            WireFormat::default().decode::<2, u16>(&[1, 2, 3]),
+            Err(DecodeError::Bincode(_))
+        ));
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn protobuf() {
+        let format = WireFormat::Protobuf;
+        assert_eq!(roundtrip(format, [8042u16, 8043]), [8042, 8043]);
+        assert_eq!(roundtrip(format, ["hi".to_owned()]), ["hi"]);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn protobuf_limit() {
+        let msg = |custom: String| test_msg(42, 7, [custom]);
+        assert!(WireFormat::Protobuf.fits(&msg("x".repeat(100))));
+        assert!(!WireFormat::Protobuf.fits(&msg("x".repeat(MAX_DATAGRAM))));
+    }
+}
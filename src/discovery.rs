@@ -1,10 +1,16 @@
-use std::fmt::Debug;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use tracing::info;
+use std::fmt::Debug;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+
+use crate::chart::{broadcast_periodically, expire_stale, handle_incoming, rejoin_watchdog};
+use crate::{util, Chart, ConvergeError, DiscoveryError, Entry, Id, Source, Status};
 
-use crate::{Chart, util};
-use crate::chart::{handle_incoming, broadcast_periodically};
+mod driver;
+pub use driver::Driver;
 
 trait AcceptErr<T, E> {
     fn accept_err_with(self, f: impl FnOnce(&E) -> bool) -> Result<Option<T>, E>;
@@ -24,61 +30,335 @@ impl<T, E> AcceptErr<T, E> for Result<T, E> {
 /// Usefull for clients on the same subnet trying to find nodes to contact.
 /// You can drop the future but then the chart will no longer be updated.
 ///
+/// # Errors
+/// Returns an error if a discovery socket stops working, see [`maintain`].
+///
 /// # Note 
 /// Take care not to call `maintain` anywhere
-#[tracing::instrument]
-pub async fn sniff<'de, const N: usize, T>(chart: Chart<N, T>) 
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub async fn sniff<const N: usize, T>(chart: Chart<N, T>) -> Result<(), DiscoveryError>
 where
     T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send
 {
     use tokio::task::JoinError;
+    let _maintained = chart.maintained();
     let f = util::spawn(handle_incoming(chart.clone()));
-    f.await.accept_err_with(JoinError::is_cancelled).unwrap();
+    tokio::select! {
+        res = f => res.accept_err_with(JoinError::is_cancelled).unwrap().unwrap_or(Ok(())),
+        () = chart.closed() => Ok(()),
+    }
 }
 
 /// This drives the chart discovery. You can drop the future but then the chart
-/// will no longer be updated.
-#[tracing::instrument]
-pub async fn maintain<'de, const N: usize, T>(chart: Chart<N, T>) 
+/// will no longer be updated. Returns once the chart is [closed](Chart::close).
+///
+/// If our own broadcasts stop arriving back the multicast groups are re-joined,
+/// an [`Event::Rejoined`](crate::Event::Rejoined) is sent when that happens.
+///
+/// # Errors
+/// Returns an error if a discovery socket stops working. Other problems, for
+/// example failing to send a message, are reported as
+/// [`Event::Error`](crate::Event::Error) while discovery continues.
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub async fn maintain<const N: usize, T>(chart: Chart<N, T>) -> Result<(), DiscoveryError>
 where
     T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send
 {
     use tokio::task::JoinError;
+    let _maintained = chart.maintained();
     let f1 = util::spawn(handle_incoming(chart.clone()));
-    let f2 = util::spawn(broadcast_periodically(chart));
-    f1.await.accept_err_with(JoinError::is_cancelled).unwrap();
-    f2.await.accept_err_with(JoinError::is_cancelled).unwrap();
+    let f2 = util::spawn(broadcast_periodically(chart.clone()));
+    let f3 = util::spawn(rejoin_watchdog(chart.clone()));
+    let f4 = util::spawn(expire_stale(chart.clone()));
+    // the other tasks only end if they panic
+    tokio::select! {
+        () = chart.closed() => Ok(()),
+        res = f1 => res.accept_err_with(JoinError::is_cancelled).unwrap().unwrap_or(Ok(())),
+        res = f2 => {
+            res.accept_err_with(JoinError::is_cancelled).unwrap();
+            Ok(())
+        }
+        res = f3 => {
+            res.accept_err_with(JoinError::is_cancelled).unwrap();
+            Ok(())
+        }
+        res = f4 => {
+            res.accept_err_with(JoinError::is_cancelled).unwrap();
+            Ok(())
+        }
+    }
+}
+
+/// Leave the cluster gracefully, for example during a rolling restart. Other
+/// instances see this one as [`Draining`](Status::Draining) for the `grace`
+/// period after which goodbyes make them forget it. Like [`Chart::close`] the
+/// goodbye is repeated, spread out over the [minimum
+/// interval](Chart::min_interval).
+///
+/// # Note
+/// Keep [`maintain`] running until this returns so newcomers are still
+/// answered while draining.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn drain<const N: usize, T>(chart: &Chart<N, T>, grace: Duration)
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    chart.set_status(Status::Draining);
+    chart.broadcast_now().await;
+    tokio::time::sleep(grace).await;
+
+    chart.say_goodbye(chart.min_interval()).await;
+    info!("drained, said goodbye to the cluster");
 }
 
-/// Block until `full_size` nodes have been found.
-#[tracing::instrument(skip(chart))]
-pub async fn found_everyone<const N:usize, T>(chart: &Chart<N, T>, full_size: u16) 
+/// wait for the chart to reach `size` then snapshot it, retries if instances
+/// where removed in between
+async fn entries_at_size<const N: usize, T>(
+    chart: &Chart<N, T>,
+    size: usize,
+) -> Vec<(Id, Entry<[T; N]>)>
 where
-    T: 'static + Debug + Clone + Serialize + DeserializeOwned
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
 {
-    let mut node_discoverd = chart.notify();
-    while chart.size() < full_size as usize {
-        node_discoverd.recv().await.unwrap();
+    loop {
+        chart.await_size(size).await;
+        if let Some(entries) = chart.entries_if_size(size) {
+            return entries;
+        }
     }
+}
+
+/// Block until `full_size` nodes have been found. Returns the discovered
+/// nodes, not including this one, as they where charted at that moment.
+///
+/// # Note
+/// Nothing is found unless [`maintain`] runs. If it is not running a few
+/// seconds into the wait that is reported as an [`Event::Error`](crate::Event::Error)
+/// with [`DiscoveryError::NotMaintained`].
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn found_everyone<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+) -> Vec<(Id, Entry<[T; N]>)>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let entries = entries_at_size(chart, full_size as usize).await;
     info!(
         "found every member of the cluster, ({} nodes)",
-        chart.size()
+        entries.len() + 1
     );
+    entries
 }
 
 /// Block until a majority of nodes have been found. Usefull when implementing vote based
-/// consensus such as Raft.
-#[tracing::instrument(skip(chart))]
-pub async fn found_majority<const N:usize, T>(chart: &Chart<N,T>, full_size: u16) 
+/// consensus such as Raft. Returns the discovered nodes, not including this
+/// one, as they where charted at that moment.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn found_majority<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+) -> Vec<(Id, Entry<[T; N]>)>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let entries = entries_at_size(chart, majority(full_size)).await;
+    info!("found majority of cluster, ({} nodes)", entries.len() + 1);
+    entries
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn majority(full_size: u16) -> usize {
+    (f32::from(full_size) * 0.5).ceil() as usize
+}
+
+/// Like [`found_majority`] but only counts instances heard from within
+/// `max_age`, see [`Chart::active_size`]. Silent instances that did not expire
+/// yet do not make up the majority. Returns the active nodes, not including
+/// this one, as they where charted at that moment.
+///
+/// # Note
+/// Pick `max_age` larger then the [maximum broadcast interval](Chart::max_interval)
+/// or instances that are alive will not be counted.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn found_active_majority<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+    max_age: Duration,
+) -> Vec<(Id, Entry<[T; N]>)>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let majority = majority(full_size);
+    let mut sizes = chart.size_stream();
+    let found = async {
+        loop {
+            if let Some(entries) = chart.active_entries_if_size(majority, max_age) {
+                return entries;
+            }
+            // hearing from a charted instance does not change the size, check
+            // again regularly
+            let _ = tokio::time::timeout(chart.min_interval(), sizes.next()).await;
+        }
+    };
+    let entries = tokio::select! {
+        entries = found => entries,
+        () = chart.report_if_not_maintained() => unreachable!("never returns"),
+    };
+    info!(
+        "found active majority of cluster, ({} nodes)",
+        entries.len() + 1
+    );
+    entries
+}
+
+/// Block until no new nodes have been found for `quiet_period`. Use this when
+/// the size of the cluster is not known in advance. Returns the discovered
+/// nodes, not including this one, as they where charted at that moment.
+///
+/// # Note
+/// Nodes that take longer then `quiet_period` to show up are missed. Pick it a
+/// few times larger then the [maximum broadcast interval](Chart::max_interval).
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn found_stable<const N: usize, T>(
+    chart: &Chart<N, T>,
+    quiet_period: Duration,
+) -> Vec<(Id, Entry<[T; N]>)>
 where
-    T: 'static + Debug + Clone + Serialize + DeserializeOwned
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
 {
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let cluster_majority = (f32::from(full_size) * 0.5).ceil() as usize;
+    let mut new_members = chart.notify();
+    let mut deadline = Instant::now() + quiet_period;
+    // lagging behind also means new members showed up
+    while let Ok(res) = tokio::time::timeout_at(deadline, new_members.recv()).await {
+        if matches!(res, Err(RecvError::Closed)) {
+            break;
+        }
+        deadline = Instant::now() + quiet_period;
+    }
+    let entries = chart
+        .entries_if_size(0)
+        .expect("every chart holds at least zero instances");
+    info!(
+        "no new members for {quiet_period:?}, ({} nodes)",
+        entries.len() + 1
+    );
+    entries
+}
+
+/// seeds are probed at most this many at the time
+const MAX_PROBES: usize = 32;
+/// spacing between the first two rounds of [`converge_fast`], doubles every round
+const FIRST_ROUND: Duration = Duration::from_millis(25);
+
+/// addresses of the instances added using [`Chart::merge_from`] that we did
+/// not hear from yet, sorted so rounds of [`converge_fast`] see them in the
+/// same order
+fn unheard_seeds<const N: usize, T>(chart: &Chart<N, T>) -> Vec<std::net::IpAddr>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let mut seeds: Vec<_> = chart
+        .snapshot()
+        .iter()
+        .filter(|(_, entry)| entry.source == Source::Merged)
+        .map(|(_, entry)| entry.ip)
+        .collect();
+    seeds.sort_unstable();
+    seeds
+}
+
+/// the seeds to probe in round `round`, at most [`MAX_PROBES`]. Each round
+/// continues where the previous one stopped so every seed gets probed.
+fn probe_window(seeds: &[std::net::IpAddr], round: usize) -> Vec<std::net::IpAddr> {
+    if seeds.len() <= MAX_PROBES {
+        return seeds.to_vec();
+    }
+    let start = round * MAX_PROBES % seeds.len();
+    seeds
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(MAX_PROBES)
+        .copied()
+        .collect()
+}
+
+/// Get the chart to `target` instances, including self, as fast as possible.
+/// Broadcasting starts over at the [minimum interval](Chart::min_interval)
+/// and in rounds our discovery message is broadcast and sent directly to the
+/// seeds: instances added using [`merge_from`](Chart::merge_from) that were not
+/// heard from yet. Seeds reply to that, also when multicast does not reach
+/// them.
+///
+/// The first rounds are 25ms apart, the spacing doubles every round and at
+/// most 32 seeds are probed per round, the next round probes the next 32. That
+/// keeps a cluster starting all at once from flooding the network.
+///
+/// # Errors
+/// Returns why `target` was not reached within `deadline`, see [`ConvergeError`].
+///
+/// # Note
+/// [`maintain`] must be running, it receives the replies.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn converge_fast<const N: usize, T>(
+    chart: &Chart<N, T>,
+    target: usize,
+    deadline: Duration,
+) -> Result<(), ConvergeError>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let deadline = Instant::now() + deadline;
+    let heard = chart.counters().heard;
+    chart.restart_rampdown();
+
+    let mut round = Instant::now();
+    let mut spacing = FIRST_ROUND;
+    let mut rounds = 0;
+    while chart.size() < target && round < deadline {
+        let seeds = unheard_seeds(chart);
+        chart.broadcast_now().await;
+        chart.probe(&probe_window(&seeds, rounds)).await;
+        rounds += 1;
+
+        round = (round + spacing).min(deadline);
+        spacing *= 2;
+        let _reached = tokio::time::timeout_at(round, chart.await_size(target)).await;
+    }
+
+    let found = chart.size();
+    if found >= target {
+        info!("converged, ({found} nodes)");
+        Ok(())
+    } else if chart.counters().heard == heard {
+        Err(ConvergeError::NothingReceived)
+    } else {
+        Err(ConvergeError::TooFew {
+            found,
+            target,
+            unanswered: unheard_seeds(chart).len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    #[test]
+    fn every_seed_probed() {
+        let seeds: Vec<_> = (0..MAX_PROBES as u8 + 8)
+            .map(|n| IpAddr::V4(Ipv4Addr::new(10, 0, 0, n)))
+            .collect();
+        // only the last seed answers
+        let answers = seeds[seeds.len() - 1];
+        let answered = (0..2).any(|round| probe_window(&seeds, round).contains(&answers));
+        assert!(answered);
 
-    let mut node_discoverd = chart.notify();
-    while chart.size() < cluster_majority {
-        node_discoverd.recv().await.unwrap();
+        let few = &seeds[..3];
+        assert_eq!(probe_window(few, 5), few);
     }
-    info!("found majority of cluster, ({} nodes)", chart.size());
 }
@@ -0,0 +1,185 @@
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Wake, Waker};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::chart::{broadcast_periodically, expire_stale, handle_incoming_inline, rejoin_watchdog};
+use crate::util::{self, Wrapper};
+use crate::{Chart, DiscoveryError};
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Drives discovery for many charts on a single task. Use this instead of
+/// spawning [`maintain`](super::maintain) for every chart when running dozens
+/// of charts in one process, for example one per simulated node. `maintain`
+/// uses a handful of tasks per chart, a driver uses one for all of them.
+///
+/// Dropping the driver stops discovery for every chart it maintains.
+///
+/// Each chart gets its own waker, only charts with work to do are polled. For
+/// a few charts spawning [`maintain`](super::maintain) is just as good.
+///
+/// # Examples
+/// ```rust
+/// # use std::error::Error;
+/// # use instance_chart::{discovery::Driver, ChartBuilder};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let driver = Driver::new();
+/// for id in 1..=3 {
+///     let chart = ChartBuilder::new()
+///         .with_id(id)
+///         .with_service_port(8042)
+/// #       .with_discovery_port(43842)
+///         .local_discovery(true)
+///         .finish()?;
+///     // discovery runs even if the returned future is not awaited
+///     let _done = driver.maintain(chart);
+/// }
+/// #   Ok(())
+/// # }
+/// ```
+pub struct Driver {
+    new: mpsc::UnboundedSender<Task>,
+    _task: Wrapper<()>,
+}
+
+impl Debug for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Driver").finish_non_exhaustive()
+    }
+}
+
+impl Driver {
+    /// Start the task driving the charts, charts are added using
+    /// [`maintain`](Self::maintain)
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime
+    #[allow(clippy::new_without_default)] // spawning should be explicit
+    #[must_use]
+    pub fn new() -> Self {
+        let (new, rx) = mpsc::unbounded_channel();
+        Self {
+            new,
+            _task: util::spawn(drive_all(rx)),
+        }
+    }
+
+    /// Like [`maintain`](super::maintain) but runs on the driver's task.
+    /// Discovery starts right away, the returned future does not need to be
+    /// polled. It resolves once the chart is [closed](Chart::close), if a
+    /// discovery socket stops working or when the driver is dropped.
+    ///
+    /// # Errors
+    /// The future returns an error if a discovery socket stops working, see
+    /// [`maintain`](super::maintain).
+    pub fn maintain<const N: usize, T>(
+        &self,
+        chart: Chart<N, T>,
+    ) -> impl Future<Output = Result<(), DiscoveryError>> + Send + 'static
+    where
+        T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        let task = Box::pin(async move {
+            let _ig_err = tx.send(drive(chart).await);
+        });
+        // the receiving end lives as long as self
+        let _ig_err = self.new.send(task);
+        async move { rx.await.unwrap_or(Ok(())) }
+    }
+}
+
+/// indices of the tasks that were woken since they were last polled
+#[derive(Default)]
+struct Ready {
+    woken: Mutex<Vec<usize>>,
+    driver: Mutex<Option<Waker>>,
+}
+
+impl Ready {
+    fn push(&self, idx: usize) {
+        self.woken.lock().unwrap().push(idx);
+        if let Some(driver) = self.driver.lock().unwrap().as_ref() {
+            driver.wake_by_ref();
+        }
+    }
+}
+
+/// wakes the driver and marks one task to be polled
+struct TaskWaker {
+    idx: usize,
+    ready: Arc<Ready>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.push(self.idx);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.push(self.idx);
+    }
+}
+
+/// poll the charts' discovery when they are woken, new charts arrive over
+/// `new`
+async fn drive_all(mut new: mpsc::UnboundedReceiver<Task>) {
+    // finished tasks leave a free slot, indices stay valid for their wakers
+    let mut tasks: Vec<Option<(Task, Waker)>> = Vec::new();
+    let mut free = Vec::new();
+    let ready = Arc::new(Ready::default());
+    std::future::poll_fn(|cx| {
+        *ready.driver.lock().unwrap() = Some(cx.waker().clone());
+        while let Poll::Ready(Some(task)) = new.poll_recv(cx) {
+            let idx = free.pop().unwrap_or(tasks.len());
+            let waker = Waker::from(Arc::new(TaskWaker {
+                idx,
+                ready: Arc::clone(&ready),
+            }));
+            if idx == tasks.len() {
+                tasks.push(None);
+            }
+            tasks[idx] = Some((task, waker));
+            ready.push(idx);
+        }
+
+        let mut woken = std::mem::take(&mut *ready.woken.lock().unwrap());
+        woken.sort_unstable();
+        woken.dedup();
+        for idx in woken {
+            let Some((task, waker)) = &mut tasks[idx] else {
+                continue; // woken after it finished
+            };
+            let mut task_cx = std::task::Context::from_waker(waker);
+            if task.as_mut().poll(&mut task_cx).is_ready() {
+                tasks[idx] = None;
+                free.push(idx);
+            }
+        }
+        Poll::<()>::Pending
+    })
+    .await;
+}
+
+/// the work of [`maintain`](super::maintain) without spawning tasks
+async fn drive<const N: usize, T>(chart: Chart<N, T>) -> Result<(), DiscoveryError>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
+{
+    let _maintained = chart.maintained();
+    tokio::select! {
+        () = chart.closed() => Ok(()),
+        res = handle_incoming_inline(chart.clone()) => res,
+        () = broadcast_periodically(chart.clone()) => Ok(()),
+        () = rejoin_watchdog(chart.clone()) => Ok(()),
+        () = expire_stale(chart.clone()) => Ok(()),
+    }
+}
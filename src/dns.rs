@@ -0,0 +1,230 @@
+//! Serve the chart as a DNS zone so components that insist on resolving
+//! hostnames can still find chart members. With zone `instances.local` the
+//! name `7.instances.local` resolves to the address instance 7 was
+//! discovered on. Point any resolver, for example hickory, at the address
+//! passed to [`serve`].
+//!
+//! Only `A` and `AAAA` queries are answered. Our own id does not resolve as
+//! we do not know our own address.
+//!
+//! Only available with the `dns` feature.
+use std::fmt::Debug;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+use crate::{Chart, Id};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// members come and go, resolvers should not cache answers for long
+const TTL_SECS: u32 = 1;
+
+const FORMERR: u8 = 1;
+const NXDOMAIN: u8 = 3;
+const NOTIMP: u8 = 4;
+const REFUSED: u8 = 5;
+
+/// Answer DNS queries for names in `zone` on `addr` until an error occurs.
+///
+/// # Errors
+/// Returns an error if `addr` could not be bound or the socket stops working.
+///
+/// # Example
+/// ```rust,no_run
+/// # use std::error::Error;
+/// # use instance_chart::{discovery, dns, ChartBuilder};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+///     .finish()?;
+/// let _ = tokio::spawn(discovery::maintain(chart.clone()));
+/// dns::serve(chart, "127.0.0.1:5353".parse()?, "instances.local").await?;
+/// #   Ok(())
+/// # }
+/// ```
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(chart)))]
+pub async fn serve<const N: usize, T>(
+    chart: Chart<N, T>,
+    addr: SocketAddr,
+    zone: &str,
+) -> io::Result<()>
+where
+    T: Debug + Clone + Serialize + DeserializeOwned,
+{
+    let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+    let sock = UdpSocket::bind(addr).await?;
+    let mut buf = [0u8; 512];
+    loop {
+        let (len, from) = sock.recv_from(&mut buf).await?;
+        let Some(response) = respond(&buf[..len], &zone, |id| chart.ip_of(id)) else {
+            continue;
+        };
+        if let Err(e) = sock.send_to(&response, from).await {
+            warn!("could not answer dns query from {from}: {e}");
+        }
+    }
+}
+
+/// The response to `query`, None if it is not worth answering
+fn respond(query: &[u8], zone: &str, lookup: impl Fn(Id) -> Option<IpAddr>) -> Option<Vec<u8>> {
+    let header = query.get(..12)?;
+    let is_response = header[2] & 0x80 != 0;
+    if is_response {
+        return None;
+    }
+    let opcode = (header[2] >> 3) & 0x0f;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    if opcode != 0 {
+        return Some(error(header, NOTIMP));
+    }
+    if questions != 1 {
+        return Some(error(header, FORMERR));
+    }
+    let Some((name, end)) = parse_name(query, 12) else {
+        return Some(error(header, FORMERR));
+    };
+    let Some(fields) = query.get(end..end + 4) else {
+        return Some(error(header, FORMERR));
+    };
+    let qtype = u16::from_be_bytes([fields[0], fields[1]]);
+    let question = &query[12..end + 4];
+
+    let Some(host) = name
+        .strip_suffix(zone)
+        .and_then(|host| host.strip_suffix('.'))
+    else {
+        return Some(error(header, REFUSED));
+    };
+    let Some(ip) = host.parse().ok().and_then(lookup) else {
+        return Some(answer(header, question, NXDOMAIN, None));
+    };
+    let matches = match ip {
+        IpAddr::V4(_) => matches!(qtype, TYPE_A | TYPE_ANY),
+        IpAddr::V6(_) => matches!(qtype, TYPE_AAAA | TYPE_ANY),
+    };
+    Some(answer(header, question, 0, matches.then_some(ip)))
+}
+
+/// lowercase dotted name starting at `pos` and the position right after it
+fn parse_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    loop {
+        let len = usize::from(*msg.get(pos)?);
+        pos += 1;
+        if len == 0 {
+            return Some((name, pos));
+        }
+        if len > 63 {
+            return None; // compression is not used in questions
+        }
+        let label = std::str::from_utf8(msg.get(pos..pos + len)?).ok()?;
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.push_str(&label.to_ascii_lowercase());
+        pos += len;
+    }
+}
+
+fn response_header(query_header: &[u8], rcode: u8, questions: u16, answers: u16) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(512);
+    msg.extend_from_slice(&query_header[..2]); // id
+    let recursion_desired = query_header[2] & 0x01;
+    let opcode = query_header[2] & 0x78;
+    msg.push(0x80 | opcode | 0x04 | recursion_desired); // response, authoritative
+    msg.push(rcode);
+    msg.extend_from_slice(&questions.to_be_bytes());
+    msg.extend_from_slice(&answers.to_be_bytes());
+    msg.extend_from_slice(&[0, 0, 0, 0]); // authority and additional
+    msg
+}
+
+fn error(query_header: &[u8], rcode: u8) -> Vec<u8> {
+    response_header(query_header, rcode, 0, 0)
+}
+
+fn answer(query_header: &[u8], question: &[u8], rcode: u8, ip: Option<IpAddr>) -> Vec<u8> {
+    let mut msg = response_header(query_header, rcode, 1, u16::from(ip.is_some()));
+    msg.extend_from_slice(question);
+    let Some(ip) = ip else {
+        return msg;
+    };
+    msg.extend_from_slice(&[0xc0, 12]); // pointer to the name in the question
+    let (rtype, rdata) = match ip {
+        IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+    };
+    msg.extend_from_slice(&rtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    msg.extend_from_slice(&TTL_SECS.to_be_bytes());
+    #[allow(clippy::cast_possible_truncation)] // at most 16 bytes
+    msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    msg.extend_from_slice(&rdata);
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut msg = vec![0xab, 0xcd, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(label.as_bytes());
+        }
+        msg.push(0);
+        msg.extend_from_slice(&qtype.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg
+    }
+
+    fn lookup(id: Id) -> Option<IpAddr> {
+        (id == 7).then_some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 4)))
+    }
+
+    fn rcode(response: &[u8]) -> u8 {
+        response[3] & 0x0f
+    }
+
+    #[test]
+    fn answers_a_record() {
+        let query = query("7.Instances.local", TYPE_A);
+        let response = respond(&query, "instances.local", lookup).unwrap();
+        assert_eq!(&response[..2], &[0xab, 0xcd]);
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 1);
+        assert_eq!(&response[response.len() - 4..], &[10, 0, 0, 4]);
+    }
+
+    #[test]
+    fn no_aaaa_for_ipv4_instance() {
+        let query = query("7.instances.local", TYPE_AAAA);
+        let response = respond(&query, "instances.local", lookup).unwrap();
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 0);
+    }
+
+    #[test]
+    fn unknown_names() {
+        let unknown = query("8.instances.local", TYPE_A);
+        let response = respond(&unknown, "instances.local", lookup).unwrap();
+        assert_eq!(rcode(&response), NXDOMAIN);
+
+        let other_zone = query("7.example.org", TYPE_A);
+        let response = respond(&other_zone, "instances.local", lookup).unwrap();
+        assert_eq!(rcode(&response), REFUSED);
+
+        assert!(respond(&[1, 2, 3], "instances.local", lookup).is_none());
+    }
+}
@@ -1,15 +1,87 @@
 #![doc= include_str!("../README.md")]
 
+#[macro_use]
+mod log;
 mod chart;
 pub mod discovery;
+#[cfg(feature = "dns")]
+pub mod dns;
+pub mod peers;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod util;
 use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
 
-pub use chart::{Chart, ChartBuilder, Notify};
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use chart::bench;
+#[cfg(feature = "packet-tap")]
+pub use chart::Direction;
+#[cfg(feature = "protobuf")]
+pub use chart::PROTO_SCHEMA;
+pub use chart::{
+    Action, AddrMismatch, Announcement, BincodeOptions, Capabilities, Chart, ChartBuilder,
+    ChartConfig, ChartMetrics, ChartResolver, ChartStats, ChartStore, Clock, ConflictPolicy,
+    Counters, DynamicBuilder, Endian, Endpoint, Entry, Event, IntEncoding, LocalDiscovery,
+    MergedChart, MergedEntry, Meta, Metadata, NoMetrics, Notify, PortHealth, ReconcileReport,
+    ReplyPolicy, SizeTrend, Snapshot, SnapshotIntoIter, SnapshotIter, SocketInfo, Source, Status,
+    Subnet, Target, TokioClock, WireFormat,
+};
+pub use chart::{
+    DEFAULT_DISCOVERY_PORT, DEFAULT_HEADER, DEFAULT_MAX_INTERVAL, DEFAULT_MIN_INTERVAL,
+    DEFAULT_RAMPDOWN,
+};
 
 /// Identifier for a single instance of `Chart`. Must be unique.
 pub type Id = u64;
 
+/// Why binding the discovery socket failed, see [`Error::Bind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BindDiagnosis {
+    /// The port is used by a socket that does not allow sharing it, probably
+    /// another application.
+    InUseExclusively,
+    /// The port is shared by sockets with `SO_REUSEPORT` set, probably other
+    /// instances using [`local_discovery`](ChartBuilder::local_discovery).
+    InUseShared,
+    /// Not allowed to bind to the port, for example by a security policy.
+    /// Ports below 1024 are reported as [`Error::PrivilegedPort`] instead.
+    PermissionDenied,
+    /// The address is not available on this host or its family is not supported.
+    AddressUnavailable,
+    /// No known cause
+    Unknown,
+}
+
+impl BindDiagnosis {
+    /// A suggestion on how to resolve the issue
+    #[must_use]
+    pub fn hint(&self) -> &'static str {
+        match self {
+            BindDiagnosis::InUseExclusively => {
+                "the port is used by another application, try another discovery port"
+            }
+            BindDiagnosis::InUseShared => {
+                "the port is shared by other instances, enable local_discovery to join them"
+            }
+            BindDiagnosis::PermissionDenied => {
+                "permission denied, check the security policy or try another discovery port"
+            }
+            BindDiagnosis::AddressUnavailable => {
+                "the address is not available, check the network configuration"
+            }
+            BindDiagnosis::Unknown => {
+                "you might want to try another discovery port and/or enable local_discovery"
+            }
+        }
+    }
+}
+
 /// Errors that can occure while building a Chart. Except for [`Bind`](Error::Bind) these rarely
 /// occur.
 #[derive(thiserror::Error, Debug)]
@@ -32,13 +104,220 @@ pub enum Error {
     /// Failed to set NonBlocking flag on the socket
     #[error("Failed to set NonBlocking flag on the socket")]
     SetNonBlocking(io::Error),
-    /// Error binding to socket, you might want to try another discovery port and/or enable [`local_discovery`](ChartBuilder::local_discovery).
-    #[error("Error binding to socket, you might want to try another discovery port and/or enable local_discovery.")]
-    Bind { error: io::Error, port: u16 },
+    /// Failed to set the receive or send buffer size of the socket
+    #[error("Failed to set the buffer size of the socket")]
+    SetBufferSize(io::Error),
+    /// Error binding to socket, the [`diagnosis`](BindDiagnosis) tells you why and
+    /// what you could do about it.
+    #[error("Error binding to socket on port {port}, {}", diagnosis.hint())]
+    Bind {
+        error: io::Error,
+        port: u16,
+        diagnosis: BindDiagnosis,
+    },
+    /// Not allowed to bind the discovery port, ports below 1024 need the
+    /// `CAP_NET_BIND_SERVICE` capability on Linux. Grant it, pick a port
+    /// above 1023 or let systemd bind the port, see
+    /// `systemd::activated_socket` (needs the `systemd` feature).
+    #[error(
+        "Not allowed to bind discovery port {port}, ports below 1024 need the \
+        CAP_NET_BIND_SERVICE capability, use a port above 1023 or socket activation"
+    )]
+    PrivilegedPort { error: io::Error, port: u16 },
+    /// Another chart instance on this host already uses the discovery port
+    /// without sharing it, it answered with [`Id`] `id`. Enable
+    /// [`local_discovery`](ChartBuilder::local_discovery) on every instance
+    /// to run them side by side. Only detected when building with service
+    /// ports using [`finish_with_retry`](ChartBuilder::finish_with_retry),
+    /// otherwise this is reported as [`Error::Bind`].
+    #[error("Instance {id} on this host already uses discovery port {port}")]
+    AlreadyRunningLocally { port: u16, id: Id },
     /// Failed joining multicast network
     #[error("Failed joining multicast network")]
     JoinMulticast(io::Error),
     /// Failed to transform blocking to async socket
     #[error("Failed to transform blocking to async socket")]
     ToTokio(io::Error),
+    /// A service port is also used as discovery port
+    #[error("Service port {0} is also used as discovery port")]
+    ServicePortIsDiscoveryPort(u16),
+    /// No port in the range passed to
+    /// [`with_discovery_port_in_range`](ChartBuilder::with_discovery_port_in_range)
+    /// is free and passes multicast
+    #[error("No port in {start}..={end} is free and passes multicast")]
+    NoUsablePort { start: u16, end: u16 },
+    /// The minimum duration between broadcasts is larger then the maximum or
+    /// the maximum is zero
+    #[error(
+        "minimum duration: {min:?} must be smaller or equal to the maximum: {max:?} \
+        which must be larger then zero"
+    )]
+    InvalidRampdown { min: Duration, max: Duration },
+    /// The same service port was set more then once
+    #[error("Service port {0} was set more then once")]
+    DuplicateServicePort(u16),
+    /// Our discovery message would not fit within the
+    /// [`limit`](BincodeOptions::limit) set using
+    /// [`with_bincode_options`](ChartBuilder::with_bincode_options)
+    #[error("Our discovery message would not fit within the bincode limit of {limit} bytes")]
+    MessageTooLarge { limit: u64 },
+    /// Multicast loopback was disabled while local discovery is enabled,
+    /// instances on the same host would never find each other
+    #[error("Multicast loopback can not be disabled when local discovery is enabled")]
+    LoopbackRequired,
+    /// No [`Id`] was set on the [`DynamicBuilder`]
+    #[error("No id was set")]
+    IdNotSet,
+    /// No service ports where set on the [`DynamicBuilder`]
+    #[error("No service ports where set")]
+    ServicePortsNotSet,
+    /// The [`DynamicBuilder`] got a different number of service ports then
+    /// the chart is build for
+    #[error("Expected {expected} service ports, got {got}")]
+    ServicePortCount { expected: usize, got: usize },
+    /// Service ports where set on the [`DynamicBuilder`] while building with
+    /// a custom msg
+    #[error("Service ports can not be set when building with a custom msg")]
+    ServicePortsWithCustomMsg,
+    /// An empty list of discovery endpoints was set on the
+    /// [`DynamicBuilder`]
+    #[error("Need at least one discovery endpoint")]
+    NoDiscoveryEndpoints,
+}
+
+impl Error {
+    /// Whether building again later could succeed. That is the case when the
+    /// discovery port is still held by another process, the address is not
+    /// (yet) available or joining the multicast group failed because the
+    /// network is not up yet. See
+    /// [`ChartBuilder::finish_with_retry`](ChartBuilder::finish_with_retry).
+    #[must_use]
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Error::Bind { diagnosis, .. } => matches!(
+                diagnosis,
+                BindDiagnosis::InUseExclusively | BindDiagnosis::AddressUnavailable
+            ),
+            Error::JoinMulticast(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Errors that occur while discovery is running. All are reported as
+/// [`Event::Error`], fatal ones also end [`discovery::maintain`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum DiscoveryError {
+    /// Sending a discovery message failed, discovery continues
+    #[error("Could not send discovery message from port {port} to {to}: {kind}")]
+    Send {
+        port: u16,
+        to: SocketAddr,
+        #[serde(serialize_with = "error_kind")]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        kind: io::ErrorKind,
+    },
+    /// Receiving on a discovery socket failed, discovery continues
+    #[error("Could not receive on discovery port {port}: {kind}")]
+    Recv {
+        port: u16,
+        #[serde(serialize_with = "error_kind")]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        kind: io::ErrorKind,
+    },
+    /// A received message could not be decoded, discovery continues
+    #[error("Could not decode message from {from}: {reason}")]
+    Parse { from: SocketAddr, reason: String },
+    /// A discovery socket stopped working, this is fatal
+    #[error("Discovery socket on port {port} stopped working: {kind}")]
+    SocketClosed {
+        port: u16,
+        #[serde(serialize_with = "error_kind")]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        kind: io::ErrorKind,
+    },
+    /// Waited on the chart, for example in [`discovery::found_everyone`],
+    /// while nothing runs discovery for it. The wait continues but will only
+    /// end once [`discovery::maintain`] is spawned.
+    #[error(
+        "Waited {waited:?} on the chart but discovery is not running, \
+        is discovery::maintain spawned?"
+    )]
+    NotMaintained { waited: Duration },
+}
+
+/// serde can not serialize [`io::ErrorKind`], use its description instead
+fn error_kind<S>(kind: &io::ErrorKind, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(kind)
+}
+
+/// Errors returned by [`ChartResolver::resolve`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResolveError {
+    /// The name is not of the form `id:<id>` or `role:<role>`
+    #[error("Can not resolve {0}, expected id:<id> or role:<role>")]
+    InvalidName(String),
+    /// No role with this name was added to the resolver
+    #[error("No role named {0}")]
+    UnknownRole(String),
+    /// No instance in the chart matches the name
+    #[error("No instance found for {0}")]
+    NotFound(String),
+}
+
+impl From<ResolveError> for io::Error {
+    fn from(err: ResolveError) -> Self {
+        let kind = match err {
+            ResolveError::NotFound(_) => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Errors returned by [`Chart::put_meta`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetaError {
+    /// Our discovery message would no longer fit in a single datagram
+    #[error("Discovery message with this metadata would exceed {max} bytes")]
+    TooLarge { max: usize },
+}
+
+/// Why [`discovery::converge_fast`] did not reach its target in time
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConvergeError {
+    /// Nothing arrived from other instances, our own echoes do not count.
+    /// The network (or firewall) probably drops multicast.
+    #[error("Nothing arrived from other instances, is multicast blocked?")]
+    NothingReceived,
+    /// Only `found` of the `target` instances, including self, were charted
+    /// in time. `unanswered` is the number of probed seeds that did not reply.
+    #[error("Found {found} of {target} instances, {unanswered} seeds did not answer")]
+    TooFew {
+        found: usize,
+        target: usize,
+        unanswered: usize,
+    },
+}
+
+/// Errors returned by [`Chart::self_test`]
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum SelfTestError {
+    /// Our own discovery message did not arrive back in time on this
+    /// discovery port. The network (or firewall) probably drops multicast.
+    #[error("Our discovery message did not arrive back on port {port} within {timeout:?}, is multicast blocked?")]
+    NoEcho { port: u16, timeout: Duration },
+    /// Multicast loopback is disabled, our own messages never arrive back.
+    /// See [`ChartBuilder::with_multicast_loop`].
+    #[error("Multicast loopback is disabled, can not check our messages arrive back")]
+    LoopbackDisabled,
 }
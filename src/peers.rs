@@ -0,0 +1,150 @@
+//! Send the same bytes to every charted instance over TCP, for simple fan-out
+//! messaging. The bytes go to the first [endpoint](crate::Endpoint) of each
+//! instance, usually its first service port.
+//!
+//! There is no framing, the receiver has to tell messages apart itself. For
+//! example by sending lines or prefixing every message with its length.
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+use crate::{Chart, Endpoint, Id};
+
+/// Connections to the charted instances, reused between calls to
+/// [`broadcast_tcp`](Peers::broadcast_tcp)
+///
+/// # Examples
+/// ```rust
+/// # use std::error::Error;
+/// # use instance_chart::{peers::Peers, ChartBuilder};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+/// #   .with_discovery_port(43838)
+///     .finish()?;
+/// let mut peers = Peers::new(chart);
+///
+/// for (id, error) in peers.broadcast_tcp(b"hello\n").await {
+///     println!("could not reach instance {id}: {error}");
+/// }
+/// #   Ok(())
+/// # }
+/// ```
+pub struct Peers<const N: usize, E: Endpoint> {
+    chart: Chart<N, E>,
+    timeout: Duration,
+    streams: HashMap<Id, (SocketAddr, TcpStream)>,
+}
+
+impl<const N: usize, E: Endpoint> fmt::Debug for Peers<N, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Peers")
+            .field("chart", &self.chart)
+            .field("timeout", &self.timeout)
+            .field("connected", &self.streams.len())
+            .finish()
+    }
+}
+
+impl<const N: usize, E> Peers<N, E>
+where
+    E: Endpoint<Addr = SocketAddr>,
+{
+    /// No connections are made until the first broadcast
+    #[must_use]
+    pub fn new(chart: Chart<N, E>) -> Self {
+        Self {
+            chart,
+            timeout: Duration::from_secs(2),
+            streams: HashMap::new(),
+        }
+    }
+
+    /// How long connecting to and sending to a single instance may take,
+    /// defaults to two seconds
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send `bytes` to every instance in the chart at the same time. Returns
+    /// the instances it could not be sent to and why.
+    ///
+    /// Connections are kept open for the next call. Those to instances that
+    /// left the chart or moved are closed. If sending over an existing
+    /// connection fails a new one is made and the bytes are sent again.
+    ///
+    /// # Note
+    /// A connection the instance closed can still accept a write. Bytes sent
+    /// right after an instance closed its side might be lost without an
+    /// error.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn broadcast_tcp(&mut self, bytes: &[u8]) -> Vec<(Id, io::Error)> {
+        let targets = self.chart.nth_addr_vec::<0>();
+        self.streams
+            .retain(|id, (addr, _)| targets.contains(&(*id, *addr)));
+
+        let bytes: Arc<[u8]> = bytes.into();
+        let mut sends = JoinSet::new();
+        for (id, addr) in targets {
+            let stream = self.streams.remove(&id).map(|(_, stream)| stream);
+            let bytes = Arc::clone(&bytes);
+            let timeout = self.timeout;
+            sends.spawn(async move {
+                let sent = tokio::time::timeout(timeout, send(stream, addr, &bytes)).await;
+                let sent = sent.unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()));
+                (id, addr, sent)
+            });
+        }
+
+        let mut failed = Vec::new();
+        while let Some(done) = sends.join_next().await {
+            match done.expect("sending does not panic") {
+                (id, addr, Ok(stream)) => {
+                    self.streams.insert(id, (addr, stream));
+                }
+                (id, addr, Err(error)) => {
+                    warn!("could not send to instance {id} at {addr}: {error}");
+                    failed.push((id, error));
+                }
+            }
+        }
+        failed
+    }
+}
+
+/// send over `stream` if there is one, connect again if that fails
+async fn send(stream: Option<TcpStream>, addr: SocketAddr, bytes: &[u8]) -> io::Result<TcpStream> {
+    if let Some(mut stream) = stream {
+        if stream.write_all(bytes).await.is_ok() {
+            return Ok(stream);
+        }
+    }
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(bytes).await?;
+    Ok(stream)
+}
+
+/// Send `bytes` to every instance in the chart over a new connection. Returns
+/// the instances it could not be sent to and why. Use [`Peers`] to reuse the
+/// connections when sending more then once.
+pub async fn broadcast_tcp<const N: usize, E>(
+    chart: &Chart<N, E>,
+    bytes: &[u8],
+) -> Vec<(Id, io::Error)>
+where
+    E: Endpoint<Addr = SocketAddr>,
+{
+    Peers::new(chart.clone()).broadcast_tcp(bytes).await
+}
@@ -0,0 +1,173 @@
+//! Reflect the discovery state in the systemd service status. Use these in
+//! services with `Type=notify` and optionally `WatchdogSec` set.
+//!
+//! Only available with the `systemd` feature.
+use std::fmt::Debug;
+use std::io;
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+#[cfg(unix)]
+use tokio::net::UdpSocket;
+
+use crate::{discovery, Chart, DiscoveryError, Entry, Id};
+
+fn status<const N: usize, T>(chart: &Chart<N, T>) -> String
+where
+    T: Debug + Clone + Serialize + DeserializeOwned,
+{
+    format!("discovered {} instances", chart.size())
+}
+
+fn ready<const N: usize, T>(chart: &Chart<N, T>) -> io::Result<()>
+where
+    T: Debug + Clone + Serialize + DeserializeOwned,
+{
+    let status = status(chart);
+    sd_notify::notify(false, &[NotifyState::Ready, NotifyState::Status(&status)])
+}
+
+/// Like [`discovery::found_everyone`] then signals systemd the service is
+/// ready.
+///
+/// # Errors
+/// Returns an error if the notification could not be sent to systemd.
+pub async fn found_everyone<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+) -> io::Result<Vec<(Id, Entry<[T; N]>)>>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let entries = discovery::found_everyone(chart, full_size).await;
+    ready(chart)?;
+    Ok(entries)
+}
+
+/// Like [`discovery::found_majority`] then signals systemd the service is
+/// ready.
+///
+/// # Errors
+/// Returns an error if the notification could not be sent to systemd.
+pub async fn found_majority<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+) -> io::Result<Vec<(Id, Entry<[T; N]>)>>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let entries = discovery::found_majority(chart, full_size).await;
+    ready(chart)?;
+    Ok(entries)
+}
+
+/// Like [`discovery::found_active_majority`] then signals systemd the service
+/// is ready.
+///
+/// # Errors
+/// Returns an error if the notification could not be sent to systemd.
+pub async fn found_active_majority<const N: usize, T>(
+    chart: &Chart<N, T>,
+    full_size: u16,
+    max_age: Duration,
+) -> io::Result<Vec<(Id, Entry<[T; N]>)>>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned,
+{
+    let entries = discovery::found_active_majority(chart, full_size, max_age).await;
+    ready(chart)?;
+    Ok(entries)
+}
+
+/// Like [`discovery::maintain`] while pinging the systemd watchdog and
+/// updating the service status with the chart size. Pinging stops if
+/// discovery crashes letting systemd restart the service.
+///
+/// # Errors
+/// Returns the error that ended [`discovery::maintain`].
+///
+/// # Note
+/// Without a watchdog configured for the service this only runs
+/// [`discovery::maintain`].
+pub async fn maintain<const N: usize, T>(chart: Chart<N, T>) -> Result<(), DiscoveryError>
+where
+    T: 'static + Debug + Clone + Serialize + DeserializeOwned + Sync + Send,
+{
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return discovery::maintain(chart).await;
+    }
+
+    let mut watchdog = tokio::time::interval(Duration::from_micros(usec) / 2);
+    let maintain = discovery::maintain(chart.clone());
+    tokio::pin!(maintain);
+    loop {
+        tokio::select! {
+            res = &mut maintain => return res,
+            _ = watchdog.tick() => {
+                let status = status(&chart);
+                let state = [NotifyState::Watchdog, NotifyState::Status(&status)];
+                if let Err(e) = sd_notify::notify(false, &state) {
+                    warn!("could not ping systemd watchdog: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+/// The UDP socket systemd passed using socket activation, ready to use with
+/// [`ChartBuilder::with_bound_socket`](crate::ChartBuilder::with_bound_socket).
+/// Lets a service without privileges discover on a port below 1024, systemd
+/// binds it instead. Returns `None` if no UDP socket was passed.
+///
+/// The default multicast group is joined and multicast loop enabled. Other
+/// sockets systemd passed are left alone, the `LISTEN_FDS` environment
+/// variable is cleared so call this only once.
+///
+/// # Example
+/// The service's `.socket` unit:
+/// ```ini
+/// [Socket]
+/// ListenDatagram=0.0.0.0:500
+/// ReusePort=true
+/// ```
+/// ```no_run
+/// # use std::error::Error;
+/// # use instance_chart::{systemd, ChartBuilder};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let sock = systemd::activated_socket()?.ok_or("not socket activated")?;
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+///     .with_bound_socket(sock)
+///     .finish()?;
+/// #   Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// Returns an error if `LISTEN_FDS` is malformed or setting up the socket
+/// failed.
+#[cfg(unix)]
+pub fn activated_socket() -> io::Result<Option<UdpSocket>> {
+    use std::os::fd::{BorrowedFd, FromRawFd};
+
+    for fd in sd_notify::listen_fds()? {
+        // SAFETY: systemd keeps passed file descriptors open for us
+        let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+        let sock = socket2::SockRef::from(&borrowed);
+        let is_udp =
+            sock.r#type()? == socket2::Type::DGRAM && sock.local_addr()?.as_socket().is_some();
+        if is_udp {
+            // SAFETY: as above, the environment variable naming the file
+            // descriptor was cleared so it is owned only here
+            let sock = unsafe { socket2::Socket::from_raw_fd(fd) };
+            return crate::chart::prepare_bound(sock).map(Some);
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,221 @@
+//! Helpers for integration tests of systems built on this crate. Record what
+//! a chart sees with an [`EventRecorder`] then assert on the order things
+//! happened in, for example that an instance joined before a quorum was
+//! reached or that nobody left during the test. Cut charts off from each
+//! other with a [`Partition`].
+//!
+//! Only available with the `testing` feature.
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::{Chart, Event, Id};
+
+mod partition;
+pub use partition::Partition;
+
+/// Something an [`EventRecorder`] saw happen to the chart
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Record {
+    /// A new instance was charted, see [`Chart::notify`]
+    Discovered { id: Id },
+    /// An [`Event`] was sent, see [`Chart::events`]
+    Event(Event),
+    /// The recorder could not keep up, `skipped` discoveries or events are
+    /// missing from the record
+    Lagged { skipped: u64 },
+}
+
+impl Record {
+    /// Whether this removed an instance from the chart
+    fn removes(&self) -> bool {
+        matches!(
+            self,
+            Record::Event(
+                Event::Left { .. }
+                    | Event::Expired { .. }
+                    | Event::Forgotten { .. }
+                    | Event::Unreachable { .. }
+            )
+        )
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Discovered { id } => write!(f, "discovered {id}"),
+            Record::Event(event) => write!(f, "{event:?}"),
+            Record::Lagged { skipped } => write!(f, "skipped {skipped} records"),
+        }
+    }
+}
+
+/// Records the discoveries and events of a chart in the order they happen.
+/// Recording starts when the recorder is created and stops once the chart
+/// is [closed](Chart::close) or the recorder is dropped.
+///
+/// The assertion methods panic with the full record, one line per record,
+/// making it easy to see what happened.
+///
+/// # Examples
+/// ```rust
+/// # use instance_chart::{discovery, testing::EventRecorder, ChartBuilder, Event};
+/// #
+/// # #[tokio::main]
+/// # async fn main() {
+/// let chart = ChartBuilder::new()
+///     .with_id(1)
+///     .with_service_port(8042)
+/// #   .with_discovery_port(43849)
+///     .finish()
+///     .unwrap();
+/// let recorder = EventRecorder::new(&chart);
+/// tokio::spawn(discovery::maintain(chart.clone()));
+/// // .. run the test
+/// recorder.assert_no_event(|event| matches!(event, Event::Left { .. }));
+/// # }
+/// ```
+pub struct EventRecorder {
+    records: Arc<Mutex<Vec<Record>>>,
+    task: JoinHandle<()>,
+}
+
+impl Debug for EventRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventRecorder")
+            .field("records", &self.records.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for EventRecorder {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// lock poisoning happens only on crash in another thread, in which
+// case panicing here is expected
+#[allow(clippy::missing_panics_doc)]
+impl EventRecorder {
+    /// Start recording the discoveries and events of `chart`
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime
+    #[must_use]
+    pub fn new<const N: usize, T>(chart: &Chart<N, T>) -> Self
+    where
+        T: 'static + Debug + Clone + Serialize + DeserializeOwned + Send + Sync,
+    {
+        let mut discoveries = chart.notify();
+        let mut events = chart.events();
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let recording = Arc::clone(&records);
+        let task = tokio::spawn(async move {
+            loop {
+                let record = tokio::select! {
+                    res = discoveries.recv_entry() => match res {
+                        Ok((id, _)) => Record::Discovered { id },
+                        Err(RecvError::Lagged(skipped)) => Record::Lagged { skipped },
+                        Err(RecvError::Closed) => return,
+                    },
+                    res = events.recv() => match res {
+                        Ok(event) => Record::Event(event),
+                        Err(RecvError::Lagged(skipped)) => Record::Lagged { skipped },
+                        Err(RecvError::Closed) => return,
+                    },
+                };
+                recording.lock().unwrap().push(record);
+            }
+        });
+        Self { records, task }
+    }
+
+    /// Everything recorded so far, oldest first
+    #[must_use]
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Index of the first record matching `pred`
+    #[must_use]
+    pub fn position(&self, pred: impl FnMut(&Record) -> bool) -> Option<usize> {
+        self.records.lock().unwrap().iter().position(pred)
+    }
+
+    /// Index of the record at which instance `id` was discovered
+    #[must_use]
+    pub fn discovered_at(&self, id: Id) -> Option<usize> {
+        self.position(|record| *record == Record::Discovered { id })
+    }
+
+    /// Index of the record at which the chart first reached `size`
+    /// instances, including this one. Assumes the chart started out empty.
+    #[must_use]
+    pub fn size_reached_at(&self, size: usize) -> Option<usize> {
+        let mut charted = 1;
+        self.position(|record| {
+            match record {
+                Record::Discovered { .. } => charted += 1,
+                record if record.removes() => charted -= 1,
+                _ => (),
+            }
+            charted >= size
+        })
+    }
+
+    /// Panics unless a record matching `first` came before one matching
+    /// `then`. Both have to be recorded.
+    #[track_caller]
+    pub fn assert_before(&self, first: impl Fn(&Record) -> bool, then: impl Fn(&Record) -> bool) {
+        match (self.position(first), self.position(then)) {
+            (Some(first), Some(then)) if first < then => (),
+            (first, then) => self.fail(&format!(
+                "expected record {first:?} to come before record {then:?}"
+            )),
+        }
+    }
+
+    /// Panics unless instance `id` was discovered before the chart reached
+    /// `size` instances, for example to check a node joined before quorum
+    #[track_caller]
+    pub fn assert_discovered_before_size(&self, id: Id, size: usize) {
+        match (self.discovered_at(id), self.size_reached_at(size)) {
+            (Some(discovered), Some(reached)) if discovered <= reached => (),
+            (discovered, reached) => self.fail(&format!(
+                "expected {id} to be discovered (at {discovered:?}) before size \
+                {size} was reached (at {reached:?})"
+            )),
+        }
+    }
+
+    /// Panics if an event matching `pred` was recorded
+    #[track_caller]
+    pub fn assert_no_event(&self, pred: impl Fn(&Event) -> bool) {
+        let found = self.position(|record| matches!(record, Record::Event(e) if pred(e)));
+        if let Some(idx) = found {
+            self.fail(&format!("unexpected event at record {idx}"));
+        }
+    }
+
+    #[track_caller]
+    fn fail(&self, msg: &str) {
+        panic!("{msg}, recorded:\n{self}");
+    }
+}
+
+/// The records one per line, prefixed with their index
+impl fmt::Display for EventRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, record) in self.records.lock().unwrap().iter().enumerate() {
+            writeln!(f, "{idx:>4}: {record}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::chart::Groups;
+use crate::{Chart, Id};
+
+/// Splits charts running in this process into groups that do not hear from
+/// each other, as if the network was partitioned, and heals them again. Use
+/// it to test how a Raft or gossip layer built on the chart handles a
+/// partition. Discovery keeps running as it would, only the datagrams sent
+/// across the partition are dropped as they are received.
+///
+/// Instances charted on the other side stay charted until they
+/// [expire](crate::ChartBuilder::with_expiry), build the charts with expiry
+/// or a [ttl](crate::ChartBuilder::with_ttl) to see them go. Once healed
+/// they are charted again as soon as they hear each other's next broadcast.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use instance_chart::{discovery, testing::Partition, ChartBuilder};
+/// # use std::time::Duration;
+/// #
+/// # #[tokio::main]
+/// # async fn main() {
+/// let node = |id| {
+///     ChartBuilder::new()
+///         .with_id(id)
+///         .with_service_port(8042 + id as u16)
+///         .with_expiry(3)
+///         .local_discovery(true)
+///         .finish()
+///         .unwrap()
+/// };
+/// let charts = [node(1), node(2), node(3)];
+/// let partition = Partition::new();
+/// for chart in &charts {
+///     partition.add(chart);
+///     tokio::spawn(discovery::maintain(chart.clone()));
+/// }
+///
+/// partition.split(&[&[1, 2], &[3]]);
+/// // .. check the cluster keeps working without instance 3
+/// partition.heal();
+/// discovery::found_everyone(&charts[2], 3).await;
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Partition {
+    groups: Groups,
+}
+
+// lock poisoning happens only on crash in another thread, in which
+// case panicing here is expected
+#[allow(clippy::missing_panics_doc)]
+impl Partition {
+    /// A partition without charts, it starts out healed
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the partition cut off `chart`, replacing any partition it was
+    /// added to before. Works on clones of `chart` too, including those
+    /// already running discovery.
+    pub fn add<const N: usize, T>(&self, chart: &Chart<N, T>)
+    where
+        T: Debug + Clone + Serialize,
+    {
+        chart.set_cut(Arc::clone(&self.groups));
+    }
+
+    /// Split the charts into `groups` of instance ids. A chart only hears
+    /// from the instances in its own group, one in none of the groups hears
+    /// from nobody. Replaces the groups of an earlier split.
+    pub fn split(&self, groups: &[&[Id]]) {
+        let groups: HashMap<Id, usize> = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, ids)| ids.iter().map(move |id| (*id, group)))
+            .collect();
+        *self.groups.write().unwrap() = Some(groups);
+    }
+
+    /// Let every chart hear from everyone again
+    pub fn heal(&self) {
+        *self.groups.write().unwrap() = None;
+    }
+
+    /// Whether the charts are split, see [`split`](Self::split)
+    #[must_use]
+    pub fn is_split(&self) -> bool {
+        self.groups.read().unwrap().is_some()
+    }
+}
@@ -13,6 +13,7 @@ where
 }
 
 /// Cancels the wrapped tokio Task on Drop.
+#[derive(Debug)]
 pub struct Wrapper<T>(task::JoinHandle<T>);
 
 impl<T> Future for Wrapper<T>{
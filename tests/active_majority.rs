@@ -0,0 +1,46 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder, Entry, Source, Status};
+
+#[tokio::test(flavor = "current_thread")]
+async fn ghosts_do_not_count() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43839)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let nodes: Vec<_> = (1..=3).map(node).collect();
+    let ghosts = (10..=11).map(|id| {
+        let entry = Entry {
+            ip: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            msg: [8042],
+            status: Status::Active,
+            group: None,
+            capabilities: Default::default(),
+            source: Source::Merged,
+            meta: Default::default(),
+            port_health: Default::default(),
+            priority: 0,
+        };
+        (id, entry)
+    });
+    nodes[0].merge_from(ghosts);
+    for node in &nodes {
+        tokio::spawn(discovery::maintain(node.clone()));
+    }
+
+    let found = tokio::time::timeout(
+        Duration::from_secs(5),
+        discovery::found_active_majority(&nodes[0], 5, Duration::from_secs(2)),
+    )
+    .await
+    .unwrap();
+    let mut ids: Vec<_> = found.into_iter().map(|(id, _)| id).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, [2, 3]);
+}
@@ -0,0 +1,34 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder};
+
+#[tokio::test(flavor = "current_thread")]
+async fn handshake_across_discovery_ports() {
+    let node = |id, port| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(port)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let a = node(1, 43847);
+    let b = node(2, 43848);
+    tokio::spawn(discovery::maintain(a.clone()));
+    tokio::spawn(discovery::maintain(b.clone()));
+
+    a.announce_to(SocketAddr::from((Ipv4Addr::LOCALHOST, 43848)))
+        .await
+        .unwrap();
+    b.announce_to(SocketAddr::from((Ipv4Addr::LOCALHOST, 43847)))
+        .await
+        .unwrap();
+    for chart in [&b, &a] {
+        let found = discovery::found_everyone(chart, 2);
+        tokio::time::timeout(Duration::from_secs(1), found)
+            .await
+            .unwrap();
+    }
+}
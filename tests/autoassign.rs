@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder};
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "current_thread")]
+async fn announced_once_port_assigned() {
+    let node = |id, port| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(port)
+            .with_service_port_zero_autoassign()
+            .with_discovery_port(43853)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let placeholder = node(1, 0);
+    let peer = node(2, 8042);
+    tokio::spawn(discovery::maintain(placeholder.clone()));
+    tokio::spawn(discovery::maintain(peer.clone()));
+
+    let found = discovery::found_everyone(&peer, 2);
+    let res = tokio::time::timeout(Duration::from_millis(300), found).await;
+    assert!(res.is_err(), "instance announced before its port was set");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    placeholder.set_service_port(0, port).await.unwrap();
+    let found = discovery::found_everyone(&peer, 2);
+    tokio::time::timeout(Duration::from_secs(1), found)
+        .await
+        .unwrap();
+    assert_eq!(peer.get_addr(1).unwrap().port(), port);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn placeholder_not_sent_by_resume_or_converge_fast() {
+    let node = |id, port| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(port)
+            .with_service_port_zero_autoassign()
+            .with_discovery_port(43855)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let placeholder = node(1, 0);
+    let peer = node(2, 8042);
+    tokio::spawn(discovery::maintain(placeholder.clone()));
+    tokio::spawn(discovery::maintain(peer.clone()));
+
+    placeholder.resume().await;
+    // we hear the peer, it must not hear us
+    let _converged = discovery::converge_fast(&placeholder, 2, Duration::from_millis(200)).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(peer.size(), 1, "peer charted the placeholder port");
+}
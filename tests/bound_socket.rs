@@ -0,0 +1,46 @@
+use instance_chart::{discovery, ChartBuilder};
+use socket2::{Domain, SockAddr, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+fn shared_socket(port: u16) -> UdpSocket {
+    let sock = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+    sock.set_reuse_port(true).unwrap();
+    sock.set_multicast_loop_v4(true).unwrap();
+    sock.set_nonblocking(true).unwrap();
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    sock.bind(&SockAddr::from(addr)).unwrap();
+    let group = Ipv4Addr::new(224, 0, 0, 251);
+    sock.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        .unwrap();
+    UdpSocket::from_std(sock.into()).unwrap()
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn discovers_over_bound_socket() {
+    let bound = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_bound_socket(shared_socket(43833))
+        .finish()
+        .unwrap();
+    let opened = ChartBuilder::new()
+        .with_id(2)
+        .with_service_port(8042)
+        .with_discovery_port(43833)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+    assert_eq!(bound.discovery_port(), 43833);
+
+    tokio::spawn(discovery::maintain(bound.clone()));
+    tokio::spawn(discovery::maintain(opened.clone()));
+    let found = async {
+        discovery::found_everyone(&bound, 2).await;
+        discovery::found_everyone(&opened, 2).await;
+    };
+    tokio::time::timeout(Duration::from_secs(2), found)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "challenge")]
+
+use instance_chart::{discovery, ChartBuilder};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn only_instances_with_key_are_charted() {
+    let node = |id, key| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43816)
+            .local_discovery(true)
+            .with_challenge(key)
+            .finish()
+            .unwrap()
+    };
+    let a = node(1, [7; 32]);
+    let b = node(2, [7; 32]);
+    let intruder = node(3, [8; 32]);
+
+    tokio::spawn(discovery::maintain(a.clone()));
+    tokio::spawn(discovery::maintain(b.clone()));
+    tokio::spawn(discovery::maintain(intruder.clone()));
+    discovery::found_everyone(&a, 2).await;
+    discovery::found_everyone(&b, 2).await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(a.size(), 2);
+    assert_eq!(b.size(), 2);
+    assert_eq!(intruder.size(), 1);
+}
@@ -0,0 +1,28 @@
+use instance_chart::{discovery, ChartBuilder};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn lowest_free_ids_are_claimed() {
+    let listen = Duration::from_millis(300);
+    let mut claimed = Vec::new();
+    let mut charts = Vec::new();
+    for original in [1000, 1001] {
+        let chart = ChartBuilder::new()
+            .with_id(original)
+            .with_service_port(8042)
+            .with_discovery_port(43834)
+            .local_discovery(true)
+            .finish()
+            .unwrap();
+        tokio::spawn(discovery::maintain(chart.clone()));
+        claimed.push(chart.claim_lowest_id(listen).await);
+        charts.push(chart);
+    }
+    assert_eq!(claimed, [0, 1]);
+
+    // the goodbye under the original id made the first forget it
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let first = charts[0].snapshot();
+    assert!(first.get(1001).is_none());
+    assert!(first.get(1).is_some());
+}
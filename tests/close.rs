@@ -0,0 +1,29 @@
+use instance_chart::{discovery, ChartBuilder, Event};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn goodbye_before_close() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43807)
+            .local_discovery(true)
+            .with_goodbye_count(2)
+            .finish()
+            .unwrap()
+    };
+    let leaving = node(1);
+    let staying = node(2);
+    let mut events = staying.events();
+
+    let maintain_leaving = tokio::spawn(discovery::maintain(leaving.clone()));
+    tokio::spawn(discovery::maintain(staying.clone()));
+    discovery::found_everyone(&staying, 2).await;
+
+    leaving.close(Duration::from_millis(50)).await;
+    maintain_leaving.await.unwrap().unwrap();
+
+    assert_eq!(events.recv().await.unwrap(), Event::Left { id: 1 });
+    assert_eq!(staying.size(), 1);
+}
@@ -0,0 +1,60 @@
+use instance_chart::{discovery, ChartBuilder, ConvergeError};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn converges_or_says_why_not() {
+    let charts: Vec<_> = (1..=2)
+        .map(|id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042 + id as u16)
+                .with_discovery_port(43836)
+                .with_rampdown(
+                    Duration::from_secs(1),
+                    Duration::from_secs(5),
+                    Duration::from_secs(5),
+                )
+                .local_discovery(true)
+                .finish()
+                .unwrap()
+        })
+        .collect();
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+
+    let deadline = Duration::from_secs(1);
+    discovery::converge_fast(&charts[0], 2, deadline)
+        .await
+        .unwrap();
+
+    let err = discovery::converge_fast(&charts[0], 3, Duration::from_millis(200))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ConvergeError::TooFew {
+            found: 2,
+            target: 3,
+            unanswered: 0
+        }
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn alone_receives_nothing() {
+    let chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43856)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+    tokio::spawn(discovery::maintain(chart.clone()));
+
+    let err = discovery::converge_fast(&chart, 2, Duration::from_millis(200))
+        .await
+        .unwrap_err();
+    assert_eq!(err, ConvergeError::NothingReceived);
+    assert!(chart.counters().received > 0, "our own echoes arrive");
+}
@@ -0,0 +1,35 @@
+use instance_chart::{discovery, ChartBuilder, Event, Status};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn peers_forget_drained() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43802)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let leaving = node(1);
+    let staying = node(2);
+    let mut events = staying.events();
+
+    tokio::spawn(discovery::maintain(leaving.clone()));
+    tokio::spawn(discovery::maintain(staying.clone()));
+    let found = discovery::found_everyone(&staying, 2).await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, 1);
+
+    discovery::drain(&leaving, Duration::from_millis(50)).await;
+    assert_eq!(leaving.our_status(), Status::Leaving);
+
+    let draining = Event::StatusChanged {
+        id: 1,
+        status: Status::Draining,
+    };
+    assert_eq!(events.recv().await.unwrap(), draining);
+    assert_eq!(events.recv().await.unwrap(), Event::Left { id: 1 });
+    assert_eq!(staying.size(), 1);
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use instance_chart::discovery::{self, Driver};
+use instance_chart::ChartBuilder;
+
+#[tokio::test(flavor = "current_thread")]
+async fn many_charts_on_one_task() {
+    const SIZE: u16 = 20;
+    let driver = Driver::new();
+    let charts: Vec<_> = (1..=u64::from(SIZE))
+        .map(|id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042)
+                .with_discovery_port(43841)
+                .local_discovery(true)
+                .finish()
+                .unwrap()
+        })
+        .collect();
+    let mut running: Vec<_> = charts
+        .iter()
+        .map(|chart| Box::pin(driver.maintain(chart.clone())))
+        .collect();
+
+    for chart in &charts {
+        let found = discovery::found_everyone(chart, SIZE);
+        tokio::time::timeout(Duration::from_secs(5), found)
+            .await
+            .unwrap();
+    }
+
+    charts[0].clone().close(Duration::ZERO).await;
+    running.remove(0).await.unwrap();
+    drop(driver);
+    running.remove(0).await.unwrap();
+}
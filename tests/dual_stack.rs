@@ -0,0 +1,31 @@
+use instance_chart::{discovery, ChartBuilder};
+
+#[tokio::test(flavor = "current_thread")]
+async fn dual_and_ipv4_only_form_one_chart() {
+    let node = |id, dual_stack| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43809)
+            .local_discovery(true)
+            .dual_stack(dual_stack)
+            .finish()
+            .unwrap()
+    };
+    let dual = node(1, true);
+    let v4_only = node(2, false);
+
+    tokio::spawn(discovery::maintain(dual.clone()));
+    tokio::spawn(discovery::maintain(v4_only.clone()));
+    discovery::found_everyone(&dual, 2).await;
+    discovery::found_everyone(&v4_only, 2).await;
+
+    // charted once and no conflict even though it is found over IPv4 and IPv6
+    let dual2 = node(3, true);
+    let mut events = dual2.events();
+    tokio::spawn(discovery::maintain(dual2.clone()));
+    discovery::found_everyone(&dual2, 3).await;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(dual2.size(), 3);
+    assert!(events.try_recv().is_err());
+}
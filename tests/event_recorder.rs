@@ -0,0 +1,45 @@
+#![cfg(feature = "testing")]
+
+use std::time::Duration;
+
+use instance_chart::testing::{EventRecorder, Record};
+use instance_chart::{discovery, ChartBuilder, Event};
+
+#[tokio::test(flavor = "current_thread")]
+async fn records_join_and_leave_order() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43850)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let charts: Vec<_> = (1..=3).map(node).collect();
+    let recorder = EventRecorder::new(&charts[0]);
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+    discovery::found_everyone(&charts[0], 3).await;
+
+    charts[2].clone().close(Duration::ZERO).await;
+    let left = Record::Event(Event::Left { id: 3 });
+    let recorded = async {
+        while recorder.position(|record| *record == left).is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(1), recorded)
+        .await
+        .unwrap();
+
+    recorder.assert_discovered_before_size(2, 3);
+    recorder.assert_before(
+        |record| *record == Record::Discovered { id: 3 },
+        |record| *record == left,
+    );
+    recorder.assert_no_event(|event| matches!(event, Event::Expired { .. }));
+    let quorum = recorder.size_reached_at(3).unwrap();
+    assert!(quorum < recorder.position(|record| *record == left).unwrap());
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "fault-injection")]
+
+use instance_chart::{discovery, ChartBuilder};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn discovers_despite_loss() {
+    let node = |id, loss_rate| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43846)
+            .local_discovery(true)
+            .with_fault_injection(
+                loss_rate,
+                Duration::from_millis(1)..Duration::from_millis(5),
+            )
+            .finish()
+            .unwrap()
+    };
+    let charts = [node(1, 0.5), node(2, 0.5), node(3, 1.0)];
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+    for chart in &charts[..2] {
+        let found = discovery::found_everyone(chart, 2);
+        tokio::time::timeout(Duration::from_secs(10), found)
+            .await
+            .unwrap();
+    }
+    assert_eq!(charts[0].size(), 2, "sends nothing");
+    assert_eq!(charts[2].size(), 1, "receives nothing");
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder};
+
+#[tokio::test(flavor = "current_thread")]
+async fn resolves_once_quiet() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43831)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let nodes: Vec<_> = (1..=4).map(node).collect();
+    for node in &nodes {
+        tokio::spawn(discovery::maintain(node.clone()));
+    }
+
+    let found = discovery::found_stable(&nodes[0], Duration::from_millis(500)).await;
+    let mut ids: Vec<_> = found.into_iter().map(|(id, _)| id).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, [2, 3, 4]);
+}
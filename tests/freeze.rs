@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder};
+
+#[tokio::test(flavor = "current_thread")]
+async fn applied_on_unfreeze() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43845)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let frozen = node(1);
+    let other = node(2);
+
+    frozen.freeze().await;
+    tokio::spawn(discovery::maintain(frozen.clone()));
+    tokio::spawn(discovery::maintain(other.clone()));
+    discovery::found_everyone(&other, 2).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(frozen.size(), 1);
+
+    frozen.unfreeze().await;
+    discovery::found_everyone(&frozen, 2).await;
+}
@@ -0,0 +1,33 @@
+use instance_chart::{discovery, ChartBuilder};
+
+#[tokio::test(flavor = "current_thread")]
+async fn partition_by_group() {
+    let node = |id, group| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43817)
+            .local_discovery(true)
+            .with_group(group)
+            .finish()
+            .unwrap()
+    };
+    let nodes = [node(1, 1), node(2, 1), node(3, 2), node(4, 2)];
+    let mut shard_two = nodes[2].notify_group(2);
+    for node in &nodes {
+        tokio::spawn(discovery::maintain(node.clone()));
+    }
+    discovery::found_everyone(&nodes[2], 4).await;
+
+    let mut shard_one: Vec<_> = nodes[2]
+        .peers_in_group(1)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    shard_one.sort_unstable();
+    assert_eq!(shard_one, [1, 2]);
+    assert_eq!(nodes[2].our_group(), Some(2));
+
+    let (id, _, _) = shard_two.recv().await.unwrap();
+    assert_eq!(id, 4);
+}
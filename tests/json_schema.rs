@@ -0,0 +1,51 @@
+use instance_chart::{discovery, ChartBuilder, Event, Snapshot};
+use schemars::schema_for;
+use serde_json::json;
+use std::time::Duration;
+
+#[test]
+fn schemas_name_the_fields() {
+    let snapshot = serde_json::to_value(schema_for!(Snapshot<1, u16>)).unwrap();
+    let entry = &snapshot["definitions"]["Entry_for_Array_size_1_of_uint16"];
+    for field in [
+        "ip",
+        "msg",
+        "status",
+        "group",
+        "capabilities",
+        "source",
+        "meta",
+    ] {
+        assert!(entry["properties"].get(field).is_some(), "{field} missing");
+    }
+
+    let event = serde_json::to_value(schema_for!(Event)).unwrap();
+    assert!(event.to_string().contains("IdCollision"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn snapshot_serializes_as_map() {
+    let charts: Vec<_> = (1..=2)
+        .map(|id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042 + id as u16)
+                .with_discovery_port(43835)
+                .local_discovery(true)
+                .finish()
+                .unwrap()
+        })
+        .collect();
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+    let found = discovery::found_everyone(&charts[0], 2);
+    tokio::time::timeout(Duration::from_secs(5), found)
+        .await
+        .unwrap();
+
+    let json = serde_json::to_value(charts[0].snapshot()).unwrap();
+    assert_eq!(json["2"]["msg"], json!([8044]));
+    assert_eq!(json["2"]["status"], json!("Active"));
+    assert_eq!(json["2"]["source"], json!("Network"));
+}
@@ -54,7 +54,7 @@ async fn node(id: u64, cluster_size: u16) {
         .finish()
         .unwrap();
     let maintain = discovery::maintain(chart.clone());
-    let _ = tokio::spawn(maintain);
+    tokio::spawn(maintain);
     discovery::found_everyone(&chart, cluster_size).await;
 
     info!("discovery complete: {chart:?}");
@@ -0,0 +1,40 @@
+use instance_chart::{discovery, ChartBuilder, Event};
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn different_port_count_is_reported() {
+    let two = ChartBuilder::new()
+        .with_id(1)
+        .with_service_ports([8042, 8043])
+        .with_discovery_port(43815)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+    let three = ChartBuilder::new()
+        .with_id(2)
+        .with_service_ports([8042, 8043, 8044])
+        .with_discovery_port(43815)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+
+    let mut events = two.events();
+    tokio::spawn(discovery::maintain(two.clone()));
+    tokio::spawn(discovery::maintain(three.clone()));
+
+    let event = tokio::time::timeout(Duration::from_secs(2), events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        event,
+        Event::MismatchedN {
+            id: 2,
+            expected: 2,
+            got: 3,
+            ..
+        }
+    ));
+    assert_eq!(two.size(), 1);
+    assert_eq!(three.size(), 1);
+}
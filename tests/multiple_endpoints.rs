@@ -0,0 +1,48 @@
+use instance_chart::{discovery, ChartBuilder};
+use std::net::Ipv4Addr;
+
+fn setup_tracing() {
+    use tracing_subscriber::{filter, prelude::*};
+
+    let filter = filter::EnvFilter::builder()
+        .parse("info,instance_chart=debug")
+        .unwrap();
+
+    let fmt = tracing_subscriber::fmt::layer().pretty().with_test_writer();
+
+    let _ignore_err = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt)
+        .try_init();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn bridge_two_groups() {
+    setup_tracing();
+
+    let group_a = (Ipv4Addr::new(224, 0, 0, 251), 43800);
+    let group_b = (Ipv4Addr::new(224, 0, 0, 252), 43801);
+
+    let node = |id, endpoints: &[(Ipv4Addr, u16)]| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_endpoints(endpoints)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+
+    let a = node(1, &[group_a]);
+    let b = node(2, &[group_b]);
+    let bridge = node(3, &[group_a, group_b]);
+
+    tokio::spawn(discovery::maintain(a.clone()));
+    tokio::spawn(discovery::maintain(b.clone()));
+    tokio::spawn(discovery::maintain(bridge.clone()));
+
+    discovery::found_everyone(&bridge, 3).await;
+    discovery::found_everyone(&a, 2).await;
+    discovery::found_everyone(&b, 2).await;
+    assert_eq!(a.size(), 2);
+}
@@ -44,7 +44,7 @@ async fn node(id: u64, cluster_size: u16) {
         .finish()
         .unwrap();
     let maintain = discovery::maintain(chart.clone());
-    let _ = tokio::spawn(maintain);
+    tokio::spawn(maintain);
 
     if id == 0 {
         let mut new = chart.notify();
@@ -67,7 +67,6 @@ async fn test_notify2() {
 
     let full_size = 4u16;
     let _handles: Vec<_> = (1..=full_size)
-        .into_iter()
         .map(|id| {
             ChartBuilder::new()
                 .with_id(id.into())
@@ -91,7 +90,7 @@ async fn test_notify2() {
 
     let mut node_discoverd = chart.notify();
     let maintain = discovery::maintain(chart.clone());
-    let _ = tokio::spawn(maintain); // maintain task will run forever
+    tokio::spawn(maintain); // maintain task will run forever
 
     while chart.size() < full_size as usize {
         let new = node_discoverd.recv().await.unwrap();
@@ -0,0 +1,49 @@
+use instance_chart::{discovery, ChartBuilder};
+use std::net::UdpSocket;
+use std::time::Duration;
+
+#[tokio::test(flavor = "current_thread")]
+async fn storm_is_deferred() {
+    let chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43832)
+        .local_discovery(true)
+        .with_packet_budget(5, Duration::from_secs(60))
+        .finish()
+        .unwrap();
+    tokio::spawn(discovery::maintain(chart.clone()));
+
+    let storm = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for _ in 0..50 {
+        storm.send_to(&[1, 2, 3], "127.0.0.1:43832").unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let counters = chart.counters();
+    assert!(counters.deferred > 0);
+    assert!(counters.decoded + counters.skipped + counters.invalid <= 5);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn every_datagram_counted() {
+    let chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43852)
+        .with_multicast_loop(false)
+        .finish()
+        .unwrap();
+    tokio::spawn(discovery::maintain(chart.clone()));
+    tokio::task::yield_now().await;
+
+    let storm = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for _ in 0..50 {
+        storm.send_to(&[1, 2, 3], "127.0.0.1:43852").unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let counters = chart.counters();
+    assert_eq!(counters.received, 50);
+    assert_eq!(counters.invalid, 50);
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "testing")]
+
+use std::time::Duration;
+
+use instance_chart::testing::Partition;
+use instance_chart::{discovery, Chart, ChartBuilder};
+
+async fn until_size(chart: &Chart<1, u16>, size: usize) {
+    let shrunk = async {
+        while chart.size() != size {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(2), shrunk)
+        .await
+        .unwrap_or_else(|_| panic!("chart {} never had size {size}", chart.our_id()));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn split_then_heal() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43854)
+            .with_rampdown(
+                Duration::from_millis(10),
+                Duration::from_millis(50),
+                Duration::from_millis(50),
+            )
+            .with_ttl(Duration::from_millis(250))
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let charts: Vec<_> = (1..=3).map(node).collect();
+    let partition = Partition::new();
+    for chart in &charts {
+        partition.add(chart);
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+    for chart in &charts {
+        until_size(chart, 3).await;
+    }
+
+    partition.split(&[&[1, 2], &[3]]);
+    assert!(partition.is_split());
+    until_size(&charts[2], 1).await;
+    until_size(&charts[0], 2).await;
+    assert!(charts[0].get_addr(2).is_some());
+    assert!(charts[0].get_addr(3).is_none());
+
+    partition.heal();
+    for chart in &charts {
+        until_size(chart, 3).await;
+    }
+}
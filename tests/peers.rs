@@ -0,0 +1,45 @@
+use instance_chart::{discovery, peers::Peers, ChartBuilder};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "current_thread")]
+async fn reuses_connections_and_reports_failures() {
+    let listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
+    let closed_port = closed.local_addr().unwrap().port();
+    drop(closed);
+
+    let charts: Vec<_> = [(1, 8042), (2, open), (3, closed_port)]
+        .into_iter()
+        .map(|(id, port)| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(port)
+                .with_discovery_port(43837)
+                .local_discovery(true)
+                .finish()
+                .unwrap()
+        })
+        .collect();
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+    let found = discovery::found_everyone(&charts[0], 3);
+    tokio::time::timeout(Duration::from_secs(5), found)
+        .await
+        .unwrap();
+
+    let mut peers = Peers::new(charts[0].clone());
+    for msg in [&b"hello "[..], b"world"] {
+        let failed = peers.broadcast_tcp(msg).await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 3);
+    }
+
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut received = [0; 11];
+    stream.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"hello world");
+}
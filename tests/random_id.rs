@@ -43,7 +43,7 @@ async fn node(cluster_size: u16) {
         .finish()
         .unwrap();
     let maintain = discovery::maintain(chart.clone());
-    let _ = tokio::spawn(maintain);
+    tokio::spawn(maintain);
     discovery::found_everyone(&chart, cluster_size).await;
 
     info!("discovery complete: {chart:?}");
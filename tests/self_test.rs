@@ -0,0 +1,29 @@
+use instance_chart::{discovery, ChartBuilder, SelfTestError};
+
+#[tokio::test(flavor = "current_thread")]
+async fn echo_arrives() {
+    let chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43805)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+
+    tokio::spawn(discovery::maintain(chart.clone()));
+    chart.self_test().await.unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn no_echo_without_maintain() {
+    let chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43806)
+        .local_discovery(true)
+        .finish()
+        .unwrap();
+
+    let err = chart.self_test().await.unwrap_err();
+    assert!(matches!(err, SelfTestError::NoEcho { port: 43806, .. }));
+}
@@ -0,0 +1,30 @@
+use instance_chart::{discovery, ChartBuilder, Event};
+
+#[tokio::test(flavor = "current_thread")]
+async fn ghost_free_nap() {
+    let node = |id| {
+        ChartBuilder::new()
+            .with_id(id)
+            .with_service_port(8042)
+            .with_discovery_port(43808)
+            .local_discovery(true)
+            .finish()
+            .unwrap()
+    };
+    let napping = node(1);
+    let awake = node(2);
+    let mut events = awake.events();
+
+    tokio::spawn(discovery::maintain(napping.clone()));
+    tokio::spawn(discovery::maintain(awake.clone()));
+    discovery::found_everyone(&awake, 2).await;
+
+    napping.suspend().await;
+    assert_eq!(events.recv().await.unwrap(), Event::Left { id: 1 });
+    assert_eq!(awake.size(), 1);
+
+    let mut discoveries = awake.notify();
+    napping.resume().await;
+    let (id, _, _) = discoveries.recv().await.unwrap();
+    assert_eq!(id, 1);
+}
@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use instance_chart::{discovery, ChartBuilder, LocalDiscovery};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn large_local_cluster() {
+    const SIZE: u16 = 100;
+    let charts: Vec<_> = (1..=u64::from(SIZE))
+        .map(|id| {
+            ChartBuilder::new()
+                .with_id(id)
+                .with_service_port(8042)
+                .with_discovery_port(43840)
+                .local_discovery(LocalDiscovery::Unix)
+                .finish()
+                .unwrap()
+        })
+        .collect();
+    for chart in &charts {
+        tokio::spawn(discovery::maintain(chart.clone()));
+    }
+
+    for chart in &charts {
+        let found = tokio::time::timeout(
+            Duration::from_secs(10),
+            discovery::found_everyone(chart, SIZE),
+        )
+        .await
+        .unwrap();
+        assert!(found
+            .iter()
+            .all(|(_, entry)| entry.ip == IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+    charts[0].self_test().await.unwrap();
+}
+
+#[tokio::test]
+async fn makes_dir_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("instance-chart-43841");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+    let _chart = ChartBuilder::new()
+        .with_id(1)
+        .with_service_port(8042)
+        .with_discovery_port(43841)
+        .local_discovery(LocalDiscovery::Unix)
+        .finish()
+        .unwrap();
+    let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+}